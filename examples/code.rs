@@ -1,3 +1,5 @@
+#![allow(clippy::identity_op, clippy::manual_c_str_literals)]
+
 use byteorder::{
 	ByteOrder,
 	NativeEndian as Ne,
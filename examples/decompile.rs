@@ -0,0 +1,27 @@
+use byteorder::{
+	BigEndian,
+	LittleEndian,
+};
+use std::{
+	error::Error,
+	ffi::CString,
+	fs::File,
+};
+use sourcemod_smx::{
+	decompile::Decompiler,
+	smx::Endianness,
+};
+
+type Smx = sourcemod_smx::Smx<CString, Vec<u8>>;
+
+/// Print the global variables and functions of a plugin as SourcePawn.
+fn main() -> Result<(), Box<dyn Error>> {
+	let path = std::env::args().nth(1).ok_or("usage: decompile <plugin.smx>")?;
+	let (smx, endianness) = Smx::read_from(&mut File::open(path)?)?;
+	let decompiler = match endianness {
+		Endianness::Little => Decompiler::from_smx::<LittleEndian, _, _>(&smx)?,
+		Endianness::Big => Decompiler::from_smx::<BigEndian, _, _>(&smx)?,
+	};
+	print!("{}", decompiler.decompile());
+	Ok(())
+}
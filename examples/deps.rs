@@ -0,0 +1,81 @@
+use byteorder::{
+	BigEndian,
+	LittleEndian,
+};
+use std::{
+	error::Error,
+	ffi::CString,
+	fs::{
+		self,
+		File,
+	},
+	path::PathBuf,
+};
+use sourcemod_smx::{
+	deps::{
+		dependencies,
+		load_order,
+	},
+	plugin::Plugin,
+	smx::Endianness,
+};
+
+type Smx = sourcemod_smx::Smx<CString, Vec<u8>>;
+
+/// Print the dependencies of every `.smx` file in a directory, and the order
+/// to load them in.
+fn main() -> Result<(), Box<dyn Error>> {
+	let dir = std::env::args().nth(1).unwrap_or_else(move || "examples".into());
+
+	let mut paths: Vec<PathBuf> = fs::read_dir(&dir)?
+		.map(move |entry| entry.map(move |entry| entry.path()))
+		.collect::<Result<_, _>>()?;
+	paths.retain(move |path| path.extension().is_some_and(move |ext| ext == "smx"));
+	paths.sort();
+
+	let mut all = Vec::new();
+	for path in paths.iter() {
+		let (smx, endianness) = Smx::read_from(&mut File::open(path)?)?;
+		let plugin = match endianness {
+			Endianness::Little => Plugin::from_smx::<LittleEndian, _, _>(&smx)?,
+			Endianness::Big => Plugin::from_smx::<BigEndian, _, _>(&smx)?,
+		};
+		let deps = dependencies(&plugin)?;
+
+		println!("{}:", path.display());
+		for ext in deps.extensions.iter() {
+			println!(
+				"\textension {} ({}), required: {}",
+				ext.name, ext.file, ext.required
+			);
+		}
+		for library in deps.libraries.iter() {
+			println!(
+				"\tlibrary {} ({}), required: {}",
+				library.name, library.file, library.required
+			);
+		}
+		for library in deps.provides.iter() {
+			println!("\tprovides library {library}");
+		}
+		for native in deps.natives.iter() {
+			let optional = deps.optional.contains(native);
+			let optional = if optional { " (optional)" } else { "" };
+			println!("\tnative {native}{optional}");
+		}
+		all.push(deps);
+	}
+
+	let order = load_order(&all);
+	println!("load order:");
+	for idx in order.order.iter() {
+		println!("\t{}", paths[*idx].display());
+	}
+	for (idx, library) in order.missing.iter() {
+		println!("{} requires missing library {}", paths[*idx].display(), library.name);
+	}
+	for idx in order.cycles.iter() {
+		println!("{} is part of a dependency cycle", paths[*idx].display());
+	}
+	Ok(())
+}
@@ -0,0 +1,17 @@
+use std::{
+	error::Error,
+	fs,
+};
+use sourcemod_smx::minify::minify;
+
+/// Minify a plugin, writing the result to a new file.
+fn main() -> Result<(), Box<dyn Error>> {
+	let mut args = std::env::args().skip(1);
+	let (Some(input), Some(output)) = (args.next(), args.next()) else {
+		return Err("usage: minify <plugin.smx> <minified.smx>".into())
+	};
+	let (minified, report) = minify(&fs::read(input)?)?;
+	fs::write(output, minified)?;
+	println!("{report}");
+	Ok(())
+}
@@ -0,0 +1,53 @@
+use byteorder::{
+	BigEndian,
+	ByteOrder,
+	LittleEndian,
+};
+use std::{
+	error::Error,
+	ffi::CString,
+	fs::File,
+	io::{
+		self,
+		Read,
+	},
+};
+use sourcemod_smx::{
+	smx::Endianness,
+	symbolize::Symbolizer,
+};
+
+type Smx = sourcemod_smx::Smx<CString, Vec<u8>>;
+
+fn symbolizer<E: ByteOrder>(
+	smx: &Smx,
+	companion: Option<&Smx>,
+) -> Result<Symbolizer, Box<dyn Error>> {
+	Ok(match companion {
+		Some(companion) => Symbolizer::from_companion::<E>(smx, companion)?,
+		None => Symbolizer::from_smx::<E, _, _>(smx)?,
+	})
+}
+
+/// Annotate the call stacks of a SourceMod error log, read from standard input,
+/// with the debug information of a plugin, or of its companion file if it was
+/// stripped.
+fn main() -> Result<(), Box<dyn Error>> {
+	let mut args = std::env::args().skip(1);
+	let path = args.next()
+		.ok_or("usage: symbolize <plugin.smx> [companion.smx] < errors.log")?;
+	let (smx, endianness) = Smx::read_from(&mut File::open(path)?)?;
+	let companion = args.next()
+		.map(move |path| Smx::read_from(&mut File::open(path)?))
+		.transpose()?
+		.map(move |(companion, _)| companion);
+	let symbolizer = match endianness {
+		Endianness::Little => symbolizer::<LittleEndian>(&smx, companion.as_ref())?,
+		Endianness::Big => symbolizer::<BigEndian>(&smx, companion.as_ref())?,
+	};
+
+	let mut log = String::new();
+	io::stdin().read_to_string(&mut log)?;
+	print!("{}", symbolizer.annotate_log(&log));
+	Ok(())
+}
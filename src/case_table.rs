@@ -0,0 +1,157 @@
+//! Case tables of `switch` statements.
+//!
+//! A `SWITCH` instruction jumps to a `CASETBL` instruction, which is encoded
+//! as a record count, a default jump target, and that many
+//! `(value, jump target)` records.
+
+use crate::{
+	vm_types::{
+		Cell,
+		read_cell,
+		write_cell,
+	},
+	Instruction,
+};
+
+use byteorder::{
+	ReadBytesExt,
+	WriteBytesExt,
+};
+use std::{
+	collections::HashMap,
+	io::{
+		Error as IoError,
+		ErrorKind as IoErrorKind,
+		Result as IoResult,
+	}
+};
+
+/// Single record in a case table.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Case {
+	/// Value that is compared against `PRI`.
+	pub value: Cell,
+	/// Code offset to jump to if `PRI` is equal to [`Self::value`].
+	pub jump: Cell,
+}
+
+/// Read the operands of a `CASETBL` instruction, excluding the opcode, and
+/// return the default jump target with the case records.
+pub fn read_from(r: &mut impl ReadBytesExt) -> IoResult<(Cell, Vec<Case>)> {
+	let n_cases = read_cell(r)?;
+	if n_cases < 0 {
+		return Err(IoError::new(
+			IoErrorKind::InvalidData,
+			format!("invalid case table record count: {n_cases}")
+		))
+	}
+
+	let default = read_cell(r)?;
+
+	let mut cases = Vec::new();
+	for _ in 0..n_cases {
+		let value = read_cell(r)?;
+		let jump = read_cell(r)?;
+		cases.push(Case {
+			value,
+			jump,
+		});
+	}
+
+	Ok((default, cases))
+}
+
+/// Write the operands of a `CASETBL` instruction, excluding the opcode.
+pub fn write_to(
+	w: &mut impl WriteBytesExt,
+	default: Cell, cases: &[Case],
+) -> IoResult<()> {
+	write_cell(w, cases.len() as _)?;
+	write_cell(w, default)?;
+	for case in cases {
+		write_cell(w, case.value)?;
+		write_cell(w, case.jump)?;
+	}
+	Ok(())
+}
+
+/// Fill in the `switch` field of every [`Instruction::Casetbl`] in a list of
+/// instructions paired with their code offsets, using the `SWITCH`
+/// instructions that jump to them.
+///
+/// Case tables that no `SWITCH` refers to get `None`.
+pub fn link_switches(instructions: &mut [(usize, Instruction)]) {
+	let owners: HashMap<Cell, Cell> = instructions.iter()
+		.filter_map(move |(offset, instruction)| match instruction {
+			Instruction::Switch { jump_1 } => Some((*jump_1, *offset as Cell)),
+			_ => None,
+		})
+		.collect();
+
+	for (offset, instruction) in instructions.iter_mut() {
+		if let Instruction::Casetbl { switch, .. } = instruction {
+			*switch = owners.get(&(*offset as Cell)).copied();
+		}
+	}
+}
+
+#[test]
+fn round_trip() -> Result<(), Box<dyn std::error::Error>> {
+	use std::io::Cursor;
+
+	let casetbl = Instruction::Casetbl {
+		default: 0x40,
+		cases: vec![
+			Case { value: 1, jump: 0x10 },
+			Case { value: 5, jump: 0x20 },
+			Case { value: -3, jump: 0x30 },
+		],
+		switch: None,
+	};
+
+	let mut data = Vec::new();
+	casetbl.write_to(&mut data)?;
+	Instruction::Retn.write_to(&mut data)?;
+	assert_eq!(data.len(), 4 * (1 + 2 + 3 * 2) + 4);
+
+	let mut r = Cursor::new(data);
+	assert_eq!(Instruction::read_from(&mut r)?, casetbl);
+	assert_eq!(Instruction::read_from(&mut r)?, Instruction::Retn);
+	Ok(())
+}
+
+#[test]
+fn negative_count() {
+	use std::io::Cursor;
+
+	let mut data = Vec::new();
+	write_cell(&mut data, 130).unwrap();
+	write_cell(&mut data, -1).unwrap();
+	write_cell(&mut data, 0).unwrap();
+	assert!(Instruction::read_from(&mut Cursor::new(data)).is_err());
+}
+
+#[test]
+fn link() {
+	let mut instructions = vec![
+		(0, Instruction::Switch { jump_1: 12 }),
+		(8, Instruction::Retn),
+		(12, Instruction::Casetbl {
+			default: 8,
+			cases: vec![Case { value: 0, jump: 8 }],
+			switch: None,
+		}),
+		(32, Instruction::Casetbl {
+			default: 8,
+			cases: Vec::new(),
+			switch: None,
+		}),
+	];
+	link_switches(&mut instructions);
+	assert!(matches!(
+		instructions[2].1, Instruction::Casetbl { switch: Some(0), .. }
+	));
+	assert!(matches!(
+		instructions[3].1, Instruction::Casetbl { switch: None, .. }
+	));
+}
@@ -0,0 +1,384 @@
+//! Control-flow graphs of functions.
+
+use crate::{
+	case_table::Case,
+	functions::Function,
+	vm_types::Cell,
+	Instruction,
+};
+
+use std::collections::{
+	BTreeMap,
+	BTreeSet,
+	HashMap,
+};
+
+/// How control leaves an instruction.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Flow {
+	/// Control continues with the next instruction.
+	Next,
+	/// Control unconditionally jumps to a code offset.
+	Jump(Cell),
+	/// Control jumps to a code offset if a condition holds, and continues with
+	/// the next instruction otherwise.
+	Branch(Cell),
+	/// Control jumps through the case table at a code offset.
+	Switch(Cell),
+	/// Control returns from the function.
+	Return,
+	/// Execution stops.
+	Halt,
+	/// Instruction is never executed, such as `ENDPROC` or a case table.
+	Never,
+}
+
+/// Return how control leaves an instruction.
+pub fn flow(instruction: &Instruction) -> Flow {
+	use Instruction as I;
+	match *instruction {
+		I::Jump { jump_1 } => Flow::Jump(jump_1),
+		I::Jzer { jump_1 }
+			| I::Jnz { jump_1 }
+			| I::Jeq { jump_1 }
+			| I::Jneq { jump_1 }
+			| I::Jsless { jump_1 }
+			| I::Jsleq { jump_1 }
+			| I::Jsgrtr { jump_1 }
+			| I::Jsgeq { jump_1 }
+			| I::Jless { jump_1 }
+			| I::Jleq { jump_1 }
+			| I::Jgrtr { jump_1 }
+			| I::Jgeq { jump_1 } => Flow::Branch(jump_1),
+		I::Switch { jump_1 } => Flow::Switch(jump_1),
+		I::Retn | I::Ret => Flow::Return,
+		I::Halt { .. } | I::JumpPri => Flow::Halt,
+		I::Endproc | I::Casetbl { .. } => Flow::Never,
+		_ => Flow::Next,
+	}
+}
+
+/// Kind of an edge between two blocks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum EdgeKind {
+	/// Control falls through to the next block.
+	Fallthrough,
+	/// Unconditional jump.
+	Jump,
+	/// Conditional jump that was taken.
+	Branch,
+	/// `switch` case with a value.
+	Case(Cell),
+	/// Default `switch` case.
+	Default,
+}
+
+/// Edge to another block.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Edge {
+	/// Index of the other block.
+	pub block: usize,
+	/// Kind of the edge.
+	pub kind: EdgeKind,
+}
+
+/// Basic block of a control-flow graph.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BasicBlock {
+	/// Code offset of the first instruction.
+	pub start: usize,
+	/// Code offset one past the last instruction.
+	pub end: usize,
+	/// Instructions of the block paired with their code offsets.
+	pub instructions: Vec<(usize, Instruction)>,
+	/// Edges to blocks that control may continue with.
+	pub successors: Vec<Edge>,
+	/// Indices of blocks that may continue with this block.
+	pub predecessors: Vec<usize>,
+}
+
+impl BasicBlock {
+	/// Return the last instruction of this block.
+	pub fn terminator(&self) -> Option<&Instruction> {
+		self.instructions.last().map(move |(_, instruction)| instruction)
+	}
+}
+
+/// Problem that was found while building a control-flow graph.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CfgIssue {
+	/// A jump target is inside of the function, but not at the start of an
+	/// instruction.
+	MisalignedTarget {
+		offset: usize,
+		target: Cell,
+	},
+	/// A jump target is outside of the function.
+	OutsideTarget {
+		offset: usize,
+		target: Cell,
+	},
+	/// A `SWITCH` does not jump to a case table.
+	MissingCaseTable {
+		offset: usize,
+		target: Cell,
+	},
+}
+
+/// Control-flow graph of a function.
+#[derive(Default, Debug, Clone, PartialEq, Eq)]
+pub struct Cfg {
+	/// Basic blocks sorted by their start offset, where the first one is the
+	/// entry block.
+	pub blocks: Vec<BasicBlock>,
+	/// Case tables of the function by their code offsets.
+	pub case_tables: BTreeMap<usize, (Cell, Vec<Case>)>,
+	/// Problems that were found.
+	pub issues: Vec<CfgIssue>,
+}
+
+impl Cfg {
+	/// Return the index of the block that starts at a code offset.
+	pub fn block_at(&self, offset: usize) -> Option<usize> {
+		self.blocks.binary_search_by_key(&offset, move |b| b.start).ok()
+	}
+
+	/// Return the index of the block that contains a code offset.
+	pub fn block_containing(&self, offset: usize) -> Option<usize> {
+		let idx = self.blocks.partition_point(move |b| b.start <= offset);
+		idx.checked_sub(1).filter(move |idx| offset < self.blocks[*idx].end)
+	}
+
+	/// Return, for each block, whether it is reachable from the entry block.
+	pub fn reachable(&self) -> Vec<bool> {
+		let mut reachable = vec![false; self.blocks.len()];
+		let mut stack = Vec::new();
+		if !self.blocks.is_empty() {
+			stack.push(0);
+		}
+		while let Some(idx) = stack.pop() {
+			if std::mem::replace(&mut reachable[idx], true) {
+				continue
+			}
+			stack.extend(self.blocks[idx].successors.iter().map(move |e| e.block));
+		}
+		reachable
+	}
+
+	/// Return the indices of blocks that are not reachable from the entry
+	/// block.
+	pub fn unreachable_blocks(&self) -> Vec<usize> {
+		self.reachable().into_iter()
+			.enumerate()
+			.filter_map(move |(idx, reachable)| (!reachable).then_some(idx))
+			.collect()
+	}
+}
+
+/// Build the control-flow graph of a function.
+pub fn build_function(function: &Function) -> Cfg {
+	build(&function.instructions, function.end)
+}
+
+/// Build a control-flow graph out of the instructions of a function paired
+/// with their code offsets, where `end` is the offset one past the function.
+///
+/// `ENDPROC` instructions and case tables are never executed, and are not part
+/// of any block.
+pub fn build(instructions: &[(usize, Instruction)], end: usize) -> Cfg {
+	let start = instructions.first().map(move |(offset, _)| *offset).unwrap_or(end);
+	let mut issues = Vec::new();
+
+	let mut case_tables = BTreeMap::new();
+	for (offset, instruction) in instructions.iter() {
+		if let Instruction::Casetbl { default, cases, .. } = instruction {
+			case_tables.insert(*offset, (*default, cases.clone()));
+		}
+	}
+
+	let boundaries: BTreeSet<usize> = instructions.iter()
+		.map(move |(offset, _)| *offset)
+		.collect();
+	let check = |issues: &mut Vec<CfgIssue>, offset: usize, target: Cell| {
+		let target_offset = target as usize;
+		if boundaries.contains(&target_offset) {
+			Some(target_offset)
+		} else {
+			issues.push(if target >= 0 && start <= target_offset && target_offset < end {
+				CfgIssue::MisalignedTarget { offset, target }
+			} else {
+				CfgIssue::OutsideTarget { offset, target }
+			});
+			None
+		}
+	};
+
+	// Targets of each terminating instruction, with their edge kinds.
+	let mut targets: HashMap<usize, Vec<(usize, EdgeKind)>> = HashMap::new();
+	let mut leaders = BTreeSet::new();
+	leaders.insert(start);
+	let mut iter = instructions.iter().peekable();
+	while let Some((offset, instruction)) = iter.next() {
+		let next = iter.peek().map(move |(offset, _)| *offset);
+		let mut out = Vec::new();
+		match flow(instruction) {
+			Flow::Next => continue,
+			Flow::Jump(target) => {
+				let target = check(&mut issues, *offset, target);
+				out.extend(target.map(move |t| (t, EdgeKind::Jump)));
+			}
+			Flow::Branch(target) => {
+				let target = check(&mut issues, *offset, target);
+				out.extend(target.map(move |t| (t, EdgeKind::Branch)));
+				out.extend(next.map(move |next| (next, EdgeKind::Fallthrough)));
+			}
+			Flow::Switch(target) => {
+				match case_tables.get(&(target as usize)) {
+					Some((default, cases)) => {
+						let default = check(&mut issues, *offset, *default);
+						out.extend(default.map(move |t| (t, EdgeKind::Default)));
+						for case in cases.iter() {
+							let target = check(&mut issues, *offset, case.jump);
+							let kind = EdgeKind::Case(case.value);
+							out.extend(target.map(move |t| (t, kind)));
+						}
+					}
+					None => issues.push(CfgIssue::MissingCaseTable {
+						offset: *offset,
+						target,
+					}),
+				}
+			}
+			Flow::Return | Flow::Halt | Flow::Never => {}
+		}
+		leaders.extend(out.iter().map(move |(target, _)| *target));
+		leaders.extend(next);
+		targets.insert(*offset, out);
+	}
+
+	let mut blocks: Vec<BasicBlock> = Vec::new();
+	for (offset, instruction) in instructions.iter() {
+		if matches!(instruction, Instruction::Endproc | Instruction::Casetbl { .. }) {
+			continue
+		}
+		let len = instruction.encoded_len();
+		match blocks.last_mut() {
+			Some(block) if !leaders.contains(offset) && block.end == *offset => {
+				block.instructions.push((*offset, instruction.clone()));
+				block.end = offset + len;
+			}
+			_ => blocks.push(BasicBlock {
+				start: *offset,
+				end: offset + len,
+				instructions: vec![(*offset, instruction.clone())],
+				successors: Vec::new(),
+				predecessors: Vec::new(),
+			}),
+		}
+	}
+
+	let block_of: HashMap<usize, usize> = blocks.iter()
+		.enumerate()
+		.map(move |(idx, block)| (block.start, idx))
+		.collect();
+	for idx in 0..blocks.len() {
+		let (last, _) = *blocks[idx].instructions.last().unwrap();
+		let successors: Vec<Edge> = match targets.get(&last) {
+			Some(out) => out.iter()
+				.filter_map(|(target, kind)| {
+					let block = *block_of.get(target)?;
+					Some(Edge { block, kind: *kind })
+				})
+				.collect(),
+			None => block_of.get(&blocks[idx].end)
+				.map(move |block| Edge { block: *block, kind: EdgeKind::Fallthrough })
+				.into_iter()
+				.collect(),
+		};
+		for edge in successors.iter() {
+			if !blocks[edge.block].predecessors.contains(&idx) {
+				blocks[edge.block].predecessors.push(idx);
+			}
+		}
+		blocks[idx].successors = successors;
+	}
+
+	Cfg {
+		blocks,
+		case_tables,
+		issues,
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::instruction::layout;
+
+	#[test]
+	fn misaligned_target() {
+		let (code, len) = layout(vec![
+			Instruction::Proc,                // 0
+			Instruction::Jump { jump_1: 8 },  // 4
+			Instruction::Jump { jump_1: 64 }, // 12
+			Instruction::Retn,                // 20
+		]);
+		let cfg = build(&code, len);
+		assert_eq!(cfg.issues, [
+			CfgIssue::MisalignedTarget { offset: 4, target: 8 },
+			CfgIssue::OutsideTarget { offset: 12, target: 64 },
+		]);
+		assert_eq!(cfg.unreachable_blocks(), [1, 2]);
+	}
+
+	#[test]
+	fn if_else() {
+		let (code, len) = layout(vec![
+			Instruction::Proc,                    // 0
+			Instruction::LoadSPri { offset: 12 }, // 4
+			Instruction::Jzer { jump_1: 36 },     // 12
+			Instruction::ConstPri { value: 1 },   // 20
+			Instruction::Jump { jump_1: 40 },     // 28
+			Instruction::ZeroPri,                 // 36
+			Instruction::Retn,                    // 40
+			Instruction::Endproc,                 // 44
+		]);
+		let cfg = build(&code, len);
+		assert!(cfg.issues.is_empty(), "{:?}", cfg.issues);
+		let starts: Vec<_> = cfg.blocks.iter().map(move |b| b.start).collect();
+		assert_eq!(starts, [0, 20, 36, 40]);
+		assert_eq!(cfg.blocks[0].successors, [
+			Edge { block: 2, kind: EdgeKind::Branch },
+			Edge { block: 1, kind: EdgeKind::Fallthrough },
+		]);
+		assert_eq!(cfg.blocks[3].predecessors, [1, 2]);
+		assert_eq!(cfg.blocks[3].end, 44);
+		assert!(cfg.unreachable_blocks().is_empty());
+	}
+
+	#[test]
+	fn switch() {
+		let (code, len) = layout(vec![
+			Instruction::Proc,                     // 0
+			Instruction::Switch { jump_1: 28 },    // 4
+			Instruction::ConstPri { value: 1 },    // 12
+			Instruction::Retn,                     // 20
+			Instruction::Retn,                     // 24
+			Instruction::Casetbl {                 // 28
+				default: 24,
+				cases: vec![Case { value: 5, jump: 12 }],
+				switch: None,
+			},
+		]);
+		let cfg = build(&code, len);
+		assert!(cfg.issues.is_empty(), "{:?}", cfg.issues);
+		assert_eq!(cfg.case_tables.len(), 1);
+		let starts: Vec<_> = cfg.blocks.iter().map(move |b| b.start).collect();
+		assert_eq!(starts, [0, 12, 24]);
+		assert_eq!(cfg.blocks[0].successors, [
+			Edge { block: 2, kind: EdgeKind::Default },
+			Edge { block: 1, kind: EdgeKind::Case(5) },
+		]);
+		assert!(cfg.unreachable_blocks().is_empty());
+	}
+}
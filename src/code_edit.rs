@@ -0,0 +1,466 @@
+//! Editing layer over decoded code that keeps absolute code references valid.
+//!
+//! Instructions are addressed by their offsets in the original code. When the
+//! edits are committed, every code reference is relocated to the new layout:
+//! jump, call and case table targets in `.code`, `.publics` entries, the
+//! `main` entry point, `.dbg.files`, `.dbg.lines` and `.dbg.symbols`, and the
+//! code ranges of `.rtti.methods`, `.dbg.globals` and `.dbg.locals`.
+//!
+//! References to an instruction that was removed are relocated to the next
+//! instruction that was kept. Code references in inserted instructions are
+//! offsets in the original code as well, and are relocated in the same way.
+
+use crate::{
+	debug::{
+		DebugInfo,
+		SymbolKind,
+	},
+	decoder::{
+		DecodeError,
+		Decoder,
+	},
+	plugin::Plugin,
+	rtti::Rtti,
+	vm_types::Cell,
+	Instruction,
+	Smx,
+};
+
+use byteorder::ByteOrder;
+use std::{
+	collections::BTreeMap,
+	error::Error,
+	ffi::CString,
+	fmt,
+	io::Error as IoError,
+};
+
+/// Structure for an error that has occurred while editing code.
+#[derive(Debug)]
+pub enum CodeEditError {
+	/// Code could not be decoded.
+	Decode(DecodeError),
+	/// Sections could not be parsed.
+	Io(IoError),
+	/// An edit refers to an offset that is not the start of an original
+	/// instruction, or to an instruction that was already replaced.
+	UnknownOffset {
+		offset: usize,
+	},
+	/// A code reference is not the start of an original instruction.
+	Target {
+		offset: Option<usize>,
+		target: Cell,
+	},
+}
+
+impl fmt::Display for CodeEditError {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			Self::Decode(e) => write!(f, "decode error {e}"),
+			Self::Io(e) => write!(f, "io error {e}"),
+			Self::UnknownOffset { offset } =>
+				write!(f, "no instruction to edit at 0x{offset:08x}"),
+			Self::Target { offset: Some(offset), target } => write!(
+				f,
+				"instruction at 0x{offset:08x} refers to invalid offset 0x{target:08x}"
+			),
+			Self::Target { offset: None, target } =>
+				write!(f, "reference to invalid offset 0x{target:08x}"),
+		}
+	}
+}
+
+impl Error for CodeEditError {}
+
+impl From<DecodeError> for CodeEditError {
+	fn from(value: DecodeError) -> Self {
+		Self::Decode(value)
+	}
+}
+
+impl From<IoError> for CodeEditError {
+	fn from(value: IoError) -> Self {
+		Self::Io(value)
+	}
+}
+
+/// Instruction in the middle of editing, with the original offsets that are
+/// relocated to it.
+#[derive(Debug, Clone)]
+struct Item {
+	origins: Vec<usize>,
+	/// Original offset of the instruction, or `None` if it was inserted.
+	offset: Option<usize>,
+	/// Instruction, or `None` if it was removed.
+	instruction: Option<Instruction>,
+}
+
+/// Editor of decoded code.
+#[derive(Debug, Clone)]
+pub struct CodeEditor {
+	/// Original offset of each instruction, sorted.
+	offsets: Vec<usize>,
+	/// Items of each original instruction, which are the instruction itself
+	/// and the instructions that were inserted around it.
+	items: Vec<Vec<Item>>,
+	code_len: usize,
+}
+
+/// Result of committing a [`CodeEditor`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Relocations {
+	/// Map of original instruction offsets to new ones.
+	///
+	/// This includes the offset one past the last instruction, so that it can
+	/// be used to relocate code ranges.
+	pub map: BTreeMap<usize, usize>,
+	/// Size of the original code.
+	pub old_len: usize,
+	/// Size of the new code.
+	pub new_len: usize,
+}
+
+impl Relocations {
+	/// Relocate an original code offset, if it is the offset of an instruction
+	/// or the end of the code.
+	pub fn relocate(&self, offset: usize) -> Option<usize> {
+		self.map.get(&offset).copied()
+	}
+
+	/// Relocate a code offset of a debug table, where offsets past the end of
+	/// the code are moved along with it.
+	fn relocate_debug(&self, offset: u32) -> Result<u32, CodeEditError> {
+		let offset = offset as usize;
+		match self.relocate(offset) {
+			Some(new) => Ok(new as _),
+			None if offset > self.old_len =>
+				Ok((offset - self.old_len + self.new_len) as _),
+			None => Err(CodeEditError::Target { offset: None, target: offset as _ }),
+		}
+	}
+
+	/// Relocate the code offsets of the `.publics` entries and the `main`
+	/// entry point of a plugin.
+	pub fn apply_to_plugin(&self, plugin: &mut Plugin) -> Result<(), CodeEditError> {
+		for public in plugin.publics.iter_mut() {
+			public.address = self.relocate_debug(public.address)?;
+		}
+		plugin.code.main = self.relocate_debug(plugin.code.main)?;
+		Ok(())
+	}
+
+	/// Relocate the code offsets of the legacy debug sections.
+	pub fn apply_to_debug(&self, debug: &mut DebugInfo) -> Result<(), CodeEditError> {
+		for file in debug.files.iter_mut() {
+			file.address = self.relocate_debug(file.address)?;
+		}
+		for line in debug.lines.iter_mut() {
+			line.address = self.relocate_debug(line.address)?;
+		}
+		for symbol in debug.symbols.iter_mut() {
+			symbol.code_start = self.relocate_debug(symbol.code_start)?;
+			symbol.code_end = self.relocate_debug(symbol.code_end)?;
+			if symbol.kind == SymbolKind::Function {
+				symbol.address = self.relocate_debug(symbol.address as _)? as _;
+			}
+		}
+		Ok(())
+	}
+
+	/// Relocate the code ranges of the RTTI method and variable tables.
+	pub fn apply_to_rtti(&self, rtti: &mut Rtti) -> Result<(), CodeEditError> {
+		for method in rtti.methods.iter_mut() {
+			method.pcode_start = self.relocate_debug(method.pcode_start)?;
+			method.pcode_end = self.relocate_debug(method.pcode_end)?;
+		}
+		for var in rtti.globals.iter_mut().chain(rtti.locals.iter_mut()) {
+			var.code_start = self.relocate_debug(var.code_start)?;
+			var.code_end = self.relocate_debug(var.code_end)?;
+		}
+		Ok(())
+	}
+}
+
+impl CodeEditor {
+	/// Decode a code blob for editing.
+	pub fn new(code: &[u8]) -> Result<Self, DecodeError> {
+		let (offsets, items) = Decoder::new(code)
+			.map(move |decoded| decoded.map(move |(offset, instruction)| {
+				(offset, vec![Item {
+					origins: vec![offset],
+					offset: Some(offset),
+					instruction: Some(instruction),
+				}])
+			}))
+			.collect::<Result<_, _>>()?;
+		Ok(Self {
+			offsets,
+			items,
+			code_len: code.len(),
+		})
+	}
+
+	/// Return the items of the original instruction at an offset, with the
+	/// index of that instruction among them.
+	fn items_at(
+		&mut self,
+		offset: usize,
+	) -> Result<(&mut Vec<Item>, usize), CodeEditError> {
+		let group = self.offsets.binary_search(&offset)
+			.map_err(move |_| CodeEditError::UnknownOffset { offset })?;
+		let items = &mut self.items[group];
+		let index = items.iter()
+			.position(move |item| item.offset.is_some() && item.instruction.is_some())
+			.ok_or(CodeEditError::UnknownOffset { offset })?;
+		Ok((items, index))
+	}
+
+	/// Return the instructions that are currently in the code, paired with
+	/// their original offsets, or `None` for inserted instructions.
+	pub fn instructions(&self) -> impl Iterator<Item = (Option<usize>, &Instruction)> {
+		self.items.iter()
+			.flatten()
+			.filter_map(move |item| Some((item.offset, item.instruction.as_ref()?)))
+	}
+
+	/// Return the original instruction at an offset.
+	pub fn get(&self, offset: usize) -> Option<&Instruction> {
+		let group = self.offsets.binary_search(&offset).ok()?;
+		self.items[group].iter()
+			.find(move |item| item.offset.is_some())?
+			.instruction
+			.as_ref()
+	}
+
+	/// Insert instructions before the instruction at an original offset.
+	///
+	/// References to that instruction are relocated to the first inserted
+	/// instruction, so the inserted code runs whenever control reaches it.
+	pub fn insert_before(
+		&mut self,
+		offset: usize,
+		instructions: impl IntoIterator<Item = Instruction>,
+	) -> Result<(), CodeEditError> {
+		let (items, index) = self.items_at(offset)?;
+		let mut new: Vec<Item> = instructions.into_iter()
+			.map(move |instruction| Item {
+				origins: Vec::new(),
+				offset: None,
+				instruction: Some(instruction),
+			})
+			.collect();
+		if let Some(first) = new.first_mut() {
+			first.origins = std::mem::take(&mut items[index].origins);
+		}
+		items.splice(index..index, new);
+		Ok(())
+	}
+
+	/// Insert instructions after the instruction at an original offset.
+	pub fn insert_after(
+		&mut self,
+		offset: usize,
+		instructions: impl IntoIterator<Item = Instruction>,
+	) -> Result<(), CodeEditError> {
+		let (items, index) = self.items_at(offset)?;
+		let new = instructions.into_iter().map(move |instruction| Item {
+			origins: Vec::new(),
+			offset: None,
+			instruction: Some(instruction),
+		});
+		items.splice(index + 1..index + 1, new);
+		Ok(())
+	}
+
+	/// Replace the instruction at an original offset.
+	///
+	/// References to that instruction are relocated to the first replacement,
+	/// or to the next instruction if there is none.
+	pub fn replace(
+		&mut self,
+		offset: usize,
+		instructions: impl IntoIterator<Item = Instruction>,
+	) -> Result<(), CodeEditError> {
+		let (items, index) = self.items_at(offset)?;
+		items[index].instruction = None;
+		let new = instructions.into_iter().map(move |instruction| Item {
+			origins: Vec::new(),
+			offset: None,
+			instruction: Some(instruction),
+		});
+		items.splice(index + 1..index + 1, new);
+		Ok(())
+	}
+
+	/// Remove the instruction at an original offset.
+	///
+	/// References to that instruction are relocated to the next instruction.
+	pub fn remove(&mut self, offset: usize) -> Result<(), CodeEditError> {
+		self.replace(offset, [])
+	}
+
+	/// Lay out the edited code, relocating its code references, and return
+	/// the new code blob along with the relocations.
+	pub fn commit(&self) -> Result<(Vec<u8>, Relocations), CodeEditError> {
+		let mut map = BTreeMap::new();
+		let mut offset = 0;
+		for item in self.items.iter().flatten() {
+			for origin in item.origins.iter() {
+				map.insert(*origin, offset);
+			}
+			offset += item.instruction.as_ref().map_or(0, Instruction::encoded_len);
+		}
+		map.insert(self.code_len, offset);
+		let relocations = Relocations {
+			map,
+			old_len: self.code_len,
+			new_len: offset,
+		};
+
+		let mut code = Vec::with_capacity(offset);
+		for item in self.items.iter().flatten() {
+			let Some(mut instruction) = item.instruction.clone() else {
+				continue
+			};
+			let relocate = |target: &mut Cell| {
+				let offset = item.offset;
+				let new = relocations.relocate(*target as usize)
+					.ok_or(CodeEditError::Target { offset, target: *target })?;
+				*target = new as Cell;
+				Ok::<_, CodeEditError>(())
+			};
+			for target in instruction.code_refs_mut() {
+				relocate(target)?;
+			}
+			if let Instruction::Casetbl { switch: Some(switch), .. } = &mut instruction {
+				relocate(switch)?;
+			}
+			let _ = instruction.write_to(&mut code);
+		}
+		Ok((code, relocations))
+	}
+}
+
+/// Apply the edits of a [`CodeEditor`] for the `.code` section of an SMX file,
+/// relocating every code reference in its standard, debug and RTTI sections.
+pub fn commit_smx<E: ByteOrder>(
+	smx: &mut Smx<CString, Vec<u8>>,
+	editor: &CodeEditor,
+) -> Result<Relocations, CodeEditError> {
+	let mut plugin = Plugin::from_smx::<E, _, _>(smx)?;
+	let mut debug = DebugInfo::from_smx::<E, _, _>(smx)?;
+	let mut rtti = Rtti::from_smx::<E, _, _>(smx)?;
+
+	let (code, relocations) = editor.commit()?;
+	plugin.code.code = code;
+	relocations.apply_to_plugin(&mut plugin)?;
+	relocations.apply_to_debug(&mut debug)?;
+	relocations.apply_to_rtti(&mut rtti)?;
+
+	plugin.write_into::<E>(smx);
+	debug.write_into::<E>(smx);
+	rtti.write_into::<E>(smx);
+	Ok(relocations)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::{
+		debug::DebugLine,
+		rtti::RttiMethod,
+		sections::Public,
+		Instruction as I,
+	};
+	use byteorder::LittleEndian as Le;
+
+	fn decode(code: &[u8]) -> Vec<(usize, Instruction)> {
+		Decoder::new(code).collect::<Result<_, _>>().unwrap()
+	}
+
+	#[test]
+	fn edit() -> Result<(), CodeEditError> {
+		let mut bytes = Vec::new();
+		let code = [I::Proc, I::Jzer { jump_1: 16 }, I::Break, I::Retn, I::Endproc];
+		for instruction in code {
+			let _ = instruction.write_to(&mut bytes);
+		}
+
+		let mut editor = CodeEditor::new(&bytes)?;
+		editor.insert_after(0, [I::Break, I::Break])?;
+		editor.insert_before(16, [I::ZeroPri])?;
+		editor.remove(12)?;
+		editor.replace(4, [I::Jnz { jump_1: 16 }])?;
+		assert!(matches!(
+			editor.remove(12),
+			Err(CodeEditError::UnknownOffset { offset: 12 })
+		));
+
+		let (new, relocations) = editor.commit()?;
+		assert_eq!(decode(&new), [
+			(0, I::Proc),
+			(4, I::Break),
+			(8, I::Break),
+			(12, I::Jnz { jump_1: 20 }),
+			(20, I::ZeroPri),
+			(24, I::Retn),
+			(28, I::Endproc),
+		]);
+		assert_eq!(relocations.relocate(12), Some(20));
+		assert_eq!(relocations.relocate(16), Some(20));
+		assert_eq!(relocations.relocate(24), Some(32));
+		Ok(())
+	}
+
+	#[test]
+	fn smx() -> Result<(), CodeEditError> {
+		let mut plugin = Plugin::default();
+		plugin.code.version = 13;
+		plugin.code.cell_size = 4;
+		for instruction in [I::Proc, I::Retn, I::Endproc, I::Proc, I::Retn, I::Endproc] {
+			let _ = instruction.write_to(&mut plugin.code.code);
+		}
+		plugin.publics.push(Public { address: 12, name: 0 });
+		let mut debug = DebugInfo::default();
+		debug.lines.push(DebugLine { address: 16, line: 3 });
+		let mut rtti = Rtti::default();
+		rtti.methods.push(RttiMethod {
+			name: 0,
+			pcode_start: 12,
+			pcode_end: 24,
+			signature: 0,
+		});
+
+		let mut smx = Smx::new();
+		plugin.write_into::<Le>(&mut smx);
+		debug.write_into::<Le>(&mut smx);
+		rtti.write_into::<Le>(&mut smx);
+
+		let mut editor = CodeEditor::new(&plugin.code.code)?;
+		editor.insert_after(0, [I::Break])?;
+		commit_smx::<Le>(&mut smx, &editor)?;
+
+		let plugin = Plugin::from_smx::<Le, _, _>(&smx)?;
+		assert_eq!(plugin.publics[0].address, 16);
+		assert_eq!(DebugInfo::from_smx::<Le, _, _>(&smx)?.lines[0].address, 20);
+		let method = Rtti::from_smx::<Le, _, _>(&smx)?.methods[0];
+		assert_eq!((method.pcode_start, method.pcode_end), (16, 28));
+		Ok(())
+	}
+
+	#[test]
+	fn invalid_main() -> Result<(), CodeEditError> {
+		let mut plugin = Plugin::default();
+		for instruction in [I::Proc, I::Retn, I::Endproc] {
+			let _ = instruction.write_to(&mut plugin.code.code);
+		}
+		plugin.code.main = 2;
+		let (_, relocations) = CodeEditor::new(&plugin.code.code)?.commit()?;
+		assert!(matches!(
+			relocations.apply_to_plugin(&mut plugin),
+			Err(CodeEditError::Target { offset: None, target: 2 })
+		));
+		Ok(())
+	}
+}
@@ -0,0 +1,212 @@
+//! Splitting of debug information into a companion file, and merging it back.
+//!
+//! The companion file is an SMX file with every `.dbg.*` section of a plugin
+//! and every RTTI section in [`DEBUG_RTTI`], since the VM only reads them to
+//! report errors, along with a copy of `.names`, which their names are
+//! offsets into. It is tagged in [`COMPANION`] with the [`code_hash`] of the
+//! stripped plugin, so that it can only be merged back into the plugin that it
+//! was split from.
+//!
+//! `.rtti.natives`, `.rtti.methods` and `.rtti.data`, which the VM reads when
+//! loading a plugin, stay in it.
+
+use crate::{
+	rtti,
+	Smx,
+};
+
+use byteorder::{
+	ByteOrder,
+	ReadBytesExt,
+	WriteBytesExt,
+};
+use core::ffi::CStr;
+use std::{
+	error::Error,
+	ffi::CString,
+	fmt,
+	io::Error as IoError,
+};
+
+/// Name of the section of a companion file with the code hash of its plugin.
+pub const COMPANION: &CStr = c".companion";
+
+/// Name of the section with code.
+const CODE: &CStr = c".code";
+/// Name of the section with names.
+const NAMES: &CStr = c".names";
+
+/// RTTI sections that the VM does not read when loading a plugin.
+pub const DEBUG_RTTI: &[&CStr] = &[
+	rtti::ENUMS,
+	rtti::TYPEDEFS,
+	rtti::TYPESETS,
+	rtti::CLASSDEFS,
+	rtti::FIELDS,
+	rtti::ENUMSTRUCTS,
+	rtti::ENUMSTRUCT_FIELDS,
+];
+
+/// Return whether a section only holds debug information.
+pub fn is_debug_section(name: &CStr) -> bool {
+	name.to_bytes().starts_with(b".dbg.") || DEBUG_RTTI.contains(&name)
+}
+
+/// Return the 64-bit FNV-1a hash of the `.code` section of a plugin.
+pub fn code_hash(smx: &Smx<CString, Vec<u8>>) -> u64 {
+	let code = smx.section(CODE).map_or(&[][..], Vec::as_slice);
+	code.iter().fold(0xcbf29ce484222325, move |hash, byte| {
+		(hash ^ u64::from(*byte)).wrapping_mul(0x100000001b3)
+	})
+}
+
+/// Structure for an error that has occurred while using a companion file.
+#[derive(Debug)]
+pub enum CompanionError {
+	/// The companion file has no valid [`COMPANION`] section.
+	MissingHash,
+	/// The companion file belongs to a plugin with different code.
+	HashMismatch {
+		expected: u64,
+		found: u64,
+	},
+	/// Debug sections could not be parsed.
+	Io(IoError),
+}
+
+impl fmt::Display for CompanionError {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			Self::MissingHash => write!(f, "companion file has no valid code hash"),
+			Self::HashMismatch { expected, found } => write!(
+				f,
+				"companion file has code hash {found:016x} instead of {expected:016x}"
+			),
+			Self::Io(e) => write!(f, "io error {e}"),
+		}
+	}
+}
+
+impl Error for CompanionError {}
+
+impl From<IoError> for CompanionError {
+	fn from(value: IoError) -> Self {
+		Self::Io(value)
+	}
+}
+
+/// Move the debug sections of a plugin into a new companion file.
+pub fn split<E: ByteOrder>(smx: &mut Smx<CString, Vec<u8>>) -> Smx<CString, Vec<u8>> {
+	let mut companion = Smx::new();
+	let names: Vec<CString> = smx.sections.keys()
+		.filter(move |name| is_debug_section(name))
+		.cloned()
+		.collect();
+	for name in names {
+		if let Some(section) = smx.sections.remove(&name) {
+			companion.sections.insert(name, section);
+		}
+	}
+	if let Some(names) = smx.section(NAMES) {
+		companion.sections.insert(NAMES.to_owned(), names.clone());
+	}
+	let mut hash = Vec::new();
+	let _ = hash.write_u64::<E>(code_hash(smx));
+	companion.sections.insert(COMPANION.to_owned(), hash);
+	companion
+}
+
+/// Check that a companion file belongs to a stripped plugin.
+pub fn check<E: ByteOrder>(
+	smx: &Smx<CString, Vec<u8>>,
+	companion: &Smx<CString, Vec<u8>>,
+) -> Result<(), CompanionError> {
+	let found = companion.section(COMPANION)
+		.filter(move |section| section.len() == 8)
+		.ok_or(CompanionError::MissingHash)?
+		.as_slice()
+		.read_u64::<E>()?;
+	let expected = code_hash(smx);
+	if found != expected {
+		return Err(CompanionError::HashMismatch { expected, found })
+	}
+	Ok(())
+}
+
+/// Move the debug sections of a companion file back into its plugin.
+pub fn merge<E: ByteOrder>(
+	smx: &mut Smx<CString, Vec<u8>>,
+	companion: &Smx<CString, Vec<u8>>,
+) -> Result<(), CompanionError> {
+	check::<E>(smx, companion)?;
+	for (name, section) in companion.sections.iter() {
+		if is_debug_section(name) {
+			smx.sections.insert(name.clone(), section.clone());
+		}
+	}
+	Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::{
+		plugin::Plugin,
+		rtti::Rtti,
+	};
+	use byteorder::LittleEndian as Le;
+
+	#[test]
+	fn split_merge() -> Result<(), CompanionError> {
+		let mut smx = Smx::new();
+		for (name, data) in [
+			(c".code", &b"code"[..]),
+			(c".names", b"main\0"),
+			(c".dbg.lines", b"lines"),
+			(c".rtti.methods", b"methods"),
+			(c".rtti.typedefs", b"typedefs"),
+		] {
+			smx.sections.insert(name.to_owned(), data.to_vec());
+		}
+		let original = smx.clone();
+
+		let companion = split::<Le>(&mut smx);
+		assert_eq!(smx.sections.len(), 3);
+		assert!(smx.section(c".rtti.methods").is_some());
+		assert_eq!(companion.sections.len(), 4);
+		assert!(companion.section(c".dbg.lines").is_some());
+		assert!(companion.section(c".rtti.typedefs").is_some());
+
+		let mut other = smx.clone();
+		other.sections.insert(CODE.to_owned(), b"edited".to_vec());
+		assert!(matches!(
+			merge::<Le>(&mut other, &companion),
+			Err(CompanionError::HashMismatch { .. })
+		));
+
+		merge::<Le>(&mut smx, &companion)?;
+		assert_eq!(smx, original);
+		Ok(())
+	}
+
+	#[test]
+	fn stripped_loads() -> Result<(), Box<dyn Error>> {
+		let mut plugin = Plugin::default();
+		plugin.code.version = 13;
+		plugin.code.cell_size = 4;
+		plugin.add_native(c"PrintToServer");
+		let mut rtti = Rtti::default();
+		rtti.add_untyped_native(plugin.natives[0].name);
+
+		let mut smx = Smx::new();
+		plugin.write_into::<Le>(&mut smx);
+		rtti.write_into::<Le>(&mut smx);
+		smx.sections.insert(c".dbg.lines".to_owned(), b"lines".to_vec());
+
+		let companion = split::<Le>(&mut smx);
+		assert!(companion.section(c".dbg.lines").is_some());
+		assert_eq!(Plugin::from_smx::<Le, _, _>(&smx)?, plugin);
+		assert_eq!(Rtti::from_smx::<Le, _, _>(&smx)?, rtti);
+		Ok(())
+	}
+}
@@ -0,0 +1,465 @@
+//! Editing of strings in the data image of compiled plugins.
+//!
+//! A string is rewritten in place if it fits into the cells that it occupies.
+//! Otherwise it is appended to the data image, and its references are updated
+//! to the new address: the fields of `myinfo`, and the constant operands of
+//! `.code` that the data-flow analysis shows to be used as addresses, as the
+//! base of a load or a store. A constant that holds the old address and is
+//! passed to a call is rejected, since it may be an address. Other cells of
+//! `.data` are not typed, so they keep the old address, whose string is left
+//! in place.
+
+use crate::{
+	dataflow::{
+		constant_location,
+		copies,
+		is_address_read,
+		DataFlow,
+		Def,
+		Location,
+	},
+	decoder::DecodeError,
+	functions::discover_plugin,
+	info::{
+		myinfo_fields,
+		InfoError,
+		InfoField,
+	},
+	plugin::Plugin,
+	size_of,
+	vm_types::Cell,
+	Instruction,
+};
+
+use byteorder::ByteOrder;
+use core::ffi::CStr;
+use std::{
+	collections::{
+		BTreeSet,
+		HashMap,
+	},
+	error::Error,
+	fmt,
+};
+
+/// Structure for an error that has occurred while editing data.
+#[derive(Debug)]
+pub enum EditError {
+	/// Code could not be decoded.
+	Decode(DecodeError),
+	/// There is no string at an address of the data image.
+	InvalidString {
+		address: Cell,
+	},
+	/// `myinfo` could not be located.
+	Info(InfoError),
+	/// A constant operand holds the address that is relocated, but its uses
+	/// do not show whether it is an address.
+	Constant {
+		offset: usize,
+		value: Cell,
+	},
+}
+
+impl fmt::Display for EditError {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			Self::Decode(e) => write!(f, "decode error {e}"),
+			Self::InvalidString { address } =>
+				write!(f, "no string at data address 0x{address:08x}"),
+			Self::Info(e) => write!(f, "{e}"),
+			Self::Constant { offset, value } =>
+				write!(f, "constant {value} at 0x{offset:08x} may be a data address"),
+		}
+	}
+}
+
+impl Error for EditError {}
+
+impl From<DecodeError> for EditError {
+	fn from(value: DecodeError) -> Self {
+		Self::Decode(value)
+	}
+}
+
+impl From<InfoError> for EditError {
+	fn from(value: InfoError) -> Self {
+		Self::Info(value)
+	}
+}
+
+/// Way that an instruction uses a value that it reads.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+enum Usage {
+	Value,
+	Address,
+	/// Argument of a call, which may be an address.
+	Argument,
+}
+
+/// Return how an instruction uses the location of its `n`th read, or `None`
+/// if it only copies it.
+fn usage(instruction: &Instruction, n: usize) -> Option<Usage> {
+	if is_address_read(instruction, n) {
+		return Some(Usage::Address)
+	}
+	if !copies(instruction, 0).is_empty() {
+		return None
+	}
+	Some(match (instruction, n) {
+		(Instruction::Call { .. } | Instruction::SysreqC { .. }, 0) => Usage::Value,
+		(Instruction::Call { .. } | Instruction::SysreqC { .. }, _)
+			| (Instruction::SysreqN { .. }, _) => Usage::Argument,
+		_ => Usage::Value,
+	})
+}
+
+/// Constant operand that holds an address, as the code offset of its
+/// instruction, the decoded instruction and the index of the operand in
+/// [`Instruction::constants_mut`].
+type Operand = (usize, Instruction, usize);
+
+/// Return the constant operands of the reachable code that are used as the
+/// address `from`.
+///
+/// Constants that are not used as addresses are skipped, and those that may
+/// be are rejected.
+fn address_operands(plugin: &Plugin, from: Cell) -> Result<Vec<Operand>, EditError> {
+	let map = discover_plugin(plugin, None)?;
+	let mut operands = Vec::new();
+	for function in map.functions.iter() {
+		let holds = move |instruction: &Instruction| {
+			instruction.clone().constants_mut().into_iter().any(move |c| *c == from)
+		};
+		if !function.instructions.iter().any(move |(_, i)| holds(i)) {
+			continue
+		}
+		let flow = DataFlow::function(function);
+		let mut usages: HashMap<(usize, Location), BTreeSet<Usage>> = HashMap::new();
+		for (pc, instruction) in function.instructions.iter() {
+			for (n, read) in flow.uses_at(*pc).iter().enumerate() {
+				let Some(usage) = usage(instruction, n) else {
+					continue
+				};
+				for (def, location) in flow.origins(*pc, read.location) {
+					if let Def::At(def) = def {
+						usages.entry((def, location)).or_default().insert(usage);
+					}
+				}
+			}
+		}
+
+		for (pc, instruction) in function.instructions.iter() {
+			let Some(state) = flow.state_at(*pc) else {
+				continue
+			};
+			let values: Vec<Cell> = instruction.clone().constants_mut().into_iter()
+				.map(move |value| *value)
+				.collect();
+			for (n, value) in values.into_iter().enumerate() {
+				if value != from {
+					continue
+				}
+				let Some(location) = constant_location(instruction, n, state.depth) else {
+					return Err(EditError::Constant { offset: *pc, value })
+				};
+				let usages = usages.get(&(*pc, location)).cloned().unwrap_or_default();
+				if usages.contains(&Usage::Argument)
+					|| usages.contains(&Usage::Address) && usages.contains(&Usage::Value)
+				{
+					return Err(EditError::Constant { offset: *pc, value })
+				}
+				if usages.contains(&Usage::Address) {
+					operands.push((*pc, instruction.clone(), n));
+				}
+			}
+		}
+	}
+	Ok(operands)
+}
+
+/// Replace `from` with `to` in the fields of `myinfo` and in constant
+/// operands.
+fn relocate_operands<E: ByteOrder>(
+	plugin: &mut Plugin,
+	operands: Vec<Operand>,
+	from: Cell,
+	to: Cell,
+) {
+	for offset in myinfo_fields(plugin).into_iter().flatten() {
+		if let Some(cell) = plugin.data.bytes.get_mut(offset..offset + size_of!(Cell)) {
+			if E::read_i32(cell) == from {
+				E::write_i32(cell, to);
+			}
+		}
+	}
+
+	for (offset, mut instruction, n) in operands {
+		if let Some(operand) = instruction.constants_mut().into_iter().nth(n) {
+			*operand = to;
+		}
+		let mut bytes = Vec::new();
+		let _ = instruction.write_to(&mut bytes);
+		plugin.code.code[offset..offset + bytes.len()].copy_from_slice(&bytes);
+	}
+}
+
+/// Replace the fields of `myinfo` and the constant operands of the code that
+/// hold the address `from` with `to`.
+///
+/// The code is analyzed before anything is changed, so an error leaves the
+/// plugin untouched.
+pub fn relocate_address<E: ByteOrder>(
+	plugin: &mut Plugin,
+	from: Cell,
+	to: Cell,
+) -> Result<(), EditError> {
+	let operands = address_operands(plugin, from)?;
+	relocate_operands::<E>(plugin, operands, from, to);
+	Ok(())
+}
+
+/// Rewrite the string at a data address if the new one fits into the cells
+/// of the old one, returning `false` if it does not.
+fn replace_in_place(
+	plugin: &mut Plugin,
+	address: Cell,
+	value: &CStr,
+) -> Result<bool, EditError> {
+	let offset = usize::try_from(address).ok()
+		.filter(move |offset| offset.is_multiple_of(size_of!(Cell)))
+		.ok_or(EditError::InvalidString { address })?;
+	let old_len = plugin.data.c_str_at(offset)
+		.ok_or(EditError::InvalidString { address })?
+		.to_bytes_with_nul()
+		.len();
+	let capacity = old_len.next_multiple_of(size_of!(Cell));
+	let bytes = value.to_bytes_with_nul();
+	if bytes.len() > capacity {
+		return Ok(false)
+	}
+
+	// The last string of an image may not be padded to a whole cell.
+	let len = plugin.data.bytes.len();
+	let end = len.min(offset + capacity).max(offset + bytes.len());
+	plugin.data.bytes.resize(len.max(end), 0);
+	let slot = &mut plugin.data.bytes[offset..end];
+	slot.fill(0);
+	slot[..bytes.len()].copy_from_slice(bytes);
+	Ok(true)
+}
+
+/// Replace the string at a data address, returning its new address.
+pub fn replace_string<E: ByteOrder>(
+	plugin: &mut Plugin,
+	address: Cell,
+	value: &CStr,
+) -> Result<Cell, EditError> {
+	if replace_in_place(plugin, address, value)? {
+		return Ok(address)
+	}
+	let operands = address_operands(plugin, address)?;
+	let new_address = plugin.data.push_string(value);
+	relocate_operands::<E>(plugin, operands, address, new_address);
+	Ok(new_address)
+}
+
+/// Replace a string field of `myinfo`.
+///
+/// A string that grows is only relocated in that field, since `myinfo` is
+/// the only known reference to it.
+pub fn set_info_field<E: ByteOrder>(
+	plugin: &mut Plugin,
+	field: InfoField,
+	value: &CStr,
+) -> Result<(), EditError> {
+	let offset = myinfo_fields(plugin)?[field as usize];
+	let cell = offset..offset + size_of!(Cell);
+	let address = plugin.data.bytes.get(cell.clone())
+		.map(E::read_i32)
+		.unwrap_or_default();
+	if !replace_in_place(plugin, address, value)? {
+		let new_address = plugin.data.push_string(value);
+		E::write_i32(&mut plugin.data.bytes[cell], new_address);
+	}
+	Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::{
+		decoder::Decoder,
+		info::PluginInfo,
+		sections::Pubvar,
+		CompressionLevel,
+		Instruction,
+		Smx,
+	};
+	use byteorder::{
+		BigEndian as Be,
+		LittleEndian as Le,
+	};
+	use std::{
+		ffi::CString,
+		io::Cursor,
+	};
+
+	/// Create a plugin with `myinfo`, and code that loads a cell of its
+	/// version and adds the address of its name to it as a value.
+	fn plugin() -> Plugin {
+		let mut plugin = Plugin::default();
+		let mut cells = Vec::new();
+		for s in ["Test", "", "Me", "1.0", "https://example.com"] {
+			cells.push(plugin.data.bytes.len() as Cell);
+			plugin.data.bytes.extend_from_slice(s.as_bytes());
+			plugin.data.bytes.push(0);
+			plugin.data.bytes.resize(plugin.data.bytes.len().next_multiple_of(4), 0);
+		}
+		let address = plugin.data.bytes.len() as u32;
+		for cell in cells.iter() {
+			plugin.data.bytes.extend_from_slice(&cell.to_ne_bytes());
+		}
+		let name = plugin.names.insert(c"myinfo") as u32;
+		plugin.pubvars.push(Pubvar { address, name });
+
+		plugin.code.version = 13;
+		plugin.code.cell_size = 4;
+		push_code(&mut plugin, [
+			Instruction::Proc,
+			Instruction::ConstPri { value: cells[3] },
+			Instruction::LoadI,
+			Instruction::PushC { const_1: cells[0] },
+			Instruction::PopAlt,
+			Instruction::Add,
+			Instruction::Retn,
+			Instruction::Endproc,
+		]);
+		plugin
+	}
+
+	fn push_code(plugin: &mut Plugin, code: impl IntoIterator<Item = Instruction>) {
+		for instruction in code {
+			let _ = instruction.write_to(&mut plugin.code.code);
+		}
+	}
+
+	fn decode(plugin: &Plugin) -> Result<Vec<Instruction>, DecodeError> {
+		Decoder::new(&plugin.code.code)
+			.map(move |decoded| decoded.map(move |(_, instruction)| instruction))
+			.collect()
+	}
+
+	#[test]
+	fn in_place() -> Result<(), EditError> {
+		let mut plugin = plugin();
+		let len = plugin.data.bytes.len();
+		set_info_field::<Le>(&mut plugin, InfoField::Version, c"1.1")?;
+		assert_eq!(plugin.data.bytes.len(), len);
+		assert_eq!(PluginInfo::from_plugin(&plugin)?.version, "1.1");
+		Ok(())
+	}
+
+	#[test]
+	fn grow() -> Result<(), Box<dyn Error>> {
+		let mut plugin = plugin();
+		let len = plugin.data.bytes.len();
+		assert_eq!(replace_string::<Le>(&mut plugin, 0x10, c"1.0.1234")?, len as Cell);
+		assert_eq!(plugin.data.bytes.len(), len + 12);
+
+		let mut smx = Smx::new();
+		plugin.write_into::<Le>(&mut smx);
+		let mut bytes = Vec::new();
+		smx.write_to::<Le>(&mut bytes, CompressionLevel::NoCompression)?;
+		let (smx, _) = Smx::<CString, Vec<u8>>::read_from(&mut Cursor::new(bytes))?;
+		let plugin = Plugin::from_smx::<Le, _, _>(&smx)?;
+
+		let info = PluginInfo::from_plugin(&plugin)?;
+		assert_eq!(info.version, "1.0.1234");
+		assert_eq!(info.url, "https://example.com");
+		assert_eq!(decode(&plugin)?[1], Instruction::ConstPri { value: len as Cell });
+		Ok(())
+	}
+
+	#[test]
+	fn address_zero() -> Result<(), Box<dyn Error>> {
+		let original = plugin();
+		let len = original.data.bytes.len();
+
+		let mut plugin = original.clone();
+		set_info_field::<Le>(&mut plugin, InfoField::Name, c"Test plugin")?;
+		assert_eq!(plugin.data.bytes[..len - 20], original.data.bytes[..len - 20]);
+		assert_eq!(plugin.code, original.code);
+		let info = PluginInfo::from_plugin(&plugin)?;
+		assert_eq!(info.name, "Test plugin");
+		assert_eq!(info.description, "");
+
+		let mut plugin = original.clone();
+		assert_eq!(replace_string::<Le>(&mut plugin, 0, c"Test plugin")?, len as Cell);
+		assert_eq!(plugin.code, original.code);
+		assert_eq!(PluginInfo::from_plugin(&plugin)?.name, "Test plugin");
+		Ok(())
+	}
+
+	#[test]
+	fn argument() {
+		let mut plugin = plugin();
+		push_code(&mut plugin, [
+			Instruction::Proc,
+			Instruction::PushC { const_1: 0x10 },
+			Instruction::SysreqN { native: 0, n_args: 1 },
+			Instruction::Retn,
+			Instruction::Endproc,
+		]);
+		let original = plugin.clone();
+		assert!(matches!(
+			replace_string::<Le>(&mut plugin, 0x10, c"1.0.1234"),
+			Err(EditError::Constant { offset: 0x2c, value: 0x10 })
+		));
+		assert_eq!(plugin, original);
+	}
+
+	#[test]
+	fn invalid() {
+		let mut plugin = plugin();
+		assert!(matches!(
+			replace_string::<Le>(&mut plugin, 2, c"x"),
+			Err(EditError::InvalidString { address: 2 })
+		));
+	}
+
+	#[test]
+	fn unpadded() -> Result<(), EditError> {
+		let mut plugin = Plugin::default();
+		plugin.data.bytes.extend_from_slice(b"ab\0");
+		assert_eq!(replace_string::<Le>(&mut plugin, 0, c"abc")?, 0);
+		assert_eq!(plugin.data.bytes, b"abc\0");
+		Ok(())
+	}
+
+	#[test]
+	fn decode_error() {
+		let mut plugin = plugin();
+		plugin.code.code.extend_from_slice(&Cell::MAX.to_ne_bytes());
+		let original = plugin.clone();
+		assert!(matches!(
+			replace_string::<Le>(&mut plugin, 0x10, c"1.0.1234"),
+			Err(EditError::Decode(_))
+		));
+		assert_eq!(plugin, original);
+	}
+
+	#[test]
+	fn big_endian() -> Result<(), EditError> {
+		let mut plugin = Plugin::default();
+		for cell in [4, 0, 0, 0, 0, 4] {
+			plugin.data.bytes.extend_from_slice(&Cell::to_be_bytes(cell));
+		}
+		let name = plugin.names.insert(c"myinfo") as u32;
+		plugin.pubvars.push(Pubvar { address: 0, name });
+		relocate_address::<Be>(&mut plugin, 4, 8)?;
+		assert_eq!(plugin.data.bytes[..4], [0, 0, 0, 8]);
+		assert_eq!(plugin.data.bytes[20..], [0, 0, 0, 4]);
+		Ok(())
+	}
+}
@@ -0,0 +1,1357 @@
+//! Data-flow analysis of the registers and the frame of functions.
+//!
+//! Each instruction is executed on symbolic values for `PRI`, `ALT` and the
+//! cells of the frame, which include the stack slots that are pushed below it.
+//! Constant arithmetic is folded, and the values of frame cells are propagated
+//! through stores and loads, until they are merged with different values where
+//! control flow meets. Reaching definitions of the same locations give the
+//! def-use chains of a function.
+//!
+//! Data addresses, such as those of strings and global arrays, are constants
+//! in the code, so they are [`Value::Const`]. [`check_formats`] uses this to
+//! find the format strings of calls to natives such as `Format`.
+
+use crate::{
+	cfg::{
+		self,
+		Cfg,
+	},
+	decoder::DecodeError,
+	functions::{
+		self,
+		Function,
+	},
+	optimize::{
+		push_operands,
+		PushKind,
+	},
+	plugin::Plugin,
+	vm_types::{
+		Cell,
+		CELL,
+	},
+	Instruction,
+};
+
+use std::{
+	collections::{
+		BTreeMap,
+		BTreeSet,
+	},
+	fmt,
+};
+
+/// Symbolic value of a register or a frame cell.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Value {
+	/// Value that is not known.
+	Unknown,
+	/// Constant, which may be a data address.
+	Const(Cell),
+	/// Current value of the frame cell at an offset, such as a local variable
+	/// or an argument.
+	Local(Cell),
+	/// Address of the frame cell at an offset.
+	LocalAddress(Cell),
+	/// Current value of the global variable at a data address.
+	Global(Cell),
+	/// Result of a native call.
+	Native {
+		/// Index of the native into `.natives`.
+		index: usize,
+		/// Code offset of the call.
+		call: usize,
+	},
+	/// Result of a function call.
+	Call {
+		/// Code offset of the called function.
+		function: usize,
+		/// Code offset of the call.
+		call: usize,
+	},
+}
+
+impl Value {
+	/// Return the value of a constant.
+	pub const fn as_const(self) -> Option<Cell> {
+		match self {
+			Self::Const(value) => Some(value),
+			_ => None,
+		}
+	}
+
+	fn from_bool(value: bool) -> Self {
+		Self::Const(Cell::from(value))
+	}
+
+	fn from_float(value: f32) -> Self {
+		Self::Const(value.to_bits() as Cell)
+	}
+
+	fn as_float(self) -> Option<f32> {
+		Some(f32::from_bits(self.as_const()? as u32))
+	}
+}
+
+/// Location that an instruction reads or writes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Location {
+	Pri,
+	Alt,
+	/// Frame cell at an offset.
+	Frame(Cell),
+}
+
+/// Definition of a location.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Def {
+	/// Value that a location holds when the function is entered, such as an
+	/// argument.
+	Entry,
+	/// Write by the instruction at a code offset.
+	At(usize),
+}
+
+/// Read of a location, with the definitions that may reach it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Use {
+	pub location: Location,
+	/// Reaching definitions, sorted.
+	pub defs: Vec<Def>,
+}
+
+/// Symbolic state before an instruction.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct State {
+	/// Bytes pushed below the frame.
+	pub depth: Cell,
+	pub pri: Value,
+	pub alt: Value,
+	/// Values of frame cells that are not [`Value::Local`].
+	frame: BTreeMap<Cell, Value>,
+	/// Definitions of locations other than [`Def::Entry`] alone.
+	defs: BTreeMap<Location, BTreeSet<Def>>,
+}
+
+impl State {
+	fn entry() -> Self {
+		Self {
+			depth: 0,
+			pri: Value::Unknown,
+			alt: Value::Unknown,
+			frame: BTreeMap::new(),
+			defs: BTreeMap::new(),
+		}
+	}
+
+	/// Return the value of the frame cell at an offset.
+	pub fn frame(&self, offset: Cell) -> Value {
+		self.frame.get(&offset).copied().unwrap_or(Value::Local(offset))
+	}
+
+	/// Return the value of a stack cell, where 0 is the top of the stack.
+	pub fn stack(&self, index: usize) -> Value {
+		self.frame(index as Cell * CELL - self.depth)
+	}
+
+	/// Return the definitions of a location that reach this state.
+	pub fn defs(&self, location: Location) -> Vec<Def> {
+		match self.defs.get(&location) {
+			Some(defs) => defs.iter().copied().collect(),
+			None => vec![Def::Entry],
+		}
+	}
+
+	/// Merge the state of another path into this one, returning `true` if
+	/// this state changed.
+	fn meet(&mut self, other: &Self) -> bool {
+		let old = self.clone();
+		if self.pri != other.pri {
+			self.pri = Value::Unknown;
+		}
+		if self.alt != other.alt {
+			self.alt = Value::Unknown;
+		}
+		self.frame.retain(move |offset, value| other.frame.get(offset) == Some(value));
+		let entry = BTreeSet::from([Def::Entry]);
+		let locations: BTreeSet<Location> =
+			self.defs.keys().chain(other.defs.keys()).copied().collect();
+		for location in locations {
+			let theirs = other.defs.get(&location).unwrap_or(&entry).clone();
+			self.defs.entry(location).or_insert_with(|| entry.clone()).extend(theirs);
+		}
+		*self != old
+	}
+
+	/// Read a location, recording its use.
+	fn read(&self, location: Location, uses: &mut Vec<Use>) -> Value {
+		uses.push(Use { location, defs: self.defs(location) });
+		match location {
+			Location::Pri => self.pri,
+			Location::Alt => self.alt,
+			Location::Frame(offset) => self.frame(offset),
+		}
+	}
+
+	/// Forget every value that matches a predicate.
+	fn forget(&mut self, stale: impl Fn(&Value) -> bool) {
+		let registers = [&mut self.pri, &mut self.alt].into_iter();
+		for value in registers.chain(self.frame.values_mut()) {
+			if stale(value) {
+				*value = Value::Unknown;
+			}
+		}
+	}
+
+	/// Write a location.
+	fn write(&mut self, location: Location, value: Value, pc: usize) {
+		self.defs.insert(location, BTreeSet::from([Def::At(pc)]));
+		match location {
+			Location::Pri => self.pri = value,
+			Location::Alt => self.alt = value,
+			Location::Frame(offset) => {
+				if value == Value::Local(offset) {
+					return
+				}
+				self.forget(move |v| *v == Value::Local(offset));
+				self.frame.insert(offset, value);
+			}
+		}
+	}
+
+	/// Write the global variable at a data address.
+	fn write_global(&mut self, address: Cell) {
+		self.forget(move |v| *v == Value::Global(address));
+	}
+
+	/// Write unknown memory, which may be any global, or any frame cell whose
+	/// address is taken.
+	fn clobber(&mut self, escaped: &BTreeSet<Cell>, pc: usize) {
+		let Some(&lowest) = escaped.first() else {
+			self.forget(move |v| matches!(v, Value::Global(_)));
+			return
+		};
+		self.forget(move |v| match *v {
+			Value::Global(_) => true,
+			Value::Local(offset) => offset >= lowest,
+			_ => false,
+		});
+		self.frame.retain(move |offset, _| *offset < lowest);
+		let clobbered: Vec<Location> = self.defs.keys()
+			.copied()
+			.filter(move |l| matches!(*l, Location::Frame(offset) if offset >= lowest))
+			.chain(escaped.iter().map(move |offset| Location::Frame(*offset)))
+			.collect();
+		for location in clobbered {
+			self.defs.entry(location)
+				.or_insert_with(|| BTreeSet::from([Def::Entry]))
+				.insert(Def::At(pc));
+		}
+	}
+
+	fn push(&mut self, value: Value, pc: usize) {
+		self.depth += CELL;
+		self.write(Location::Frame(-self.depth), value, pc);
+	}
+
+	fn pop(&mut self, uses: &mut Vec<Use>) -> Value {
+		let value = self.read(Location::Frame(-self.depth), uses);
+		self.drop_cells(CELL);
+		value
+	}
+
+	/// Pop bytes off the stack without reading them.
+	fn drop_cells(&mut self, bytes: Cell) {
+		let depth = self.depth;
+		self.depth -= bytes;
+		self.frame.retain(move |offset, _| !(-depth..-depth + bytes).contains(offset));
+	}
+
+	/// Read the cell at an address.
+	fn load(&self, address: Value, uses: &mut Vec<Use>) -> Value {
+		match address {
+			Value::LocalAddress(offset) => self.read(Location::Frame(offset), uses),
+			Value::Const(address) => Value::Global(address),
+			_ => Value::Unknown,
+		}
+	}
+
+	/// Write the cell at an address.
+	fn store(
+		&mut self,
+		address: Value,
+		value: Value,
+		pc: usize,
+		escaped: &BTreeSet<Cell>,
+	) {
+		match address {
+			Value::LocalAddress(offset) => self.write(Location::Frame(offset), value, pc),
+			Value::Const(address) => self.write_global(address),
+			_ => self.clobber(escaped, pc),
+		}
+	}
+
+	/// Read the arguments of `SYSREQ.C` and the float instructions, which stay
+	/// on the stack below their count.
+	fn peek_args(&self, count: Option<usize>, uses: &mut Vec<Use>) -> Vec<Value> {
+		let bytes = self.read(Location::Frame(-self.depth), uses);
+		let count = count.unwrap_or(match bytes {
+			Value::Const(bytes) => (bytes / CELL).max(0) as usize,
+			_ => 0,
+		});
+		(1..=count)
+			.map(|n| self.read(Location::Frame(n as Cell * CELL - self.depth), uses))
+			.collect()
+	}
+
+	/// Execute an instruction, recording the locations that it reads, and the
+	/// arguments of calls.
+	fn step(
+		&mut self,
+		pc: usize,
+		instruction: &Instruction,
+		escaped: &BTreeSet<Cell>,
+		uses: &mut Vec<Use>,
+		args: &mut Option<Vec<Value>>,
+	) {
+		use Instruction as I;
+		use Location::{
+			Alt,
+			Frame,
+			Pri,
+		};
+		type Fold = fn(Cell, Cell) -> Option<Cell>;
+		type Compare = fn(&Cell, &Cell) -> bool;
+
+		if let Some((kind, operands)) = push_operands(instruction) {
+			for operand in operands {
+				let value = match kind {
+					PushKind::Const => Value::Const(operand),
+					PushKind::Addr => Value::Global(operand),
+					PushKind::Stack => self.read(Frame(operand), uses),
+					PushKind::Adr => Value::LocalAddress(operand),
+				};
+				self.push(value, pc);
+			}
+			return
+		}
+
+		let register = move |pri: bool| if pri { Pri } else { Alt };
+		let binary = |this: &mut Self, uses: &mut Vec<Use>, op: Fold| {
+			let (pri, alt) = (this.read(Pri, uses), this.read(Alt, uses));
+			let value = match (pri, alt) {
+				(Value::Const(a), Value::Const(b)) => {
+					op(a, b).map_or(Value::Unknown, Value::Const)
+				}
+				_ => Value::Unknown,
+			};
+			this.write(Pri, value, pc);
+		};
+		let compare = |this: &mut Self, uses: &mut Vec<Use>, op: Compare| {
+			let (pri, alt) = (this.read(Pri, uses), this.read(Alt, uses));
+			let value = match (pri, alt) {
+				(Value::Const(a), Value::Const(b)) => Value::from_bool(op(&a, &b)),
+				_ => Value::Unknown,
+			};
+			this.write(Pri, value, pc);
+		};
+		match *instruction {
+			I::LoadPri { offset } => self.write(Pri, Value::Global(offset), pc),
+			I::LoadAlt { offset } => self.write(Alt, Value::Global(offset), pc),
+			I::LoadSPri { offset } => {
+				let value = self.read(Frame(offset), uses);
+				self.write(Pri, value, pc);
+			}
+			I::LoadSAlt { offset } => {
+				let value = self.read(Frame(offset), uses);
+				self.write(Alt, value, pc);
+			}
+			I::LrefPri { .. } => self.write(Pri, Value::Unknown, pc),
+			I::LrefAlt { .. } => self.write(Alt, Value::Unknown, pc),
+			I::LrefSPri { offset } | I::LrefSAlt { offset } => {
+				let address = self.read(Frame(offset), uses);
+				let value = self.load(address, uses);
+				let register = register(matches!(instruction, I::LrefSPri { .. }));
+				self.write(register, value, pc);
+			}
+			I::LoadBoth { addr_1, addr_2 } => {
+				self.write(Pri, Value::Global(addr_1), pc);
+				self.write(Alt, Value::Global(addr_2), pc);
+			}
+			I::LoadSBoth { stack_1, stack_2 } => {
+				let pri = self.read(Frame(stack_1), uses);
+				let alt = self.read(Frame(stack_2), uses);
+				self.write(Pri, pri, pc);
+				self.write(Alt, alt, pc);
+			}
+			I::LoadI => {
+				let address = self.read(Pri, uses);
+				let value = self.load(address, uses);
+				self.write(Pri, value, pc);
+			}
+			I::LodbI { .. } | I::Cmps { .. } | I::Lctrl { .. } => {
+				self.read(Pri, uses);
+				self.write(Pri, Value::Unknown, pc);
+			}
+			I::ConstPri { value } => self.write(Pri, Value::Const(value), pc),
+			I::ConstAlt { value } => self.write(Alt, Value::Const(value), pc),
+			I::ZeroPri => self.write(Pri, Value::Const(0), pc),
+			I::ZeroAlt => self.write(Alt, Value::Const(0), pc),
+			I::AddrPri { offset } => self.write(Pri, Value::LocalAddress(offset), pc),
+			I::AddrAlt { offset } => self.write(Alt, Value::LocalAddress(offset), pc),
+			I::LdgfnPri { .. } => self.write(Pri, Value::Unknown, pc),
+			I::StorPri { offset } | I::StorAlt { offset } => {
+				let register = register(matches!(instruction, I::StorPri { .. }));
+				self.read(register, uses);
+				self.write_global(offset);
+			}
+			I::StorSPri { offset } | I::StorSAlt { offset } => {
+				let register = register(matches!(instruction, I::StorSPri { .. }));
+				let value = self.read(register, uses);
+				self.write(Frame(offset), value, pc);
+			}
+			I::SrefPri { .. } | I::SrefAlt { .. } => {
+				let register = register(matches!(instruction, I::SrefPri { .. }));
+				self.read(register, uses);
+				self.clobber(escaped, pc);
+			}
+			I::SrefSPri { offset } | I::SrefSAlt { offset } => {
+				let register = register(matches!(instruction, I::SrefSPri { .. }));
+				let value = self.read(register, uses);
+				let address = self.read(Frame(offset), uses);
+				self.store(address, value, pc, escaped);
+			}
+			I::StorI => {
+				let (value, address) = (self.read(Pri, uses), self.read(Alt, uses));
+				self.store(address, value, pc, escaped);
+			}
+			I::StrbI { .. } => {
+				self.read(Pri, uses);
+				let address = self.read(Alt, uses);
+				self.store(address, Value::Unknown, pc, escaped);
+			}
+			I::Zero { addr_1 }
+				| I::Const { addr_1, .. }
+				| I::Inc { addr_1 }
+				| I::Dec { addr_1 } => {
+				self.write_global(addr_1);
+			}
+			I::ZeroS { stack_1 } => self.write(Frame(stack_1), Value::Const(0), pc),
+			I::ConstS { stack_1, const_1 } => {
+				self.write(Frame(stack_1), Value::Const(const_1), pc);
+			}
+			I::IncS { stack_1 } | I::DecS { stack_1 } => {
+				let delta = if matches!(instruction, I::IncS { .. }) { 1 } else { -1 };
+				let value = match self.read(Frame(stack_1), uses) {
+					Value::Const(value) => Value::Const(value.wrapping_add(delta)),
+					_ => Value::Unknown,
+				};
+				self.write(Frame(stack_1), value, pc);
+			}
+			I::IncI | I::DecI => {
+				let address = self.read(Pri, uses);
+				let delta = if *instruction == I::IncI { 1 } else { -1 };
+				let value = match self.load(address, uses) {
+					Value::Const(value) => Value::Const(value.wrapping_add(delta)),
+					_ => Value::Unknown,
+				};
+				self.store(address, value, pc, escaped);
+			}
+			I::Lidx | I::LidxB { .. } | I::Idxaddr | I::IdxaddrB { .. } => {
+				let (index, base) = (self.read(Pri, uses), self.read(Alt, uses));
+				let shift = match *instruction {
+					I::LidxB { shift } | I::IdxaddrB { shift } => shift,
+					_ => 2,
+				};
+				let offset = index.as_const().map(move |i| i.wrapping_shl(shift as u32));
+				let address = match (base, offset) {
+					(Value::Const(base), Some(offset)) => {
+						Value::Const(base.wrapping_add(offset))
+					}
+					(Value::LocalAddress(base), Some(offset)) => {
+						Value::LocalAddress(base.wrapping_add(offset))
+					}
+					_ => Value::Unknown,
+				};
+				let value = match instruction {
+					I::Lidx | I::LidxB { .. } => self.load(address, uses),
+					_ => address,
+				};
+				self.write(Pri, value, pc);
+			}
+			I::MovePri => {
+				let value = self.read(Alt, uses);
+				self.write(Pri, value, pc);
+			}
+			I::MoveAlt => {
+				let value = self.read(Pri, uses);
+				self.write(Alt, value, pc);
+			}
+			I::Xchg => {
+				let (pri, alt) = (self.read(Pri, uses), self.read(Alt, uses));
+				self.write(Pri, alt, pc);
+				self.write(Alt, pri, pc);
+			}
+			I::PushPri | I::PushAlt => {
+				let value = self.read(register(*instruction == I::PushPri), uses);
+				self.push(value, pc);
+			}
+			I::PopPri | I::PopAlt => {
+				let value = self.pop(uses);
+				self.write(register(*instruction == I::PopPri), value, pc);
+			}
+			I::SwapPri | I::SwapAlt => {
+				let register = register(*instruction == I::SwapPri);
+				let value = self.read(register, uses);
+				let top = self.read(Frame(-self.depth), uses);
+				self.write(Frame(-self.depth), value, pc);
+				self.write(register, top, pc);
+			}
+			I::PushR { const_1 } => {
+				let value = self.read(Pri, uses);
+				for _ in 0..const_1 {
+					self.push(value, pc);
+				}
+			}
+			I::Stack { const_1 } if const_1 < 0 => self.depth -= const_1,
+			I::Stack { const_1 } => self.drop_cells(const_1),
+			I::Heap { .. } => self.write(Alt, Value::Unknown, pc),
+			I::Call { func_1 } => {
+				let bytes = self.pop(uses);
+				let count = bytes.as_const().map_or(0, move |bytes| bytes / CELL);
+				*args = Some((0..count).map(|_| self.pop(uses)).collect());
+				self.clobber(escaped, pc);
+				let value = Value::Call { function: func_1 as usize, call: pc };
+				self.write(Pri, value, pc);
+				self.write(Alt, Value::Unknown, pc);
+			}
+			I::SysreqN { native, n_args } => {
+				*args = Some((0..n_args).map(|_| self.pop(uses)).collect());
+				self.clobber(escaped, pc);
+				self.write(Pri, Value::Native { index: native as usize, call: pc }, pc);
+				self.write(Alt, Value::Unknown, pc);
+			}
+			I::SysreqC { native_1 } => {
+				*args = Some(self.peek_args(None, uses));
+				self.clobber(escaped, pc);
+				self.write(Pri, Value::Native { index: native_1 as usize, call: pc }, pc);
+				self.write(Alt, Value::Unknown, pc);
+			}
+			I::Float => {
+				let value = self.peek_args(Some(1), uses)[0];
+				let value = value.as_const()
+					.map_or(Value::Unknown, move |v| Value::from_float(v as f32));
+				self.write(Pri, value, pc);
+			}
+			I::Floatadd | I::Floatsub | I::Floatmul | I::Floatdiv => {
+				let values = self.peek_args(Some(2), uses);
+				let value = match (values[0].as_float(), values[1].as_float()) {
+					(Some(a), Some(b)) => Value::from_float(match instruction {
+						I::Floatadd => a + b,
+						I::Floatsub => a - b,
+						I::Floatmul => a * b,
+						_ => a / b,
+					}),
+					_ => Value::Unknown,
+				};
+				self.write(Pri, value, pc);
+			}
+			I::Fabs => {
+				let value = self.peek_args(Some(1), uses)[0];
+				let value = value.as_float()
+					.map_or(Value::Unknown, move |v| Value::from_float(v.abs()));
+				self.write(Pri, value, pc);
+			}
+			I::Floatcmp => {
+				self.peek_args(Some(2), uses);
+				self.write(Pri, Value::Unknown, pc);
+			}
+			I::RndToNearest | I::RndToFloor | I::RndToCeil | I::RndToZero => {
+				self.peek_args(Some(1), uses);
+				self.write(Pri, Value::Unknown, pc);
+			}
+			I::Genarray { const_1 } | I::GenarrayZ { const_1 } => {
+				for _ in 1..const_1 {
+					self.pop(uses);
+				}
+				self.read(Frame(-self.depth), uses);
+				self.write(Frame(-self.depth), Value::Unknown, pc);
+			}
+			I::Movs { const_1 } | I::Fill { const_1 } => {
+				let (mut value, address) = (self.read(Pri, uses), self.read(Alt, uses));
+				if matches!(instruction, I::Movs { .. }) {
+					value = Value::Unknown;
+				}
+				match address {
+					Value::LocalAddress(base) => {
+						for offset in (0..const_1).step_by(CELL as usize) {
+							self.write(Frame(base + offset), value, pc);
+						}
+					}
+					address => self.store(address, value, pc, escaped),
+				}
+			}
+			I::Shl => binary(self, uses, move |a, b| Some(a.wrapping_shl(b as u32))),
+			I::Shr => binary(self, uses, move |a, b| {
+				Some((a as u32).wrapping_shr(b as u32) as Cell)
+			}),
+			I::Sshr => binary(self, uses, move |a, b| Some(a.wrapping_shr(b as u32))),
+			I::Smul | I::Umul => binary(self, uses, move |a, b| Some(a.wrapping_mul(b))),
+			I::Sdiv | I::Udiv | I::SdivAlt | I::UdivAlt => {
+				let (pri, alt) = (self.read(Pri, uses), self.read(Alt, uses));
+				let (a, b) = match instruction {
+					I::Sdiv | I::Udiv => (pri, alt),
+					_ => (alt, pri),
+				};
+				let (quotient, remainder) = match (a, b) {
+					(Value::Const(a), Value::Const(b)) if b != 0 => {
+						(Value::Const(a.wrapping_div(b)), Value::Const(a.wrapping_rem(b)))
+					}
+					_ => (Value::Unknown, Value::Unknown),
+				};
+				self.write(Pri, quotient, pc);
+				self.write(Alt, remainder, pc);
+			}
+			I::Add | I::Sub | I::SubAlt => {
+				let (pri, alt) = (self.read(Pri, uses), self.read(Alt, uses));
+				let value = match instruction {
+					I::Add => add(pri, alt),
+					I::Sub => sub(pri, alt),
+					_ => sub(alt, pri),
+				};
+				self.write(Pri, value, pc);
+			}
+			I::And => binary(self, uses, move |a, b| Some(a & b)),
+			I::Or => binary(self, uses, move |a, b| Some(a | b)),
+			I::Xor => binary(self, uses, move |a, b| Some(a ^ b)),
+			I::Eq => compare(self, uses, Cell::eq),
+			I::Neq => compare(self, uses, Cell::ne),
+			I::Sless => compare(self, uses, Cell::lt),
+			I::Sleq => compare(self, uses, Cell::le),
+			I::Sgrtr => compare(self, uses, Cell::gt),
+			I::Sgeq => compare(self, uses, Cell::ge),
+			I::Less => compare(self, uses, |a, b| (*a as u32) < (*b as u32)),
+			I::Leq => compare(self, uses, |a, b| (*a as u32) <= (*b as u32)),
+			I::Grtr => compare(self, uses, |a, b| (*a as u32) > (*b as u32)),
+			I::Geq => compare(self, uses, |a, b| (*a as u32) >= (*b as u32)),
+			I::Not | I::Neg | I::Invert | I::IncPri | I::DecPri => {
+				let value = match self.read(Pri, uses) {
+					Value::Const(value) => Value::Const(match instruction {
+						I::Not => Cell::from(value == 0),
+						I::Neg => value.wrapping_neg(),
+						I::Invert => !value,
+						I::IncPri => value.wrapping_add(1),
+						_ => value.wrapping_sub(1),
+					}),
+					// Addresses of frame cells are never null.
+					Value::LocalAddress(_) if *instruction == I::Not => Value::Const(0),
+					_ => Value::Unknown,
+				};
+				self.write(Pri, value, pc);
+			}
+			I::IncAlt | I::DecAlt => {
+				let delta = if *instruction == I::IncAlt { 1 } else { -1 };
+				let value = add(self.read(Alt, uses), Value::Const(delta));
+				self.write(Alt, value, pc);
+			}
+			I::AddC { const_1 } => {
+				let value = add(self.read(Pri, uses), Value::Const(const_1));
+				self.write(Pri, value, pc);
+			}
+			I::SmulC { const_1 } => {
+				let value = match self.read(Pri, uses) {
+					Value::Const(value) => Value::Const(value.wrapping_mul(const_1)),
+					_ => Value::Unknown,
+				};
+				self.write(Pri, value, pc);
+			}
+			I::ShlCPri { const_1 } | I::ShrCPri { const_1 } | I::ShlCAlt { const_1 }
+				| I::ShrCAlt { const_1 } =>
+			{
+				let register = match instruction {
+					I::ShlCPri { .. } | I::ShrCPri { .. } => Pri,
+					_ => Alt,
+				};
+				let value = match self.read(register, uses) {
+					Value::Const(value) => Value::Const(match instruction {
+						I::ShlCPri { .. } | I::ShlCAlt { .. } => {
+							value.wrapping_shl(const_1 as u32)
+						}
+						_ => (value as u32).wrapping_shr(const_1 as u32) as Cell,
+					}),
+					_ => Value::Unknown,
+				};
+				self.write(register, value, pc);
+			}
+			I::EqCPri { const_1 } | I::EqCAlt { const_1 } => {
+				let register = register(matches!(instruction, I::EqCPri { .. }));
+				let value = match self.read(register, uses) {
+					Value::Const(value) => Value::from_bool(value == const_1),
+					_ => Value::Unknown,
+				};
+				self.write(Pri, value, pc);
+			}
+			I::SignPri | I::SignAlt => {
+				let register = register(*instruction == I::SignPri);
+				let value = match self.read(register, uses) {
+					Value::Const(value) => Value::Const(value as i8 as Cell),
+					_ => Value::Unknown,
+				};
+				self.write(register, value, pc);
+			}
+			I::AlignPri { .. } | I::AlignAlt { .. } => {}
+			I::Jzer { .. } | I::Jnz { .. } | I::Switch { .. } | I::Retn | I::Ret
+				| I::JumpPri | I::Bounds { .. } | I::SysreqPri | I::CallPri =>
+			{
+				self.read(Pri, uses);
+				if matches!(instruction, I::SysreqPri | I::CallPri) {
+					self.clobber(escaped, pc);
+					self.write(Pri, Value::Unknown, pc);
+					self.write(Alt, Value::Unknown, pc);
+				}
+			}
+			I::Jeq { .. }
+				| I::Jneq { .. }
+				| I::Jless { .. }
+				| I::Jleq { .. }
+				| I::Jgrtr { .. }
+				| I::Jgeq { .. }
+				| I::Jsless { .. }
+				| I::Jsleq { .. }
+				| I::Jsgrtr { .. }
+				| I::Jsgeq { .. } => {
+				self.read(Pri, uses);
+				self.read(Alt, uses);
+			}
+			I::Proc => self.depth = 0,
+			I::Jump { .. }
+				| I::Jrel { .. }
+				| I::Casetbl { .. }
+				| I::Endproc
+				| I::Nop
+				| I::Break
+				| I::Halt { .. }
+				| I::HeapSave
+				| I::HeapRestore
+				| I::TrackerPushC { .. }
+				| I::TrackerPopSetheap
+				| I::File { .. }
+				| I::Line { .. }
+				| I::Symbol { .. }
+				| I::Srange { .. }
+				| I::Symtag { .. } => {}
+			_ => {
+				self.clobber(escaped, pc);
+				self.write(Pri, Value::Unknown, pc);
+				self.write(Alt, Value::Unknown, pc);
+			}
+		}
+	}
+}
+
+/// Return `a + b`, where `a` or `b` may be the address of a frame cell.
+fn add(a: Value, b: Value) -> Value {
+	match (a, b) {
+		(Value::Const(a), Value::Const(b)) => Value::Const(a.wrapping_add(b)),
+		(Value::LocalAddress(a), Value::Const(b))
+			| (Value::Const(b), Value::LocalAddress(a)) => {
+			Value::LocalAddress(a.wrapping_add(b))
+		}
+		_ => Value::Unknown,
+	}
+}
+
+/// Return `a - b`, where `a` may be the address of a frame cell.
+fn sub(a: Value, b: Value) -> Value {
+	match (a, b) {
+		(Value::Const(a), Value::Const(b)) => Value::Const(a.wrapping_sub(b)),
+		(Value::LocalAddress(a), Value::Const(b)) => {
+			Value::LocalAddress(a.wrapping_sub(b))
+		}
+		(Value::LocalAddress(a), Value::LocalAddress(b)) => {
+			Value::Const(a.wrapping_sub(b))
+		}
+		_ => Value::Unknown,
+	}
+}
+
+/// Return the locations that an instruction writes with copies of other
+/// locations, paired with their sources, where `depth` is the number of bytes
+/// pushed before it.
+pub(crate) fn copies(
+	instruction: &Instruction,
+	depth: Cell,
+) -> Vec<(Location, Location)> {
+	use Instruction as I;
+	use Location::{
+		Alt,
+		Frame,
+		Pri,
+	};
+	let top = Frame(-depth);
+	let pushed = move |n: usize| Frame(-depth - (n as Cell + 1) * CELL);
+	if let Some((PushKind::Stack, operands)) = push_operands(instruction) {
+		return operands.into_iter()
+			.enumerate()
+			.map(move |(n, offset)| (pushed(n), Frame(offset)))
+			.collect()
+	}
+	match *instruction {
+		I::MovePri => vec![(Pri, Alt)],
+		I::MoveAlt => vec![(Alt, Pri)],
+		I::Xchg => vec![(Pri, Alt), (Alt, Pri)],
+		I::LoadSPri { offset } => vec![(Pri, Frame(offset))],
+		I::LoadSAlt { offset } => vec![(Alt, Frame(offset))],
+		I::LoadSBoth { stack_1, stack_2 } => {
+			vec![(Pri, Frame(stack_1)), (Alt, Frame(stack_2))]
+		}
+		I::StorSPri { offset } => vec![(Frame(offset), Pri)],
+		I::StorSAlt { offset } => vec![(Frame(offset), Alt)],
+		I::PushPri => vec![(pushed(0), Pri)],
+		I::PushAlt => vec![(pushed(0), Alt)],
+		I::PushR { const_1 } => (0..const_1.max(0) as usize)
+			.map(move |n| (pushed(n), Pri))
+			.collect(),
+		I::PopPri => vec![(Pri, top)],
+		I::PopAlt => vec![(Alt, top)],
+		I::SwapPri => vec![(Pri, top), (top, Pri)],
+		I::SwapAlt => vec![(Alt, top), (top, Alt)],
+		_ => Vec::new(),
+	}
+}
+
+/// Return the location that a constant operand of an instruction is loaded
+/// or pushed into, where `depth` is the number of bytes pushed before it.
+pub(crate) fn constant_location(
+	instruction: &Instruction,
+	n: usize,
+	depth: Cell,
+) -> Option<Location> {
+	match *instruction {
+		Instruction::ConstPri { .. } => Some(Location::Pri),
+		Instruction::ConstAlt { .. } => Some(Location::Alt),
+		Instruction::ConstS { stack_1, .. } => Some(Location::Frame(stack_1)),
+		_ => match push_operands(instruction) {
+			Some((PushKind::Const, _)) => {
+				Some(Location::Frame(-depth - (n as Cell + 1) * CELL))
+			}
+			_ => None,
+		},
+	}
+}
+
+/// Return `true` if an instruction uses the location of its `n`th read in
+/// [`DataFlow::uses_at`] as a data address.
+pub(crate) fn is_address_read(instruction: &Instruction, n: usize) -> bool {
+	use Instruction as I;
+	matches!(
+		(instruction, n),
+		(I::LoadI | I::LodbI { .. } | I::IncI | I::DecI, 0)
+			| (I::LrefSPri { .. } | I::LrefSAlt { .. } | I::Movs { .. }, 0)
+			| (I::StorI | I::StrbI { .. } | I::Fill { .. } | I::Movs { .. }, 1)
+			| (I::SrefSPri { .. } | I::SrefSAlt { .. }, 1)
+			| (I::Lidx | I::LidxB { .. } | I::Idxaddr | I::IdxaddrB { .. }, 1)
+	)
+}
+
+/// Result of the data-flow analysis of a function.
+#[derive(Debug, Clone, Default)]
+pub struct DataFlow {
+	/// State before each reachable instruction.
+	states: BTreeMap<usize, State>,
+	/// Locations that each instruction reads.
+	uses: BTreeMap<usize, Vec<Use>>,
+	/// Arguments of each call, in order.
+	args: BTreeMap<usize, Vec<Value>>,
+	/// Locations that each instruction writes with copies, with their
+	/// sources.
+	copies: BTreeMap<usize, Vec<(Location, Location)>>,
+}
+
+impl DataFlow {
+	/// Analyze a control-flow graph.
+	pub fn new(cfg: &Cfg) -> Self {
+		// Memory that is written through an address is only known to be
+		// within the frame cells whose address is taken.
+		let escaped: BTreeSet<Cell> = cfg.blocks.iter()
+			.flat_map(move |block| block.instructions.iter())
+			.flat_map(move |(_, instruction)| match *instruction {
+				Instruction::AddrPri { offset } | Instruction::AddrAlt { offset } => {
+					vec![offset]
+				}
+				_ => match push_operands(instruction) {
+					Some((PushKind::Adr, operands)) => operands,
+					_ => Vec::new(),
+				},
+			})
+			.collect();
+
+		let mut entry_states: Vec<Option<State>> = vec![None; cfg.blocks.len()];
+		let mut worklist = Vec::new();
+		if !cfg.blocks.is_empty() {
+			entry_states[0] = Some(State::entry());
+			worklist.push(0);
+		}
+		while let Some(idx) = worklist.pop() {
+			let mut state = entry_states[idx].clone().unwrap();
+			for (pc, instruction) in cfg.blocks[idx].instructions.iter() {
+				state.step(*pc, instruction, &escaped, &mut Vec::new(), &mut None);
+			}
+			for edge in cfg.blocks[idx].successors.iter() {
+				let next = &mut entry_states[edge.block];
+				let changed = match next {
+					Some(entry) => entry.meet(&state),
+					None => {
+						*next = Some(state.clone());
+						true
+					}
+				};
+				if changed && !worklist.contains(&edge.block) {
+					worklist.push(edge.block);
+				}
+			}
+		}
+
+		let mut flow = Self::default();
+		for (block, entry) in cfg.blocks.iter().zip(entry_states) {
+			let Some(mut state) = entry else {
+				continue
+			};
+			for (pc, instruction) in block.instructions.iter() {
+				let copies = copies(instruction, state.depth);
+				if !copies.is_empty() {
+					flow.copies.insert(*pc, copies);
+				}
+				flow.states.insert(*pc, state.clone());
+				let (mut uses, mut args) = (Vec::new(), None);
+				state.step(*pc, instruction, &escaped, &mut uses, &mut args);
+				flow.uses.insert(*pc, uses);
+				if let Some(args) = args {
+					flow.args.insert(*pc, args);
+				}
+			}
+		}
+		flow
+	}
+
+	/// Analyze a function.
+	pub fn function(function: &Function) -> Self {
+		Self::new(&cfg::build_function(function))
+	}
+
+	/// Return the state before the instruction at a code offset.
+	pub fn state_at(&self, pc: usize) -> Option<&State> {
+		self.states.get(&pc)
+	}
+
+	/// Return the locations that the instruction at a code offset reads, with
+	/// their reaching definitions.
+	pub fn uses_at(&self, pc: usize) -> &[Use] {
+		self.uses.get(&pc).map_or(&[], Vec::as_slice)
+	}
+
+	/// Return the reads of the locations that the instruction at a code
+	/// offset writes, as code offsets and locations.
+	pub fn uses_of(&self, def: usize) -> Vec<(usize, Location)> {
+		self.uses.iter()
+			.flat_map(move |(pc, uses)| uses.iter().map(move |u| (*pc, u)))
+			.filter(move |(_, u)| u.defs.contains(&Def::At(def)))
+			.map(move |(pc, u)| (pc, u.location))
+			.collect()
+	}
+
+	/// Return the definitions of a location that the instruction at a code
+	/// offset reads, following copies between registers and frame cells back
+	/// to the definitions that computed the value, with the locations that
+	/// they wrote.
+	pub fn origins(&self, pc: usize, location: Location) -> Vec<(Def, Location)> {
+		let mut origins = BTreeSet::new();
+		let mut visited = BTreeSet::from([(pc, location)]);
+		let mut stack = vec![(pc, location)];
+		while let Some((pc, location)) = stack.pop() {
+			let defs = self.uses_at(pc).iter()
+				.filter(move |u| u.location == location)
+				.flat_map(move |u| u.defs.iter().copied());
+			for def in defs {
+				let Def::At(at) = def else {
+					origins.insert((def, location));
+					continue
+				};
+				let source = self.copies.get(&at)
+					.and_then(move |copies| copies.iter().find(move |c| c.0 == location));
+				match source {
+					Some((_, source)) => {
+						if visited.insert((at, *source)) {
+							stack.push((at, *source));
+						}
+					}
+					None => {
+						origins.insert((def, location));
+					}
+				}
+			}
+		}
+		origins.into_iter().collect()
+	}
+
+	/// Return the arguments of the call at a code offset, in order.
+	pub fn call_args(&self, pc: usize) -> Option<&[Value]> {
+		self.args.get(&pc).map(Vec::as_slice)
+	}
+
+	/// Return the calls of the analyzed code with their arguments, by code
+	/// offset.
+	pub fn calls(&self) -> impl Iterator<Item = (usize, &[Value])> {
+		self.args.iter().map(move |(pc, args)| (*pc, args.as_slice()))
+	}
+}
+
+/// Natives that format their arguments, with the index of the format.
+const FORMAT_NATIVES: &[(&str, usize)] = &[
+	("ClientCommand", 1),
+	("FakeClientCommand", 1),
+	("FakeClientCommandEx", 1),
+	("Format", 2),
+	("FormatEx", 2),
+	("InsertServerCommand", 0),
+	("KickClient", 1),
+	("KickClientEx", 1),
+	("LogAction", 2),
+	("LogError", 0),
+	("LogMessage", 0),
+	("LogToFile", 1),
+	("LogToFileEx", 1),
+	("LogToGame", 0),
+	("PrintCenterText", 1),
+	("PrintCenterTextAll", 0),
+	("PrintHintText", 1),
+	("PrintHintTextToAll", 0),
+	("PrintToChat", 1),
+	("PrintToChatAll", 0),
+	("PrintToConsole", 1),
+	("PrintToConsoleAll", 0),
+	("PrintToServer", 0),
+	("ReplyToCommand", 1),
+	("ServerCommand", 0),
+	("SetFailState", 0),
+	("ShowActivity", 1),
+	("ShowActivity2", 2),
+	("ShowHudText", 2),
+	("ShowSyncHudText", 2),
+	("ThrowError", 0),
+	("ThrowNativeError", 1),
+];
+
+/// Return the index of the format argument of a native that formats its
+/// arguments.
+pub fn format_arg(native: &str) -> Option<usize> {
+	FORMAT_NATIVES.iter().find(move |(name, _)| *name == native).map(move |(_, arg)| *arg)
+}
+
+/// Number of arguments that a format string consumes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FormatArgs {
+	pub count: usize,
+	/// `true` if the format contains translations, whose phrases consume
+	/// more arguments.
+	pub more: bool,
+}
+
+impl FormatArgs {
+	/// Return `true` if a call with the specified number of arguments after
+	/// the format matches it.
+	pub const fn accepts(&self, n_args: usize) -> bool {
+		n_args == self.count || self.more && n_args > self.count
+	}
+}
+
+/// Kind of a problem with a formatting call.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FormatIssueKind {
+	/// Number of arguments that does not match the format.
+	ArgCount {
+		expected: FormatArgs,
+		found: usize,
+	},
+	/// Conversion that is not supported, at a byte offset into the format.
+	InvalidSpecifier {
+		position: usize,
+		specifier: char,
+	},
+	/// `%` at the end of the format.
+	Trailing,
+}
+
+/// Problem with a call of a native that formats its arguments.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FormatIssue {
+	/// Code offset of the call.
+	pub offset: usize,
+	/// Name of the native.
+	pub native: String,
+	pub format: String,
+	pub kind: FormatIssueKind,
+}
+
+impl fmt::Display for FormatIssue {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		let (offset, native) = (self.offset, &self.native);
+		write!(f, "0x{offset:08x}: {native} with format {:?} ", self.format)?;
+		match self.kind {
+			FormatIssueKind::ArgCount { expected, found } => {
+				let more = if expected.more { " or more" } else { "" };
+				write!(f, "has {found} arguments, expected {}{more}", expected.count)
+			}
+			FormatIssueKind::InvalidSpecifier { position, specifier } => {
+				write!(f, "has invalid specifier %{specifier} at {position}")
+			}
+			FormatIssueKind::Trailing => write!(f, "ends with %"),
+		}
+	}
+}
+
+/// Parse a format string of SourceMod, returning the arguments that it
+/// consumes, or the problem with it.
+pub fn parse_format(format: &str) -> Result<FormatArgs, FormatIssueKind> {
+	let mut args = FormatArgs { count: 0, more: false };
+	let mut chars = format.char_indices().peekable();
+	while let Some((position, c)) = chars.next() {
+		if c != '%' {
+			continue
+		}
+		// Flags, width and precision.
+		while chars.next_if(move |(_, c)| matches!(c, '-' | '0'..='9' | '.')).is_some() {}
+		let Some((_, specifier)) = chars.next() else {
+			return Err(FormatIssueKind::Trailing)
+		};
+		match specifier {
+			'%' => {}
+			'b' | 'c' | 'd' | 'f' | 'i' | 'L' | 'N' | 's' | 'u' | 'x' | 'X' => {
+				args.count += 1;
+			}
+			't' => {
+				args.count += 1;
+				args.more = true;
+			}
+			'T' => {
+				args.count += 2;
+				args.more = true;
+			}
+			specifier => {
+				return Err(FormatIssueKind::InvalidSpecifier { position, specifier })
+			}
+		}
+	}
+	Ok(args)
+}
+
+/// Check the format strings that are passed as constants to natives that
+/// format their arguments.
+pub fn check_formats(plugin: &Plugin) -> Result<Vec<FormatIssue>, DecodeError> {
+	let map = functions::discover_plugin(plugin, None)?;
+	let mut issues = Vec::new();
+	for function in map.functions.iter() {
+		let flow = DataFlow::function(function);
+		for (offset, args) in flow.calls() {
+			let call = function.instructions.iter().find(move |(pc, _)| *pc == offset);
+			let native = match call {
+				Some((_, Instruction::SysreqN { native, .. })) => *native,
+				Some((_, Instruction::SysreqC { native_1 })) => *native_1,
+				_ => continue,
+			};
+			let Some(name) = plugin.native_name(native as usize) else {
+				continue
+			};
+			let name = name.to_string_lossy().into_owned();
+			let Some(index) = format_arg(&name) else {
+				continue
+			};
+			let Some(Value::Const(address)) = args.get(index).copied() else {
+				continue
+			};
+			let address = address as usize;
+			if !plugin.data.is_string_start(address) {
+				continue
+			}
+			let Some(format) = plugin.data.c_str_at(address) else {
+				continue
+			};
+			let format = format.to_string_lossy().into_owned();
+			let found = args.len() - index - 1;
+			let kind = match parse_format(&format) {
+				Ok(expected) if expected.accepts(found) => continue,
+				Ok(expected) => FormatIssueKind::ArgCount { expected, found },
+				Err(kind) => kind,
+			};
+			issues.push(FormatIssue { offset, native: name, format, kind });
+		}
+	}
+	Ok(issues)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::instruction::layout;
+
+	#[test]
+	fn constants() {
+		let (instructions, end) = layout(vec![
+			Instruction::Proc,
+			Instruction::ConstPri { value: 6 },
+			Instruction::ConstAlt { value: 7 },
+			Instruction::Smul,
+			Instruction::PushPri,
+			Instruction::AddrPri { offset: -4 },
+			Instruction::LoadI,
+			Instruction::AddC { const_1: 2 },
+			Instruction::StorSPri { offset: -4 },
+			Instruction::LoadSPri { offset: -4 },
+			Instruction::Stack { const_1: 4 },
+			Instruction::Retn,
+		]);
+		let function = Function {
+			start: 0,
+			end,
+			instructions: instructions.clone(),
+			public: false,
+			name: None,
+		};
+		let flow = DataFlow::function(&function);
+		let state = move |n: usize| flow.state_at(instructions[n].0).unwrap().clone();
+		assert_eq!(state(4).pri, Value::Const(42));
+		assert_eq!(state(5).stack(0), Value::Const(42));
+		assert_eq!(state(8).pri, Value::Const(44));
+		assert_eq!(state(10).pri, Value::Const(44));
+		assert_eq!(state(11).depth, 0);
+	}
+
+	#[test]
+	fn def_use() {
+		let (instructions, end) = layout(vec![
+			// 0x00
+			Instruction::Proc,
+			Instruction::PushC { const_1: 0 },
+			Instruction::LoadSPri { offset: 12 },
+			Instruction::Jzer { jump_1: 0x28 },
+			// 0x1c
+			Instruction::ConstS { stack_1: -4, const_1: 1 },
+			// 0x28
+			Instruction::LoadSPri { offset: -4 },
+			Instruction::Stack { const_1: 4 },
+			Instruction::Retn,
+		]);
+		assert_eq!(instructions[5].0, 0x28);
+		let function = Function {
+			start: 0,
+			end,
+			instructions,
+			public: false,
+			name: None,
+		};
+		let flow = DataFlow::function(&function);
+		assert_eq!(flow.uses_at(0x0c), [Use {
+			location: Location::Frame(12),
+			defs: vec![Def::Entry],
+		}]);
+		assert_eq!(flow.uses_at(0x28), [Use {
+			location: Location::Frame(-4),
+			defs: vec![Def::At(0x04), Def::At(0x1c)],
+		}]);
+		assert_eq!(flow.uses_of(0x1c), [(0x28, Location::Frame(-4))]);
+		assert_eq!(flow.state_at(0x28).unwrap().frame(-4), Value::Local(-4));
+		assert_eq!(flow.state_at(0x1c).unwrap().frame(-4), Value::Const(0));
+		assert_eq!(flow.origins(0x28, Location::Frame(-4)), [
+			(Def::At(0x04), Location::Frame(-4)),
+			(Def::At(0x1c), Location::Frame(-4)),
+		]);
+	}
+
+	#[test]
+	fn origins() {
+		let (instructions, end) = layout(vec![
+			// 0x00
+			Instruction::Proc,
+			Instruction::ConstPri { value: 8 },
+			Instruction::PushPri,
+			Instruction::PopAlt,
+			// 0x14
+			Instruction::LoadSPri { offset: 12 },
+			Instruction::Xchg,
+			Instruction::LoadI,
+			Instruction::Retn,
+		]);
+		let function = Function {
+			start: 0,
+			end,
+			instructions,
+			public: false,
+			name: None,
+		};
+		let flow = DataFlow::function(&function);
+		assert_eq!(flow.origins(0x20, Location::Pri), [(Def::At(0x04), Location::Pri)]);
+		assert_eq!(
+			flow.origins(0x1c, Location::Pri),
+			[(Def::Entry, Location::Frame(12))]
+		);
+	}
+
+	#[test]
+	fn formats() -> Result<(), DecodeError> {
+		let mut plugin = Plugin::default();
+		plugin.code.version = 13;
+		plugin.code.cell_size = 4;
+		plugin.data.bytes = [&b"%d of %s\0\0\0\0"[..], b"100%\0\0\0\0"].concat();
+		plugin.add_native(c"PrintToServer");
+		plugin.add_native(c"Format");
+		for instruction in [
+			// 0x00
+			Instruction::Proc,
+			Instruction::PushC { const_1: 0x20 },
+			Instruction::PushC { const_1: 0 },
+			Instruction::SysreqN { native: 0, n_args: 2 },
+			// 0x20
+			Instruction::ConstPri { value: 12 },
+			Instruction::PushPri,
+			Instruction::PushC { const_1: 64 },
+			Instruction::PushAdr { stack_1: -0x40 },
+			Instruction::SysreqN { native: 1, n_args: 3 },
+			Instruction::ZeroPri,
+			Instruction::Retn,
+			Instruction::Endproc,
+		] {
+			let _ = instruction.write_to(&mut plugin.code.code);
+		}
+		let issues = check_formats(&plugin)?;
+		assert_eq!(issues, [
+			FormatIssue {
+				offset: 0x14,
+				native: "PrintToServer".into(),
+				format: "%d of %s".into(),
+				kind: FormatIssueKind::ArgCount {
+					expected: FormatArgs { count: 2, more: false },
+					found: 1,
+				},
+			},
+			FormatIssue {
+				offset: 0x3c,
+				native: "Format".into(),
+				format: "100%".into(),
+				kind: FormatIssueKind::Trailing,
+			},
+		]);
+		assert_eq!(
+			issues[0].to_string(),
+			concat!(
+				"0x00000014: PrintToServer with format \"%d of %s\" ",
+				"has 1 arguments, expected 2",
+			)
+		);
+		assert_eq!(parse_format("%-5.2f %T %%"), Ok(FormatArgs { count: 3, more: true }));
+		assert_eq!(
+			parse_format("%q"),
+			Err(FormatIssueKind::InvalidSpecifier { position: 0, specifier: 'q' })
+		);
+		Ok(())
+	}
+}
@@ -0,0 +1,619 @@
+//! Models of the legacy `.dbg.*` debug sections emitted by spcomp.
+
+use crate::{
+	sections::{
+		read_table,
+		write_table,
+		TableEntry,
+	},
+	size_of,
+	smx_table::CStrTable,
+	Smx,
+};
+
+use byteorder::{
+	ByteOrder,
+	ReadBytesExt,
+	WriteBytesExt,
+};
+use core::ffi::CStr;
+use std::{
+	borrow::Borrow,
+	ffi::CString,
+	hash::Hash,
+	io::{
+		Cursor,
+		Error as IoError,
+		ErrorKind as IoErrorKind,
+		Result as IoResult,
+	},
+};
+
+/// Name of the section with debug table counts.
+pub const INFO: &CStr = c".dbg.info";
+/// Name of the section with the names of debug symbols and files.
+pub const STRINGS: &CStr = c".dbg.strings";
+/// Name of the section with source files by code offset.
+pub const FILES: &CStr = c".dbg.files";
+/// Name of the section with source lines by code offset.
+pub const LINES: &CStr = c".dbg.lines";
+/// Name of the section with symbols.
+pub const SYMBOLS: &CStr = c".dbg.symbols";
+/// Name of the section with native prototypes.
+pub const NATIVES: &CStr = c".dbg.natives";
+
+/// `.dbg.info` section.
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct DebugCounts {
+	/// Number of entries in `.dbg.files`.
+	pub num_files: u32,
+	/// Number of entries in `.dbg.lines`.
+	pub num_lines: u32,
+	/// Number of entries in `.dbg.symbols`.
+	pub num_syms: u32,
+	/// Number of symbols that are arrays.
+	pub num_arrays: u32,
+}
+
+impl TableEntry for DebugCounts {
+	const SIZE: usize = size_of!(u32 + u32 + u32 + u32);
+
+	fn read<E: ByteOrder>(r: &mut impl ReadBytesExt) -> IoResult<Self> {
+		Ok(Self {
+			num_files: r.read_u32::<E>()?,
+			num_lines: r.read_u32::<E>()?,
+			num_syms: r.read_u32::<E>()?,
+			num_arrays: r.read_u32::<E>()?,
+		})
+	}
+
+	fn write<E: ByteOrder>(&self, w: &mut impl WriteBytesExt) -> IoResult<()> {
+		w.write_u32::<E>(self.num_files)?;
+		w.write_u32::<E>(self.num_lines)?;
+		w.write_u32::<E>(self.num_syms)?;
+		w.write_u32::<E>(self.num_arrays)
+	}
+}
+
+/// Entry of the `.dbg.files` section.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct DebugFile {
+	/// Code offset where the file starts.
+	pub address: u32,
+	/// Offset of the file name into `.dbg.strings`.
+	pub name: u32,
+}
+
+impl TableEntry for DebugFile {
+	const SIZE: usize = size_of!(u32 + u32);
+
+	fn read<E: ByteOrder>(r: &mut impl ReadBytesExt) -> IoResult<Self> {
+		Ok(Self {
+			address: r.read_u32::<E>()?,
+			name: r.read_u32::<E>()?,
+		})
+	}
+
+	fn write<E: ByteOrder>(&self, w: &mut impl WriteBytesExt) -> IoResult<()> {
+		w.write_u32::<E>(self.address)?;
+		w.write_u32::<E>(self.name)
+	}
+}
+
+/// Entry of the `.dbg.lines` section.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct DebugLine {
+	/// Code offset where the line starts.
+	pub address: u32,
+	/// Zero-based line number.
+	pub line: u32,
+}
+
+impl TableEntry for DebugLine {
+	const SIZE: usize = size_of!(u32 + u32);
+
+	fn read<E: ByteOrder>(r: &mut impl ReadBytesExt) -> IoResult<Self> {
+		Ok(Self {
+			address: r.read_u32::<E>()?,
+			line: r.read_u32::<E>()?,
+		})
+	}
+
+	fn write<E: ByteOrder>(&self, w: &mut impl WriteBytesExt) -> IoResult<()> {
+		w.write_u32::<E>(self.address)?;
+		w.write_u32::<E>(self.line)
+	}
+}
+
+/// Type class of a symbol, `ident` in spcomp.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SymbolKind {
+	/// Cell with a value.
+	Variable,
+	/// Cell that is passed by reference.
+	Reference,
+	/// Array.
+	Array,
+	/// Array that is passed by reference.
+	RefArray,
+	/// Function.
+	Function,
+	/// Variadic arguments.
+	Varargs,
+	/// Unknown type class.
+	Other(u8),
+}
+
+impl From<u8> for SymbolKind {
+	fn from(value: u8) -> Self {
+		match value {
+			1 => Self::Variable,
+			2 => Self::Reference,
+			3 => Self::Array,
+			4 => Self::RefArray,
+			9 => Self::Function,
+			11 => Self::Varargs,
+			_ => Self::Other(value),
+		}
+	}
+}
+
+impl From<SymbolKind> for u8 {
+	fn from(value: SymbolKind) -> Self {
+		match value {
+			SymbolKind::Variable => 1,
+			SymbolKind::Reference => 2,
+			SymbolKind::Array => 3,
+			SymbolKind::RefArray => 4,
+			SymbolKind::Function => 9,
+			SymbolKind::Varargs => 11,
+			SymbolKind::Other(value) => value,
+		}
+	}
+}
+
+/// Scope of a symbol, `vclass` in spcomp.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SymbolScope {
+	/// Global symbol, addressed relative to the data image.
+	Global,
+	/// Local symbol, addressed relative to the frame.
+	Local,
+	/// Static symbol, addressed relative to the data image.
+	Static,
+	/// Unknown scope.
+	Other(u8),
+}
+
+impl From<u8> for SymbolScope {
+	fn from(value: u8) -> Self {
+		match value {
+			0 => Self::Global,
+			1 => Self::Local,
+			2 => Self::Static,
+			_ => Self::Other(value),
+		}
+	}
+}
+
+impl From<SymbolScope> for u8 {
+	fn from(value: SymbolScope) -> Self {
+		match value {
+			SymbolScope::Global => 0,
+			SymbolScope::Local => 1,
+			SymbolScope::Static => 2,
+			SymbolScope::Other(value) => value,
+		}
+	}
+}
+
+/// Dimension of an array symbol.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ArrayDim {
+	/// Tag of the dimension.
+	pub tag: i16,
+	/// Size of the dimension.
+	pub size: u32,
+}
+
+impl TableEntry for ArrayDim {
+	const SIZE: usize = size_of!(i16 + u32);
+
+	fn read<E: ByteOrder>(r: &mut impl ReadBytesExt) -> IoResult<Self> {
+		Ok(Self {
+			tag: r.read_i16::<E>()?,
+			size: r.read_u32::<E>()?,
+		})
+	}
+
+	fn write<E: ByteOrder>(&self, w: &mut impl WriteBytesExt) -> IoResult<()> {
+		w.write_i16::<E>(self.tag)?;
+		w.write_u32::<E>(self.size)
+	}
+}
+
+fn read_dims<E: ByteOrder>(
+	r: &mut impl ReadBytesExt,
+	count: u16,
+) -> IoResult<Vec<ArrayDim>> {
+	(0..count).map(move |_| ArrayDim::read::<E>(r)).collect()
+}
+
+fn write_dims<E: ByteOrder>(
+	w: &mut impl WriteBytesExt,
+	dims: &[ArrayDim],
+) -> IoResult<()> {
+	for dim in dims.iter() {
+		dim.write::<E>(w)?;
+	}
+	Ok(())
+}
+
+/// Entry of the `.dbg.symbols` section.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Symbol {
+	/// Address relative to the data image or the frame, or the code offset of
+	/// a function.
+	pub address: i32,
+	/// Tag of the symbol.
+	pub tag: i16,
+	/// Code offset where the symbol comes into scope.
+	pub code_start: u32,
+	/// Code offset where the symbol goes out of scope.
+	pub code_end: u32,
+	/// Type class of the symbol.
+	pub kind: SymbolKind,
+	/// Scope of the symbol.
+	pub scope: SymbolScope,
+	/// Array dimensions, which follow the symbol in the section.
+	pub dims: Vec<ArrayDim>,
+	/// Offset of the name into `.dbg.strings`.
+	pub name: u32,
+}
+
+impl Symbol {
+	/// Size of a symbol without its array dimensions.
+	pub const SIZE: usize = size_of!(i32 + i16 + u32 + u32 + u8 + u8 + u16 + u32);
+
+	/// Return `true` if a code offset is in the scope of this symbol.
+	pub const fn in_scope(&self, offset: usize) -> bool {
+		self.code_start as usize <= offset && offset < self.code_end as usize
+	}
+
+	/// Read a symbol and its array dimensions.
+	pub fn read<E: ByteOrder>(r: &mut impl ReadBytesExt) -> IoResult<Self> {
+		let address = r.read_i32::<E>()?;
+		let tag = r.read_i16::<E>()?;
+		let code_start = r.read_u32::<E>()?;
+		let code_end = r.read_u32::<E>()?;
+		let kind = r.read_u8()?.into();
+		let scope = r.read_u8()?.into();
+		let dim_count = r.read_u16::<E>()?;
+		let name = r.read_u32::<E>()?;
+		Ok(Self {
+			address,
+			tag,
+			code_start,
+			code_end,
+			kind,
+			scope,
+			dims: read_dims::<E>(r, dim_count)?,
+			name,
+		})
+	}
+
+	/// Write a symbol and its array dimensions.
+	pub fn write<E: ByteOrder>(&self, w: &mut impl WriteBytesExt) -> IoResult<()> {
+		w.write_i32::<E>(self.address)?;
+		w.write_i16::<E>(self.tag)?;
+		w.write_u32::<E>(self.code_start)?;
+		w.write_u32::<E>(self.code_end)?;
+		w.write_u8(self.kind.into())?;
+		w.write_u8(self.scope.into())?;
+		w.write_u16::<E>(self.dims.len() as _)?;
+		w.write_u32::<E>(self.name)?;
+		write_dims::<E>(w, &self.dims)
+	}
+}
+
+/// Argument of a native in `.dbg.natives`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct NativeArg {
+	/// Type class of the argument.
+	pub kind: SymbolKind,
+	/// Tag of the argument.
+	pub tag: i16,
+	/// Array dimensions.
+	pub dims: Vec<ArrayDim>,
+	/// Offset of the name into `.dbg.strings`.
+	pub name: u32,
+}
+
+/// Entry of the `.dbg.natives` section.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct DebugNative {
+	/// Index of the native in `.natives`.
+	pub index: u32,
+	/// Offset of the name into `.dbg.strings`.
+	pub name: u32,
+	/// Return tag.
+	pub tag: i16,
+	/// Formal arguments.
+	pub args: Vec<NativeArg>,
+}
+
+impl DebugNative {
+	/// Read a native and its arguments.
+	pub fn read<E: ByteOrder>(r: &mut impl ReadBytesExt) -> IoResult<Self> {
+		let index = r.read_u32::<E>()?;
+		let name = r.read_u32::<E>()?;
+		let tag = r.read_i16::<E>()?;
+		let arg_count = r.read_u16::<E>()?;
+		let mut args = Vec::with_capacity(arg_count as usize);
+		for _ in 0..arg_count {
+			let kind = r.read_u8()?.into();
+			let tag = r.read_i16::<E>()?;
+			let dim_count = r.read_u16::<E>()?;
+			let name = r.read_u32::<E>()?;
+			args.push(NativeArg {
+				kind,
+				tag,
+				dims: read_dims::<E>(r, dim_count)?,
+				name,
+			});
+		}
+		Ok(Self {
+			index,
+			name,
+			tag,
+			args,
+		})
+	}
+
+	/// Write a native and its arguments.
+	pub fn write<E: ByteOrder>(&self, w: &mut impl WriteBytesExt) -> IoResult<()> {
+		w.write_u32::<E>(self.index)?;
+		w.write_u32::<E>(self.name)?;
+		w.write_i16::<E>(self.tag)?;
+		w.write_u16::<E>(self.args.len() as _)?;
+		for arg in self.args.iter() {
+			w.write_u8(arg.kind.into())?;
+			w.write_i16::<E>(arg.tag)?;
+			w.write_u16::<E>(arg.dims.len() as _)?;
+			w.write_u32::<E>(arg.name)?;
+			write_dims::<E>(w, &arg.dims)?;
+		}
+		Ok(())
+	}
+}
+
+/// Parsed view of the legacy debug sections of an SMX file.
+///
+/// Sections that are missing from the file are treated as empty.
+#[derive(Default, Debug, Clone, PartialEq, Eq)]
+pub struct DebugInfo {
+	/// `.dbg.strings` section.
+	pub strings: CStrTable,
+	/// `.dbg.files` section, sorted by code offset.
+	pub files: Vec<DebugFile>,
+	/// `.dbg.lines` section, sorted by code offset.
+	pub lines: Vec<DebugLine>,
+	/// `.dbg.symbols` section.
+	pub symbols: Vec<Symbol>,
+	/// `.dbg.natives` section.
+	pub natives: Vec<DebugNative>,
+}
+
+impl DebugInfo {
+	/// Parse the debug sections of an SMX file.
+	///
+	/// The number of symbols is taken from `.dbg.info`, if present, and
+	/// otherwise symbols are read until the end of `.dbg.symbols`.
+	pub fn from_smx<E, Name, Sect>(smx: &Smx<Name, Sect>) -> IoResult<Self>
+	where
+		E: ByteOrder,
+		Name: Borrow<CStr> + Eq + Hash,
+		Sect: AsRef<[u8]>,
+	{
+		let section = move |name: &CStr| smx.section(name).map(AsRef::as_ref);
+		let counts = section(INFO)
+			.map(move |bytes| DebugCounts::read::<E>(&mut Cursor::new(bytes)))
+			.transpose()?;
+
+		let symbols = match section(SYMBOLS) {
+			Some(bytes) => {
+				let mut r = Cursor::new(bytes);
+				let mut symbols = Vec::new();
+				while match counts {
+					Some(counts) => symbols.len() < counts.num_syms as usize,
+					None => (r.position() as usize) < bytes.len(),
+				} {
+					symbols.push(Symbol::read::<E>(&mut r)?);
+				}
+				symbols
+			}
+			None => Vec::new(),
+		};
+
+		let natives = match section(NATIVES) {
+			Some(bytes) => {
+				let mut r = Cursor::new(bytes);
+				let count = r.read_u32::<E>()?;
+				(0..count)
+					.map(move |_| DebugNative::read::<E>(&mut r))
+					.collect::<Result<_, _>>()?
+			}
+			None => Vec::new(),
+		};
+
+		let info = Self {
+			strings: section(STRINGS)
+				.map(move |blob| CStrTable::from_blob(blob.to_vec()))
+				.unwrap_or_default(),
+			files: section(FILES)
+				.map(read_table::<E, _>)
+				.transpose()?
+				.unwrap_or_default(),
+			lines: section(LINES)
+				.map(read_table::<E, _>)
+				.transpose()?
+				.unwrap_or_default(),
+			symbols,
+			natives,
+		};
+
+		if let Some(counts) = counts {
+			if counts.num_files as usize != info.files.len()
+				|| counts.num_lines as usize != info.lines.len()
+			{
+				return Err(IoError::new(
+					IoErrorKind::InvalidData,
+					format!("{counts:?} does not match the debug tables"),
+				))
+			}
+		}
+		Ok(info)
+	}
+
+	/// Return the `.dbg.info` section for this debug information.
+	pub fn counts(&self) -> DebugCounts {
+		DebugCounts {
+			num_files: self.files.len() as _,
+			num_lines: self.lines.len() as _,
+			num_syms: self.symbols.len() as _,
+			num_arrays: self.symbols.iter()
+				.filter(move |symbol| !symbol.dims.is_empty())
+				.count() as _,
+		}
+	}
+
+	/// Write the debug sections into an SMX file.
+	///
+	/// If there are no files, lines or symbols, only `.dbg.natives` and
+	/// `.dbg.strings` are written, if they are non-empty.
+	pub fn write_into<E: ByteOrder>(&self, smx: &mut Smx<CString, Vec<u8>>) {
+		let mut put = move |name: &CStr, data: Vec<u8>| {
+			smx.sections.insert(name.to_owned(), data);
+		};
+		if !self.files.is_empty() || !self.lines.is_empty() || !self.symbols.is_empty() {
+			put(INFO, write_table::<E, _>(&[self.counts()]));
+			put(FILES, write_table::<E, _>(&self.files));
+			put(LINES, write_table::<E, _>(&self.lines));
+			let mut symbols = Vec::new();
+			for symbol in self.symbols.iter() {
+				let _ = symbol.write::<E>(&mut symbols);
+			}
+			put(SYMBOLS, symbols);
+		}
+		if !self.natives.is_empty() {
+			let mut natives = Vec::new();
+			let _ = natives.write_u32::<E>(self.natives.len() as _);
+			for native in self.natives.iter() {
+				let _ = native.write::<E>(&mut natives);
+			}
+			put(NATIVES, natives);
+		}
+		if !self.strings.is_empty() {
+			put(STRINGS, self.strings.blob().clone());
+		}
+	}
+
+	/// Return the name at an offset into `.dbg.strings`.
+	pub fn name(&self, offset: u32) -> Option<CString> {
+		self.strings.get_c_string(offset as _)
+	}
+
+	/// Return the name of the source file that a code offset belongs to.
+	pub fn file_at(&self, offset: usize) -> Option<CString> {
+		let idx = self.files.partition_point(move |file| file.address as usize <= offset);
+		self.name(self.files.get(idx.checked_sub(1)?)?.name)
+	}
+
+	/// Return the one-based source line that a code offset belongs to.
+	pub fn line_at(&self, offset: usize) -> Option<u32> {
+		let idx = self.lines.partition_point(move |line| line.address as usize <= offset);
+		Some(self.lines.get(idx.checked_sub(1)?)?.line + 1)
+	}
+
+	/// Return the function symbol whose scope contains a code offset.
+	pub fn function_at(&self, offset: usize) -> Option<&Symbol> {
+		self.symbols.iter()
+			.find(move |symbol| {
+				symbol.kind == SymbolKind::Function && symbol.in_scope(offset)
+			})
+	}
+
+	/// Return the code offsets and names of every function symbol.
+	pub fn function_names(&self) -> Vec<(usize, CString)> {
+		self.symbols.iter()
+			.filter(move |symbol| symbol.kind == SymbolKind::Function)
+			.filter_map(move |symbol| {
+				Some((symbol.code_start as usize, self.name(symbol.name)?))
+			})
+			.collect()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use byteorder::LittleEndian as Le;
+
+	#[test]
+	fn round_trip() -> IoResult<()> {
+		let mut info = DebugInfo::default();
+		let file = info.strings.insert(c"hello_world.sp") as u32;
+		let main = info.strings.insert(c"OnPluginStart") as u32;
+		let buffer = info.strings.insert(c"buffer") as u32;
+		let format = info.strings.insert(c"format") as u32;
+		let print = info.strings.insert(c"PrintToServer") as u32;
+		info.files.push(DebugFile { address: 0, name: file });
+		info.lines.push(DebugLine { address: 0, line: 11 });
+		info.lines.push(DebugLine { address: 8, line: 12 });
+		info.symbols.push(Symbol {
+			address: 0,
+			tag: 0,
+			code_start: 0,
+			code_end: 32,
+			kind: SymbolKind::Function,
+			scope: SymbolScope::Global,
+			dims: Vec::new(),
+			name: main,
+		});
+		info.symbols.push(Symbol {
+			address: -64,
+			tag: 0,
+			code_start: 4,
+			code_end: 28,
+			kind: SymbolKind::Array,
+			scope: SymbolScope::Local,
+			dims: vec![ArrayDim { tag: 0, size: 64 }],
+			name: buffer,
+		});
+		info.natives.push(DebugNative {
+			index: 0,
+			name: print,
+			tag: 0,
+			args: vec![NativeArg {
+				kind: SymbolKind::RefArray,
+				tag: 0,
+				dims: vec![ArrayDim { tag: 0, size: 0 }],
+				name: format,
+			}],
+		});
+
+		let mut smx = Smx::new();
+		info.write_into::<Le>(&mut smx);
+		let symbols_len = 2 * Symbol::SIZE + ArrayDim::SIZE;
+		assert_eq!(smx.section(SYMBOLS).unwrap().len(), symbols_len);
+		assert_eq!(info.counts().num_arrays, 1);
+		assert_eq!(DebugInfo::from_smx::<Le, _, _>(&smx)?, info);
+
+		assert_eq!(info.file_at(20).as_deref(), Some(c"hello_world.sp"));
+		assert_eq!(info.line_at(4), Some(12));
+		assert_eq!(info.line_at(8), Some(13));
+		assert_eq!(info.function_at(20).map(move |f| f.name), Some(main));
+		assert_eq!(info.function_names(), [(0, c"OnPluginStart".to_owned())]);
+		Ok(())
+	}
+}
@@ -0,0 +1,201 @@
+//! See [`Decoder`].
+
+use crate::{
+	vm_types::Cell,
+	Instruction,
+	OpcodeSet,
+};
+
+use std::{
+	collections::HashMap,
+	error::Error,
+	fmt,
+	io::{
+		Cursor,
+		Error as IoError,
+	}
+};
+
+/// Iterator that decodes a blob of code into [`Instruction`]s paired with
+/// their offsets into the blob.
+///
+/// If an instruction fails to decode, a [`DecodeError`] is yielded, and
+/// decoding continues from the cell following the erroneous opcode.
+/// To stop at the first error instead, collect the iterator into a
+/// [`Result`].
+///
+/// [`Instruction::Casetbl`]s that are reached through a `SWITCH` which was
+/// decoded earlier get their `switch` field filled in.
+#[derive(Debug, Clone)]
+pub struct Decoder<'a> {
+	code: &'a [u8],
+	offset: usize,
+	set: OpcodeSet,
+	switches: HashMap<Cell, Cell>,
+}
+
+impl<'a> Decoder<'a> {
+	/// Create a [`Decoder`] that starts at the beginning of `code`.
+	pub fn new(code: &'a [u8]) -> Self {
+		Self::at(code, 0)
+	}
+
+	/// Create a [`Decoder`] that starts at an arbitrary offset into `code`.
+	pub fn at(code: &'a [u8], offset: usize) -> Self {
+		Self {
+			code,
+			offset,
+			set: OpcodeSet::Current,
+			switches: HashMap::new(),
+		}
+	}
+
+	/// Set the [`OpcodeSet`] that is accepted by this decoder.
+	pub fn with_opcode_set(mut self, set: OpcodeSet) -> Self {
+		self.set = set;
+		self
+	}
+
+	/// Return the offset of the next instruction to be decoded.
+	pub const fn offset(&self) -> usize {
+		self.offset
+	}
+
+	/// Continue decoding from another offset.
+	pub fn seek(&mut self, offset: usize) {
+		self.offset = offset;
+	}
+
+	/// Return the code blob that is being decoded.
+	pub const fn code(&self) -> &'a [u8] {
+		self.code
+	}
+}
+
+impl Iterator for Decoder<'_> {
+	type Item = Result<(usize, Instruction), DecodeError>;
+	fn next(&mut self) -> Option<Self::Item> {
+		let offset = self.offset;
+		if offset >= self.code.len() {
+			return None
+		}
+
+		let mut r = Cursor::new(&self.code[offset..]);
+		match Instruction::read_with(&mut r, self.set) {
+			Ok(mut instruction) => {
+				self.offset += r.position() as usize;
+				match instruction {
+					Instruction::Switch { jump_1 } => {
+						self.switches.insert(jump_1, offset as _);
+					}
+					Instruction::Casetbl { ref mut switch, .. } => {
+						*switch = self.switches.get(&(offset as Cell)).copied();
+					}
+					_ => {}
+				}
+				Some(Ok((offset, instruction)))
+			}
+			Err(error) => {
+				self.offset = (offset + core::mem::size_of::<Cell>())
+					.min(self.code.len());
+				Some(Err(DecodeError {
+					offset,
+					error,
+				}))
+			}
+		}
+	}
+}
+
+/// Structure for an error that has occurred while decoding an instruction.
+#[derive(Debug)]
+pub struct DecodeError {
+	/// Offset of the instruction that failed to decode.
+	pub offset: usize,
+	/// Underlying I/O error.
+	pub error: IoError,
+}
+
+impl fmt::Display for DecodeError {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		write!(f, "at code offset 0x{:08x}: {}", self.offset, self.error)
+	}
+}
+
+impl Error for DecodeError {
+	fn source(&self) -> Option<&(dyn Error + 'static)> {
+		Some(&self.error)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::vm_types::write_cell;
+
+	fn assemble(instructions: &[Instruction]) -> Vec<u8> {
+		let mut code = Vec::new();
+		for instruction in instructions {
+			instruction.write_to(&mut code).unwrap();
+		}
+		code
+	}
+
+	#[test]
+	fn offsets() -> Result<(), DecodeError> {
+		let code = assemble(&[
+			Instruction::Proc,
+			Instruction::PushC { const_1: 1 },
+			Instruction::SysreqN { native: 0, n_args: 1 },
+			Instruction::Retn,
+		]);
+		let decoded: Vec<_> = Decoder::new(&code).collect::<Result<_, _>>()?;
+		let offsets: Vec<_> = decoded.iter().map(move |(o, ..)| *o).collect();
+		assert_eq!(offsets, [0, 4, 12, 24]);
+		Ok(())
+	}
+
+	#[test]
+	fn start_at_offset() -> Result<(), DecodeError> {
+		let code = assemble(&[
+			Instruction::Proc,
+			Instruction::ZeroPri,
+			Instruction::Retn,
+		]);
+		let decoded: Vec<_> = Decoder::at(&code, 4).collect::<Result<_, _>>()?;
+		assert_eq!(decoded, [(4, Instruction::ZeroPri), (8, Instruction::Retn)]);
+		Ok(())
+	}
+
+	#[test]
+	fn resync() {
+		let mut code = assemble(&[Instruction::Proc]);
+		write_cell(&mut code, 5).unwrap();
+		Instruction::Retn.write_to(&mut code).unwrap();
+		code.push(0);
+
+		let mut decoder = Decoder::new(&code);
+		assert_eq!(decoder.next().unwrap().unwrap(), (0, Instruction::Proc));
+		assert_eq!(decoder.next().unwrap().unwrap_err().offset, 4);
+		assert_eq!(decoder.next().unwrap().unwrap(), (8, Instruction::Retn));
+		assert_eq!(decoder.next().unwrap().unwrap_err().offset, 12);
+		assert!(decoder.next().is_none());
+	}
+
+	#[test]
+	fn links_case_tables() -> Result<(), DecodeError> {
+		let code = assemble(&[
+			Instruction::Switch { jump_1: 8 },
+			Instruction::Casetbl {
+				default: 0,
+				cases: Vec::new(),
+				switch: None,
+			},
+		]);
+		let decoded: Vec<_> = Decoder::new(&code).collect::<Result<_, _>>()?;
+		assert!(matches!(
+			decoded[1], (8, Instruction::Casetbl { switch: Some(0), .. })
+		));
+		Ok(())
+	}
+}
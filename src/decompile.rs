@@ -0,0 +1,3543 @@
+//! Decompilation of functions to SourcePawn.
+//!
+//! Each function is decompiled in three passes:
+//! - the instructions of every basic block are executed symbolically, while
+//!   tracking the expressions that `PRI`, `ALT` and the stack slots of the
+//!   frame hold, and turned into statements;
+//! - the control-flow graph is structured into `if`, `while`, `do`/`while`,
+//!   `for` and `switch` statements, and chains of conditional jumps into `&&`,
+//!   `||` and `?:`;
+//! - constants are rewritten as floats, bools, strings or functions wherever
+//!   the types of variables, arguments and return values call for it.
+//!
+//! Variables, arguments and natives are named and typed from RTTI if present,
+//! and otherwise from `.dbg.symbols` and `.dbg.natives`. Without debug
+//! information, variables get names such as `local_2` and `arg_0`, and string
+//! literals are only recognized in native calls. Instructions that cannot be
+//! decompiled are written as `#emit` directives, and control flow that cannot
+//! be structured as `goto`, which is not valid SourcePawn, but is readable.
+
+use crate::{
+	cfg::{
+		build_function,
+		flow,
+		Cfg,
+		EdgeKind,
+		Flow,
+	},
+	debug::{
+		ArrayDim,
+		SymbolKind,
+		SymbolScope,
+	},
+	decoder::DecodeError,
+	dot,
+	functions::{
+		discover_plugin,
+		Function,
+		FunctionMap,
+	},
+	optimize::{
+		push_operands,
+		PushKind,
+	},
+	plugin::Plugin,
+	rtti::{
+		Signature,
+		Type,
+		VarClass,
+	},
+	sections::{
+		self,
+		read_table,
+		Tag,
+	},
+	symbolize::Symbolizer,
+	vm_types::{
+		Cell,
+		CELL,
+		FIRST_ARG,
+	},
+	Instruction,
+	Smx,
+};
+
+use byteorder::ByteOrder;
+use core::ffi::CStr;
+use std::{
+	borrow::Borrow,
+	collections::{
+		BTreeMap,
+		BTreeSet,
+	},
+	error::Error,
+	fmt::{
+		self,
+		Write as _,
+	},
+	hash::Hash,
+	io::Error as IoError,
+};
+
+/// Flag of tag ids for function tags.
+const FUNCTION_TAG: u32 = 0x2000_0000;
+/// Mask of tag ids for the tag index.
+const TAG_INDEX: u32 = 0x01ff_ffff;
+
+/// Type of a variable, as it is written in a declaration.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct VarType {
+	/// Name of the base type, such as `int` or `Handle`.
+	pub name: String,
+	/// Array dimensions, outermost first, where `None` is an unspecified size.
+	pub dims: Vec<Option<u32>>,
+	/// `true` for arguments that are passed by reference.
+	pub by_ref: bool,
+	/// `true` for `const` arguments.
+	pub is_const: bool,
+	/// `true` for function types, whose values are function ids.
+	pub function: bool,
+}
+
+impl VarType {
+	/// Create a scalar type with a name.
+	pub fn new(name: impl Into<String>) -> Self {
+		Self {
+			name: name.into(),
+			dims: Vec::new(),
+			by_ref: false,
+			is_const: false,
+			function: false,
+		}
+	}
+
+	fn int() -> Self {
+		Self::new("int")
+	}
+
+	/// Return `true` for `float`.
+	pub fn is_float(&self) -> bool {
+		self.name == "float" && self.dims.is_empty()
+	}
+
+	/// Return `true` for `bool`.
+	pub fn is_bool(&self) -> bool {
+		self.name == "bool" && self.dims.is_empty()
+	}
+
+	/// Return `true` for `void`.
+	pub fn is_void(&self) -> bool {
+		self.name == "void"
+	}
+
+	/// Return the type of the elements of an array type.
+	pub fn element(&self) -> Self {
+		Self {
+			dims: self.dims.get(1..).unwrap_or_default().to_vec(),
+			by_ref: false,
+			..self.clone()
+		}
+	}
+
+	/// Return the size of a variable of this type, in cells, if it is known.
+	fn cells(&self) -> Option<Cell> {
+		let mut cells: Cell = 1;
+		for (n, dim) in self.dims.iter().enumerate() {
+			let size = Cell::try_from((*dim)?).ok()?;
+			cells = cells.checked_mul(if n + 1 == self.dims.len() && self.name == "char" {
+				(size + CELL - 1) / CELL
+			} else {
+				size
+			})?;
+		}
+		Some(cells)
+	}
+
+	/// Format this type without a variable name, such as `const char[]`.
+	fn name_only(&self) -> String {
+		let mut out = String::new();
+		if self.is_const {
+			out.push_str("const ");
+		}
+		out.push_str(&self.name);
+		for _ in self.dims.iter() {
+			out.push_str("[]");
+		}
+		if self.by_ref {
+			out.push('&');
+		}
+		out
+	}
+
+	/// Format a declaration of a variable of this type, such as
+	/// `char name[64]`.
+	fn declare(&self, name: &str) -> String {
+		let mut out = String::new();
+		if self.is_const {
+			out.push_str("const ");
+		}
+		out.push_str(&self.name);
+		out.push_str(if self.by_ref { "& " } else { " " });
+		out.push_str(name);
+		for dim in self.dims.iter() {
+			match dim {
+				Some(size) => { let _ = write!(out, "[{size}]"); }
+				None => out.push_str("[]"),
+			}
+		}
+		out
+	}
+}
+
+/// Storage of a variable.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum VarKind {
+	/// Global or static variable, with its data address.
+	Global(Cell),
+	/// Local variable, with its frame offset.
+	Local(Cell),
+	/// Argument, with its index.
+	Arg(usize),
+	/// Temporary that holds an intermediate value.
+	Temp,
+}
+
+/// Variable of a decompiled function.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Variable {
+	pub name: String,
+	pub ty: VarType,
+	pub kind: VarKind,
+}
+
+/// Register of the virtual machine.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Register {
+	Pri,
+	Alt,
+	/// Top of the stack.
+	Stack,
+}
+
+/// Unary operator.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum UnaryOp {
+	/// `!`
+	Not,
+	/// `-`
+	Neg,
+	/// `~`
+	Invert,
+}
+
+impl UnaryOp {
+	fn symbol(self) -> &'static str {
+		match self {
+			Self::Not => "!",
+			Self::Neg => "-",
+			Self::Invert => "~",
+		}
+	}
+}
+
+/// Binary operator.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum BinaryOp {
+	Mul,
+	Div,
+	Mod,
+	Add,
+	Sub,
+	Shl,
+	/// `>>`, which is an arithmetic shift.
+	Shr,
+	/// `>>>`, which is a logical shift.
+	Ushr,
+	Lt,
+	Le,
+	Gt,
+	Ge,
+	Eq,
+	Ne,
+	BitAnd,
+	BitXor,
+	BitOr,
+	And,
+	Or,
+}
+
+impl BinaryOp {
+	fn symbol(self) -> &'static str {
+		match self {
+			Self::Mul => "*",
+			Self::Div => "/",
+			Self::Mod => "%",
+			Self::Add => "+",
+			Self::Sub => "-",
+			Self::Shl => "<<",
+			Self::Shr => ">>",
+			Self::Ushr => ">>>",
+			Self::Lt => "<",
+			Self::Le => "<=",
+			Self::Gt => ">",
+			Self::Ge => ">=",
+			Self::Eq => "==",
+			Self::Ne => "!=",
+			Self::BitAnd => "&",
+			Self::BitXor => "^",
+			Self::BitOr => "|",
+			Self::And => "&&",
+			Self::Or => "||",
+		}
+	}
+
+	fn precedence(self) -> u8 {
+		match self {
+			Self::Mul | Self::Div | Self::Mod => 11,
+			Self::Add | Self::Sub => 10,
+			Self::Shl | Self::Shr | Self::Ushr => 9,
+			Self::Lt | Self::Le | Self::Gt | Self::Ge => 8,
+			Self::Eq | Self::Ne => 7,
+			Self::BitAnd => 6,
+			Self::BitXor => 5,
+			Self::BitOr => 4,
+			Self::And => 3,
+			Self::Or => 2,
+		}
+	}
+
+	/// Return the comparison that holds exactly when this one does not.
+	fn inverse(self) -> Option<Self> {
+		Some(match self {
+			Self::Lt => Self::Ge,
+			Self::Le => Self::Gt,
+			Self::Gt => Self::Le,
+			Self::Ge => Self::Lt,
+			Self::Eq => Self::Ne,
+			Self::Ne => Self::Eq,
+			_ => return None,
+		})
+	}
+
+	/// Return `true` for operators whose result is a bool.
+	fn is_boolean(self) -> bool {
+		self.inverse().is_some() || matches!(self, Self::And | Self::Or)
+	}
+
+	/// Return `true` for operators that have a compound assignment form.
+	fn is_compound(self) -> bool {
+		!self.is_boolean()
+	}
+}
+
+/// Function that is called.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Callee {
+	/// Function of the plugin, with its code offset.
+	Function {
+		start: usize,
+		name: String,
+	},
+	/// Native, with its index into `.natives`.
+	Native {
+		index: usize,
+		name: String,
+	},
+	/// Function that an instruction implements, such as `RoundToFloor`.
+	Builtin(&'static str),
+}
+
+impl Callee {
+	/// Return the name of the called function.
+	pub fn name(&self) -> &str {
+		match self {
+			Self::Function { name, .. } | Self::Native { name, .. } => name,
+			Self::Builtin(name) => name,
+		}
+	}
+}
+
+/// Decompiled expression.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Expr {
+	Int(Cell),
+	/// Float, as its bits.
+	Float(Cell),
+	Bool(bool),
+	String(String),
+	/// Function id, as the name of the function.
+	Function(String),
+	/// Index into [`DecompiledFunction::vars`].
+	Var(usize),
+	/// Array element.
+	Index(Box<Expr>, Box<Expr>),
+	/// Value at an address, such as an argument passed by reference.
+	Deref(Box<Expr>),
+	/// Address of a variable or an array element.
+	Address(Box<Expr>),
+	Unary(UnaryOp, Box<Expr>),
+	Binary(BinaryOp, Box<Expr>, Box<Expr>),
+	Ternary(Box<Expr>, Box<Expr>, Box<Expr>),
+	Call(Callee, Vec<Expr>),
+	/// Dynamic array with dimensions.
+	NewArray(Vec<Expr>),
+	/// Unknown value of a register.
+	Register(Register),
+}
+
+impl Expr {
+	fn binary(op: BinaryOp, a: Self, b: Self) -> Self {
+		Self::Binary(op, Box::new(a), Box::new(b))
+	}
+
+	fn index(base: Self, index: Self) -> Self {
+		Self::Index(Box::new(base), Box::new(index))
+	}
+
+	/// Return `true` if this expression calls a function.
+	fn has_call(&self) -> bool {
+		let mut found = false;
+		self.visit(&mut |expr| found |= matches!(expr, Self::Call(..)));
+		found
+	}
+
+	/// Call `f` on this expression and on every expression it contains.
+	fn visit(&self, f: &mut impl FnMut(&Self)) {
+		f(self);
+		match self {
+			Self::Index(a, b) | Self::Binary(_, a, b) => {
+				a.visit(f);
+				b.visit(f);
+			}
+			Self::Deref(a) | Self::Address(a) | Self::Unary(_, a) => a.visit(f),
+			Self::Ternary(a, b, c) => {
+				a.visit(f);
+				b.visit(f);
+				c.visit(f);
+			}
+			Self::Call(_, args) | Self::NewArray(args) => {
+				args.iter().for_each(|arg| arg.visit(f));
+			}
+			_ => {}
+		}
+	}
+
+	/// Return `true` if evaluating this expression may read a variable, or
+	/// any memory if `var` is `None`.
+	fn reads(&self, var: Option<usize>) -> bool {
+		match self {
+			Self::Var(v) => var.is_none_or(move |var| var == *v),
+			Self::Address(inner) => match &**inner {
+				Self::Var(_) => false,
+				Self::Index(base, index) => {
+					Self::Address(base.clone()).reads(var) || index.reads(var)
+				}
+				other => other.reads(var),
+			},
+			Self::Deref(inner) => var.is_none() || inner.reads(var),
+			Self::Index(a, b) | Self::Binary(_, a, b) => a.reads(var) || b.reads(var),
+			Self::Unary(_, a) => a.reads(var),
+			Self::Ternary(a, b, c) => a.reads(var) || b.reads(var) || c.reads(var),
+			Self::Call(..) => true,
+			Self::NewArray(dims) => dims.iter().any(move |dim| dim.reads(var)),
+			_ => false,
+		}
+	}
+
+	/// Return the variable that an lvalue is a part of.
+	fn root(&self) -> Option<usize> {
+		match self {
+			Self::Var(v) => Some(*v),
+			Self::Index(base, _) | Self::Address(base) | Self::Deref(base) => base.root(),
+			_ => None,
+		}
+	}
+
+	/// Return the array that an address designates.
+	fn designator(self) -> Self {
+		match self {
+			Self::Address(inner) => *inner,
+			other => other,
+		}
+	}
+}
+
+/// Return the negation of a condition.
+fn negate(expr: Expr) -> Expr {
+	match expr {
+		Expr::Unary(UnaryOp::Not, inner) => *inner,
+		Expr::Bool(value) => Expr::Bool(!value),
+		Expr::Binary(BinaryOp::And, a, b) => {
+			Expr::binary(BinaryOp::Or, negate(*a), negate(*b))
+		}
+		Expr::Binary(BinaryOp::Or, a, b) => {
+			Expr::binary(BinaryOp::And, negate(*a), negate(*b))
+		}
+		Expr::Binary(op, a, b) => match op.inverse() {
+			Some(inverse) => Expr::Binary(inverse, a, b),
+			None => Expr::Unary(UnaryOp::Not, Box::new(Expr::Binary(op, a, b))),
+		},
+		other => Expr::Unary(UnaryOp::Not, Box::new(other)),
+	}
+}
+
+/// Return `condition ? a : b`, simplified for bools.
+fn ternary(condition: Expr, a: Expr, b: Expr) -> Expr {
+	match (a, b) {
+		(Expr::Int(1), Expr::Int(0)) => condition,
+		(Expr::Int(0), Expr::Int(1)) => negate(condition),
+		(a, b) => Expr::Ternary(Box::new(condition), Box::new(a), Box::new(b)),
+	}
+}
+
+/// Return the index of the element at a byte offset into an array.
+fn element_index(offset: Expr, byte: bool) -> Expr {
+	if byte {
+		return offset
+	}
+	match offset {
+		Expr::Int(value) if value % CELL == 0 => Expr::Int(value / CELL),
+		Expr::Binary(BinaryOp::Shl, index, shift) if *shift == Expr::Int(2) => *index,
+		Expr::Binary(BinaryOp::Mul, index, size) if *size == Expr::Int(CELL) => *index,
+		other => Expr::binary(BinaryOp::Div, other, Expr::Int(CELL)),
+	}
+}
+
+/// Return the lvalue at an address, which holds a cell, or a character if
+/// `byte` is `true`.
+fn lvalue_at(address: Expr, byte: bool) -> Expr {
+	match address {
+		Expr::Address(inner) if !byte || matches!(*inner, Expr::Index(..)) => *inner,
+		Expr::Address(inner) => Expr::index(*inner, Expr::Int(0)),
+		Expr::Binary(BinaryOp::Add, a, b) => {
+			let (base, offset) = match (*a, *b) {
+				(base @ Expr::Address(_), offset) | (offset, base @ Expr::Address(_)) =>
+					(base, offset),
+				(base, offset) => (base, offset),
+			};
+			Expr::index(base.designator(), element_index(offset, byte))
+		}
+		other if byte => Expr::index(other, Expr::Int(0)),
+		other => Expr::Deref(Box::new(other)),
+	}
+}
+
+/// Decompiled statement.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Stmt {
+	/// Declaration of a variable, with an optional initial value.
+	Decl(usize, Option<Expr>),
+	Assign(Expr, Expr),
+	Inc(Expr),
+	Dec(Expr),
+	Expr(Expr),
+	/// `if`, with its `then` and `else` branches.
+	If(Expr, Vec<Stmt>, Vec<Stmt>),
+	While(Expr, Vec<Stmt>),
+	DoWhile(Vec<Stmt>, Expr),
+	/// `for`, with its initializer, condition, step and body.
+	For(Box<Stmt>, Expr, Box<Stmt>, Vec<Stmt>),
+	/// `switch`, with the values and body of each case, and the default case.
+	Switch(Expr, Vec<(Vec<Expr>, Vec<Stmt>)>, Option<Vec<Stmt>>),
+	Break,
+	Continue,
+	Return(Option<Expr>),
+	/// Jump to the label at a code offset.
+	Goto(usize),
+	/// Label at a code offset.
+	Label(usize),
+	/// Instruction that could not be decompiled.
+	Emit(Instruction),
+}
+
+impl Stmt {
+	/// Call `f` on every expression of this statement and of the statements
+	/// it contains.
+	fn visit(&self, f: &mut impl FnMut(&Expr)) {
+		let mut expr = |expr: &Expr| expr.visit(f);
+		match self {
+			Self::Decl(_, Some(e)) | Self::Inc(e) | Self::Dec(e) | Self::Expr(e)
+				| Self::Return(Some(e)) => expr(e),
+			Self::Assign(a, b) => {
+				expr(a);
+				expr(b);
+			}
+			Self::If(c, a, b) => {
+				expr(c);
+				a.iter().chain(b.iter()).for_each(|stmt| stmt.visit(f));
+			}
+			Self::While(c, body) | Self::DoWhile(body, c) => {
+				expr(c);
+				body.iter().for_each(|stmt| stmt.visit(f));
+			}
+			Self::For(init, c, step, body) => {
+				expr(c);
+				init.visit(f);
+				step.visit(f);
+				body.iter().for_each(|stmt| stmt.visit(f));
+			}
+			Self::Switch(value, cases, default) => {
+				expr(value);
+				let bodies = cases.iter().flat_map(|(_, body)| body);
+				for stmt in bodies.chain(default.iter().flatten()) {
+					stmt.visit(f);
+				}
+			}
+			_ => {}
+		}
+	}
+
+	fn reads(&self, var: usize) -> bool {
+		let mut found = false;
+		self.visit(&mut |expr| found |= *expr == Expr::Var(var));
+		found
+	}
+}
+
+/// Return `true` if control never continues after the last statement.
+fn ends_with_jump(stmts: &[Stmt]) -> bool {
+	matches!(
+		stmts.last(),
+		Some(Stmt::Break | Stmt::Continue | Stmt::Return(_) | Stmt::Goto(_))
+	)
+}
+
+/// Return `true` if statements `continue` the innermost loop around them.
+fn has_continue(stmts: &[Stmt]) -> bool {
+	stmts.iter().any(move |stmt| match stmt {
+		Stmt::Continue => true,
+		Stmt::If(_, a, b) => has_continue(a) || has_continue(b),
+		Stmt::Switch(_, cases, default) => {
+			cases.iter().any(move |(_, body)| has_continue(body))
+				|| default.as_deref().is_some_and(has_continue)
+		}
+		_ => false,
+	})
+}
+
+/// Decompiled function.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DecompiledFunction {
+	pub name: String,
+	/// Code offset of the function.
+	pub start: usize,
+	/// `true` for public functions.
+	pub public: bool,
+	/// Return type, which is `void` for functions without a return value.
+	pub ret: VarType,
+	/// Indices of the arguments into [`Self::vars`].
+	pub args: Vec<usize>,
+	/// Every variable that is referenced by the body.
+	pub vars: Vec<Variable>,
+	pub body: Vec<Stmt>,
+}
+
+/// Precedence of `?:`.
+const TERNARY: u8 = 1;
+/// Precedence of unary operators.
+const UNARY: u8 = 12;
+/// Precedence of calls and array indexing.
+const POSTFIX: u8 = 13;
+
+/// Format a float like SourcePawn, always with a decimal point.
+fn format_float(bits: Cell) -> String {
+	let value = f32::from_bits(bits as u32);
+	if !value.is_finite() {
+		return format!("view_as<float>({bits})")
+	}
+	let text = format!("{value:?}");
+	match text.find('e') {
+		Some(e) if !text[..e].contains('.') => format!("{}.0{}", &text[..e], &text[e..]),
+		_ => text,
+	}
+}
+
+/// Quote a string literal.
+fn quote(value: &str) -> String {
+	let mut out = String::from("\"");
+	for c in value.chars() {
+		match c {
+			'"' => out.push_str("\\\""),
+			'\\' => out.push_str("\\\\"),
+			'\n' => out.push_str("\\n"),
+			'\r' => out.push_str("\\r"),
+			'\t' => out.push_str("\\t"),
+			c if c.is_control() => { let _ = write!(out, "\\x{:02x};", c as u32); }
+			c => out.push(c),
+		}
+	}
+	out.push('"');
+	out
+}
+
+fn indent(out: &mut String, depth: usize) {
+	out.extend(std::iter::repeat_n('\t', depth));
+}
+
+impl DecompiledFunction {
+	/// Format an expression, in parentheses if its precedence is lower than
+	/// `precedence`.
+	fn expr(&self, expr: &Expr, precedence: u8) -> String {
+		let (text, own) = match expr {
+			Expr::Int(value) => {
+				(value.to_string(), if *value < 0 { UNARY } else { POSTFIX })
+			}
+			Expr::Float(bits) => {
+				let text = format_float(*bits);
+				let own = if text.starts_with('-') { UNARY } else { POSTFIX };
+				(text, own)
+			}
+			Expr::Bool(value) => (value.to_string(), POSTFIX),
+			Expr::String(value) => (quote(value), POSTFIX),
+			Expr::Function(name) => (name.clone(), POSTFIX),
+			Expr::Var(var) => (self.var_name(*var), POSTFIX),
+			Expr::Index(base, index) => {
+				let (base, index) = (self.expr(base, POSTFIX), self.expr(index, 0));
+				let text = format!("{base}[{index}]");
+				(text, POSTFIX)
+			}
+			Expr::Deref(inner) => match **inner {
+				Expr::Var(var)
+					if self.vars.get(var).is_none_or(|v| v.ty.dims.is_empty()) =>
+					(self.var_name(var), POSTFIX),
+				_ => (format!("{}[0]", self.expr(inner, POSTFIX)), POSTFIX),
+			},
+			Expr::Address(inner) => return self.expr(inner, precedence),
+			Expr::Unary(op, inner) => {
+				let operand = self.expr(inner, UNARY);
+				let operand = if operand.starts_with(op.symbol()) {
+					format!("({operand})")
+				} else {
+					operand
+				};
+				(format!("{}{operand}", op.symbol()), UNARY)
+			}
+			Expr::Binary(op, a, b) => {
+				let own = op.precedence();
+				let text = format!(
+					"{} {} {}",
+					self.expr(a, own),
+					op.symbol(),
+					self.expr(b, own + 1)
+				);
+				(text, own)
+			}
+			Expr::Ternary(condition, a, b) => {
+				let text = format!(
+					"{} ? {} : {}",
+					self.expr(condition, TERNARY + 1),
+					self.expr(a, TERNARY),
+					self.expr(b, TERNARY)
+				);
+				(text, TERNARY)
+			}
+			Expr::Call(callee, args) => {
+				let args: Vec<String> = args.iter()
+					.map(|arg| self.expr(arg, 0))
+					.collect();
+				(format!("{}({})", callee.name(), args.join(", ")), POSTFIX)
+			}
+			Expr::NewArray(dims) => {
+				let mut text = String::from("new int");
+				for dim in dims.iter() {
+					let _ = write!(text, "[{}]", self.expr(dim, 0));
+				}
+				(text, UNARY)
+			}
+			Expr::Register(Register::Pri) => ("pri".into(), POSTFIX),
+			Expr::Register(Register::Alt) => ("alt".into(), POSTFIX),
+			Expr::Register(Register::Stack) => ("stk".into(), POSTFIX),
+		};
+		if own < precedence {
+			format!("({text})")
+		} else {
+			text
+		}
+	}
+
+	fn var_name(&self, var: usize) -> String {
+		self.vars.get(var)
+			.map_or_else(move || format!("var_{var}"), move |v| v.name.clone())
+	}
+
+	/// Format a statement that fits on one line, without its `;`.
+	fn simple(&self, stmt: &Stmt) -> String {
+		match stmt {
+			Stmt::Decl(var, init) => {
+				let mut text = match self.vars.get(*var) {
+					Some(v) => v.ty.declare(&v.name),
+					None => VarType::int().declare(&self.var_name(*var)),
+				};
+				if let Some(init) = init {
+					let _ = write!(text, " = {}", self.expr(init, 0));
+				}
+				text
+			}
+			Stmt::Assign(lvalue, value) => match value {
+				Expr::Binary(op, a, b) if op.is_compound() && **a == *lvalue => {
+					let (lvalue, b) = (self.expr(lvalue, 0), self.expr(b, 0));
+					format!("{lvalue} {}= {b}", op.symbol())
+				}
+				_ => format!("{} = {}", self.expr(lvalue, 0), self.expr(value, 0)),
+			},
+			Stmt::Inc(lvalue) => format!("{}++", self.expr(lvalue, POSTFIX)),
+			Stmt::Dec(lvalue) => format!("{}--", self.expr(lvalue, POSTFIX)),
+			Stmt::Expr(expr) => self.expr(expr, 0),
+			Stmt::Break => "break".into(),
+			Stmt::Continue => "continue".into(),
+			Stmt::Return(None) => "return".into(),
+			Stmt::Return(Some(value)) => format!("return {}", self.expr(value, 0)),
+			Stmt::Goto(offset) => format!("goto label_{offset:08x}"),
+			Stmt::Emit(instruction) => format!("#emit {instruction}"),
+			_ => String::new(),
+		}
+	}
+
+	fn write_block(&self, out: &mut String, stmts: &[Stmt], depth: usize) {
+		indent(out, depth);
+		out.push_str("{\n");
+		for stmt in stmts.iter() {
+			self.write_stmt(out, stmt, depth + 1);
+		}
+		indent(out, depth);
+		out.push_str("}\n");
+	}
+
+	/// Write an `if` statement, whose indentation is already written.
+	fn write_if(&self, out: &mut String, stmt: &Stmt, depth: usize) {
+		let Stmt::If(condition, then, otherwise) = stmt else {
+			return
+		};
+		let _ = writeln!(out, "if ({})", self.expr(condition, 0));
+		self.write_block(out, then, depth);
+		match otherwise.as_slice() {
+			[] => {}
+			[nested @ Stmt::If(..)] => {
+				indent(out, depth);
+				out.push_str("else ");
+				self.write_if(out, nested, depth);
+			}
+			_ => {
+				indent(out, depth);
+				out.push_str("else\n");
+				self.write_block(out, otherwise, depth);
+			}
+		}
+	}
+
+	fn write_stmt(&self, out: &mut String, stmt: &Stmt, depth: usize) {
+		match stmt {
+			Stmt::If(..) => {
+				indent(out, depth);
+				self.write_if(out, stmt, depth);
+			}
+			Stmt::While(condition, body) => {
+				indent(out, depth);
+				let _ = writeln!(out, "while ({})", self.expr(condition, 0));
+				self.write_block(out, body, depth);
+			}
+			Stmt::DoWhile(body, condition) => {
+				indent(out, depth);
+				out.push_str("do\n");
+				self.write_block(out, body, depth);
+				indent(out, depth);
+				let _ = writeln!(out, "while ({});", self.expr(condition, 0));
+			}
+			Stmt::For(init, condition, step, body) => {
+				indent(out, depth);
+				let _ = writeln!(
+					out,
+					"for ({}; {}; {})",
+					self.simple(init),
+					self.expr(condition, 0),
+					self.simple(step)
+				);
+				self.write_block(out, body, depth);
+			}
+			Stmt::Switch(value, cases, default) => {
+				indent(out, depth);
+				let _ = writeln!(out, "switch ({})", self.expr(value, 0));
+				indent(out, depth);
+				out.push_str("{\n");
+				for (values, body) in cases.iter() {
+					let values: Vec<String> = values.iter()
+						.map(|v| self.expr(v, 0))
+						.collect();
+					indent(out, depth + 1);
+					let _ = writeln!(out, "case {}:", values.join(", "));
+					self.write_block(out, body, depth + 1);
+				}
+				if let Some(body) = default {
+					indent(out, depth + 1);
+					out.push_str("default:\n");
+					self.write_block(out, body, depth + 1);
+				}
+				indent(out, depth);
+				out.push_str("}\n");
+			}
+			Stmt::Label(offset) => {
+				indent(out, depth.saturating_sub(1));
+				let _ = writeln!(out, "label_{offset:08x}:");
+			}
+			Stmt::Emit(_) => {
+				indent(out, depth);
+				let _ = writeln!(out, "{}", self.simple(stmt));
+			}
+			_ => {
+				indent(out, depth);
+				let _ = writeln!(out, "{};", self.simple(stmt));
+			}
+		}
+	}
+}
+
+/// Write the function as SourcePawn.
+impl fmt::Display for DecompiledFunction {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		let args: Vec<String> = self.args.iter()
+			.filter_map(|arg| self.vars.get(*arg))
+			.map(move |arg| arg.ty.declare(&arg.name))
+			.collect();
+		let public = if self.public { "public " } else { "" };
+		let ret = self.ret.name_only();
+		writeln!(f, "{public}{ret} {}({})", self.name, args.join(", "))?;
+		let mut out = String::new();
+		self.write_block(&mut out, &self.body, 0);
+		f.write_str(&out)
+	}
+}
+
+/// Structure for an error that has occurred while decompiling a plugin.
+#[derive(Debug)]
+pub enum DecompileError {
+	/// Sections could not be parsed.
+	Io(IoError),
+	/// Code could not be decoded.
+	Decode(DecodeError),
+}
+
+impl fmt::Display for DecompileError {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			Self::Io(e) => write!(f, "io error {e}"),
+			Self::Decode(e) => write!(f, "decode error {e}"),
+		}
+	}
+}
+
+impl Error for DecompileError {}
+
+impl From<IoError> for DecompileError {
+	fn from(value: IoError) -> Self {
+		Self::Io(value)
+	}
+}
+
+impl From<DecodeError> for DecompileError {
+	fn from(value: DecodeError) -> Self {
+		Self::Decode(value)
+	}
+}
+
+/// Local variable or argument that is described by debug information.
+#[derive(Debug, Clone)]
+struct DebugLocal {
+	offset: Cell,
+	start: usize,
+	end: usize,
+	name: String,
+	ty: VarType,
+}
+
+/// Types of the return value and the arguments of a function.
+#[derive(Default, Debug, Clone)]
+struct Sig {
+	ret: Option<VarType>,
+	args: Vec<VarType>,
+	/// Type of the variadic arguments.
+	variadic: Option<VarType>,
+}
+
+impl Sig {
+	fn arg(&self, index: usize) -> Option<&VarType> {
+		self.args.get(index).or(self.variadic.as_ref())
+	}
+}
+
+/// Decompiler of the functions of a plugin.
+#[derive(Debug, Clone)]
+pub struct Decompiler {
+	plugin: Plugin,
+	symbols: Symbolizer,
+	tags: Vec<Tag>,
+	map: FunctionMap,
+	/// Names and types of global variables, by address.
+	globals: BTreeMap<Cell, (String, VarType)>,
+}
+
+impl Decompiler {
+	/// Read the plugin and the debug information of an SMX file.
+	pub fn from_smx<E, Name, Sect>(smx: &Smx<Name, Sect>) -> Result<Self, DecompileError>
+	where
+		E: ByteOrder,
+		Name: Borrow<CStr> + Eq + Hash,
+		Sect: AsRef<[u8]>,
+	{
+		let tags = smx.section(sections::TAGS)
+			.map(move |section| read_table::<E, _>(section.as_ref()))
+			.transpose()?
+			.unwrap_or_default();
+		Ok(Self::new(
+			Plugin::from_smx::<E, _, _>(smx)?,
+			Symbolizer::from_smx::<E, _, _>(smx)?,
+			tags,
+		)?)
+	}
+
+	/// Create a decompiler for a plugin with its debug information and the
+	/// entries of its `.tags` section.
+	pub fn new(
+		plugin: Plugin,
+		symbols: Symbolizer,
+		tags: Vec<Tag>,
+	) -> Result<Self, DecodeError> {
+		let map = discover_plugin(&plugin, Some(&symbols.debug))?;
+		let mut decompiler = Self {
+			plugin,
+			symbols,
+			tags,
+			map,
+			globals: BTreeMap::new(),
+		};
+		decompiler.globals = decompiler.read_globals();
+		Ok(decompiler)
+	}
+
+	/// Return the functions of the plugin.
+	pub fn functions(&self) -> &[Function] {
+		&self.map.functions
+	}
+
+	/// Decompile every global variable and function of the plugin.
+	pub fn decompile(&self) -> String {
+		let mut out = String::new();
+		for (name, ty) in self.globals.values() {
+			let _ = writeln!(out, "{};", ty.declare(name));
+		}
+		for function in self.map.functions.iter() {
+			if !out.is_empty() {
+				out.push('\n');
+			}
+			let _ = write!(out, "{}", self.decompile_function(function));
+		}
+		out
+	}
+
+	/// Decompile the function that starts at a code offset.
+	pub fn function(&self, start: usize) -> Option<DecompiledFunction> {
+		Some(self.decompile_function(self.map.at(start)?))
+	}
+
+	fn decompile_function(&self, function: &Function) -> DecompiledFunction {
+		let cfg = build_function(function);
+		let graph = Graph::new(&cfg);
+		let mut labels = BTreeSet::new();
+		loop {
+			let mut context = Context::new(self, function, &cfg, &graph, labels);
+			let body = context.run();
+			// Labels are only known once every `goto` is, so try again with them.
+			if context.gotos.is_subset(&context.labels) {
+				return context.finish(body)
+			}
+			labels = context.gotos.union(&context.labels).copied().collect();
+		}
+	}
+
+	fn name(&self, offset: u32) -> Option<String> {
+		let name = self.symbols.names.get_c_string(offset as _)?;
+		Some(name.to_string_lossy().into_owned())
+	}
+
+	fn debug_name(&self, offset: u32) -> Option<String> {
+		Some(self.symbols.debug.name(offset)?.to_string_lossy().into_owned())
+	}
+
+	/// Return the display name of the function at a code offset.
+	fn function_name(&self, start: usize) -> String {
+		match self.map.at(start) {
+			Some(function) if function.name.is_some() => dot::function_name(function),
+			_ => self.symbols.function_at(start)
+				.unwrap_or_else(move || format!("sub_{start:08x}")),
+		}
+	}
+
+	/// Return the name of the public function with a function id.
+	fn function_id_name(&self, id: Cell) -> Option<String> {
+		if id & 1 == 0 {
+			return None
+		}
+		let name = self.plugin.public_name(usize::try_from(id >> 1).ok()?)?;
+		Some(name.to_string_lossy().into_owned())
+	}
+
+	fn native_name(&self, index: usize) -> String {
+		self.plugin.native_name(index)
+			.map(|name| name.to_string_lossy().into_owned())
+			.unwrap_or_else(move || format!("native_{index}"))
+	}
+
+	/// Convert an RTTI type.
+	fn rtti_type(&self, ty: &Type) -> VarType {
+		let rtti = &self.symbols.rtti;
+		match ty {
+			Type::FixedArray { size, element } => {
+				let mut ty = self.rtti_type(element);
+				ty.dims.insert(0, Some(*size));
+				ty
+			}
+			Type::Array(element) => {
+				let mut ty = self.rtti_type(element);
+				ty.dims.insert(0, None);
+				ty
+			}
+			Type::ByRef(inner) | Type::Variadic(inner) => VarType {
+				by_ref: true,
+				..self.rtti_type(inner)
+			},
+			Type::Const(inner) => VarType {
+				is_const: true,
+				..self.rtti_type(inner)
+			},
+			Type::TopFunction | Type::Function(_) => VarType {
+				function: true,
+				..VarType::new("Function")
+			},
+			Type::Typedef(_) | Type::Typeset(_) => VarType {
+				function: true,
+				..VarType::new(rtti.format_type(ty, &self.symbols.names))
+			},
+			ty => VarType::new(rtti.format_type(ty, &self.symbols.names)),
+		}
+	}
+
+	fn rtti_type_id(&self, type_id: u32) -> VarType {
+		self.symbols.rtti.type_of(type_id)
+			.map_or_else(move |_| VarType::int(), move |ty| self.rtti_type(&ty))
+	}
+
+	fn rtti_sig(&self, signature: &Signature) -> Sig {
+		let mut sig = Sig {
+			ret: Some(self.rtti_type(&signature.ret)),
+			..Default::default()
+		};
+		for arg in signature.args.iter() {
+			match arg {
+				Type::Variadic(_) => sig.variadic = Some(self.rtti_type(arg)),
+				arg => sig.args.push(self.rtti_type(arg)),
+			}
+		}
+		sig
+	}
+
+	/// Convert the tag of a `.dbg.symbols` entry.
+	fn tag_type(&self, tag: i16, kind: SymbolKind, dims: &[ArrayDim]) -> VarType {
+		let tag = u32::from(tag as u16);
+		let entry = self.tags.iter().find(move |t| t.id & TAG_INDEX == tag);
+		let name = entry.and_then(move |t| self.plugin.name(t.name))
+			.map(move |name| name.to_string_lossy().into_owned());
+		let mut ty = VarType::new(match name.as_deref() {
+			None | Some("_") => "int",
+			Some("Float") => "float",
+			Some("String") => "char",
+			Some(name) => name,
+		});
+		ty.function = entry.is_some_and(move |t| t.id & FUNCTION_TAG != 0);
+		ty.dims = dims.iter()
+			.map(move |dim| (dim.size != 0).then_some(dim.size))
+			.collect();
+		match kind {
+			SymbolKind::Reference | SymbolKind::Varargs => ty.by_ref = true,
+			SymbolKind::Array | SymbolKind::RefArray if ty.dims.is_empty() => {
+				ty.dims.push(None);
+			}
+			_ => {}
+		}
+		ty
+	}
+
+	fn read_globals(&self) -> BTreeMap<Cell, (String, VarType)> {
+		let rtti = &self.symbols.rtti;
+		let mut globals = BTreeMap::new();
+		if !rtti.globals.is_empty() {
+			let statics = rtti.locals.iter()
+				.filter(move |var| var.class() == VarClass::Static);
+			for var in rtti.globals.iter().chain(statics) {
+				let name = self.name(var.name)
+					.unwrap_or_else(|| format!("g_{}", var.address));
+				globals.insert(var.address, (name, self.rtti_type_id(var.type_id)));
+			}
+			return globals
+		}
+		for symbol in self.symbols.debug.symbols.iter() {
+			if !matches!(symbol.scope, SymbolScope::Global | SymbolScope::Static)
+				|| symbol.kind == SymbolKind::Function
+			{
+				continue
+			}
+			let Some(name) = self.debug_name(symbol.name) else {
+				continue
+			};
+			let ty = self.tag_type(symbol.tag, symbol.kind, &symbol.dims);
+			globals.insert(symbol.address, (name, ty));
+		}
+		globals
+	}
+
+	/// Return the debug information of the locals and arguments of a
+	/// function.
+	fn debug_locals(&self, function: &Function) -> Vec<DebugLocal> {
+		let rtti = &self.symbols.rtti;
+		let method = rtti.methods.iter()
+			.position(|m| m.pcode_start as usize == function.start);
+		if let Some(method) = method {
+			let debug = rtti.debug_methods.iter()
+				.position(|m| m.method_index as usize == method);
+			return debug.map(|index| rtti.method_locals(index)).unwrap_or_default()
+				.iter()
+				.filter(move |var| matches!(var.class(), VarClass::Local | VarClass::Arg))
+				.map(|var| DebugLocal {
+					offset: var.address,
+					start: var.code_start as usize,
+					end: var.code_end as usize,
+					name: self.name(var.name).unwrap_or_default(),
+					ty: self.rtti_type_id(var.type_id),
+				})
+				.collect()
+		}
+		self.symbols.debug.symbols.iter()
+			.filter(move |symbol| {
+				symbol.scope == SymbolScope::Local
+					&& symbol.kind != SymbolKind::Function
+					&& function.contains(symbol.code_start as usize)
+			})
+			.map(|symbol| DebugLocal {
+				offset: symbol.address,
+				start: symbol.code_start as usize,
+				end: symbol.code_end as usize,
+				name: self.debug_name(symbol.name).unwrap_or_default(),
+				ty: self.tag_type(symbol.tag, symbol.kind, &symbol.dims),
+			})
+			.collect()
+	}
+
+	/// Return the signature of the function at a code offset.
+	fn function_sig(&self, start: usize) -> Option<Sig> {
+		let rtti = &self.symbols.rtti;
+		let method = rtti.methods.iter().find(|m| m.pcode_start as usize == start);
+		if let Some(method) = method {
+			return rtti.signature_at(method.signature).ok().map(|sig| self.rtti_sig(&sig))
+		}
+		let symbol = self.symbols.debug.symbols.iter().find(move |symbol| {
+			symbol.kind == SymbolKind::Function && symbol.address as usize == start
+		})?;
+		let function = self.map.at(start)?;
+		let mut args: Vec<DebugLocal> = self.debug_locals(function).into_iter()
+			.filter(move |local| local.offset >= FIRST_ARG)
+			.collect();
+		args.sort_by_key(move |local| local.offset);
+		args.dedup_by_key(|local| local.offset);
+		Some(Sig {
+			ret: Some(self.tag_type(symbol.tag, SymbolKind::Variable, &[])),
+			args: args.into_iter().map(move |local| local.ty).collect(),
+			variadic: None,
+		})
+	}
+
+	/// Return the signature of a native.
+	fn native_sig(&self, index: usize) -> Option<Sig> {
+		let rtti = &self.symbols.rtti;
+		if let Some(native) = rtti.natives.get(index) {
+			return rtti.signature_at(native.signature).ok().map(|sig| self.rtti_sig(&sig))
+		}
+		let native = self.symbols.debug.natives.iter()
+			.find(|n| n.index as usize == index)?;
+		let mut sig = Sig {
+			ret: Some(self.tag_type(native.tag, SymbolKind::Variable, &[])),
+			..Default::default()
+		};
+		for arg in native.args.iter() {
+			let ty = self.tag_type(arg.tag, arg.kind, &arg.dims);
+			if arg.kind == SymbolKind::Varargs {
+				sig.variadic = Some(ty);
+			} else {
+				sig.args.push(ty);
+			}
+		}
+		Some(sig)
+	}
+
+	fn callee_sig(&self, callee: &Callee) -> Option<Sig> {
+		match callee {
+			Callee::Function { start, .. } => self.function_sig(*start),
+			Callee::Native { index, .. } => self.native_sig(*index),
+			Callee::Builtin("float") => Some(Sig {
+				ret: Some(VarType::new("float")),
+				args: vec![VarType::int()],
+				variadic: None,
+			}),
+			Callee::Builtin(name) => Some(Sig {
+				ret: Some(VarType::new(
+					if name.starts_with("Round") { "int" } else { "float" }
+				)),
+				args: vec![VarType::new("float"); 2],
+				variadic: None,
+			}),
+		}
+	}
+}
+
+/// Loop of a control-flow graph.
+#[derive(Debug, Clone)]
+struct Loop {
+	/// For each block, whether it is part of the loop.
+	blocks: Vec<bool>,
+	/// Block that control continues with after the loop.
+	exit: Option<usize>,
+}
+
+/// Dominance information of a control-flow graph.
+#[derive(Debug, Clone)]
+struct Graph {
+	/// Immediate post-dominator of each block.
+	ipdom: Vec<Option<usize>>,
+	/// Loop of each loop header.
+	loops: Vec<Option<Loop>>,
+}
+
+impl Graph {
+	fn new(cfg: &Cfg) -> Self {
+		let n = cfg.blocks.len();
+		let reachable = cfg.reachable();
+
+		let mut dom = vec![vec![true; n]; n];
+		if n > 0 {
+			dom[0] = vec![false; n];
+			dom[0][0] = true;
+		}
+		let mut changed = true;
+		while changed {
+			changed = false;
+			for b in 1..n {
+				if !reachable[b] {
+					continue
+				}
+				let mut set = vec![true; n];
+				for p in cfg.blocks[b].predecessors.iter().filter(|p| reachable[**p]) {
+					set.iter_mut().zip(dom[*p].iter()).for_each(|(s, d)| *s &= *d);
+				}
+				set[b] = true;
+				if set != dom[b] {
+					dom[b] = set;
+					changed = true;
+				}
+			}
+		}
+
+		// Natural loops, out of back edges to blocks that dominate their source.
+		let mut bodies: Vec<Option<Vec<bool>>> = vec![None; n];
+		let mut latches = vec![Vec::new(); n];
+		for u in (0..n).filter(|u| reachable[*u]) {
+			for h in cfg.blocks[u].successors.iter().map(move |e| e.block) {
+				if !dom[u][h] {
+					continue
+				}
+				latches[h].push(u);
+				let blocks = bodies[h].get_or_insert_with(|| {
+					let mut blocks = vec![false; n];
+					blocks[h] = true;
+					blocks
+				});
+				let mut stack = vec![u];
+				while let Some(x) = stack.pop() {
+					if !std::mem::replace(&mut blocks[x], true) {
+						let predecessors = cfg.blocks[x].predecessors.iter();
+						stack.extend(predecessors.filter(|p| reachable[**p]));
+					}
+				}
+			}
+		}
+		let loops = bodies.into_iter()
+			.enumerate()
+			.map(|(h, blocks)| {
+				let blocks = blocks?;
+				let exits = |b: usize| -> Vec<usize> {
+					cfg.blocks[b].successors.iter()
+						.map(move |e| e.block)
+						.filter(|s| !blocks[*s])
+						.collect()
+				};
+				let conditional_exit = |b: usize| {
+					let exits = exits(b);
+					let two_way = cfg.blocks[b].successors.len() == 2;
+					(two_way && exits.len() == 1).then(|| exits[0])
+				};
+				let last = blocks.iter().rposition(move |b| *b).unwrap_or(h);
+				let exit = conditional_exit(h)
+					.or_else(|| {
+						latches[h].iter().max().and_then(|l| conditional_exit(*l))
+					})
+					.or_else(|| {
+						(0..n).filter(|b| blocks[*b])
+							.flat_map(exits)
+							.filter(move |e| *e > last)
+							.min()
+					});
+				Some(Loop { blocks, exit })
+			})
+			.collect();
+
+		// Post-dominators, where only the last return is a real exit, so that
+		// early returns do not hide where branches meet.
+		let exits: Vec<usize> = (0..n)
+			.filter(|b| reachable[*b] && cfg.blocks[*b].successors.is_empty())
+			.collect();
+		let is_return = |b: &usize| {
+			cfg.blocks[*b].terminator().map(flow) == Some(Flow::Return)
+		};
+		let real = exits.iter().rfind(|b| is_return(b)).or(exits.last()).copied();
+		let mut pdom = vec![vec![true; n]; n];
+		if let Some(real) = real {
+			pdom[real] = vec![false; n];
+			pdom[real][real] = true;
+		}
+		changed = true;
+		while changed {
+			changed = false;
+			for b in (0..n).rev() {
+				if !reachable[b] || cfg.blocks[b].successors.is_empty() {
+					continue
+				}
+				let mut set = vec![true; n];
+				for s in cfg.blocks[b].successors.iter() {
+					set.iter_mut().zip(pdom[s.block].iter()).for_each(|(x, p)| *x &= *p);
+				}
+				set[b] = true;
+				if set != pdom[b] {
+					pdom[b] = set;
+					changed = true;
+				}
+			}
+		}
+		let count = |set: &Vec<bool>| set.iter().filter(move |x| **x).count();
+		let ipdom = (0..n)
+			.map(|b| {
+				if count(&pdom[b]) == n {
+					return None
+				}
+				(0..n).filter(|p| *p != b && pdom[b][*p]).max_by_key(|p| count(&pdom[*p]))
+			})
+			.collect();
+
+		Self { ipdom, loops }
+	}
+}
+
+/// Expression that a register holds.
+#[derive(Debug, Clone)]
+struct Value {
+	expr: Expr,
+	/// Statement that computes the expression, if it may still be inlined.
+	stmt: Option<usize>,
+}
+
+impl Value {
+	fn new(expr: Expr) -> Self {
+		Self { expr, stmt: None }
+	}
+}
+
+/// Stack slot of the frame.
+#[derive(Debug, Clone)]
+struct Slot {
+	/// Size, in bytes.
+	size: Cell,
+	/// Variable that the slot is declared as.
+	var: Option<usize>,
+	/// Declaration of the slot, if it may still be inlined.
+	stmt: Option<usize>,
+}
+
+/// Symbolic state of the virtual machine.
+#[derive(Debug, Clone)]
+struct State {
+	pri: Value,
+	alt: Value,
+	stack: Vec<Slot>,
+	/// Size of the stack slots, in bytes.
+	depth: Cell,
+}
+
+impl State {
+	fn new() -> Self {
+		Self {
+			pri: Value::new(Expr::Register(Register::Pri)),
+			alt: Value::new(Expr::Register(Register::Alt)),
+			stack: Vec::new(),
+			depth: 0,
+		}
+	}
+}
+
+/// Statements that are being built, where inlined statements are `None`.
+#[derive(Default, Debug, Clone)]
+struct Body {
+	stmts: Vec<Option<Stmt>>,
+}
+
+impl Body {
+	fn push(&mut self, stmt: Stmt) -> usize {
+		self.stmts.push(Some(stmt));
+		self.stmts.len() - 1
+	}
+
+	fn is_last(&self, index: usize) -> bool {
+		self.stmts.get(index).is_some_and(Option::is_some)
+			&& self.stmts[index + 1..].iter().all(Option::is_none)
+	}
+
+	fn is_empty(&self) -> bool {
+		self.stmts.iter().all(Option::is_none)
+	}
+
+	fn finish(self) -> Vec<Stmt> {
+		self.stmts.into_iter().flatten().collect()
+	}
+}
+
+/// Inline the declaration of a stack slot if it is the last statement, and
+/// return its value.
+fn take_slot(body: &mut Body, slot: &mut Slot) -> Expr {
+	if let Some(index) = slot.stmt.take() {
+		if body.is_last(index) {
+			if let Some(Stmt::Decl(_, Some(expr))) = body.stmts[index].take() {
+				slot.var = None;
+				return expr
+			}
+		}
+	}
+	slot.var.map_or(Expr::Register(Register::Stack), Expr::Var)
+}
+
+/// How control leaves a block.
+#[derive(Debug)]
+enum Exit {
+	/// Control continues with the only successor.
+	Next,
+	/// Control jumps if a condition holds.
+	Branch(Expr),
+	Switch(Expr),
+	Return(Option<Expr>),
+	/// Control does not continue.
+	Stop,
+}
+
+/// Loop whose body is being structured.
+#[derive(Debug, Clone, Copy)]
+struct Scope {
+	header: usize,
+	exit: Option<usize>,
+}
+
+/// Decompilation of a single function.
+struct Context<'a> {
+	dec: &'a Decompiler,
+	function: &'a Function,
+	cfg: &'a Cfg,
+	graph: &'a Graph,
+	locals: Vec<DebugLocal>,
+	sig: Option<Sig>,
+	vars: Vec<Variable>,
+	args: Vec<usize>,
+	temps: usize,
+	visited: Vec<bool>,
+	/// Blocks that get a label.
+	labels: BTreeSet<usize>,
+	/// Blocks that are jumped to with `goto`.
+	gotos: BTreeSet<usize>,
+}
+
+impl<'a> Context<'a> {
+	fn new(
+		dec: &'a Decompiler,
+		function: &'a Function,
+		cfg: &'a Cfg,
+		graph: &'a Graph,
+		labels: BTreeSet<usize>,
+	) -> Self {
+		let mut context = Self {
+			dec,
+			function,
+			cfg,
+			graph,
+			locals: dec.debug_locals(function),
+			sig: dec.function_sig(function.start),
+			vars: Vec::new(),
+			args: Vec::new(),
+			temps: 0,
+			visited: vec![false; cfg.blocks.len()],
+			labels,
+			gotos: BTreeSet::new(),
+		};
+		let mut count = context.sig.as_ref().map_or(0, move |sig| sig.args.len());
+		for (_, instruction) in function.instructions.iter() {
+			for offset in frame_offsets(instruction) {
+				if offset >= FIRST_ARG {
+					count = count.max(((offset - FIRST_ARG) / CELL) as usize + 1);
+				}
+			}
+		}
+		if count > 0 {
+			context.arg(count - 1);
+		}
+		context
+	}
+
+	fn run(&mut self) -> Vec<Stmt> {
+		let mut body = Body::default();
+		if !self.cfg.blocks.is_empty() {
+			self.structure(0, None, &mut Vec::new(), &mut State::new(), &mut body, false);
+		}
+		body.finish()
+	}
+
+	fn var(&mut self, kind: VarKind, name: String, ty: VarType) -> usize {
+		let existing = self.vars.iter().position(|v| v.kind == kind && v.name == name);
+		if let Some(index) = existing {
+			return index
+		}
+		self.vars.push(Variable { name, ty, kind });
+		self.vars.len() - 1
+	}
+
+	fn temp(&mut self) -> usize {
+		let name = format!("tmp_{}", self.temps);
+		self.temps += 1;
+		self.var(VarKind::Temp, name, VarType::int())
+	}
+
+	fn arg(&mut self, index: usize) -> usize {
+		while self.args.len() <= index {
+			let n = self.args.len();
+			let offset = FIRST_ARG + n as Cell * CELL;
+			let local = self.locals.iter().find(move |local| local.offset == offset);
+			let name = local
+				.map_or_else(|| format!("arg_{n}"), |local| local.name.clone());
+			let ty = self.sig.as_ref()
+				.and_then(move |sig| sig.args.get(n))
+				.or(local.map(move |local| &local.ty))
+				.cloned()
+				.unwrap_or_else(VarType::int);
+			let var = self.var(VarKind::Arg(n), name, ty);
+			self.args.push(var);
+		}
+		self.args[index]
+	}
+
+	/// Return the local variable at a frame offset, which is in scope at a
+	/// code offset.
+	fn local(&mut self, offset: Cell, pc: usize, size: Cell) -> usize {
+		let local = self.locals.iter()
+			.find(move |local| {
+				local.offset == offset && local.start <= pc && pc <= local.end
+			});
+		let (name, ty) = match local {
+			Some(local) => (local.name.clone(), local.ty.clone()),
+			None => {
+				let mut ty = VarType::int();
+				if size > CELL {
+					ty.dims.push(Some((size / CELL) as u32));
+				}
+				(format!("local_{}", -offset / CELL), ty)
+			}
+		};
+		self.var(VarKind::Local(offset), name, ty)
+	}
+
+	/// Return the global variable, or the element of a global array, at an
+	/// address.
+	fn global(&mut self, address: Cell) -> Expr {
+		let containing = self.dec.globals.range(..=address).next_back();
+		if let Some((&base, (name, ty))) = containing {
+			let var = self.var(VarKind::Global(base), name.clone(), ty.clone());
+			if base == address {
+				return Expr::Var(var)
+			}
+			if ty.cells().is_some_and(move |cells| address < base + cells * CELL) {
+				return Expr::index(Expr::Var(var), Expr::Int((address - base) / CELL))
+			}
+		}
+		let name = format!("global_{address}");
+		Expr::Var(self.var(VarKind::Global(address), name, VarType::int()))
+	}
+
+	/// Return the lvalue at a frame offset.
+	fn frame(&mut self, state: &mut State, offset: Cell, pc: usize) -> Expr {
+		if offset >= FIRST_ARG {
+			return Expr::Var(self.arg(((offset - FIRST_ARG) / CELL) as usize))
+		}
+		if offset >= 0 {
+			let name = format!("frame_{offset}");
+			return Expr::Var(self.var(VarKind::Local(offset), name, VarType::int()))
+		}
+		let mut top = 0;
+		for slot in state.stack.iter_mut() {
+			top += slot.size;
+			let base = -top;
+			if base <= offset && offset < base + slot.size {
+				// The slot is a variable, so its declaration must stay.
+				slot.stmt = None;
+				if let Some(var) = slot.var {
+					return if offset == base {
+						Expr::Var(var)
+					} else {
+						Expr::index(Expr::Var(var), Expr::Int((offset - base) / CELL))
+					}
+				}
+			}
+		}
+		Expr::Var(self.local(offset, pc, CELL))
+	}
+
+	fn snapshot(&self) -> (usize, usize) {
+		(self.vars.len(), self.temps)
+	}
+
+	fn restore(&mut self, (vars, temps): (usize, usize)) {
+		self.vars.truncate(vars);
+		self.temps = temps;
+	}
+
+	/// Return the expression of a register, inlining the statement that
+	/// computes it if it is the last one, and keeping it in a temporary
+	/// otherwise.
+	fn take(&mut self, body: &mut Body, value: &mut Value, register: Register) -> Expr {
+		if let Some(index) = value.stmt.take() {
+			if body.is_last(index) {
+				if let Some(Stmt::Expr(expr)) = body.stmts[index].take() {
+					value.expr = Expr::Register(register);
+					return expr
+				}
+			}
+			self.materialize(body, value, index);
+		}
+		value.expr.clone()
+	}
+
+	/// Keep the result of a statement in a temporary.
+	fn materialize(&mut self, body: &mut Body, value: &mut Value, index: usize) {
+		if let Some(Stmt::Expr(expr)) = body.stmts[index].take() {
+			let temp = self.temp();
+			body.stmts[index] = Some(Stmt::Decl(temp, Some(expr)));
+			value.expr = Expr::Var(temp);
+		}
+	}
+
+	fn take_pri(&mut self, body: &mut Body, state: &mut State) -> Expr {
+		self.take(body, &mut state.pri, Register::Pri)
+	}
+
+	fn take_alt(&mut self, body: &mut Body, state: &mut State) -> Expr {
+		self.take(body, &mut state.alt, Register::Alt)
+	}
+
+	/// Take `PRI` and `ALT`, the later computed one first.
+	fn take_both(&mut self, body: &mut Body, state: &mut State) -> (Expr, Expr) {
+		if state.alt.stmt > state.pri.stmt {
+			let alt = self.take_alt(body, state);
+			(self.take_pri(body, state), alt)
+		} else {
+			let pri = self.take_pri(body, state);
+			(pri, self.take_alt(body, state))
+		}
+	}
+
+	/// Return a value for an expression, whose calls are kept in a statement.
+	fn value(&mut self, body: &mut Body, expr: Expr) -> Value {
+		if expr.has_call() {
+			Value { stmt: Some(body.push(Stmt::Expr(expr.clone()))), expr }
+		} else {
+			Value::new(expr)
+		}
+	}
+
+	/// Keep an expression in a temporary if it is evaluated more than once.
+	fn once(&mut self, body: &mut Body, expr: Expr) -> Expr {
+		if !expr.has_call() {
+			return expr
+		}
+		let temp = self.temp();
+		body.push(Stmt::Decl(temp, Some(expr)));
+		Expr::Var(temp)
+	}
+
+	/// Keep the registers in temporaries if they read an lvalue that is about
+	/// to be written.
+	fn protect(&mut self, body: &mut Body, state: &mut State, lvalue: &Expr) {
+		let root = lvalue.root();
+		for value in [&mut state.pri, &mut state.alt] {
+			if value.stmt.is_none() && value.expr.reads(root) {
+				let temp = self.temp();
+				body.push(Stmt::Decl(temp, Some(value.expr.clone())));
+				value.expr = Expr::Var(temp);
+			}
+		}
+	}
+
+	/// Write a statement with a side effect on an lvalue.
+	fn effect(&mut self, body: &mut Body, state: &mut State, lvalue: &Expr, stmt: Stmt) {
+		self.protect(body, state, lvalue);
+		body.push(stmt);
+	}
+
+	/// Store `PRI` into an lvalue.
+	fn store_pri(
+		&mut self,
+		body: &mut Body,
+		state: &mut State,
+		lvalue: Expr,
+		value: Expr,
+	) {
+		state.pri = Value::new(Expr::Register(Register::Pri));
+		self.effect(body, state, &lvalue, Stmt::Assign(lvalue.clone(), value));
+		state.pri = Value::new(lvalue);
+	}
+
+	/// Store `ALT` into an lvalue.
+	fn store_alt(&mut self, body: &mut Body, state: &mut State, lvalue: Expr) {
+		let value = self.take_alt(body, state);
+		state.alt = Value::new(Expr::Register(Register::Alt));
+		self.effect(body, state, &lvalue, Stmt::Assign(lvalue.clone(), value));
+		state.alt = Value::new(lvalue);
+	}
+
+	fn store(&mut self, body: &mut Body, state: &mut State, lvalue: Expr, value: Expr) {
+		self.effect(body, state, &lvalue, Stmt::Assign(lvalue.clone(), value));
+	}
+
+	/// Keep the pending values of the registers and the stack slots, before
+	/// statements are written to another body.
+	fn seal(&mut self, state: &mut State) {
+		if state.pri.stmt.take().is_some() {
+			state.pri.expr = Expr::Register(Register::Pri);
+		}
+		if state.alt.stmt.take().is_some() {
+			state.alt.expr = Expr::Register(Register::Alt);
+		}
+		for slot in state.stack.iter_mut() {
+			slot.stmt = None;
+		}
+	}
+
+	/// Forget the registers, where control flow meets.
+	fn reset(&mut self, state: &mut State) {
+		self.seal(state);
+		state.pri = Value::new(Expr::Register(Register::Pri));
+		state.alt = Value::new(Expr::Register(Register::Alt));
+	}
+
+	fn push(&mut self, body: &mut Body, state: &mut State, expr: Expr, pc: usize) {
+		state.depth += CELL;
+		let var = self.local(-state.depth, pc, CELL);
+		let stmt = body.push(Stmt::Decl(var, Some(expr)));
+		state.stack.push(Slot { size: CELL, var: Some(var), stmt: Some(stmt) });
+	}
+
+	fn pop(&mut self, body: &mut Body, state: &mut State) -> Expr {
+		match state.stack.pop() {
+			Some(mut slot) => {
+				state.depth -= slot.size;
+				take_slot(body, &mut slot)
+			}
+			None => Expr::Register(Register::Stack),
+		}
+	}
+
+	fn pop_bytes(&mut self, state: &mut State, mut bytes: Cell) {
+		while bytes > 0 {
+			let Some(slot) = state.stack.pop() else {
+				break
+			};
+			state.depth -= slot.size;
+			bytes -= slot.size;
+		}
+	}
+
+	/// Take arguments that stay on the stack below an argument count, as
+	/// `SYSREQ.C` and the float instructions leave them.
+	fn peek_args(
+		&mut self,
+		body: &mut Body,
+		state: &mut State,
+		count: Option<usize>,
+	) -> Vec<Expr> {
+		let mut args = Vec::new();
+		let len = state.stack.len();
+		let Some(top) = len.checked_sub(1) else {
+			return args
+		};
+		let bytes = take_slot(body, &mut state.stack[top]);
+		let count = count.unwrap_or(match bytes {
+			Expr::Int(bytes) => (bytes / CELL) as usize,
+			_ => 0,
+		});
+		for n in 0..count {
+			match top.checked_sub(n + 1) {
+				Some(index) => args.push(take_slot(body, &mut state.stack[index])),
+				None => args.push(Expr::Register(Register::Stack)),
+			}
+		}
+		args
+	}
+
+	/// Set `PRI` to the result of a call.
+	fn call(&mut self, body: &mut Body, state: &mut State, expr: Expr) {
+		state.pri = self.value(body, expr);
+		state.alt = Value::new(Expr::Register(Register::Alt));
+	}
+
+	fn call_native(
+		&mut self,
+		body: &mut Body,
+		state: &mut State,
+		index: Cell,
+		args: Vec<Expr>,
+	) {
+		let index = index as usize;
+		let name = self.dec.native_name(index);
+		let op = match name.as_str() {
+			"FloatAdd" => Some(BinaryOp::Add),
+			"FloatSub" => Some(BinaryOp::Sub),
+			"FloatMul" => Some(BinaryOp::Mul),
+			"FloatDiv" => Some(BinaryOp::Div),
+			_ => None,
+		};
+		let expr = match (op, <[Expr; 2]>::try_from(args)) {
+			(Some(op), Ok([a, b])) => Expr::binary(op, float(a), float(b)),
+			(_, Ok(args)) => Expr::Call(Callee::Native { index, name }, args.into()),
+			(_, Err(args)) => Expr::Call(Callee::Native { index, name }, args),
+		};
+		self.call(body, state, expr);
+	}
+
+	/// Execute a float instruction, whose arguments stay on the stack.
+	fn float_op(
+		&mut self,
+		body: &mut Body,
+		state: &mut State,
+		instruction: &Instruction,
+	) {
+		use Instruction as I;
+		let (arity, builtin, op) = match instruction {
+			I::Float => (1, "float", None),
+			I::Floatadd => (2, "", Some(BinaryOp::Add)),
+			I::Floatsub => (2, "", Some(BinaryOp::Sub)),
+			I::Floatmul => (2, "", Some(BinaryOp::Mul)),
+			I::Floatdiv => (2, "", Some(BinaryOp::Div)),
+			I::Floatcmp => (2, "FloatCompare", None),
+			I::RndToNearest => (1, "RoundToNearest", None),
+			I::RndToFloor => (1, "RoundToFloor", None),
+			I::RndToCeil => (1, "RoundToCeil", None),
+			I::RndToZero => (1, "RoundToZero", None),
+			_ => (1, "FloatAbs", None),
+		};
+		let args = self.peek_args(body, state, Some(arity));
+		let expr = match (op, <[Expr; 2]>::try_from(args)) {
+			(Some(op), Ok([a, b])) => Expr::binary(op, float(a), float(b)),
+			(_, Ok(args)) => Expr::Call(Callee::Builtin(builtin), args.map(float).into()),
+			(_, Err(args)) if builtin == "float" => {
+				Expr::Call(Callee::Builtin(builtin), args)
+			}
+			(_, Err(args)) => {
+				let args = args.into_iter().map(float).collect();
+				Expr::Call(Callee::Builtin(builtin), args)
+			}
+		};
+		state.pri = self.value(body, expr);
+	}
+
+	fn emit(&mut self, body: &mut Body, state: &mut State, instruction: &Instruction) {
+		body.push(Stmt::Emit(instruction.clone()));
+		self.reset(state);
+	}
+
+	/// Execute an instruction that does not end a block.
+	fn step(
+		&mut self,
+		body: &mut Body,
+		state: &mut State,
+		pc: usize,
+		instruction: &Instruction,
+	) {
+		use Instruction as I;
+		use BinaryOp as B;
+
+		if let Some((kind, operands)) = push_operands(instruction) {
+			for operand in operands {
+				let expr = match kind {
+					PushKind::Const => Expr::Int(operand),
+					PushKind::Addr => self.global(operand),
+					PushKind::Stack => self.frame(state, operand, pc),
+					PushKind::Adr => {
+						Expr::Address(Box::new(self.frame(state, operand, pc)))
+					}
+				};
+				self.push(body, state, expr, pc);
+			}
+			return
+		}
+
+		let binary = |this: &mut Self, body: &mut Body, state: &mut State, op, swap| {
+			let (mut a, mut b) = this.take_both(body, state);
+			if swap {
+				std::mem::swap(&mut a, &mut b);
+			}
+			let expr = Expr::binary(op, a, b);
+			state.pri = Value::new(expr);
+		};
+		match *instruction {
+			I::LoadPri { offset } => state.pri = Value::new(self.global(offset)),
+			I::LoadAlt { offset } => state.alt = Value::new(self.global(offset)),
+			I::LoadSPri { offset } => {
+				state.pri = Value::new(self.frame(state, offset, pc));
+			}
+			I::LoadSAlt { offset } => {
+				state.alt = Value::new(self.frame(state, offset, pc));
+			}
+			I::LrefPri { offset } => {
+				state.pri = Value::new(Expr::Deref(Box::new(self.global(offset))));
+			}
+			I::LrefAlt { offset } => {
+				state.alt = Value::new(Expr::Deref(Box::new(self.global(offset))));
+			}
+			I::LrefSPri { offset } => {
+				let expr = Expr::Deref(Box::new(self.frame(state, offset, pc)));
+				state.pri = Value::new(expr);
+			}
+			I::LrefSAlt { offset } => {
+				let expr = Expr::Deref(Box::new(self.frame(state, offset, pc)));
+				state.alt = Value::new(expr);
+			}
+			I::LoadBoth { addr_1, addr_2 } => {
+				state.pri = Value::new(self.global(addr_1));
+				state.alt = Value::new(self.global(addr_2));
+			}
+			I::LoadSBoth { stack_1, stack_2 } => {
+				state.pri = Value::new(self.frame(state, stack_1, pc));
+				state.alt = Value::new(self.frame(state, stack_2, pc));
+			}
+			I::LoadI => {
+				let address = self.take_pri(body, state);
+				state.pri = Value::new(lvalue_at(address, false));
+			}
+			I::LodbI { .. } => {
+				let address = self.take_pri(body, state);
+				state.pri = Value::new(lvalue_at(address, true));
+			}
+			I::ConstPri { value } => state.pri = Value::new(Expr::Int(value)),
+			I::ConstAlt { value } => state.alt = Value::new(Expr::Int(value)),
+			I::ZeroPri => state.pri = Value::new(Expr::Int(0)),
+			I::ZeroAlt => state.alt = Value::new(Expr::Int(0)),
+			I::AddrPri { offset } => {
+				let lvalue = self.frame(state, offset, pc);
+				state.pri = Value::new(Expr::Address(Box::new(lvalue)));
+			}
+			I::AddrAlt { offset } => {
+				let lvalue = self.frame(state, offset, pc);
+				state.alt = Value::new(Expr::Address(Box::new(lvalue)));
+			}
+			I::LdgfnPri { func_1 } => {
+				let name = self.dec.function_name(func_1 as usize);
+				state.pri = Value::new(Expr::Function(name));
+			}
+			I::StorPri { offset } => {
+				let value = self.take_pri(body, state);
+				let lvalue = self.global(offset);
+				self.store_pri(body, state, lvalue, value);
+			}
+			I::StorSPri { offset } => {
+				let value = self.take_pri(body, state);
+				let lvalue = self.frame(state, offset, pc);
+				self.store_pri(body, state, lvalue, value);
+			}
+			I::SrefPri { offset } => {
+				let value = self.take_pri(body, state);
+				let lvalue = Expr::Deref(Box::new(self.global(offset)));
+				self.store_pri(body, state, lvalue, value);
+			}
+			I::SrefSPri { offset } => {
+				let value = self.take_pri(body, state);
+				let lvalue = Expr::Deref(Box::new(self.frame(state, offset, pc)));
+				self.store_pri(body, state, lvalue, value);
+			}
+			I::StorAlt { offset } => {
+				let lvalue = self.global(offset);
+				self.store_alt(body, state, lvalue);
+			}
+			I::StorSAlt { offset } => {
+				let lvalue = self.frame(state, offset, pc);
+				self.store_alt(body, state, lvalue);
+			}
+			I::SrefAlt { offset } => {
+				let lvalue = Expr::Deref(Box::new(self.global(offset)));
+				self.store_alt(body, state, lvalue);
+			}
+			I::SrefSAlt { offset } => {
+				let lvalue = Expr::Deref(Box::new(self.frame(state, offset, pc)));
+				self.store_alt(body, state, lvalue);
+			}
+			I::StorI | I::StrbI { .. } => {
+				let (value, address) = self.take_both(body, state);
+				state.alt = Value::new(address.clone());
+				let lvalue = lvalue_at(address, matches!(instruction, I::StrbI { .. }));
+				self.store_pri(body, state, lvalue, value);
+			}
+			I::Zero { addr_1 } => {
+				let lvalue = self.global(addr_1);
+				self.store(body, state, lvalue, Expr::Int(0));
+			}
+			I::ZeroS { stack_1 } => {
+				let lvalue = self.frame(state, stack_1, pc);
+				self.store(body, state, lvalue, Expr::Int(0));
+			}
+			I::Const { addr_1, const_1 } => {
+				let lvalue = self.global(addr_1);
+				self.store(body, state, lvalue, Expr::Int(const_1));
+			}
+			I::ConstS { stack_1, const_1 } => {
+				let lvalue = self.frame(state, stack_1, pc);
+				self.store(body, state, lvalue, Expr::Int(const_1));
+			}
+			I::Lidx | I::LidxB { .. } | I::Idxaddr | I::IdxaddrB { .. } => {
+				let (index, base) = self.take_both(body, state);
+				state.alt = Value::new(base.clone());
+				let element = Expr::index(base.designator(), index);
+				state.pri = Value::new(match instruction {
+					I::Idxaddr | I::IdxaddrB { .. } => Expr::Address(Box::new(element)),
+					_ => element,
+				});
+			}
+			I::MovePri => state.pri = Value::new(state.alt.expr.clone()),
+			I::MoveAlt => {
+				let pri = self.take_pri(body, state);
+				let pri = self.once(body, pri);
+				state.pri = Value::new(pri.clone());
+				state.alt = Value::new(pri);
+			}
+			I::Xchg => std::mem::swap(&mut state.pri, &mut state.alt),
+			I::PushPri => {
+				let pri = self.take_pri(body, state);
+				self.push(body, state, pri, pc);
+			}
+			I::PushAlt => {
+				let alt = self.take_alt(body, state);
+				self.push(body, state, alt, pc);
+			}
+			I::PopPri => {
+				let expr = self.pop(body, state);
+				state.pri = self.value(body, expr);
+			}
+			I::PopAlt => {
+				let expr = self.pop(body, state);
+				state.alt = self.value(body, expr);
+			}
+			I::SwapPri | I::SwapAlt => {
+				let value = match instruction {
+					I::SwapPri => self.take_pri(body, state),
+					_ => self.take_alt(body, state),
+				};
+				let Some(top) = state.stack.len().checked_sub(1) else {
+					return self.emit(body, state, instruction)
+				};
+				let inlined = state.stack[top].stmt
+					.is_some_and(|stmt| body.is_last(stmt));
+				let old = take_slot(body, &mut state.stack[top]);
+				let old = if inlined {
+					old
+				} else {
+					let temp = self.temp();
+					body.push(Stmt::Decl(temp, Some(old)));
+					Expr::Var(temp)
+				};
+				let var = match state.stack[top].var {
+					Some(var) => var,
+					None => self.local(-state.depth, pc, CELL),
+				};
+				state.stack[top].var = Some(var);
+				state.stack[top].stmt = None;
+				body.push(Stmt::Assign(Expr::Var(var), value));
+				match instruction {
+					I::SwapPri => state.pri = Value::new(old),
+					_ => state.alt = Value::new(old),
+				}
+			}
+			I::Stack { const_1 } if const_1 < 0 => {
+				state.depth -= const_1;
+				let var = self.local(-state.depth, pc, -const_1);
+				body.push(Stmt::Decl(var, None));
+				state.stack.push(Slot { size: -const_1, var: Some(var), stmt: None });
+			}
+			I::Stack { const_1 } => self.pop_bytes(state, const_1),
+			I::Heap { const_1 } => {
+				let temp = self.temp();
+				if const_1 > CELL {
+					self.vars[temp].ty.dims.push(Some((const_1 / CELL) as u32));
+				}
+				body.push(Stmt::Decl(temp, None));
+				state.alt = Value::new(Expr::Address(Box::new(Expr::Var(temp))));
+			}
+			I::Call { func_1 } => {
+				let bytes = self.pop(body, state);
+				let count = match bytes {
+					Expr::Int(bytes) => bytes / CELL,
+					_ => 0,
+				};
+				let args = (0..count).map(|_| self.pop(body, state)).collect();
+				let start = func_1 as usize;
+				let name = self.dec.function_name(start);
+				let expr = Expr::Call(Callee::Function { start, name }, args);
+				self.call(body, state, expr);
+			}
+			I::SysreqN { native, n_args } => {
+				let args = (0..n_args).map(|_| self.pop(body, state)).collect();
+				self.call_native(body, state, native, args);
+			}
+			I::SysreqC { native_1 } => {
+				let args = self.peek_args(body, state, None);
+				self.call_native(body, state, native_1, args);
+			}
+			I::Float
+				| I::Floatadd
+				| I::Floatsub
+				| I::Floatmul
+				| I::Floatdiv
+				| I::Floatcmp
+				| I::RndToNearest
+				| I::RndToFloor
+				| I::RndToCeil
+				| I::RndToZero
+				| I::Fabs => self.float_op(body, state, instruction),
+			I::Genarray { const_1 } | I::GenarrayZ { const_1 } => {
+				let mut dims: Vec<Expr> = (0..const_1)
+					.map(|_| self.pop(body, state))
+					.collect();
+				dims.reverse();
+				self.push(body, state, Expr::NewArray(dims), pc);
+			}
+			I::Shl => binary(self, body, state, B::Shl, false),
+			I::Shr => binary(self, body, state, B::Ushr, false),
+			I::Sshr => binary(self, body, state, B::Shr, false),
+			I::Smul | I::Umul => binary(self, body, state, B::Mul, false),
+			I::Sdiv | I::Udiv | I::SdivAlt | I::UdivAlt => {
+				let (pri, alt) = self.take_both(body, state);
+				let (pri, alt) = (self.once(body, pri), self.once(body, alt));
+				let (a, b) = match instruction {
+					I::Sdiv | I::Udiv => (pri, alt),
+					_ => (alt, pri),
+				};
+				state.pri = Value::new(Expr::binary(B::Div, a.clone(), b.clone()));
+				state.alt = Value::new(Expr::binary(B::Mod, a, b));
+			}
+			I::Add => {
+				let (pri, alt) = self.take_both(body, state);
+				state.pri = Value::new(add(pri, alt));
+			}
+			I::Sub => binary(self, body, state, B::Sub, false),
+			I::SubAlt => binary(self, body, state, B::Sub, true),
+			I::And => binary(self, body, state, B::BitAnd, false),
+			I::Or => binary(self, body, state, B::BitOr, false),
+			I::Xor => binary(self, body, state, B::BitXor, false),
+			I::Eq => binary(self, body, state, B::Eq, false),
+			I::Neq => binary(self, body, state, B::Ne, false),
+			I::Less | I::Sless => binary(self, body, state, B::Lt, false),
+			I::Leq | I::Sleq => binary(self, body, state, B::Le, false),
+			I::Grtr | I::Sgrtr => binary(self, body, state, B::Gt, false),
+			I::Geq | I::Sgeq => binary(self, body, state, B::Ge, false),
+			I::Not => {
+				let pri = self.take_pri(body, state);
+				state.pri = Value::new(negate(pri));
+			}
+			I::Neg | I::Invert => {
+				let pri = self.take_pri(body, state);
+				let op = match instruction {
+					I::Neg => UnaryOp::Neg,
+					_ => UnaryOp::Invert,
+				};
+				state.pri = Value::new(Expr::Unary(op, Box::new(pri)));
+			}
+			I::AddC { const_1 } => {
+				let pri = self.take_pri(body, state);
+				state.pri = Value::new(add(pri, Expr::Int(const_1)));
+			}
+			I::SmulC { const_1 } => {
+				let pri = self.take_pri(body, state);
+				state.pri = Value::new(Expr::binary(B::Mul, pri, Expr::Int(const_1)));
+			}
+			I::ShlCPri { const_1 } | I::ShrCPri { const_1 } => {
+				let op = match instruction {
+					I::ShlCPri { .. } => B::Shl,
+					_ => B::Ushr,
+				};
+				let pri = self.take_pri(body, state);
+				state.pri = Value::new(Expr::binary(op, pri, Expr::Int(const_1)));
+			}
+			I::ShlCAlt { const_1 } | I::ShrCAlt { const_1 } => {
+				let op = match instruction {
+					I::ShlCAlt { .. } => B::Shl,
+					_ => B::Ushr,
+				};
+				let alt = self.take_alt(body, state);
+				state.alt = Value::new(Expr::binary(op, alt, Expr::Int(const_1)));
+			}
+			I::EqCPri { const_1 } => {
+				let pri = self.take_pri(body, state);
+				state.pri = Value::new(Expr::binary(B::Eq, pri, Expr::Int(const_1)));
+			}
+			I::EqCAlt { const_1 } => {
+				let alt = self.take_alt(body, state);
+				state.pri = Value::new(Expr::binary(B::Eq, alt, Expr::Int(const_1)));
+			}
+			I::IncPri | I::DecPri => {
+				let op = if *instruction == I::IncPri { B::Add } else { B::Sub };
+				let pri = self.take_pri(body, state);
+				state.pri = Value::new(Expr::binary(op, pri, Expr::Int(1)));
+			}
+			I::IncAlt | I::DecAlt => {
+				let op = if *instruction == I::IncAlt { B::Add } else { B::Sub };
+				let alt = self.take_alt(body, state);
+				state.alt = Value::new(Expr::binary(op, alt, Expr::Int(1)));
+			}
+			I::Inc { addr_1 } | I::Dec { addr_1 } => {
+				let lvalue = self.global(addr_1);
+				let stmt = match instruction {
+					I::Inc { .. } => Stmt::Inc,
+					_ => Stmt::Dec,
+				};
+				self.effect(body, state, &lvalue, stmt(lvalue.clone()));
+			}
+			I::IncS { stack_1 } | I::DecS { stack_1 } => {
+				let lvalue = self.frame(state, stack_1, pc);
+				let stmt = match instruction {
+					I::IncS { .. } => Stmt::Inc,
+					_ => Stmt::Dec,
+				};
+				self.effect(body, state, &lvalue, stmt(lvalue.clone()));
+			}
+			I::IncI | I::DecI => {
+				let address = self.take_pri(body, state);
+				let lvalue = lvalue_at(address, false);
+				let stmt = if *instruction == I::IncI { Stmt::Inc } else { Stmt::Dec };
+				self.effect(body, state, &lvalue, stmt(lvalue.clone()));
+			}
+			I::Movs { .. } => {
+				let (source, target) = self.take_both(body, state);
+				let lvalue = target.designator();
+				self.store(body, state, lvalue, source.designator());
+			}
+			I::Fill { .. } if state.pri.expr == Expr::Int(0) => {}
+			I::Proc
+				| I::Endproc
+				| I::Nop
+				| I::Break
+				| I::Bounds { .. }
+				| I::AlignPri { .. }
+				| I::AlignAlt { .. }
+				| I::SignPri
+				| I::SignAlt
+				| I::HeapSave
+				| I::HeapRestore
+				| I::TrackerPushC { .. }
+				| I::TrackerPopSetheap
+				| I::File { .. }
+				| I::Line { .. }
+				| I::Symbol { .. }
+				| I::Srange { .. }
+				| I::Symtag { .. } => {}
+			_ => self.emit(body, state, instruction),
+		}
+	}
+
+	/// Return the condition under which a conditional jump is taken.
+	fn condition(
+		&mut self,
+		body: &mut Body,
+		state: &mut State,
+		instruction: &Instruction,
+	) -> Expr {
+		use Instruction as I;
+		let op = match instruction {
+			I::Jzer { .. } => return negate(self.take_pri(body, state)),
+			I::Jnz { .. } => return self.take_pri(body, state),
+			I::Jeq { .. } => BinaryOp::Eq,
+			I::Jneq { .. } => BinaryOp::Ne,
+			I::Jsless { .. } | I::Jless { .. } => BinaryOp::Lt,
+			I::Jsleq { .. } | I::Jleq { .. } => BinaryOp::Le,
+			I::Jsgrtr { .. } | I::Jgrtr { .. } => BinaryOp::Gt,
+			_ => BinaryOp::Ge,
+		};
+		let (pri, alt) = self.take_both(body, state);
+		Expr::binary(op, pri, alt)
+	}
+
+	/// Execute the instructions of a block.
+	fn lift_block(&mut self, block: usize, state: &mut State, body: &mut Body) -> Exit {
+		let cfg = self.cfg;
+		let mut exit = Exit::Next;
+		for (pc, instruction) in cfg.blocks[block].instructions.iter() {
+			let next = pc + instruction.encoded_len();
+			exit = match flow(instruction) {
+				Flow::Next => {
+					self.step(body, state, next, instruction);
+					Exit::Next
+				}
+				Flow::Jump(_) => Exit::Next,
+				Flow::Branch(_) => Exit::Branch(self.condition(body, state, instruction)),
+				Flow::Switch(_) => Exit::Switch(self.take_pri(body, state)),
+				Flow::Return => {
+					let value = self.take_pri(body, state);
+					Exit::Return((!matches!(value, Expr::Register(_))).then_some(value))
+				}
+				Flow::Halt => {
+					body.push(Stmt::Emit(instruction.clone()));
+					Exit::Stop
+				}
+				Flow::Never => Exit::Stop,
+			};
+		}
+		exit
+	}
+
+	/// Structure the blocks from `entry` until `stop`.
+	fn structure(
+		&mut self,
+		entry: usize,
+		stop: Option<usize>,
+		loops: &mut Vec<Scope>,
+		state: &mut State,
+		body: &mut Body,
+		mut at_header: bool,
+	) {
+		let cfg = self.cfg;
+		let mut next = Some(entry);
+		let mut keep_state = false;
+		while let Some(b) = next {
+			if Some(b) == stop {
+				return
+			}
+			if let Some(scope) = loops.last() {
+				if b == scope.header && !at_header {
+					body.push(Stmt::Continue);
+					return
+				}
+				if Some(b) == scope.exit {
+					body.push(Stmt::Break);
+					return
+				}
+			}
+			if self.visited[b] {
+				self.gotos.insert(b);
+				body.push(Stmt::Goto(cfg.blocks[b].start));
+				return
+			}
+			if self.graph.loops[b].is_some() && !at_header {
+				next = self.structure_loop(b, loops, state, body);
+				keep_state = false;
+				continue
+			}
+			at_header = false;
+			self.visited[b] = true;
+			if self.labels.contains(&b) {
+				self.reset(state);
+				body.push(Stmt::Label(cfg.blocks[b].start));
+			} else if cfg.blocks[b].predecessors.len() > 1 && !keep_state {
+				self.reset(state);
+			}
+			keep_state = false;
+			next = match self.lift_block(b, state, body) {
+				Exit::Next => {
+					cfg.blocks[b].successors.first().map(move |edge| edge.block)
+				}
+				Exit::Stop => None,
+				Exit::Return(value) => {
+					body.push(Stmt::Return(value));
+					None
+				}
+				Exit::Branch(condition) => {
+					let merge;
+					(merge, keep_state) =
+						self.structure_if(b, condition, loops, state, body);
+					merge
+				}
+				Exit::Switch(value) => {
+					self.structure_switch(b, value, loops, state, body)
+				}
+			};
+		}
+	}
+
+	fn structure_loop(
+		&mut self,
+		header: usize,
+		loops: &mut Vec<Scope>,
+		state: &mut State,
+		body: &mut Body,
+	) -> Option<usize> {
+		let exit = self.graph.loops[header].as_ref().and_then(move |l| l.exit);
+		self.reset(state);
+		let mut inner = Body::default();
+		let mut inner_state = state.clone();
+		loops.push(Scope { header, exit });
+		self.structure(header, None, loops, &mut inner_state, &mut inner, true);
+		loops.pop();
+		body.push(Stmt::While(Expr::Bool(true), inner.finish()));
+		exit
+	}
+
+	/// Return where the branches of a block meet.
+	fn merge(&self, b: usize, loops: &[Scope]) -> Option<usize> {
+		let merge = self.graph.ipdom[b]?;
+		match loops.last() {
+			Some(scope) if !self.graph.loops[scope.header].as_ref()?.blocks[merge] => {
+				None
+			}
+			_ => Some(merge),
+		}
+	}
+
+	/// Structure a conditional jump, returning the block that follows and
+	/// whether the registers hold a value there.
+	fn structure_if(
+		&mut self,
+		b: usize,
+		condition: Expr,
+		loops: &mut Vec<Scope>,
+		state: &mut State,
+		body: &mut Body,
+	) -> (Option<usize>, bool) {
+		let cfg = self.cfg;
+		let target = move |block: usize, kind: EdgeKind| {
+			cfg.blocks[block].successors.iter()
+				.find(move |e| e.kind == kind)
+				.map(|e| e.block)
+		};
+		let (Some(mut taken), Some(mut fall)) =
+			(target(b, EdgeKind::Branch), target(b, EdgeKind::Fallthrough))
+		else {
+			return (cfg.blocks[b].successors.first().map(move |edge| edge.block), false)
+		};
+		let mut condition = condition;
+
+		// Fold chains of conditional jumps into `&&` and `||`.
+		loop {
+			let block = &cfg.blocks[fall];
+			if self.visited[fall]
+				|| block.predecessors.len() != 1
+				|| self.graph.loops[fall].is_some()
+				|| self.labels.contains(&fall)
+				|| loops.last().is_some_and(move |scope| Some(fall) == scope.exit)
+			{
+				break
+			}
+			let snapshot = self.snapshot();
+			let mut speculative = state.clone();
+			let mut scratch = Body::default();
+			let exit = self.lift_block(fall, &mut speculative, &mut scratch);
+			let targets = (
+				target(fall, EdgeKind::Branch),
+				target(fall, EdgeKind::Fallthrough),
+			);
+			let (Exit::Branch(next), (Some(next_taken), Some(next_fall))) =
+				(exit, targets)
+			else {
+				self.restore(snapshot);
+				break
+			};
+			if !scratch.is_empty() {
+				self.restore(snapshot);
+				break
+			}
+			if next_taken == taken {
+				condition = Expr::binary(BinaryOp::Or, condition, next);
+			} else if next_fall == taken {
+				condition = Expr::binary(BinaryOp::And, negate(condition), next);
+				taken = next_taken;
+			} else {
+				self.restore(snapshot);
+				break
+			}
+			self.visited[fall] = true;
+			*state = speculative;
+			fall = next_fall;
+		}
+		if taken == fall {
+			return (Some(taken), false)
+		}
+
+		// Jumps out of a loop or to its next iteration.
+		if let Some(scope) = loops.last().copied() {
+			for (target, other, condition) in [
+				(taken, fall, condition.clone()),
+				(fall, taken, negate(condition.clone())),
+			] {
+				let jump = if Some(target) == scope.exit {
+					Stmt::Break
+				} else if target == scope.header {
+					Stmt::Continue
+				} else {
+					continue
+				};
+				self.seal(state);
+				body.push(Stmt::If(condition, vec![jump], Vec::new()));
+				return (Some(other), false)
+			}
+		}
+
+		let merge = self.merge(b, loops);
+		if let Some(merge) = merge {
+			if let Some(value) = self.ternary(&condition, taken, fall, merge, state) {
+				state.pri = Value::new(value);
+				return (Some(merge), true)
+			}
+		}
+		self.seal(state);
+		let then = self.arm(fall, merge, loops, state);
+		let otherwise = self.arm(taken, merge, loops, state);
+		body.push(Stmt::If(negate(condition), then, otherwise));
+		self.reset(state);
+		(merge, false)
+	}
+
+	/// Return the value of `?:` if both branches only compute `PRI` before
+	/// they meet.
+	fn ternary(
+		&mut self,
+		condition: &Expr,
+		taken: usize,
+		fall: usize,
+		merge: usize,
+		state: &State,
+	) -> Option<Expr> {
+		if taken == merge
+			|| fall == merge
+			|| self.cfg.blocks[merge].predecessors.len() != 2
+		{
+			return None
+		}
+		let snapshot = self.snapshot();
+		let fall_value = self.ternary_arm(fall, merge, state);
+		match (fall_value, self.ternary_arm(taken, merge, state)) {
+			(Some(a), Some(b)) => {
+				self.visited[fall] = true;
+				self.visited[taken] = true;
+				Some(ternary(negate(condition.clone()), a, b))
+			}
+			_ => {
+				self.restore(snapshot);
+				None
+			}
+		}
+	}
+
+	fn ternary_arm(&mut self, block: usize, merge: usize, state: &State) -> Option<Expr> {
+		let b = &self.cfg.blocks[block];
+		if self.visited[block]
+			|| !b.predecessors.iter().all(|p| self.visited[*p])
+			|| b.successors.len() != 1
+			|| b.successors[0].block != merge
+			|| self.labels.contains(&block)
+			|| self.graph.loops[block].is_some()
+		{
+			return None
+		}
+		let mut arm = state.clone();
+		let mut scratch = Body::default();
+		let Exit::Next = self.lift_block(block, &mut arm, &mut scratch) else {
+			return None
+		};
+		let unchanged = arm.pri.expr == state.pri.expr;
+		let pure = scratch.is_empty() && arm.depth == state.depth;
+		(pure && !unchanged).then_some(arm.pri.expr)
+	}
+
+	/// Structure the blocks from `start` until `merge` into a new body.
+	fn arm(
+		&mut self,
+		start: usize,
+		merge: Option<usize>,
+		loops: &mut Vec<Scope>,
+		state: &State,
+	) -> Vec<Stmt> {
+		if Some(start) == merge {
+			return Vec::new()
+		}
+		let mut body = Body::default();
+		self.structure(start, merge, loops, &mut state.clone(), &mut body, false);
+		body.finish()
+	}
+
+	fn structure_switch(
+		&mut self,
+		b: usize,
+		value: Expr,
+		loops: &mut Vec<Scope>,
+		state: &mut State,
+		body: &mut Body,
+	) -> Option<usize> {
+		let merge = self.merge(b, loops);
+		self.seal(state);
+		let mut groups: Vec<(usize, Vec<Expr>)> = Vec::new();
+		let mut default = None;
+		for edge in self.cfg.blocks[b].successors.iter() {
+			match edge.kind {
+				EdgeKind::Case(case) => {
+					let group = groups.iter_mut().find(|(t, _)| *t == edge.block);
+					match group {
+						Some((_, values)) => values.push(Expr::Int(case)),
+						None => groups.push((edge.block, vec![Expr::Int(case)])),
+					}
+				}
+				EdgeKind::Default => default = Some(edge.block),
+				_ => {}
+			}
+		}
+		let cases = groups.into_iter()
+			.map(|(target, values)| (values, self.arm(target, merge, loops, state)))
+			.collect();
+		let default = default.filter(move |d| Some(*d) != merge)
+			.map(|d| self.arm(d, merge, loops, state));
+		body.push(Stmt::Switch(value, cases, default));
+		self.reset(state);
+		merge
+	}
+
+	fn finish(mut self, body: Vec<Stmt>) -> DecompiledFunction {
+		let mut body = simplify(body);
+		let ret = self.sig.as_ref().and_then(move |sig| sig.ret.clone());
+		let ret = ret.unwrap_or_else(|| {
+			let mut void = true;
+			visit_returns(&body, &mut |value| {
+				void &= matches!(value, None | Some(Expr::Int(0) | Expr::Register(_)));
+			});
+			VarType::new(if void { "void" } else { "int" })
+		});
+		if ret.is_void() {
+			strip_return_values(&mut body);
+			if body.last() == Some(&Stmt::Return(None)) {
+				body.pop();
+			}
+		}
+
+		// Temporaries take the type of their value, and locals without debug
+		// information are floats if a float is assigned to them.
+		let inferred: Vec<bool> = self.vars.iter()
+			.map(|var| match var.kind {
+				VarKind::Temp => true,
+				VarKind::Local(offset) => {
+					var.ty.dims.is_empty()
+						&& !self.locals.iter().any(move |local| local.offset == offset)
+				}
+				_ => false,
+			})
+			.collect();
+		let mut typer = Typer { dec: self.dec, vars: &mut self.vars, ret: &ret };
+		let mut assigns = Vec::new();
+		visit_assigns(&body, &mut |var, value| assigns.push((var, value.clone())));
+		for (var, value) in assigns {
+			if !inferred.get(var).copied().unwrap_or_default() {
+				continue
+			}
+			let ty = typer.type_of(&value).filter(move |ty| ty.dims.is_empty());
+			match ty {
+				Some(ty) if typer.vars[var].kind == VarKind::Temp => {
+					typer.vars[var].ty = VarType { by_ref: false, is_const: false, ..ty };
+				}
+				Some(ty) if ty.is_float() => typer.vars[var].ty = ty,
+				_ => {}
+			}
+		}
+		body.iter_mut().for_each(|stmt| typer.stmt(stmt));
+
+		DecompiledFunction {
+			name: self.dec.function_name(self.function.start),
+			start: self.function.start,
+			public: self.function.public,
+			ret,
+			args: self.args,
+			vars: self.vars,
+			body,
+		}
+	}
+}
+
+/// Return `a + b`, recognizing the address of a row of an array, which is
+/// the address of its indirection vector entry plus the entry.
+fn add(a: Expr, b: Expr) -> Expr {
+	match (a, b) {
+		(Expr::Address(x), Expr::Int(0)) | (Expr::Int(0), Expr::Address(x)) => {
+			Expr::Address(x)
+		}
+		(Expr::Address(x), y) | (y, Expr::Address(x)) if *x == y => Expr::Address(x),
+		(a, b) => Expr::binary(BinaryOp::Add, a, b),
+	}
+}
+
+/// Mark an integer constant as float bits.
+fn float(expr: Expr) -> Expr {
+	match expr {
+		Expr::Int(bits) => Expr::Float(bits),
+		other => other,
+	}
+}
+
+/// Return the frame offsets that an instruction accesses.
+fn frame_offsets(instruction: &Instruction) -> Vec<Cell> {
+	use Instruction as I;
+	match *instruction {
+		I::LoadSPri { offset }
+			| I::LoadSAlt { offset }
+			| I::LrefSPri { offset }
+			| I::LrefSAlt { offset }
+			| I::AddrPri { offset }
+			| I::AddrAlt { offset }
+			| I::StorSPri { offset }
+			| I::StorSAlt { offset }
+			| I::SrefSPri { offset }
+			| I::SrefSAlt { offset } => vec![offset],
+		I::ZeroS { stack_1 }
+			| I::IncS { stack_1 }
+			| I::DecS { stack_1 }
+			| I::ConstS { stack_1, .. } => vec![stack_1],
+		I::LoadSBoth { stack_1, stack_2 } => vec![stack_1, stack_2],
+		_ => match push_operands(instruction) {
+			Some((PushKind::Stack | PushKind::Adr, operands)) => operands,
+			_ => Vec::new(),
+		},
+	}
+}
+
+fn visit_returns(stmts: &[Stmt], f: &mut impl FnMut(Option<&Expr>)) {
+	for stmt in stmts.iter() {
+		match stmt {
+			Stmt::Return(value) => f(value.as_ref()),
+			Stmt::If(_, a, b) => {
+				visit_returns(a, f);
+				visit_returns(b, f);
+			}
+			Stmt::While(_, body) | Stmt::DoWhile(body, _) | Stmt::For(_, _, _, body) => {
+				visit_returns(body, f);
+			}
+			Stmt::Switch(_, cases, default) => {
+				cases.iter().for_each(|(_, body)| visit_returns(body, f));
+				visit_returns(default.as_deref().unwrap_or_default(), f);
+			}
+			_ => {}
+		}
+	}
+}
+
+/// Call `f` on every value that is assigned to a variable.
+fn visit_assigns(stmts: &[Stmt], f: &mut impl FnMut(usize, &Expr)) {
+	for stmt in stmts.iter() {
+		match stmt {
+			Stmt::Decl(var, Some(value)) | Stmt::Assign(Expr::Var(var), value) => {
+				f(*var, value);
+			}
+			Stmt::If(_, a, b) => {
+				visit_assigns(a, f);
+				visit_assigns(b, f);
+			}
+			Stmt::While(_, body) | Stmt::DoWhile(body, _) => visit_assigns(body, f),
+			Stmt::For(init, _, step, body) => {
+				visit_assigns(std::slice::from_ref(init), f);
+				visit_assigns(std::slice::from_ref(step), f);
+				visit_assigns(body, f);
+			}
+			Stmt::Switch(_, cases, default) => {
+				cases.iter().for_each(|(_, body)| visit_assigns(body, f));
+				visit_assigns(default.as_deref().unwrap_or_default(), f);
+			}
+			_ => {}
+		}
+	}
+}
+
+fn strip_return_values(stmts: &mut [Stmt]) {
+	for stmt in stmts.iter_mut() {
+		match stmt {
+			Stmt::Return(value) => *value = None,
+			Stmt::If(_, a, b) => {
+				strip_return_values(a);
+				strip_return_values(b);
+			}
+			Stmt::While(_, body) | Stmt::DoWhile(body, _) | Stmt::For(_, _, _, body) => {
+				strip_return_values(body);
+			}
+			Stmt::Switch(_, cases, default) => {
+				cases.iter_mut().for_each(|(_, body)| strip_return_values(body));
+				strip_return_values(default.as_deref_mut().unwrap_or_default());
+			}
+			_ => {}
+		}
+	}
+}
+
+/// Rewrite structured statements into their idiomatic forms.
+fn simplify(stmts: Vec<Stmt>) -> Vec<Stmt> {
+	let mut out: Vec<Stmt> = Vec::new();
+	for stmt in stmts {
+		match stmt {
+			Stmt::If(condition, then, otherwise) => {
+				let (then, otherwise) = (simplify(then), simplify(otherwise));
+				let (condition, then, otherwise) = if then.is_empty() {
+					(negate(condition), otherwise, Vec::new())
+				} else {
+					(condition, then, otherwise)
+				};
+				if then.is_empty() {
+					continue
+				}
+				if !otherwise.is_empty() && ends_with_jump(&then) {
+					out.push(Stmt::If(condition, then, Vec::new()));
+					out.extend(otherwise);
+				} else {
+					out.push(Stmt::If(condition, then, otherwise));
+				}
+			}
+			Stmt::While(condition, body) => {
+				out.push(simplify_loop(condition, simplify(body)));
+			}
+			Stmt::DoWhile(body, condition) => {
+				out.push(Stmt::DoWhile(simplify(body), condition));
+			}
+			Stmt::Switch(value, cases, default) => {
+				let cases = cases.into_iter()
+					.map(move |(values, body)| (values, simplify(body)))
+					.collect();
+				out.push(Stmt::Switch(value, cases, default.map(simplify)));
+			}
+			Stmt::Assign(Expr::Var(var), value) if !value.reads(Some(var))
+				&& out.last() == Some(&Stmt::Decl(var, None)) =>
+			{
+				out.pop();
+				out.push(Stmt::Decl(var, Some(value)));
+			}
+			stmt => out.push(stmt),
+		}
+	}
+	fold_for(out)
+}
+
+fn simplify_loop(condition: Expr, mut body: Vec<Stmt>) -> Stmt {
+	if body.last() == Some(&Stmt::Continue) {
+		body.pop();
+	}
+	if condition != Expr::Bool(true) {
+		return Stmt::While(condition, body)
+	}
+	if let Some(Stmt::If(exit, then, otherwise)) = body.first() {
+		if *then == [Stmt::Break] && otherwise.is_empty() {
+			let condition = negate(exit.clone());
+			body.remove(0);
+			return Stmt::While(condition, body)
+		}
+	}
+	if let [.., Stmt::If(repeat, then, otherwise), Stmt::Break] = body.as_slice() {
+		if *then == [Stmt::Continue] && otherwise.is_empty() {
+			let repeat = repeat.clone();
+			body.truncate(body.len() - 2);
+			if !has_continue(&body) {
+				return Stmt::DoWhile(body, repeat)
+			}
+			body.push(Stmt::If(repeat, vec![Stmt::Continue], Vec::new()));
+			body.push(Stmt::Break);
+		}
+	}
+	Stmt::While(condition, body)
+}
+
+/// Turn `while` loops that step a variable after it is initialized into
+/// `for` loops.
+fn fold_for(stmts: Vec<Stmt>) -> Vec<Stmt> {
+	let mut out: Vec<Stmt> = Vec::new();
+	let mut iter = stmts.into_iter().peekable();
+	while let Some(stmt) = iter.next() {
+		let var = match &stmt {
+			Stmt::Decl(var, Some(_)) | Stmt::Assign(Expr::Var(var), _) => *var,
+			_ => {
+				out.push(stmt);
+				continue
+			}
+		};
+		let is_step = |step: &Stmt| match step {
+			Stmt::Inc(Expr::Var(v))
+				| Stmt::Dec(Expr::Var(v))
+				| Stmt::Assign(Expr::Var(v), _) =>
+				*v == var,
+			_ => false,
+		};
+		let foldable = match iter.peek() {
+			Some(Stmt::While(condition, body)) => {
+				condition.reads(Some(var))
+					&& body.len() > 1
+					&& body.last().is_some_and(is_step)
+					&& !has_continue(body)
+			}
+			_ => false,
+		};
+		if !foldable {
+			out.push(stmt);
+			continue
+		}
+		let Some(Stmt::While(condition, mut body)) = iter.next() else {
+			unreachable!()
+		};
+		// A declaration is scoped to the loop, so it must not be used later.
+		let rest: Vec<Stmt> = iter.collect();
+		let (init, rest) = match stmt {
+			Stmt::Decl(..) if rest.iter().any(move |stmt| stmt.reads(var)) => {
+				out.push(stmt);
+				out.push(Stmt::While(condition, body));
+				out.extend(fold_for(rest));
+				return out
+			}
+			init => (init, rest),
+		};
+		let step = body.pop().unwrap();
+		out.push(Stmt::For(Box::new(init), condition, Box::new(step), body));
+		out.extend(fold_for(rest));
+		return out
+	}
+	out
+}
+
+/// Retyping of constants in decompiled statements.
+struct Typer<'a> {
+	dec: &'a Decompiler,
+	vars: &'a mut Vec<Variable>,
+	ret: &'a VarType,
+}
+
+impl Typer<'_> {
+	fn stmt(&mut self, stmt: &mut Stmt) {
+		match stmt {
+			Stmt::Decl(var, Some(init)) => {
+				let ty = self.vars[*var].ty.clone();
+				self.coerce(init, &ty);
+			}
+			Stmt::Assign(lvalue, value) => {
+				self.expr(lvalue);
+				match self.type_of(lvalue) {
+					Some(ty) => self.coerce(value, &ty),
+					None => self.expr(value),
+				}
+			}
+			Stmt::Inc(expr) | Stmt::Dec(expr) | Stmt::Expr(expr) => self.expr(expr),
+			Stmt::If(condition, then, otherwise) => {
+				self.expr(condition);
+				for stmt in then.iter_mut().chain(otherwise.iter_mut()) {
+					self.stmt(stmt);
+				}
+			}
+			Stmt::While(condition, body) | Stmt::DoWhile(body, condition) => {
+				self.expr(condition);
+				body.iter_mut().for_each(|stmt| self.stmt(stmt));
+			}
+			Stmt::For(init, condition, step, body) => {
+				self.stmt(init);
+				self.expr(condition);
+				self.stmt(step);
+				body.iter_mut().for_each(|stmt| self.stmt(stmt));
+			}
+			Stmt::Switch(value, cases, default) => {
+				self.expr(value);
+				let ty = self.type_of(value);
+				for (values, body) in cases.iter_mut() {
+					if let Some(ty) = ty.as_ref() {
+						values.iter_mut().for_each(|value| self.coerce(value, ty));
+					}
+					body.iter_mut().for_each(|stmt| self.stmt(stmt));
+				}
+				default.iter_mut().flatten().for_each(|stmt| self.stmt(stmt));
+			}
+			Stmt::Return(Some(value)) => {
+				let ret = self.ret.clone();
+				self.coerce(value, &ret);
+			}
+			_ => {}
+		}
+	}
+
+	fn expr(&mut self, expr: &mut Expr) {
+		match expr {
+			Expr::Call(callee, args) => {
+				let sig = self.dec.callee_sig(callee);
+				let native = matches!(callee, Callee::Native { .. });
+				for (n, arg) in args.iter_mut().enumerate() {
+					match sig.as_ref().and_then(move |sig| sig.arg(n)) {
+						Some(ty) => self.coerce(arg, ty),
+						// Without types, only literals of native calls are recognized.
+						None if native => self.coerce(arg, &VarType {
+							dims: vec![None],
+							..VarType::int()
+						}),
+						None => self.expr(arg),
+					}
+				}
+			}
+			Expr::Binary(op, a, b) => {
+				self.expr(a);
+				self.expr(b);
+				// Operands of `&&` and `||` are conditions on their own.
+				if !matches!(op, BinaryOp::And | BinaryOp::Or) {
+					let scalar = |ty: &VarType| ty.dims.is_empty();
+					if let Some(ty) = self.type_of(a).filter(scalar) {
+						self.coerce(b, &ty);
+					} else if let Some(ty) = self.type_of(b).filter(scalar) {
+						self.coerce(a, &ty);
+					}
+				}
+			}
+			Expr::Ternary(a, b, c) => {
+				self.expr(a);
+				self.expr(b);
+				self.expr(c);
+			}
+			Expr::Index(a, b) => {
+				self.expr(a);
+				self.expr(b);
+			}
+			Expr::Deref(a) | Expr::Address(a) | Expr::Unary(_, a) => self.expr(a),
+			Expr::NewArray(dims) => dims.iter_mut().for_each(|dim| self.expr(dim)),
+			_ => {}
+		}
+	}
+
+	/// Rewrite an expression that is used as a value of a type.
+	fn coerce(&mut self, expr: &mut Expr, ty: &VarType) {
+		match expr {
+			Expr::Int(value) => {
+				let value = *value;
+				if ty.is_float() {
+					*expr = Expr::Float(value);
+				} else if ty.is_bool() && (value == 0 || value == 1) {
+					*expr = Expr::Bool(value == 1);
+				} else if ty.function {
+					if let Some(name) = self.dec.function_id_name(value) {
+						*expr = Expr::Function(name);
+					}
+				} else if !ty.dims.is_empty() || ty.by_ref {
+					if let Some((name, global)) = self.dec.globals.get(&value) {
+						let var = self.vars.iter()
+							.position(|v| {
+								v.kind == VarKind::Global(value) && v.name == *name
+							})
+							.unwrap_or_else(|| {
+								self.vars.push(Variable {
+									name: name.clone(),
+									ty: global.clone(),
+									kind: VarKind::Global(value),
+								});
+								self.vars.len() - 1
+							});
+						*expr = Expr::Var(var);
+					} else if usize::try_from(value)
+						.is_ok_and(|at| self.dec.plugin.data.is_string_start(at))
+					{
+						if let Some(string) = self.dec.plugin.data.string_at(value) {
+							*expr = Expr::String(string);
+						}
+					}
+				}
+			}
+			Expr::Ternary(condition, a, b) => {
+				self.expr(condition);
+				self.coerce(a, ty);
+				self.coerce(b, ty);
+			}
+			other => self.expr(other),
+		}
+	}
+
+	/// Return the type of an expression, if it is known.
+	fn type_of(&self, expr: &Expr) -> Option<VarType> {
+		match expr {
+			Expr::Var(var) => self.vars.get(*var).map(move |v| v.ty.clone()),
+			Expr::Index(base, _) => self.type_of(base).map(move |ty| ty.element()),
+			Expr::Deref(inner) | Expr::Address(inner) => self.type_of(inner),
+			Expr::Float(_) => Some(VarType::new("float")),
+			Expr::Bool(_) => Some(VarType::new("bool")),
+			Expr::String(_) => Some(VarType { dims: vec![None], ..VarType::new("char") }),
+			Expr::Call(callee, _) => self.dec.callee_sig(callee)?.ret,
+			Expr::Binary(op, _, _) if op.is_boolean() => Some(VarType::new("bool")),
+			Expr::Binary(_, a, b) => {
+				[a, b].into_iter().filter_map(|e| self.type_of(e)).find(VarType::is_float)
+			}
+			Expr::Unary(UnaryOp::Not, _) => Some(VarType::new("bool")),
+			Expr::Unary(_, inner) => self.type_of(inner),
+			Expr::Ternary(_, a, b) => self.type_of(a).or_else(|| self.type_of(b)),
+			_ => None,
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::{
+		case_table::Case,
+		debug::{
+			DebugInfo,
+			Symbol,
+		},
+		rtti::{
+			type_codes,
+			DebugMethod,
+			DebugVar,
+			Rtti,
+			RttiMethod,
+		},
+		sections::Public,
+	};
+	use byteorder::LittleEndian as Le;
+
+	/// Decompile a plugin with code, data, publics and a `PrintToServer`
+	/// native.
+	fn decompile(
+		code: Vec<Instruction>,
+		data: &[u8],
+		publics: &[(u32, &CStr)],
+	) -> Result<String, Box<dyn Error>> {
+		let mut plugin = Plugin::default();
+		plugin.code.version = 13;
+		plugin.code.cell_size = 4;
+		for instruction in code {
+			let _ = instruction.write_to(&mut plugin.code.code);
+		}
+		plugin.data.bytes = data.to_vec();
+		plugin.add_native(c"PrintToServer");
+		for (address, name) in publics.iter() {
+			let name = plugin.names.insert(name) as u32;
+			plugin.publics.push(Public { address: *address, name });
+		}
+		let mut smx = Smx::new();
+		plugin.write_into::<Le>(&mut smx);
+		Ok(Decompiler::from_smx::<Le, _, _>(&smx)?.decompile())
+	}
+
+	#[test]
+	fn if_else() -> Result<(), Box<dyn Error>> {
+		let code = vec![
+			Instruction::Proc,
+			Instruction::PushC { const_1: 5 },
+			Instruction::LoadSPri { offset: -4 },
+			Instruction::ConstAlt { value: 3 },
+			Instruction::Sgrtr,
+			Instruction::Jzer { jump_1: 68 },
+			Instruction::PushC { const_1: 0 },
+			Instruction::SysreqN { native: 0, n_args: 1 },
+			Instruction::Jump { jump_1: 88 },
+			// 68: else
+			Instruction::PushC { const_1: 4 },
+			Instruction::SysreqN { native: 0, n_args: 1 },
+			// 88: end
+			Instruction::Stack { const_1: 4 },
+			Instruction::ZeroPri,
+			Instruction::Retn,
+			Instruction::Endproc,
+		];
+		let output = decompile(code, b"big\0small\0\0\0", &[(0, c"OnPluginStart")])?;
+		assert_eq!(output, concat!(
+			"public void OnPluginStart()\n",
+			"{\n",
+			"\tint local_1 = 5;\n",
+			"\tif (local_1 > 3)\n",
+			"\t{\n",
+			"\t\tPrintToServer(\"big\");\n",
+			"\t}\n",
+			"\telse\n",
+			"\t{\n",
+			"\t\tPrintToServer(\"small\");\n",
+			"\t}\n",
+			"}\n",
+		));
+		Ok(())
+	}
+
+	#[test]
+	fn for_loop() -> Result<(), Box<dyn Error>> {
+		let code = vec![
+			Instruction::Proc,
+			Instruction::PushC { const_1: 0 },
+			// 12: condition
+			Instruction::LoadSPri { offset: -4 },
+			Instruction::ConstAlt { value: 10 },
+			Instruction::Jsgeq { jump_1: 80 },
+			Instruction::PushS { stack_1: -4 },
+			Instruction::PushC { const_1: 0 },
+			Instruction::SysreqN { native: 0, n_args: 2 },
+			Instruction::IncS { stack_1: -4 },
+			Instruction::Jump { jump_1: 12 },
+			// 80: exit
+			Instruction::Stack { const_1: 4 },
+			Instruction::ZeroPri,
+			Instruction::Retn,
+			Instruction::Endproc,
+		];
+		let output = decompile(code, b"%d\0\0", &[(0, c"OnPluginStart")])?;
+		assert_eq!(output, concat!(
+			"public void OnPluginStart()\n",
+			"{\n",
+			"\tfor (int local_1 = 0; local_1 < 10; local_1++)\n",
+			"\t{\n",
+			"\t\tPrintToServer(\"%d\", local_1);\n",
+			"\t}\n",
+			"}\n",
+		));
+		Ok(())
+	}
+
+	#[test]
+	fn switch_ternary() -> Result<(), Box<dyn Error>> {
+		let code = vec![
+			// 0: helper
+			Instruction::Proc,
+			Instruction::LoadSPri { offset: 12 },
+			Instruction::ConstAlt { value: 0 },
+			Instruction::Jsleq { jump_1: 68 },
+			Instruction::LoadSPri { offset: 12 },
+			Instruction::ConstAlt { value: 10 },
+			Instruction::Jsgeq { jump_1: 68 },
+			Instruction::ConstPri { value: 5 },
+			Instruction::Jump { jump_1: 76 },
+			// 68
+			Instruction::ConstPri { value: 7 },
+			// 76
+			Instruction::Retn,
+			Instruction::Endproc,
+			// 84: OnPluginStart
+			Instruction::Proc,
+			Instruction::PushC { const_1: 3 },
+			Instruction::PushC { const_1: 4 },
+			Instruction::Call { func_1: 0 },
+			Instruction::Switch { jump_1: 120 },
+			Instruction::Casetbl {
+				default: 212,
+				cases: vec![
+					Case { value: 5, jump: 156 },
+					Case { value: 1, jump: 184 },
+					Case { value: 2, jump: 184 },
+				],
+				switch: None,
+			},
+			// 156
+			Instruction::PushC { const_1: 0 },
+			Instruction::SysreqN { native: 0, n_args: 1 },
+			Instruction::Jump { jump_1: 232 },
+			// 184
+			Instruction::PushC { const_1: 8 },
+			Instruction::SysreqN { native: 0, n_args: 1 },
+			Instruction::Jump { jump_1: 232 },
+			// 212
+			Instruction::PushC { const_1: 12 },
+			Instruction::SysreqN { native: 0, n_args: 1 },
+			// 232
+			Instruction::ZeroPri,
+			Instruction::Retn,
+			Instruction::Endproc,
+		];
+		let data = b"five\0\0\0\0low\0other\0\0\0";
+		let output = decompile(code, data, &[(84, c"OnPluginStart")])?;
+		assert_eq!(output, concat!(
+			"int sub_00000000(int arg_0)\n",
+			"{\n",
+			"\treturn arg_0 > 0 && arg_0 < 10 ? 5 : 7;\n",
+			"}\n",
+			"\n",
+			"public void OnPluginStart()\n",
+			"{\n",
+			"\tswitch (sub_00000000(3))\n",
+			"\t{\n",
+			"\t\tcase 5:\n",
+			"\t\t{\n",
+			"\t\t\tPrintToServer(\"five\");\n",
+			"\t\t}\n",
+			"\t\tcase 1, 2:\n",
+			"\t\t{\n",
+			"\t\t\tPrintToServer(\"low\");\n",
+			"\t\t}\n",
+			"\t\tdefault:\n",
+			"\t\t{\n",
+			"\t\t\tPrintToServer(\"other\");\n",
+			"\t\t}\n",
+			"\t}\n",
+			"}\n",
+		));
+		Ok(())
+	}
+
+	/// Create a plugin whose `OnPluginStart` has a `float`, a `bool` and a
+	/// `char[8]` local, and whose data image has room for `g_Ratio`, `g_Ok`,
+	/// `g_Grid[2][3]` and `g_Name[16]`.
+	fn typed_plugin() -> Plugin {
+		let mut plugin = Plugin::default();
+		plugin.code.version = 13;
+		plugin.code.cell_size = 4;
+		for instruction in [
+			Instruction::Proc,
+			Instruction::PushC { const_1: 0x3f80_0000 },
+			Instruction::PushC { const_1: 1 },
+			Instruction::Stack { const_1: -8 },
+			Instruction::PushAdr { stack_1: -16 },
+			Instruction::SysreqN { native: 0, n_args: 1 },
+			Instruction::LoadSPri { offset: -4 },
+			Instruction::StorPri { offset: 0 },
+			Instruction::LoadSPri { offset: -8 },
+			Instruction::StorPri { offset: 4 },
+			Instruction::Stack { const_1: 16 },
+			Instruction::ZeroPri,
+			Instruction::Retn,
+			Instruction::Endproc,
+		] {
+			let _ = instruction.write_to(&mut plugin.code.code);
+		}
+		plugin.data.bytes = vec![0; 48];
+		plugin.add_native(c"PrintToServer");
+		let name = plugin.names.insert(c"OnPluginStart") as u32;
+		plugin.publics.push(Public { address: 0, name });
+		plugin
+	}
+
+	#[test]
+	fn debug_symbols() -> Result<(), Box<dyn Error>> {
+		let mut plugin = typed_plugin();
+		let end = plugin.code.code.len() as u32;
+		let tags = [c"_", c"Float", c"bool", c"String"].into_iter().enumerate()
+			.map(|(id, name)| Tag {
+				id: id as u32,
+				name: plugin.names.insert(name) as u32,
+			})
+			.collect();
+
+		let mut debug = DebugInfo::default();
+		let mut symbol = |name: &CStr, address, tag, kind, scope, dims: &[u32]| {
+			let name = debug.strings.insert(name) as u32;
+			debug.symbols.push(Symbol {
+				address,
+				tag,
+				code_start: 0,
+				code_end: end,
+				kind,
+				scope,
+				dims: dims.iter().map(move |&size| ArrayDim { tag: 0, size }).collect(),
+				name,
+			});
+		};
+		use SymbolKind::*;
+		use SymbolScope::*;
+		symbol(c"OnPluginStart", 0, 0, Function, Global, &[]);
+		symbol(c"g_Ratio", 0, 1, Variable, Global, &[]);
+		symbol(c"g_Ok", 4, 2, Variable, Global, &[]);
+		symbol(c"g_Grid", 8, 0, Array, Global, &[2, 3]);
+		symbol(c"g_Name", 32, 3, Array, Global, &[16]);
+		symbol(c"ratio", -4, 1, Variable, Local, &[]);
+		symbol(c"ok", -8, 2, Variable, Local, &[]);
+		symbol(c"name", -16, 3, Array, Local, &[8]);
+
+		let symbols = Symbolizer { debug, ..Default::default() };
+		let output = Decompiler::new(plugin, symbols, tags)?.decompile();
+		assert_eq!(output, concat!(
+			"float g_Ratio;\n",
+			"bool g_Ok;\n",
+			"int g_Grid[2][3];\n",
+			"char g_Name[16];\n",
+			"\n",
+			"public int OnPluginStart()\n",
+			"{\n",
+			"\tfloat ratio = 1.0;\n",
+			"\tbool ok = true;\n",
+			"\tchar name[8];\n",
+			"\tPrintToServer(name);\n",
+			"\tg_Ratio = ratio;\n",
+			"\tg_Ok = ok;\n",
+			"\treturn 0;\n",
+			"}\n",
+		));
+		Ok(())
+	}
+
+	#[test]
+	fn rtti_types() -> Result<(), Box<dyn Error>> {
+		let mut plugin = typed_plugin();
+		let end = plugin.code.code.len() as u32;
+		let inline = move |codes: &[u8]| {
+			let mut bytes = [0; 4];
+			bytes[..codes.len()].copy_from_slice(codes);
+			u32::from_le_bytes(bytes) << 4
+		};
+		use type_codes::*;
+		let mut rtti = Rtti::default();
+		rtti.data.extend_from_slice(&[FUNCTION, 0, VOID]);
+		rtti.data.extend_from_slice(&[FIXED_ARRAY, 2, FIXED_ARRAY, 3, INT32]);
+		let grid = 3 << 4 | 1;
+		rtti.methods.push(RttiMethod {
+			name: plugin.names.insert(c"OnPluginStart") as u32,
+			pcode_start: 0,
+			pcode_end: end,
+			signature: 0,
+		});
+		rtti.debug_methods.push(DebugMethod { method_index: 0, first_local: 0 });
+
+		let mut var = |name: &CStr, address, vclass, type_id| DebugVar {
+			address,
+			vclass,
+			name: plugin.names.insert(name) as u32,
+			code_start: 0,
+			code_end: end,
+			type_id,
+		};
+		rtti.globals = vec![
+			var(c"g_Ratio", 0, 0, inline(&[FLOAT32])),
+			var(c"g_Ok", 4, 0, inline(&[BOOL])),
+			var(c"g_Grid", 8, 0, grid),
+			var(c"g_Name", 32, 0, inline(&[FIXED_ARRAY, 16, CHAR8])),
+		];
+		rtti.locals = vec![
+			var(c"ratio", -4, 1, inline(&[FLOAT32])),
+			var(c"ok", -8, 1, inline(&[BOOL])),
+			var(c"name", -16, 1, inline(&[FIXED_ARRAY, 8, CHAR8])),
+		];
+
+		let symbols = Symbolizer {
+			rtti,
+			names: plugin.names.clone(),
+			..Default::default()
+		};
+		let output = Decompiler::new(plugin, symbols, Vec::new())?.decompile();
+		assert_eq!(output, concat!(
+			"float g_Ratio;\n",
+			"bool g_Ok;\n",
+			"int g_Grid[2][3];\n",
+			"char g_Name[16];\n",
+			"\n",
+			"public void OnPluginStart()\n",
+			"{\n",
+			"\tfloat ratio = 1.0;\n",
+			"\tbool ok = true;\n",
+			"\tchar name[8];\n",
+			"\tPrintToServer(name);\n",
+			"\tg_Ratio = ratio;\n",
+			"\tg_Ok = ok;\n",
+			"}\n",
+		));
+		Ok(())
+	}
+}
@@ -0,0 +1,347 @@
+//! Dependency reports of plugins, and load order of sets of plugins.
+//!
+//! Extensions are required through public variables named `__ext_*` and
+//! library plugins through `__pl_*`, whose data holds the following structs,
+//! one cell per field, with strings as data offsets:
+//!
+//! ```sp
+//! struct Extension {
+//!     const char[] name;
+//!     const char[] file;
+//!     bool autoload;
+//!     bool required;
+//! };
+//!
+//! struct SharedPlugin {
+//!     const char[] name;
+//!     const char[] file;
+//!     bool required;
+//! };
+//! ```
+//!
+//! Optional natives and provided libraries are found through calls of
+//! `MarkNativeAsOptional` and `RegPluginLibrary` with constant strings.
+
+use crate::{
+	decoder::{
+		DecodeError,
+		Decoder,
+	},
+	optimize::{
+		push_operands,
+		PushKind,
+	},
+	plugin::Plugin,
+	vm_types::Cell,
+	Instruction,
+};
+
+use std::{
+	collections::{
+		BTreeSet,
+		HashMap,
+	},
+	error::Error,
+	fmt,
+};
+
+/// Extension that a plugin depends on, from an `__ext_*` public variable.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ExtensionDep {
+	/// Short name of the extension.
+	pub name: String,
+	/// File name of the extension.
+	pub file: String,
+	/// `true` if the extension should be loaded automatically.
+	pub autoload: bool,
+	/// `true` if the plugin fails to load without the extension.
+	pub required: bool,
+}
+
+/// Library plugin that a plugin depends on, from a `__pl_*` public variable.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct LibraryDep {
+	/// Name of the library.
+	pub name: String,
+	/// File name of the plugin that provides the library.
+	pub file: String,
+	/// `true` if the plugin fails to load without the library.
+	pub required: bool,
+}
+
+/// Dependencies of a plugin.
+#[derive(Default, Debug, Clone, PartialEq, Eq)]
+pub struct Dependencies {
+	/// Names of the natives in `.natives`.
+	pub natives: Vec<String>,
+	/// Extensions that are depended on.
+	pub extensions: Vec<ExtensionDep>,
+	/// Library plugins that are depended on.
+	pub libraries: Vec<LibraryDep>,
+	/// Natives that are marked as optional.
+	pub optional: BTreeSet<String>,
+	/// Libraries that the plugin registers.
+	pub provides: BTreeSet<String>,
+}
+
+/// Structure for an error that has occurred while building a dependency
+/// report.
+#[derive(Debug)]
+pub enum DependencyError {
+	/// Code could not be decoded.
+	Decode(DecodeError),
+	/// A dependency variable does not point to valid data.
+	InvalidVariable {
+		name: String,
+	},
+}
+
+impl fmt::Display for DependencyError {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			Self::Decode(e) => write!(f, "decode error {e}"),
+			Self::InvalidVariable { name } =>
+				write!(f, "dependency variable {name} has invalid data"),
+		}
+	}
+}
+
+impl Error for DependencyError {}
+
+impl From<DecodeError> for DependencyError {
+	fn from(value: DecodeError) -> Self {
+		Self::Decode(value)
+	}
+}
+
+/// Return the constant strings that are passed as the first argument to a
+/// native, wherever it is called.
+fn string_args(plugin: &Plugin, native: &str) -> Result<BTreeSet<String>, DecodeError> {
+	let mut strings = BTreeSet::new();
+	let Some(index) = plugin.natives.iter()
+		.enumerate()
+		.find(move |(index, _)| plugin.native_name(*index)
+			.is_some_and(move |name| name.to_bytes() == native.as_bytes()))
+		.map(move |(index, _)| index as Cell)
+	else {
+		return Ok(strings)
+	};
+
+	// Arguments are pushed in reverse order, so the first one is on top.
+	let mut top = None;
+	for decoded in Decoder::new(&plugin.code.code) {
+		let (_, instruction) = decoded?;
+		if let Instruction::SysreqN { native, .. } = instruction {
+			if native == index {
+				strings.extend(top.and_then(|address| plugin.data.string_at(address)));
+			}
+		}
+		top = match push_operands(&instruction) {
+			Some((PushKind::Const, ops)) => ops.last().copied(),
+			_ => None,
+		};
+	}
+	Ok(strings)
+}
+
+/// Build the dependency report of a plugin.
+pub fn dependencies(plugin: &Plugin) -> Result<Dependencies, DependencyError> {
+	let mut deps = Dependencies {
+		natives: (0..plugin.natives.len())
+			.filter_map(move |index| plugin.native_name(index))
+			.map(move |name| name.to_string_lossy().into_owned())
+			.collect(),
+		..Default::default()
+	};
+
+	for (index, pubvar) in plugin.pubvars.iter().enumerate() {
+		let Some(var) = plugin.pubvar_name(index) else {
+			continue
+		};
+		let var = var.to_string_lossy().into_owned();
+		let invalid = || DependencyError::InvalidVariable { name: var.clone() };
+		let address = pubvar.address as usize;
+		if var.starts_with("__ext_") {
+			let [name, file, autoload, required] = plugin.data.cells_at(address)
+				.ok_or_else(invalid)?;
+			deps.extensions.push(ExtensionDep {
+				name: plugin.data.string_at(name).ok_or_else(invalid)?,
+				file: plugin.data.string_at(file).ok_or_else(invalid)?,
+				autoload: autoload != 0,
+				required: required != 0,
+			});
+		} else if var.starts_with("__pl_") {
+			let [name, file, required] = plugin.data.cells_at(address)
+				.ok_or_else(invalid)?;
+			deps.libraries.push(LibraryDep {
+				name: plugin.data.string_at(name).ok_or_else(invalid)?,
+				file: plugin.data.string_at(file).ok_or_else(invalid)?,
+				required: required != 0,
+			});
+		}
+	}
+
+	deps.optional = string_args(plugin, "MarkNativeAsOptional")?;
+	deps.provides = string_args(plugin, "RegPluginLibrary")?;
+	Ok(deps)
+}
+
+/// Load order of a set of plugins.
+#[derive(Default, Debug, Clone, PartialEq, Eq)]
+pub struct LoadOrder {
+	/// Indices of plugins, where providers of libraries come before the
+	/// plugins that depend on them.
+	pub order: Vec<usize>,
+	/// Required libraries that no plugin of the set provides, by the index of
+	/// the plugin that requires them.
+	pub missing: Vec<(usize, LibraryDep)>,
+	/// Indices of plugins whose dependencies form a cycle, which are
+	/// appended to [`Self::order`] in their original order.
+	pub cycles: Vec<usize>,
+}
+
+/// Work out the load order of a set of plugins from their dependencies.
+pub fn load_order(plugins: &[Dependencies]) -> LoadOrder {
+	let mut result = LoadOrder::default();
+
+	let mut providers: HashMap<&str, Vec<usize>> = HashMap::new();
+	for (idx, deps) in plugins.iter().enumerate() {
+		for library in deps.provides.iter() {
+			providers.entry(library).or_default().push(idx);
+		}
+	}
+
+	let mut depends_on: Vec<BTreeSet<usize>> = vec![BTreeSet::new(); plugins.len()];
+	for (idx, deps) in plugins.iter().enumerate() {
+		for library in deps.libraries.iter() {
+			match providers.get(library.name.as_str()) {
+				Some(provider) => depends_on[idx].extend(
+					provider.iter().copied().filter(move |p| *p != idx)
+				),
+				None if library.required => result.missing.push((idx, library.clone())),
+				None => {}
+			}
+		}
+	}
+
+	let mut done = vec![false; plugins.len()];
+	loop {
+		let ready: Vec<usize> = (0..plugins.len())
+			.filter(|idx| !done[*idx] && depends_on[*idx].iter().all(|dep| done[*dep]))
+			.collect();
+		if ready.is_empty() {
+			break
+		}
+		for idx in ready {
+			done[idx] = true;
+			result.order.push(idx);
+		}
+	}
+	result.cycles = (0..plugins.len()).filter(move |idx| !done[*idx]).collect();
+	result.order.extend(result.cycles.iter().copied());
+	result
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::sections::{
+		Native,
+		Pubvar,
+	};
+
+	/// Append cells to the data image, returning their offset.
+	fn push_cells(plugin: &mut Plugin, cells: &[Cell]) -> Cell {
+		let offset = plugin.data.bytes.len() as Cell;
+		for cell in cells {
+			plugin.data.bytes.extend_from_slice(&cell.to_ne_bytes());
+		}
+		offset
+	}
+
+	/// Append a string to the data image, returning its offset.
+	fn push_str(plugin: &mut Plugin, s: &str) -> Cell {
+		let offset = plugin.data.bytes.len() as Cell;
+		plugin.data.bytes.extend_from_slice(s.as_bytes());
+		plugin.data.bytes.resize((offset as usize + s.len()).next_multiple_of(4) + 4, 0);
+		offset
+	}
+
+	#[test]
+	fn report() -> Result<(), DependencyError> {
+		let mut plugin = Plugin::default();
+		let sdktools = push_str(&mut plugin, "sdktools");
+		let sdktools_ext = push_str(&mut plugin, "sdktools.ext");
+		let ext = push_cells(&mut plugin, &[sdktools, sdktools_ext, 1, 1]);
+		let lib = push_str(&mut plugin, "mylib");
+		let lib_file = push_str(&mut plugin, "mylib.smx");
+		let pl = push_cells(&mut plugin, &[lib, lib_file, 0]);
+		let optional = push_str(&mut plugin, "MyLib_Do");
+
+		for (name, address) in [(c"__ext_sdktools", ext), (c"__pl_mylib", pl)] {
+			let name = plugin.names.insert(name) as u32;
+			plugin.pubvars.push(Pubvar { address: address as u32, name });
+		}
+		for name in [c"MarkNativeAsOptional", c"MyLib_Do"] {
+			let name = plugin.names.insert(name) as u32;
+			plugin.natives.push(Native { name });
+		}
+		for instruction in [
+			Instruction::PushC { const_1: optional },
+			Instruction::SysreqN { native: 0, n_args: 1 },
+		] {
+			let _ = instruction.write_to(&mut plugin.code.code);
+		}
+
+		let deps = dependencies(&plugin)?;
+		assert_eq!(deps.natives, ["MarkNativeAsOptional", "MyLib_Do"]);
+		assert_eq!(deps.extensions, [ExtensionDep {
+			name: "sdktools".into(),
+			file: "sdktools.ext".into(),
+			autoload: true,
+			required: true,
+		}]);
+		assert_eq!(deps.libraries, [LibraryDep {
+			name: "mylib".into(),
+			file: "mylib.smx".into(),
+			required: false,
+		}]);
+		assert_eq!(deps.optional, BTreeSet::from(["MyLib_Do".into()]));
+		assert!(deps.provides.is_empty());
+		Ok(())
+	}
+
+	#[test]
+	fn order() {
+		let library = move |name: &str, required| LibraryDep {
+			name: name.into(),
+			file: format!("{name}.smx"),
+			required,
+		};
+		let plugins = [
+			Dependencies {
+				libraries: vec![library("b", true), library("c", true)],
+				..Default::default()
+			},
+			Dependencies {
+				provides: BTreeSet::from(["b".into()]),
+				libraries: vec![library("d", false)],
+				..Default::default()
+			},
+			Dependencies {
+				provides: BTreeSet::from(["e".into()]),
+				libraries: vec![library("f", true)],
+				..Default::default()
+			},
+			Dependencies {
+				provides: BTreeSet::from(["f".into()]),
+				libraries: vec![library("e", true)],
+				..Default::default()
+			},
+		];
+		let order = load_order(&plugins);
+		assert_eq!(order.order, [1, 0, 2, 3]);
+		assert_eq!(order.missing, [(0, library("c", true))]);
+		assert_eq!(order.cycles, [2, 3]);
+	}
+}
@@ -0,0 +1,181 @@
+//! Export of control-flow and call graphs as Graphviz DOT text.
+
+use crate::{
+	cfg::{
+		Cfg,
+		EdgeKind,
+	},
+	functions::{
+		Function,
+		FunctionMap,
+	},
+	plugin::Plugin,
+	Instruction,
+};
+
+use std::{
+	collections::BTreeSet,
+	fmt::Write,
+};
+
+/// Escape a string for use inside of a quoted DOT string.
+fn escape(s: &str) -> String {
+	let mut out = String::with_capacity(s.len());
+	for c in s.chars() {
+		match c {
+			'"' | '\\' => {
+				out.push('\\');
+				out.push(c);
+			}
+			'\n' => out.push_str("\\n"),
+			_ => out.push(c),
+		}
+	}
+	out
+}
+
+/// Return the display name of a function, falling back to `sub_XXXXXXXX`.
+pub fn function_name(function: &Function) -> String {
+	function.name.as_ref()
+		.map(move |name| name.to_string_lossy().into_owned())
+		.unwrap_or_else(move || format!("sub_{:08x}", function.start))
+}
+
+/// Render a control-flow graph as a DOT digraph with the specified name.
+///
+/// Every block is labeled with its disassembled instructions.
+pub fn cfg_to_dot(cfg: &Cfg, name: &str) -> String {
+	let mut out = String::new();
+	let _ = writeln!(out, "digraph \"{}\" {{", escape(name));
+	out.push_str("\tnode [shape=box, fontname=\"monospace\"];\n");
+
+	for (idx, block) in cfg.blocks.iter().enumerate() {
+		let mut label = String::new();
+		for (offset, instruction) in block.instructions.iter() {
+			label.push_str(&escape(&format!("0x{offset:08x}: {instruction}")));
+			label.push_str("\\l");
+		}
+		let _ = writeln!(out, "\tb{idx} [label=\"{label}\"];");
+	}
+
+	for (idx, block) in cfg.blocks.iter().enumerate() {
+		for edge in block.successors.iter() {
+			let attrs = match edge.kind {
+				EdgeKind::Fallthrough => String::new(),
+				EdgeKind::Jump => " [style=bold]".into(),
+				EdgeKind::Branch => " [label=\"taken\", color=green]".into(),
+				EdgeKind::Case(value) => format!(" [label=\"{value}\"]"),
+				EdgeKind::Default => " [label=\"default\"]".into(),
+			};
+			let _ = writeln!(out, "\tb{idx} -> b{}{attrs};", edge.block);
+		}
+	}
+
+	out.push_str("}\n");
+	out
+}
+
+/// Render the call graph of a plugin as a DOT digraph.
+///
+/// Functions are named from `.publics` where possible, and natives from
+/// `.natives`. Public functions are drawn with a double outline, and natives
+/// as boxes.
+pub fn call_graph_to_dot(plugin: &Plugin, functions: &FunctionMap) -> String {
+	let mut out = String::new();
+	out.push_str("digraph calls {\n");
+
+	for function in functions.functions.iter() {
+		let shape = if function.public { "doubleoctagon" } else { "ellipse" };
+		let _ = writeln!(
+			out,
+			"\tf{:08x} [label=\"{}\", shape={shape}];",
+			function.start, escape(&function_name(function))
+		);
+	}
+
+	let mut natives = BTreeSet::new();
+	let mut edges = BTreeSet::new();
+	for function in functions.functions.iter() {
+		for (_, instruction) in function.instructions.iter() {
+			let callee = match instruction {
+				Instruction::Call { func_1 } => format!("f{:08x}", *func_1 as usize),
+				Instruction::SysreqC { native_1: native }
+					| Instruction::SysreqN { native, .. } => {
+					natives.insert(*native);
+					format!("n{native}")
+				}
+				_ => continue,
+			};
+			edges.insert((function.start, callee));
+		}
+	}
+
+	for native in natives {
+		let name = usize::try_from(native).ok()
+			.and_then(move |index| plugin.native_name(index))
+			.map(move |name| name.to_string_lossy().into_owned())
+			.unwrap_or_else(move || format!("native_{native}"));
+		let _ = writeln!(out, "\tn{native} [label=\"{}\", shape=box];", escape(&name));
+	}
+
+	for (caller, callee) in edges {
+		let _ = writeln!(out, "\tf{caller:08x} -> {callee};");
+	}
+
+	out.push_str("}\n");
+	out
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::{
+		cfg,
+		functions,
+		instruction::layout,
+		sections::Native,
+	};
+	use std::ffi::CString;
+
+	#[test]
+	fn cfg() {
+		let (code, len) = layout(vec![
+			Instruction::Jzer { jump_1: 12 },
+			Instruction::Nop,
+			Instruction::Retn,
+		]);
+		let dot = cfg_to_dot(&cfg::build(&code, len), "say \"hi\"");
+		assert!(dot.starts_with("digraph \"say \\\"hi\\\"\" {\n"));
+		assert!(dot.contains("b0 [label=\"0x00000000: jzer 0x0000000c\\l\"];"));
+		assert!(dot.contains("b0 -> b1;"));
+		assert!(dot.contains("b0 -> b2 [label=\"taken\", color=green];"));
+	}
+
+	#[test]
+	fn call_graph() {
+		let mut plugin = Plugin::default();
+		let name = plugin.names.insert(c"PrintToServer") as u32;
+		plugin.natives.push(Native { name });
+
+		let (code, len) = layout(vec![
+			Instruction::Proc,
+			Instruction::Call { func_1: 32 },
+			Instruction::SysreqN { native: 0, n_args: 1 },
+			Instruction::Retn,
+			Instruction::Endproc,
+			Instruction::Proc,
+			Instruction::Retn,
+			Instruction::Endproc,
+		]);
+		let publics = [(0, CString::new("OnPluginStart").unwrap())];
+		let map = functions::discover(&code, len, &publics);
+		let dot = call_graph_to_dot(&plugin, &map);
+		assert!(
+			dot.contains("f00000000 [label=\"OnPluginStart\", shape=doubleoctagon];")
+		);
+		assert!(dot.contains("f00000020 [label=\"sub_00000020\", shape=ellipse];"));
+		assert!(dot.contains("n0 [label=\"PrintToServer\", shape=box];"));
+		assert!(dot.contains("f00000000 -> f00000020;"));
+		assert!(dot.contains("f00000000 -> n0;"));
+	}
+}
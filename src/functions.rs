@@ -0,0 +1,326 @@
+//! Discovery of function boundaries in code.
+//!
+//! Functions start at `PROC` instructions, `.publics` entries and `CALL`
+//! targets, and end after their `ENDPROC` instruction, or at the start of the
+//! next function for code without `ENDPROC`. They are named after their
+//! `.publics` entry, or else after their debug symbol.
+
+use crate::{
+	debug::DebugInfo,
+	decoder::{
+		DecodeError,
+		Decoder,
+	},
+	plugin::Plugin,
+	Instruction,
+};
+
+use std::{
+	collections::{
+		BTreeSet,
+		HashMap,
+		HashSet,
+	},
+	ffi::CString,
+};
+
+/// Function in code.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Function {
+	/// Code offset of the first instruction.
+	pub start: usize,
+	/// Code offset one past the last instruction.
+	pub end: usize,
+	/// Instructions of the function paired with their code offsets.
+	pub instructions: Vec<(usize, Instruction)>,
+	/// `true` if the function is listed in `.publics`.
+	pub public: bool,
+	/// Name of the function, if known.
+	pub name: Option<CString>,
+}
+
+impl Function {
+	/// Return `true` if a code offset is inside of this function.
+	pub const fn contains(&self, offset: usize) -> bool {
+		self.start <= offset && offset < self.end
+	}
+}
+
+/// Problem with function boundaries that was found during discovery.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BoundaryIssue {
+	/// Code between functions that does not belong to any of them.
+	Gap {
+		start: usize,
+		end: usize,
+	},
+	/// A function's `ENDPROC` is past the start of the next function.
+	Overlap {
+		function: usize,
+		next: usize,
+	},
+	/// A function entry is not a `PROC` instruction.
+	NotProc {
+		offset: usize,
+	},
+	/// A function entry is not at the start of an instruction.
+	InvalidEntry {
+		offset: usize,
+	},
+}
+
+/// Result of function discovery.
+#[derive(Default, Debug, Clone, PartialEq, Eq)]
+pub struct FunctionMap {
+	/// Functions, sorted by their start offset.
+	pub functions: Vec<Function>,
+	/// Problems that were found.
+	pub issues: Vec<BoundaryIssue>,
+}
+
+impl FunctionMap {
+	/// Return the function that starts at a code offset.
+	pub fn at(&self, start: usize) -> Option<&Function> {
+		self.functions.binary_search_by_key(&start, move |f| f.start)
+			.ok()
+			.map(|idx| &self.functions[idx])
+	}
+
+	/// Return the function that contains a code offset.
+	pub fn containing(&self, offset: usize) -> Option<&Function> {
+		let idx = self.functions.partition_point(move |f| f.start <= offset);
+		idx.checked_sub(1)
+			.map(|idx| &self.functions[idx])
+			.filter(move |f| f.contains(offset))
+	}
+}
+
+/// Discover functions in decoded instructions paired with their offsets,
+/// where `code_len` is the size of the code that they were decoded from.
+///
+/// `publics` are the code offsets and names of public functions.
+pub fn discover(
+	instructions: &[(usize, Instruction)],
+	code_len: usize,
+	publics: &[(usize, CString)],
+) -> FunctionMap {
+	let mut issues = Vec::new();
+
+	let index: HashMap<usize, usize> = instructions.iter()
+		.enumerate()
+		.map(move |(idx, (offset, _))| (*offset, idx))
+		.collect();
+
+	let mut starts = BTreeSet::new();
+	let mut invalid = HashSet::new();
+	let entries = instructions.iter()
+		.filter_map(move |(offset, instruction)| match instruction {
+			Instruction::Proc => Some(*offset),
+			Instruction::Call { func_1 } => Some(*func_1 as usize),
+			_ => None,
+		})
+		.chain(publics.iter().map(move |(offset, _)| *offset));
+	for entry in entries {
+		match index.get(&entry) {
+			Some(idx) => {
+				if starts.insert(entry) && instructions[*idx].1 != Instruction::Proc {
+					issues.push(BoundaryIssue::NotProc { offset: entry });
+				}
+			}
+			None => {
+				if invalid.insert(entry) {
+					issues.push(BoundaryIssue::InvalidEntry { offset: entry });
+				}
+			}
+		}
+	}
+
+	let names: HashMap<usize, &CString> = publics.iter()
+		.map(move |(offset, name)| (*offset, name))
+		.collect();
+
+	let starts: Vec<usize> = starts.into_iter().collect();
+	let mut functions = Vec::with_capacity(starts.len());
+	let mut covered = 0;
+	for (n, start) in starts.iter().copied().enumerate() {
+		let next = starts.get(n + 1).copied().unwrap_or(code_len);
+
+		if covered < start {
+			issues.push(BoundaryIssue::Gap {
+				start: covered,
+				end: start,
+			});
+		}
+
+		let first = index[&start];
+		let mut end = next;
+		for (offset, instruction) in instructions[first..].iter() {
+			if *offset != start && *instruction == Instruction::Proc {
+				break
+			}
+			if *instruction == Instruction::Endproc {
+				let endproc_end = offset + instruction.encoded_len();
+				if endproc_end > next {
+					issues.push(BoundaryIssue::Overlap {
+						function: start,
+						next,
+					});
+				} else {
+					end = endproc_end;
+				}
+				break
+			}
+		}
+
+		covered = end;
+
+		let len = instructions[first..].partition_point(move |(offset, _)| *offset < end);
+		functions.push(Function {
+			start,
+			end,
+			instructions: instructions[first..first + len].to_vec(),
+			public: names.contains_key(&start),
+			name: names.get(&start).map(move |name| (*name).clone()),
+		});
+	}
+	if covered < code_len {
+		issues.push(BoundaryIssue::Gap {
+			start: covered,
+			end: code_len,
+		});
+	}
+
+	FunctionMap {
+		functions,
+		issues,
+	}
+}
+
+/// Decode the code of a [`Plugin`] and discover its functions, naming them
+/// with its `.publics`, and other functions with the symbols of `debug`.
+pub fn discover_plugin(
+	plugin: &Plugin,
+	debug: Option<&DebugInfo>,
+) -> Result<FunctionMap, DecodeError> {
+	let code = &plugin.code.code;
+	let instructions = Decoder::new(code).collect::<Result<Vec<_>, _>>()?;
+	let publics: Vec<_> = (0..plugin.publics.len())
+		.filter_map(move |index| {
+			let address = plugin.publics[index].address as usize;
+			plugin.public_name(index).map(move |name| (address, name))
+		})
+		.collect();
+	let mut map = discover(&instructions, code.len(), &publics);
+	if let Some(debug) = debug {
+		let names: HashMap<usize, CString> = debug.function_names().into_iter().collect();
+		for function in map.functions.iter_mut().filter(move |f| f.name.is_none()) {
+			function.name = names.get(&function.start).cloned();
+		}
+	}
+	Ok(map)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::{
+		debug::{
+			Symbol,
+			SymbolKind,
+			SymbolScope,
+		},
+		instruction::layout,
+		sections::Public,
+	};
+
+	#[test]
+	fn two_functions() {
+		let (code, len) = layout(vec![
+			Instruction::Proc,
+			Instruction::Call { func_1: 20 },
+			Instruction::Retn,
+			Instruction::Endproc,
+			Instruction::Proc,
+			Instruction::Retn,
+			Instruction::Endproc,
+		]);
+		let map = discover(&code, len, &[(0, CString::new("OnPluginStart").unwrap())]);
+		assert!(map.issues.is_empty(), "{:?}", map.issues);
+		assert_eq!(map.functions.len(), 2);
+
+		let main = &map.functions[0];
+		assert_eq!((main.start, main.end), (0, 20));
+		assert!(main.public);
+		assert_eq!(main.name.as_deref(), Some(c"OnPluginStart"));
+		assert_eq!(main.instructions.len(), 4);
+
+		let helper = map.containing(24).unwrap();
+		assert_eq!((helper.start, helper.end), (20, 32));
+		assert!(!helper.public);
+	}
+
+	#[test]
+	fn issues() {
+		let (code, len) = layout(vec![
+			Instruction::Nop,
+			Instruction::Proc,
+			Instruction::Call { func_1: 16 },
+			Instruction::Call { func_1: 14 },
+			Instruction::Retn,
+			Instruction::Endproc,
+		]);
+		let map = discover(&code, len, &[]);
+		assert_eq!(map.issues, [
+			BoundaryIssue::NotProc { offset: 16 },
+			BoundaryIssue::InvalidEntry { offset: 14 },
+			BoundaryIssue::Gap { start: 0, end: 4 },
+			BoundaryIssue::Overlap { function: 4, next: 16 },
+		]);
+	}
+
+	#[test]
+	fn debug_names() -> Result<(), DecodeError> {
+		let mut plugin = Plugin::default();
+		for instruction in [
+			Instruction::Proc,
+			Instruction::Call { func_1: 20 },
+			Instruction::Retn,
+			Instruction::Endproc,
+			Instruction::Proc,
+			Instruction::Retn,
+			Instruction::Endproc,
+		] {
+			let _ = instruction.write_to(&mut plugin.code.code);
+		}
+		let name = plugin.names.insert(c"OnPluginStart") as u32;
+		plugin.publics.push(Public { address: 0, name });
+
+		let mut debug = DebugInfo::default();
+		for (code_start, name) in [(0, c"Main"), (20, c"Helper")] {
+			let name = debug.strings.insert(name) as u32;
+			debug.symbols.push(Symbol {
+				address: code_start,
+				tag: 0,
+				code_start: code_start as u32,
+				code_end: code_start as u32 + 12,
+				kind: SymbolKind::Function,
+				scope: SymbolScope::Global,
+				dims: Vec::new(),
+				name,
+			});
+		}
+
+		let names = move |map: FunctionMap| map.functions.into_iter()
+			.map(move |function| function.name)
+			.collect::<Vec<_>>();
+		assert_eq!(names(discover_plugin(&plugin, None)?), [
+			Some(c"OnPluginStart".to_owned()),
+			None,
+		]);
+		assert_eq!(names(discover_plugin(&plugin, Some(&debug))?), [
+			Some(c"OnPluginStart".to_owned()),
+			Some(c"Helper".to_owned()),
+		]);
+		Ok(())
+	}
+}
@@ -0,0 +1,351 @@
+//! Minimal parser of native declarations in SourcePawn include files.
+//!
+//! Only as much of the language is understood as is needed to find `native`
+//! declarations, including those of methodmaps and their properties, and
+//! natives that are marked as optional with `MarkNativeAsOptional`.
+
+use std::collections::{
+	HashMap,
+	HashSet,
+};
+
+/// Prototype of a native.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Prototype {
+	/// Name of the native, as it appears in `.natives`.
+	///
+	/// Methodmap natives are named `Methodmap.Method`, and property accessors
+	/// `Methodmap.Property.get` or `Methodmap.Property.set`.
+	pub name: String,
+	/// Number of arguments without default values, including the implicit
+	/// `this` of methodmap natives.
+	pub required: usize,
+	/// Number of declared arguments, including the implicit `this` of
+	/// methodmap natives.
+	pub max: usize,
+	/// `true` if the native takes variadic arguments.
+	pub variadic: bool,
+}
+
+impl Prototype {
+	/// Return `true` if a call with the specified number of arguments matches
+	/// this prototype.
+	pub const fn accepts(&self, n_args: usize) -> bool {
+		self.required <= n_args && (self.variadic || n_args <= self.max)
+	}
+}
+
+/// Natives that were declared in include files.
+#[derive(Default, Debug, Clone, PartialEq, Eq)]
+pub struct Includes {
+	/// Prototypes by native name.
+	pub prototypes: HashMap<String, Prototype>,
+	/// Names of natives that are marked as optional.
+	pub optional: HashSet<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+	Word(String),
+	Str(String),
+	Punct(char),
+}
+
+impl Token {
+	fn is_word(&self, word: &str) -> bool {
+		matches!(self, Self::Word(w) if w == word)
+	}
+
+	fn word(&self) -> Option<&str> {
+		match self {
+			Self::Word(w) => Some(w),
+			_ => None,
+		}
+	}
+}
+
+/// Split source into tokens, skipping comments and preprocessor directives.
+fn tokenize(source: &str) -> Vec<Token> {
+	let chars: Vec<char> = source.chars().collect();
+	let mut tokens = Vec::new();
+	let mut line_start = true;
+	let mut i = 0;
+	while i < chars.len() {
+		let c = chars[i];
+		match c {
+			'\n' => {
+				line_start = true;
+				i += 1;
+				continue
+			}
+			_ if c.is_whitespace() => {
+				i += 1;
+				continue
+			}
+			'#' if line_start => {
+				while i < chars.len() && chars[i] != '\n' {
+					if chars[i] == '\\' {
+						i += 1;
+					}
+					i += 1;
+				}
+				continue
+			}
+			'/' if chars.get(i + 1) == Some(&'/') => {
+				while i < chars.len() && chars[i] != '\n' {
+					i += 1;
+				}
+				continue
+			}
+			'/' if chars.get(i + 1) == Some(&'*') => {
+				i += 2;
+				while i < chars.len() && chars[i..].get(..2) != Some(&['*', '/']) {
+					i += 1;
+				}
+				i += 2;
+			}
+			'"' | '\'' => {
+				let mut s = String::new();
+				i += 1;
+				while i < chars.len() && chars[i] != c {
+					if chars[i] == '\\' {
+						i += 1;
+					}
+					s.extend(chars.get(i));
+					i += 1;
+				}
+				i += 1;
+				tokens.push(Token::Str(s));
+			}
+			_ if c.is_alphanumeric() || c == '_' || c == '@' => {
+				let start = i;
+				i += 1;
+				while i < chars.len() && (chars[i].is_alphanumeric() || chars[i] == '_') {
+					i += 1;
+				}
+				tokens.push(Token::Word(chars[start..i].iter().collect()));
+			}
+			_ => {
+				tokens.push(Token::Punct(c));
+				i += 1;
+			}
+		}
+		line_start = false;
+	}
+	tokens
+}
+
+/// Scope opened by a `{`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Scope {
+	Methodmap(String),
+	Property(String),
+	Other,
+}
+
+impl Includes {
+	/// Create an empty set of includes.
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	/// Parse the source of an include file, adding its natives.
+	pub fn parse(&mut self, source: &str) {
+		let tokens = tokenize(source);
+		let mut scopes: Vec<Scope> = Vec::new();
+		let mut pending = Scope::Other;
+		let mut i = 0;
+		while i < tokens.len() {
+			match &tokens[i] {
+				Token::Punct('{') => {
+					scopes.push(std::mem::replace(&mut pending, Scope::Other));
+				}
+				Token::Punct('}') => {
+					scopes.pop();
+				}
+				Token::Punct(';') => pending = Scope::Other,
+				Token::Word(w) if w == "methodmap" => {
+					if let Some(name) = tokens.get(i + 1).and_then(Token::word) {
+						pending = Scope::Methodmap(name.into());
+					}
+				}
+				Token::Word(w) if w == "property" => {
+					// The name is the last word before the `{`.
+					let name = tokens[i + 1..].iter()
+						.take_while(move |t| **t != Token::Punct('{'))
+						.filter_map(Token::word)
+						.last();
+					if let Some(name) = name {
+						pending = Scope::Property(name.into());
+					}
+				}
+				Token::Word(w) if w == "MarkNativeAsOptional" => {
+					if let (Some(Token::Punct('(')), Some(Token::Str(name))) =
+						(tokens.get(i + 1), tokens.get(i + 2))
+					{
+						self.optional.insert(name.clone());
+					}
+				}
+				Token::Word(w) if w == "native" => {
+					let is_static = tokens[i.saturating_sub(2)..i].iter()
+						.any(move |t| t.is_word("static"));
+					if let Some((prototype, next)) = self.parse_native(&tokens, i + 1) {
+						let prototype = qualify(prototype, &scopes, is_static);
+						self.prototypes.insert(prototype.name.clone(), prototype);
+						i = next;
+						continue
+					}
+				}
+				_ => {}
+			}
+			i += 1;
+		}
+	}
+
+	/// Parse a native declaration after the `native` keyword, returning the
+	/// unqualified prototype and the index of the token after its arguments.
+	fn parse_native(&self, tokens: &[Token], start: usize) -> Option<(Prototype, usize)> {
+		let open = start + tokens[start..].iter()
+			.position(move |t| matches!(t, Token::Punct('(' | ';' | '{')))?;
+		if tokens[open] != Token::Punct('(') {
+			return None
+		}
+		let name = tokens[start..open].iter().rev().find_map(Token::word)?;
+
+		let mut depth = 0;
+		let mut args: Vec<Vec<&Token>> = vec![Vec::new()];
+		let mut close = None;
+		for (idx, token) in tokens.iter().enumerate().skip(open + 1) {
+			match token {
+				Token::Punct('(' | '[' | '{') => depth += 1,
+				Token::Punct(')') if depth == 0 => {
+					close = Some(idx);
+					break
+				}
+				Token::Punct(')' | ']' | '}') => depth -= 1,
+				Token::Punct(',') if depth == 0 => {
+					args.push(Vec::new());
+					continue
+				}
+				_ => {}
+			}
+			args.last_mut().unwrap().push(token);
+		}
+		let close = close?;
+		args.retain(move |arg| !arg.is_empty());
+
+		let is_variadic = move |arg: &Vec<&Token>| {
+			arg.windows(3).any(move |w| w.iter().all(move |t| **t == Token::Punct('.')))
+		};
+		let variadic = args.iter().any(is_variadic);
+		let fixed: Vec<_> = args.iter().filter(move |arg| !is_variadic(arg)).collect();
+		let required = fixed.iter()
+			.filter(move |arg| !arg.contains(&&Token::Punct('=')))
+			.count();
+		Some((
+			Prototype {
+				name: name.into(),
+				required,
+				max: fixed.len(),
+				variadic,
+			},
+			close + 1,
+		))
+	}
+
+	/// Return the prototype of a native.
+	pub fn get(&self, name: &str) -> Option<&Prototype> {
+		self.prototypes.get(name)
+	}
+
+	/// Return `true` if a native is marked as optional.
+	pub fn is_optional(&self, name: &str) -> bool {
+		self.optional.contains(name)
+	}
+}
+
+/// Qualify a prototype that was declared in a methodmap, adding its `this`
+/// argument.
+fn qualify(mut prototype: Prototype, scopes: &[Scope], is_static: bool) -> Prototype {
+	let (methodmap, property) = match scopes {
+		[.., Scope::Methodmap(methodmap)] => (methodmap, None),
+		[.., Scope::Methodmap(methodmap), Scope::Property(property)] =>
+			(methodmap, Some(property)),
+		_ => return prototype,
+	};
+	let is_constructor = property.is_none() && prototype.name == *methodmap;
+	prototype.name = match property {
+		Some(property) => format!("{methodmap}.{property}.{}", prototype.name),
+		None => format!("{methodmap}.{}", prototype.name),
+	};
+	if !is_static && !is_constructor {
+		prototype.required += 1;
+		prototype.max += 1;
+	}
+	prototype
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn parse() {
+		let mut includes = Includes::new();
+		includes.parse(r#"
+			#if defined _foo_included
+			 #endinput
+			#endif
+			/* native void Commented(); */
+			native void PrintToServer(const char[] format, any ...);
+			native bool:IsClientInGame(client);  // old syntax
+			native int GetClientCount(bool inGameOnly = true);
+			native void SetArray(const int[] values = {1, 2}, int size);
+
+			methodmap Handle {
+				public native Handle(int value);
+				public native void Close();
+				public static native Handle Find(const char[] name);
+				property int Value {
+					public native get();
+					public native set(int value);
+				}
+			}
+
+			public void __pl_foo_SetNTVOptional() {
+				MarkNativeAsOptional("Foo_Bar");
+			}
+		"#);
+
+		let prototype = move |name: &str, required, max, variadic| Prototype {
+			name: name.into(),
+			required,
+			max,
+			variadic,
+		};
+		let mut prototypes: Vec<_> = includes.prototypes.values().cloned().collect();
+		prototypes.sort_by(move |a, b| a.name.cmp(&b.name));
+		assert_eq!(prototypes, [
+			prototype("GetClientCount", 0, 1, false),
+			prototype("Handle.Close", 1, 1, false),
+			prototype("Handle.Find", 1, 1, false),
+			prototype("Handle.Handle", 1, 1, false),
+			prototype("Handle.Value.get", 1, 1, false),
+			prototype("Handle.Value.set", 2, 2, false),
+			prototype("IsClientInGame", 1, 1, false),
+			prototype("PrintToServer", 1, 1, true),
+			prototype("SetArray", 1, 2, false),
+		]);
+		assert!(includes.is_optional("Foo_Bar"));
+		assert!(includes.get("PrintToServer").unwrap().accepts(3));
+		assert!(!includes.get("GetClientCount").unwrap().accepts(2));
+	}
+
+	#[test]
+	fn parse_at_sign() {
+		let mut includes = Includes::new();
+		includes.parse("native void Foo(int @x, @);\nnative void Bar(int y);");
+		assert_eq!(includes.get("Foo").map(move |p| p.max), Some(2));
+		assert_eq!(includes.get("Bar").map(move |p| p.required), Some(1));
+	}
+}
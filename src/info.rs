@@ -0,0 +1,182 @@
+//! See [`PluginInfo`].
+
+use crate::{
+	plugin::Plugin,
+	vm_types::Cell,
+};
+
+use core::ffi::CStr;
+use std::{
+	error::Error,
+	fmt,
+};
+
+/// Name of the public variable with plugin information.
+pub const MYINFO: &CStr = c"myinfo";
+
+/// Plugin information from `public Plugin myinfo`, whose data holds the
+/// following struct, one cell per field, with strings as data offsets:
+///
+/// ```sp
+/// struct Plugin {
+///     public const char[] name;
+///     public const char[] description;
+///     public const char[] author;
+///     public const char[] version;
+///     public const char[] url;
+/// };
+/// ```
+#[derive(Default, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct PluginInfo {
+	/// Plugin name.
+	pub name: String,
+	/// Plugin description.
+	pub description: String,
+	/// Plugin author.
+	pub author: String,
+	/// Plugin version.
+	pub version: String,
+	/// Plugin URL.
+	pub url: String,
+}
+
+/// Field of [`PluginInfo`], in the order of the `Plugin` struct.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum InfoField {
+	Name,
+	Description,
+	Author,
+	Version,
+	Url,
+}
+
+impl InfoField {
+	/// Every field, in the order of the `Plugin` struct.
+	pub const ALL: [Self; 5] = [
+		Self::Name,
+		Self::Description,
+		Self::Author,
+		Self::Version,
+		Self::Url,
+	];
+
+	/// Return the name of this field in SourcePawn.
+	pub const fn name(self) -> &'static str {
+		match self {
+			Self::Name => "name",
+			Self::Description => "description",
+			Self::Author => "author",
+			Self::Version => "version",
+			Self::Url => "url",
+		}
+	}
+
+	/// Return the data offset of this field, relative to `myinfo`.
+	pub const fn offset(self) -> usize {
+		self as usize * core::mem::size_of::<Cell>()
+	}
+}
+
+/// Structure for an error that has occurred while reading [`PluginInfo`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum InfoError {
+	/// The plugin has no `myinfo` public variable.
+	Missing,
+	/// `myinfo` is outside of the data image.
+	InvalidAddress {
+		address: u32,
+	},
+	/// A field of `myinfo` does not point to a string in the data image.
+	InvalidField {
+		field: InfoField,
+		address: Cell,
+	},
+}
+
+impl fmt::Display for InfoError {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			Self::Missing => f.write_str("plugin has no `myinfo`"),
+			Self::InvalidAddress { address } =>
+				write!(f, "`myinfo` at 0x{address:08x} is out of bounds"),
+			Self::InvalidField { field, address } => write!(
+				f,
+				"`myinfo.{}` points to invalid string at 0x{address:08x}",
+				field.name()
+			),
+		}
+	}
+}
+
+impl Error for InfoError {}
+
+/// Locate `myinfo` through `.pubvars`, returning the data offsets of its
+/// fields.
+pub fn myinfo_fields(plugin: &Plugin) -> Result<[usize; 5], InfoError> {
+	let index = plugin.find_pubvar(MYINFO).ok_or(InfoError::Missing)?;
+	let address = plugin.pubvars[index].address;
+	let fields = InfoField::ALL.map(move |field| address as usize + field.offset());
+	if plugin.data.cell_at(fields[4]).is_none() {
+		return Err(InfoError::InvalidAddress { address })
+	}
+	Ok(fields)
+}
+
+impl PluginInfo {
+	/// Locate `myinfo` through `.pubvars` and read its strings from the data
+	/// image.
+	pub fn from_plugin(plugin: &Plugin) -> Result<Self, InfoError> {
+		let fields = myinfo_fields(plugin)?;
+		let mut strings = InfoField::ALL.into_iter().zip(fields)
+			.map(move |(field, offset)| {
+				let address = plugin.data.cell_at(offset).unwrap_or_default();
+				plugin.data.string_at(address)
+					.ok_or(InfoError::InvalidField { field, address })
+			});
+		let mut next = move || strings.next().unwrap();
+		Ok(Self {
+			name: next()?,
+			description: next()?,
+			author: next()?,
+			version: next()?,
+			url: next()?,
+		})
+	}
+}
+
+#[test]
+fn from_plugin() {
+	use crate::sections::Pubvar;
+
+	let mut plugin = Plugin::default();
+	assert_eq!(PluginInfo::from_plugin(&plugin), Err(InfoError::Missing));
+
+	let strings = ["Hello, world!", "A classic example.", "Me", "0.1.0", ""];
+	let mut cells = Vec::new();
+	for s in strings {
+		cells.push(plugin.data.bytes.len() as i32);
+		plugin.data.bytes.extend_from_slice(s.as_bytes());
+		plugin.data.bytes.push(0);
+		plugin.data.bytes.resize(plugin.data.bytes.len().next_multiple_of(4), 0);
+	}
+	let address = plugin.data.bytes.len() as u32;
+	for cell in cells {
+		plugin.data.bytes.extend_from_slice(&cell.to_ne_bytes());
+	}
+	let name = plugin.names.insert(c"myinfo") as u32;
+	plugin.pubvars.push(Pubvar { address, name });
+
+	assert_eq!(PluginInfo::from_plugin(&plugin), Ok(PluginInfo {
+		name: "Hello, world!".into(),
+		description: "A classic example.".into(),
+		author: "Me".into(),
+		version: "0.1.0".into(),
+		url: "".into(),
+	}));
+
+	plugin.data.bytes.truncate(address as usize + 4);
+	assert_eq!(
+		PluginInfo::from_plugin(&plugin),
+		Err(InfoError::InvalidAddress { address })
+	);
+}
@@ -0,0 +1,276 @@
+//! Hand-written helpers for [`Instruction`], complementing the generated
+//! `opcodes` module.
+
+use crate::{
+	vm_types::{
+		Cell,
+		read_cell,
+	},
+	Instruction,
+};
+
+use std::{
+	fmt,
+	io::{
+		Cursor,
+		Result as IoResult,
+		Write,
+	}
+};
+
+/// Writer that only counts the bytes written to it.
+struct ByteCounter(usize);
+
+impl Write for ByteCounter {
+	fn write(&mut self, buf: &[u8]) -> IoResult<usize> {
+		self.0 += buf.len();
+		Ok(buf.len())
+	}
+
+	fn flush(&mut self) -> IoResult<()> {
+		Ok(())
+	}
+}
+
+impl Instruction {
+	/// Return the size of this instruction when encoded, in bytes.
+	pub fn encoded_len(&self) -> usize {
+		let mut counter = ByteCounter(0);
+		let _ = self.write_to(&mut counter);
+		counter.0
+	}
+
+	/// Return mutable references to every operand of this instruction that
+	/// holds an absolute code offset.
+	///
+	/// The `switch` field of [`Instruction::Casetbl`] is not an operand, and is
+	/// not included.
+	pub fn code_refs_mut(&mut self) -> Vec<&mut Cell> {
+		match self {
+			Self::Call { func_1 } | Self::LdgfnPri { func_1 } => vec![func_1],
+			Self::Jump { jump_1 }
+				| Self::Jzer { jump_1 }
+				| Self::Jnz { jump_1 }
+				| Self::Jeq { jump_1 }
+				| Self::Jneq { jump_1 }
+				| Self::Jsless { jump_1 }
+				| Self::Jsleq { jump_1 }
+				| Self::Jsgrtr { jump_1 }
+				| Self::Jsgeq { jump_1 }
+				| Self::Jless { jump_1 }
+				| Self::Jleq { jump_1 }
+				| Self::Jgrtr { jump_1 }
+				| Self::Jgeq { jump_1 }
+				| Self::Switch { jump_1 } => vec![jump_1],
+			Self::Casetbl { default, cases, .. } => {
+				let mut refs = vec![default];
+				refs.extend(cases.iter_mut().map(move |case| &mut case.jump));
+				refs
+			}
+			_ => Vec::new(),
+		}
+	}
+
+	/// Return mutable references to every constant operand of this
+	/// instruction that is loaded into a register, pushed or stored, and may
+	/// therefore hold a data address.
+	pub fn constants_mut(&mut self) -> Vec<&mut Cell> {
+		match self {
+			Self::ConstPri { value } | Self::ConstAlt { value } => vec![value],
+			Self::PushC { const_1 }
+				| Self::Const { const_1, .. }
+				| Self::ConstS { const_1, .. } => vec![const_1],
+			Self::Push2C { const_1, const_2 } => vec![const_1, const_2],
+			Self::Push3C { const_1, const_2, const_3 } => vec![const_1, const_2, const_3],
+			Self::Push4C { const_1, const_2, const_3, const_4 } =>
+				vec![const_1, const_2, const_3, const_4],
+			Self::Push5C { const_1, const_2, const_3, const_4, const_5 } =>
+				vec![const_1, const_2, const_3, const_4, const_5],
+			_ => Vec::new(),
+		}
+	}
+
+	/// Return mutable references to every operand of this instruction that
+	/// holds the address of a global variable.
+	pub fn data_refs_mut(&mut self) -> Vec<&mut Cell> {
+		match self {
+			Self::LoadPri { offset }
+				| Self::LoadAlt { offset }
+				| Self::StorPri { offset }
+				| Self::StorAlt { offset }
+				| Self::LrefPri { offset }
+				| Self::LrefAlt { offset }
+				| Self::SrefPri { offset }
+				| Self::SrefAlt { offset } => vec![offset],
+			Self::Const { addr_1, .. }
+				| Self::Inc { addr_1 }
+				| Self::Dec { addr_1 }
+				| Self::Zero { addr_1 }
+				| Self::Push { addr_1 }
+				| Self::InitarrayPri { addr_1, .. }
+				| Self::InitarrayAlt { addr_1, .. }
+				| Self::Rebase { addr_1, .. } => vec![addr_1],
+			Self::LoadBoth { addr_1, addr_2 } | Self::Push2 { addr_1, addr_2 } =>
+				vec![addr_1, addr_2],
+			Self::Push3 { addr_1, addr_2, addr_3 } => vec![addr_1, addr_2, addr_3],
+			Self::Push4 { addr_1, addr_2, addr_3, addr_4 } =>
+				vec![addr_1, addr_2, addr_3, addr_4],
+			Self::Push5 { addr_1, addr_2, addr_3, addr_4, addr_5 } =>
+				vec![addr_1, addr_2, addr_3, addr_4, addr_5],
+			_ => Vec::new(),
+		}
+	}
+
+	/// Return every operand of this instruction that holds an absolute code
+	/// offset.
+	///
+	/// See [`Self::code_refs_mut`].
+	pub fn code_refs(&self) -> Vec<Cell> {
+		self.clone().code_refs_mut().into_iter().map(move |r| *r).collect()
+	}
+}
+
+/// Disassemble an instruction into its mnemonic followed by its operands.
+///
+/// Operands that hold code offsets are written in hexadecimal.
+impl fmt::Display for Instruction {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.write_str(self.mnemonic())?;
+
+		if let Self::Casetbl { default, cases, .. } = self {
+			write!(f, " default 0x{default:08x}")?;
+			for case in cases.iter() {
+				write!(f, ", {} 0x{:08x}", case.value, case.jump)?;
+			}
+			return Ok(())
+		}
+
+		let mut encoded = Vec::new();
+		let _ = self.write_to(&mut encoded);
+		let mut r = Cursor::new(&encoded[core::mem::size_of::<Cell>()..]);
+		let is_code_ref = !self.code_refs().is_empty();
+		let mut first = true;
+		while let Ok(operand) = read_cell(&mut r) {
+			f.write_str(if first { " " } else { ", " })?;
+			first = false;
+			if is_code_ref {
+				write!(f, "0x{operand:08x}")?;
+			} else {
+				write!(f, "{operand}")?;
+			}
+		}
+		Ok(())
+	}
+}
+
+/// Lay out instructions one after another, returning them paired with their
+/// code offsets, and the total code size.
+#[cfg(test)]
+pub(crate) fn layout(
+	instructions: Vec<Instruction>,
+) -> (Vec<(usize, Instruction)>, usize) {
+	let mut offset = 0;
+	let mut out = Vec::new();
+	for instruction in instructions {
+		let len = instruction.encoded_len();
+		out.push((offset, instruction));
+		offset += len;
+	}
+	(out, offset)
+}
+
+#[test]
+fn encoded_len() {
+	use crate::case_table::Case;
+
+	assert_eq!(Instruction::Retn.encoded_len(), 4);
+	assert_eq!(Instruction::SysreqN { native: 0, n_args: 1 }.encoded_len(), 12);
+	assert_eq!(
+		Instruction::Casetbl {
+			default: 0,
+			cases: vec![Case { value: 0, jump: 0 }],
+			switch: None,
+		}.encoded_len(),
+		20
+	);
+}
+
+#[test]
+fn display() {
+	use crate::case_table::Case;
+
+	assert_eq!(Instruction::Retn.to_string(), "retn");
+	assert_eq!(
+		Instruction::SysreqN { native: 2, n_args: 1 }.to_string(),
+		"sysreq.n 2, 1"
+	);
+	assert_eq!(Instruction::StorSPri { offset: -4 }.to_string(), "stor.s.pri -4");
+	assert_eq!(Instruction::Jzer { jump_1: 0x40 }.to_string(), "jzer 0x00000040");
+	assert_eq!(
+		Instruction::Casetbl {
+			default: 0x10,
+			cases: vec![Case { value: 3, jump: 0x20 }],
+			switch: None,
+		}.to_string(),
+		"casetbl default 0x00000010, 3 0x00000020"
+	);
+}
+
+#[test]
+fn code_refs() {
+	assert_eq!(Instruction::Call { func_1: 0x10 }.code_refs(), [0x10]);
+	assert_eq!(Instruction::LdgfnPri { func_1: 0x20 }.code_refs(), [0x20]);
+	assert_eq!(Instruction::Jzer { jump_1: 0x30 }.code_refs(), [0x30]);
+	assert!(Instruction::ConstPri { value: 0x40 }.code_refs().is_empty());
+}
+
+#[test]
+fn legacy_rejected() -> Result<(), Box<dyn std::error::Error>> {
+	use crate::OpcodeSet;
+
+	let mut data = Vec::new();
+	Instruction::LrefPri { offset: 0x10 }.write_to(&mut data)?;
+	assert!(Instruction::read_from(&mut Cursor::new(&data)).is_err());
+
+	let instruction = Instruction::read_with(
+		&mut Cursor::new(&data), OpcodeSet::Legacy
+	)?;
+	assert_eq!(instruction, Instruction::LrefPri { offset: 0x10 });
+	assert!(instruction.is_legacy());
+	Ok(())
+}
+
+#[test]
+fn legacy_variable_length() -> Result<(), Box<dyn std::error::Error>> {
+	use crate::{
+		vm_types::write_cell,
+		OpcodeSet,
+	};
+
+	let mut data = Vec::new();
+	write_cell(&mut data, 124)?;
+	write_cell(&mut data, 9)?;
+	write_cell(&mut data, 0)?;
+	data.extend_from_slice(b"a.sp\0\0\0\0");
+	Instruction::Retn.write_to(&mut data)?;
+
+	let mut r = Cursor::new(&data);
+	let file = Instruction::read_with(&mut r, OpcodeSet::Legacy)?;
+	assert!(matches!(
+		file,
+		Instruction::File { size: 9, ref data } if data.len() == 3
+	));
+	assert_eq!(Instruction::read_with(&mut r, OpcodeSet::Legacy)?, Instruction::Retn);
+
+	let mut written = Vec::new();
+	file.write_to(&mut written)?;
+	Instruction::Retn.write_to(&mut written)?;
+	assert_eq!(written, data);
+	Ok(())
+}
+
+#[test]
+fn current_not_legacy() {
+	assert!(!Instruction::Retn.is_legacy());
+	assert!(!Instruction::SysreqN { native: 0, n_args: 0 }.is_legacy());
+}
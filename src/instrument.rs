@@ -0,0 +1,321 @@
+//! Instrumentation of function entries and exits with native calls.
+//!
+//! Every selected function gets an id, which is its index in the returned
+//! [`Manifest`]. Right after its `PROC`, the entry native is called as one of
+//! the following, depending on [`Instrumentation::pass_args`]:
+//!
+//! ```sp
+//! native void OnEnter(int id);
+//! native void OnEnter(int id, const any[] args, int arg_bytes);
+//! ```
+//!
+//! Right before each of its `RETN`, the exit native is called with the return
+//! value, which is preserved in `PRI`:
+//!
+//! ```sp
+//! native void OnExit(int id, any value);
+//! ```
+//!
+//! The natives are added to `.natives` if needed, and every code reference is
+//! relocated with [`CodeEditor`].
+
+use crate::{
+	code_edit::{
+		commit_smx,
+		CodeEditError,
+		CodeEditor,
+	},
+	decoder::DecodeError,
+	dot::function_name,
+	functions::{
+		discover_plugin,
+		Function,
+	},
+	plugin::Plugin,
+	rtti::Rtti,
+	symbolize::Symbolizer,
+	vm_types::{
+		Cell,
+		ARG_SIZE,
+		FIRST_ARG,
+	},
+	Instruction,
+	Smx,
+};
+
+use byteorder::ByteOrder;
+use std::{
+	error::Error,
+	ffi::CString,
+	fmt,
+	io::Error as IoError,
+};
+
+/// Natives that are called on function entry and exit.
+#[derive(Default, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Instrumentation {
+	/// Native that is called on function entry.
+	pub entry: Option<CString>,
+	/// Native that is called on function exit.
+	pub exit: Option<CString>,
+	/// `true` to pass the arguments of the function to the entry native.
+	pub pass_args: bool,
+}
+
+/// Instrumented function.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ManifestEntry {
+	/// Id that is passed to the natives.
+	pub id: Cell,
+	/// Code offset of the function in the instrumented plugin.
+	pub start: usize,
+	/// Code offset of the function in the original plugin.
+	pub original_start: usize,
+	/// Name of the function.
+	pub name: String,
+}
+
+/// List of instrumented functions, indexed by their ids.
+#[derive(Default, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Manifest {
+	pub entries: Vec<ManifestEntry>,
+}
+
+impl Manifest {
+	/// Return the instrumented function with an id.
+	pub fn get(&self, id: Cell) -> Option<&ManifestEntry> {
+		self.entries.get(usize::try_from(id).ok()?)
+	}
+}
+
+/// Write one line per function, with its id, its code offset and its name.
+impl fmt::Display for Manifest {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		for entry in self.entries.iter() {
+			writeln!(f, "{}\t0x{:08x}\t{}", entry.id, entry.start, entry.name)?;
+		}
+		Ok(())
+	}
+}
+
+/// Structure for an error that has occurred while instrumenting a plugin.
+#[derive(Debug)]
+pub enum InstrumentError {
+	/// Sections could not be parsed.
+	Io(IoError),
+	/// Code could not be decoded.
+	Decode(DecodeError),
+	/// Code could not be edited.
+	Edit(CodeEditError),
+	/// A selected function does not start with `PROC`.
+	NotProc {
+		offset: usize,
+	},
+}
+
+impl fmt::Display for InstrumentError {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			Self::Io(e) => write!(f, "io error {e}"),
+			Self::Decode(e) => write!(f, "decode error {e}"),
+			Self::Edit(e) => write!(f, "{e}"),
+			Self::NotProc { offset } =>
+				write!(f, "function at 0x{offset:08x} does not start with `proc`"),
+		}
+	}
+}
+
+impl Error for InstrumentError {}
+
+impl From<IoError> for InstrumentError {
+	fn from(value: IoError) -> Self {
+		Self::Io(value)
+	}
+}
+
+impl From<DecodeError> for InstrumentError {
+	fn from(value: DecodeError) -> Self {
+		Self::Decode(value)
+	}
+}
+
+impl From<CodeEditError> for InstrumentError {
+	fn from(value: CodeEditError) -> Self {
+		Self::Edit(value)
+	}
+}
+
+/// Instrument the functions of an SMX file that `select` returns `true` for.
+pub fn instrument<E: ByteOrder>(
+	smx: &mut Smx<CString, Vec<u8>>,
+	instrumentation: &Instrumentation,
+	mut select: impl FnMut(&Function) -> bool,
+) -> Result<Manifest, InstrumentError> {
+	let mut plugin = Plugin::from_smx::<E, _, _>(smx)?;
+	let mut rtti = Rtti::from_smx::<E, _, _>(smx)?;
+	let symbolizer = Symbolizer::from_smx::<E, _, _>(smx)?;
+	let map = discover_plugin(&plugin, Some(&symbolizer.debug))?;
+
+	let mut add_native = |plugin: &mut Plugin, name: &CString| {
+		let natives = plugin.natives.len();
+		let index = plugin.add_native(name);
+		if plugin.natives.len() > natives && !rtti.natives.is_empty() {
+			rtti.add_untyped_native(plugin.natives[index].name);
+		}
+		index as Cell
+	};
+	let entry = instrumentation.entry.as_ref().map(|name| add_native(&mut plugin, name));
+	let exit = instrumentation.exit.as_ref().map(|name| add_native(&mut plugin, name));
+
+	let mut editor = CodeEditor::new(&plugin.code.code)?;
+	let mut entries = Vec::new();
+	for function in map.functions.iter().filter(move |function| select(function)) {
+		if !matches!(function.instructions.first(), Some((_, Instruction::Proc))) {
+			return Err(InstrumentError::NotProc { offset: function.start })
+		}
+		let id = entries.len() as Cell;
+		if let Some(native) = entry {
+			let call = if instrumentation.pass_args {
+				vec![
+					Instruction::PushS { stack_1: ARG_SIZE },
+					Instruction::PushAdr { stack_1: FIRST_ARG },
+					Instruction::PushC { const_1: id },
+					Instruction::SysreqN { native, n_args: 3 },
+				]
+			} else {
+				vec![
+					Instruction::PushC { const_1: id },
+					Instruction::SysreqN { native, n_args: 1 },
+				]
+			};
+			editor.insert_after(function.start, call)?;
+		}
+		if let Some(native) = exit {
+			for (offset, instruction) in function.instructions.iter() {
+				if *instruction == Instruction::Retn {
+					editor.insert_before(*offset, [
+						Instruction::PushPri,
+						Instruction::PushPri,
+						Instruction::PushC { const_1: id },
+						Instruction::SysreqN { native, n_args: 2 },
+						Instruction::PopPri,
+					])?;
+				}
+			}
+		}
+		entries.push(ManifestEntry {
+			id,
+			start: function.start,
+			original_start: function.start,
+			name: symbolizer.function_at(function.start)
+				.unwrap_or_else(|| function_name(function)),
+		});
+	}
+
+	// Edit a copy, so that an error leaves `smx` untouched.
+	let mut edited = smx.clone();
+	plugin.write_into::<E>(&mut edited);
+	rtti.write_into::<E>(&mut edited);
+	let relocations = commit_smx::<E>(&mut edited, &editor)?;
+	for entry in entries.iter_mut() {
+		entry.start = relocations.relocate(entry.original_start).unwrap_or(entry.start);
+	}
+	*smx = edited;
+	Ok(Manifest { entries })
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::{
+		decoder::Decoder,
+		sections::Public,
+		verify::verify_plugin,
+	};
+	use byteorder::LittleEndian as Le;
+
+	#[test]
+	fn entry_exit() -> Result<(), Box<dyn Error>> {
+		let mut plugin = Plugin::default();
+		plugin.code.version = 13;
+		plugin.code.cell_size = 4;
+		for instruction in [
+			// 0x00: OnPluginStart
+			Instruction::Proc,
+			Instruction::PushC { const_1: 0 },
+			Instruction::Call { func_1: 0x1c },
+			Instruction::Retn,
+			Instruction::Endproc,
+			// 0x1c: helper
+			Instruction::Proc,
+			Instruction::ConstPri { value: 1 },
+			Instruction::Retn,
+			Instruction::Endproc,
+		] {
+			let _ = instruction.write_to(&mut plugin.code.code);
+		}
+		let name = plugin.names.insert(c"OnPluginStart") as u32;
+		plugin.publics.push(Public { address: 0, name });
+		let mut smx = Smx::new();
+		plugin.write_into::<Le>(&mut smx);
+
+		let instrumentation = Instrumentation {
+			entry: Some(c"Profiler_Enter".into()),
+			exit: Some(c"Profiler_Exit".into()),
+			pass_args: true,
+		};
+		let manifest = instrument::<Le>(&mut smx, &instrumentation, move |_| true)?;
+		assert_eq!(
+			manifest.to_string(),
+			"0\t0x00000000\tOnPluginStart\n1\t0x00000060\tsub_0000001c\n"
+		);
+
+		let plugin = Plugin::from_smx::<Le, _, _>(&smx)?;
+		assert_eq!(plugin.native_name(1).as_deref(), Some(c"Profiler_Exit"));
+		verify_plugin(&plugin)?;
+		let code: Vec<_> = Decoder::new(&plugin.code.code)
+			.map(move |decoded| decoded.map(move |(_, instruction)| instruction))
+			.collect::<Result<_, _>>()?;
+		assert_eq!(code[..6], [
+			Instruction::Proc,
+			Instruction::PushS { stack_1: 8 },
+			Instruction::PushAdr { stack_1: 12 },
+			Instruction::PushC { const_1: 0 },
+			Instruction::SysreqN { native: 0, n_args: 3 },
+			Instruction::PushC { const_1: 0 },
+		]);
+		assert!(code.contains(&Instruction::Call { func_1: 0x60 }));
+		Ok(())
+	}
+
+	#[test]
+	fn failed_edit() {
+		let mut plugin = Plugin::default();
+		plugin.code.version = 13;
+		plugin.code.cell_size = 4;
+		for instruction in [
+			Instruction::Proc,
+			Instruction::Jump { jump_1: 2 },
+			Instruction::Retn,
+			Instruction::Endproc,
+		] {
+			let _ = instruction.write_to(&mut plugin.code.code);
+		}
+		let name = plugin.names.insert(c"OnPluginStart") as u32;
+		plugin.publics.push(Public { address: 0, name });
+		let mut smx = Smx::new();
+		plugin.write_into::<Le>(&mut smx);
+		let original = smx.clone();
+
+		let instrumentation = Instrumentation {
+			entry: Some(c"Profiler_Enter".into()),
+			exit: None,
+			pass_args: false,
+		};
+		assert!(matches!(
+			instrument::<Le>(&mut smx, &instrumentation, move |_| true),
+			Err(InstrumentError::Edit(CodeEditError::Target { target: 2, .. }))
+		));
+		assert_eq!(smx, original);
+	}
+}
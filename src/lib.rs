@@ -5,6 +5,7 @@ use byteorder::{
 };
 use core::ffi::CStr;
 use std::{
+	borrow::Borrow,
 	collections::HashMap,
 	ffi::CString,
 	hash::Hash,
@@ -15,12 +16,43 @@ use std::{
 
 pub use byteorder;
 
+pub mod case_table;
+pub mod cfg;
+pub mod code_edit;
+pub mod companion;
+pub mod data_edit;
+pub mod dataflow;
+pub mod debug;
+pub mod decoder;
+pub mod decompile;
+pub mod deps;
+pub mod dot;
+pub mod functions;
+pub mod includes;
+pub mod info;
+pub mod instrument;
+pub mod link;
+mod instruction;
+pub mod minify;
+pub mod native_check;
+pub mod obfuscate;
 mod opcodes;
+pub mod optimize;
+pub mod plugin;
+pub mod redirect;
+pub mod rtti;
+pub mod sections;
 pub mod smx_table;
 pub mod smx;
+pub mod symbolize;
+pub mod verify;
+pub mod version;
 pub mod vm_types;
 
-pub use opcodes::Instruction;
+pub use opcodes::{
+	Instruction,
+	OpcodeSet,
+};
 pub use smx::CompressionLevel;
 
 use smx::{
@@ -62,6 +94,18 @@ impl<Name, Sect> Smx<Name, Sect> {
 	}
 }
 
+impl<Name: Borrow<CStr> + Eq + Hash, Sect> Smx<Name, Sect> {
+	/// Return a reference to the section with the specified name.
+	pub fn section(&self, name: &CStr) -> Option<&Sect> {
+		self.sections.get(name)
+	}
+
+	/// Return a mutable reference to the section with the specified name.
+	pub fn section_mut(&mut self, name: &CStr) -> Option<&mut Sect> {
+		self.sections.get_mut(name)
+	}
+}
+
 impl<Name: AsRef<CStr>, Sect: Section> Smx<Name, Sect> {
 	/// Write this SMX file to a writer.
 	pub fn write_to<E: ByteOrder>(
@@ -131,6 +175,7 @@ mod helper_tests {
 
 	type Sx = Smx<CString, Vec<u8>>;
 
+	#[allow(clippy::unnecessary_to_owned)]
 	fn hex_dump(data: &[u8]) {
 		for (offset, window) in
 			((0usize..).map(move |offset| offset * 0x10))
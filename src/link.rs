@@ -0,0 +1,1087 @@
+//! Linking of functions from one plugin into another.
+//!
+//! The requested public functions of the source plugin are copied to the end
+//! of the target's code, along with every function that they call. A called
+//! function that is public in the source and whose name is already public in
+//! the target is not copied, and calls to it are resolved to the target's
+//! function instead. Requested functions whose names are already public in the
+//! target are rejected.
+//!
+//! The whole data image of the source is appended to the target's, so that
+//! relative references inside of it stay valid. Address operands of copied
+//! instructions are relocated, and so are constant operands whose value the
+//! data-flow analysis shows to be used as an address: as the base of a load or
+//! a store, or as an argument whose parameter is an array or a reference
+//! according to the RTTI or the debug information of the source. A constant
+//! that holds the address of a known global variable or of a string, but
+//! whose use does not show whether it is an address, is rejected.
+//!
+//! Natives are merged by name. Copied public functions are inserted into
+//! `.publics` in order of their names, since SourceMod finds publics with a
+//! binary search. Function ids, which refer to publics by their index, are
+//! renumbered in the code of both plugins when they are passed to parameters
+//! with a function type, and in global variables with a function type. Public
+//! functions of the source that copied code refers to by their ids are copied
+//! as well.
+
+use crate::{
+	dataflow::{
+		constant_location,
+		is_address_read,
+		DataFlow,
+		Def,
+		Location,
+	},
+	debug::{
+		DebugInfo,
+		SymbolKind,
+		SymbolScope,
+	},
+	decoder::DecodeError,
+	functions::{
+		discover_plugin,
+		Function,
+		FunctionMap,
+	},
+	plugin::Plugin,
+	rtti::{
+		Rtti,
+		Signature,
+		Type,
+		VarClass,
+	},
+	sections::{
+		read_table,
+		DataSection,
+		Public,
+		Tag,
+		TAGS,
+	},
+	size_of,
+	vm_types::{
+		Cell,
+		CELL,
+		FIRST_ARG,
+	},
+	Instruction,
+	Smx,
+};
+
+use byteorder::ByteOrder;
+use core::ffi::CStr;
+use std::{
+	borrow::Borrow,
+	collections::{
+		BTreeMap,
+		BTreeSet,
+		HashMap,
+	},
+	error::Error,
+	ffi::CString,
+	fmt,
+	hash::Hash,
+	io::Error as IoError,
+};
+
+/// Function that was copied into the target plugin.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct LinkedFunction {
+	/// Name of the function, if it is public.
+	pub name: Option<CString>,
+	/// Code offset of the function in the source plugin.
+	pub source_start: usize,
+	/// Code offset of the function in the target plugin.
+	pub start: usize,
+}
+
+/// Summary of a link.
+#[derive(Default, Debug, Clone, PartialEq, Eq)]
+pub struct LinkReport {
+	/// Functions that were copied, in code order.
+	pub functions: Vec<LinkedFunction>,
+	/// Names of called functions that were resolved to the target's publics.
+	pub resolved: Vec<CString>,
+	/// Names of the natives that were added to the target.
+	pub added_natives: Vec<CString>,
+	/// Address of the source's data image in the target's.
+	pub data_base: Cell,
+}
+
+impl LinkReport {
+	/// Return the code offset of a copied public function in the target.
+	pub fn start_of(&self, name: &CStr) -> Option<usize> {
+		self.functions.iter()
+			.find(move |function| function.name.as_deref() == Some(name))
+			.map(move |function| function.start)
+	}
+}
+
+/// Structure for an error that has occurred while linking plugins.
+#[derive(Debug)]
+pub enum LinkError {
+	/// Sections could not be parsed.
+	Io(IoError),
+	/// Code could not be decoded.
+	Decode(DecodeError),
+	/// The source plugin has no public function with a name.
+	UnknownFunction(CString),
+	/// The target plugin already has a public function with a name.
+	Conflict(CString),
+	/// A copied instruction refers to code outside of its function, or calls
+	/// an offset that is not the start of a function.
+	Target {
+		offset: usize,
+		target: Cell,
+	},
+	/// A native is called through `SYSREQ.PRI`, so natives cannot be
+	/// renumbered.
+	DynamicNative {
+		offset: usize,
+	},
+	/// A constant operand may be a data address, but its uses do not show
+	/// whether it is one.
+	Constant {
+		offset: usize,
+		value: Cell,
+	},
+	/// A constant operand may be a function id, but its uses do not show
+	/// whether it is one.
+	FunctionId {
+		offset: usize,
+		value: Cell,
+	},
+}
+
+impl fmt::Display for LinkError {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			Self::Io(e) => write!(f, "io error {e}"),
+			Self::Decode(e) => write!(f, "decode error {e}"),
+			Self::UnknownFunction(name) =>
+				write!(f, "source has no public function {name:?}"),
+			Self::Conflict(name) =>
+				write!(f, "target already has a public function {name:?}"),
+			Self::Target { offset, target } => write!(
+				f,
+				"instruction at 0x{offset:08x} refers to unlinked code at 0x{target:08x}"
+			),
+			Self::DynamicNative { offset } =>
+				write!(f, "native is called dynamically at 0x{offset:08x}"),
+			Self::Constant { offset, value } =>
+				write!(f, "constant {value} at 0x{offset:08x} may be a data address"),
+			Self::FunctionId { offset, value } =>
+				write!(f, "constant {value} at 0x{offset:08x} may be a function id"),
+		}
+	}
+}
+
+impl Error for LinkError {}
+
+impl From<IoError> for LinkError {
+	fn from(value: IoError) -> Self {
+		Self::Io(value)
+	}
+}
+
+impl From<DecodeError> for LinkError {
+	fn from(value: DecodeError) -> Self {
+		Self::Decode(value)
+	}
+}
+
+/// Flag of tag ids for function tags.
+const FUNCTION_TAG: u32 = 0x2000_0000;
+/// Mask of tag ids for the tag index.
+const TAG_INDEX: u32 = 0x01ff_ffff;
+
+/// Way that an instruction uses a value that it reads.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+enum Usage {
+	/// Value that is neither a data address nor a function id.
+	Value,
+	Address,
+	/// Function id, which refers to a public function by its index.
+	FunctionId,
+	/// Argument of a call whose parameter is not known.
+	Argument,
+}
+
+/// Return how a parameter of a type is used.
+fn type_usage(ty: &Type) -> Usage {
+	match ty {
+		Type::FixedArray { .. }
+			| Type::Array(_)
+			| Type::ByRef(_)
+			| Type::EnumStruct(_)
+			| Type::Variadic(_) => Usage::Address,
+		Type::Function(_) | Type::TopFunction | Type::Typedef(_) | Type::Typeset(_) => {
+			Usage::FunctionId
+		}
+		Type::Const(inner) => type_usage(inner),
+		_ => Usage::Value,
+	}
+}
+
+/// How the parameters of a native or a function are used.
+#[derive(Default, Debug, Clone)]
+struct Params {
+	args: Vec<Usage>,
+	/// `true` if the function takes variadic arguments, which are always
+	/// passed by reference.
+	variadic: bool,
+}
+
+impl Params {
+	fn from_signature(signature: &Signature) -> Self {
+		let mut params = Self::default();
+		for arg in signature.args.iter() {
+			match arg {
+				Type::Variadic(_) => params.variadic = true,
+				arg => params.args.push(type_usage(arg)),
+			}
+		}
+		params
+	}
+
+	fn usage(&self, index: usize) -> Usage {
+		match self.args.get(index) {
+			Some(usage) => *usage,
+			None if self.variadic => Usage::Address,
+			None => Usage::Argument,
+		}
+	}
+}
+
+/// Return the function id of a public function.
+const fn function_id(index: usize) -> Cell {
+	(index as Cell) << 1 | 1
+}
+
+/// Constant operand of an instruction, with the ways that its value is used.
+#[derive(Debug, Clone)]
+struct Constant {
+	/// Code offset of the instruction.
+	offset: usize,
+	/// Index of the operand in [`Instruction::constants_mut`].
+	n: usize,
+	value: Cell,
+	usages: BTreeSet<Usage>,
+}
+
+impl Constant {
+	/// Return `true` if the constant is a data address, where `may_be` tells
+	/// whether a value looks like one.
+	fn is_address(&self, may_be: impl Fn(Cell) -> bool) -> Result<bool, LinkError> {
+		let other = self.usages.contains(&Usage::Value)
+			|| self.usages.contains(&Usage::FunctionId);
+		match (self.usages.contains(&Usage::Address), other) {
+			(true, false) => Ok(true),
+			(false, true) => Ok(false),
+			(false, false) if !may_be(self.value) => Ok(false),
+			_ => Err(LinkError::Constant { offset: self.offset, value: self.value }),
+		}
+	}
+
+	/// Return the index into `.publics` of the function that the constant
+	/// refers to by its id, where `may_be` tells whether an index is that of
+	/// a function whose id changes.
+	///
+	/// Function ids are used by passing them to natives and functions, so a
+	/// constant that looks like one is only rejected when it is passed to a
+	/// parameter that is not known.
+	fn public_index(
+		&self,
+		may_be: impl Fn(usize) -> bool,
+	) -> Result<Option<usize>, LinkError> {
+		let index = Some(self.value)
+			.filter(move |value| *value > 0 && value & 1 == 1)
+			.map(move |value| (value >> 1) as usize)
+			.filter(move |index| may_be(*index));
+		let Some(index) = index else {
+			return Ok(None)
+		};
+		let used = self.usages.contains(&Usage::FunctionId);
+		let other = self.usages.contains(&Usage::Value)
+			|| self.usages.contains(&Usage::Address);
+		match (used, other, self.usages.contains(&Usage::Argument)) {
+			(true, false, _) => Ok(Some(index)),
+			(false, _, false) => Ok(None),
+			_ => Err(LinkError::FunctionId { offset: self.offset, value: self.value }),
+		}
+	}
+}
+
+/// Debug information and RTTI of a plugin.
+struct Types {
+	debug: DebugInfo,
+	rtti: Rtti,
+	tags: Vec<Tag>,
+}
+
+impl Types {
+	fn from_smx<E, Name, Sect>(smx: &Smx<Name, Sect>) -> Result<Self, IoError>
+	where
+		E: ByteOrder,
+		Name: Borrow<CStr> + Eq + Hash,
+		Sect: AsRef<[u8]>,
+	{
+		Ok(Self {
+			debug: DebugInfo::from_smx::<E, _, _>(smx)?,
+			rtti: Rtti::from_smx::<E, _, _>(smx)?,
+			tags: smx.section(TAGS)
+				.map(move |section| read_table::<E, _>(section.as_ref()))
+				.transpose()?
+				.unwrap_or_default(),
+		})
+	}
+
+	/// Return `true` if a tag of `.dbg.symbols` is a function tag.
+	fn is_function_tag(&self, tag: i16) -> bool {
+		let tag = u32::from(tag as u16);
+		self.tags.iter().any(move |t| t.id & TAG_INDEX == tag && t.id & FUNCTION_TAG != 0)
+	}
+
+	/// Return how a parameter of `.dbg.*` is used.
+	fn symbol_usage(&self, kind: SymbolKind, tag: i16) -> Usage {
+		match kind {
+			SymbolKind::Reference
+				| SymbolKind::Array
+				| SymbolKind::RefArray
+				| SymbolKind::Varargs => Usage::Address,
+			_ if self.is_function_tag(tag) => Usage::FunctionId,
+			_ => Usage::Value,
+		}
+	}
+
+	/// Return the addresses of the global variables that are listed.
+	fn global_addresses(&self) -> impl Iterator<Item = Cell> + '_ {
+		let symbols = self.debug.symbols.iter()
+			.filter(move |symbol| {
+				matches!(symbol.scope, SymbolScope::Global | SymbolScope::Static)
+			})
+			.map(move |symbol| symbol.address);
+		let vars = self.rtti.globals.iter()
+			.chain(self.rtti.locals.iter())
+			.filter(move |var| matches!(var.class(), VarClass::Global | VarClass::Static))
+			.map(move |var| var.address);
+		symbols.chain(vars)
+	}
+
+	/// Return the addresses of the global variables that hold a function id.
+	fn function_globals(&self) -> Vec<Cell> {
+		if !self.rtti.globals.is_empty() {
+			return self.rtti.globals.iter()
+				.chain(self.rtti.locals.iter())
+				.filter(move |var| {
+					matches!(var.class(), VarClass::Global | VarClass::Static)
+						&& self.rtti.type_of(var.type_id)
+							.is_ok_and(move |ty| type_usage(&ty) == Usage::FunctionId)
+				})
+				.map(move |var| var.address)
+				.collect()
+		}
+		self.debug.symbols.iter()
+			.filter(move |symbol| {
+				matches!(symbol.scope, SymbolScope::Global | SymbolScope::Static)
+					&& symbol.kind == SymbolKind::Variable
+					&& self.is_function_tag(symbol.tag)
+			})
+			.map(move |symbol| symbol.address)
+			.collect()
+	}
+
+	fn native_params(&self, index: usize) -> Option<Params> {
+		if let Some(native) = self.rtti.natives.get(index) {
+			let signature = self.rtti.signature_at(native.signature).ok()?;
+			return Some(Params::from_signature(&signature))
+		}
+		let native = self.debug.natives.iter().find(move |n| n.index as usize == index)?;
+		let mut params = Params::default();
+		for arg in native.args.iter() {
+			match arg.kind {
+				SymbolKind::Varargs => params.variadic = true,
+				kind => params.args.push(self.symbol_usage(kind, arg.tag)),
+			}
+		}
+		Some(params)
+	}
+
+	fn function_params(&self, function: &Function) -> Option<Params> {
+		let method = self.rtti.methods.iter()
+			.find(move |m| m.pcode_start as usize == function.start);
+		if let Some(method) = method {
+			let signature = self.rtti.signature_at(method.signature).ok()?;
+			return Some(Params::from_signature(&signature))
+		}
+		let start = function.start;
+		self.debug.symbols.iter().find(move |symbol| {
+			symbol.kind == SymbolKind::Function && symbol.address as usize == start
+		})?;
+		let mut args = BTreeMap::new();
+		for symbol in self.debug.symbols.iter() {
+			if symbol.scope == SymbolScope::Local
+				&& symbol.address >= FIRST_ARG
+				&& function.contains(symbol.code_start as usize)
+			{
+				args.insert((symbol.address - FIRST_ARG) / CELL, symbol);
+			}
+		}
+		let mut params = Params::default();
+		for (index, symbol) in args {
+			if index as usize != params.args.len() || params.variadic {
+				break
+			}
+			match symbol.kind {
+				SymbolKind::Varargs => params.variadic = true,
+				kind => params.args.push(self.symbol_usage(kind, symbol.tag)),
+			}
+		}
+		Some(params)
+	}
+
+	/// Return how an instruction uses a location that it reads, where `n` is
+	/// the index of the read in [`DataFlow::uses_at`], or `None` if that is
+	/// not known.
+	fn usage(
+		&self,
+		map: &FunctionMap,
+		instruction: &Instruction,
+		n: usize,
+	) -> Option<Usage> {
+		use Instruction as I;
+		let params = move |params: Option<Params>, index: usize| {
+			params.map_or(Usage::Argument, move |params| params.usage(index))
+		};
+		Some(match (instruction, n) {
+			_ if is_address_read(instruction, n) => Usage::Address,
+			(I::Lidx | I::LidxB { .. } | I::Idxaddr | I::IdxaddrB { .. }, 0)
+				| (I::Bounds { .. } | I::Call { .. } | I::SysreqC { .. }, 0) => {
+				Usage::Value
+			}
+			(I::Call { func_1 }, n) => {
+				let function = map.at(*func_1 as usize);
+				params(function.and_then(|f| self.function_params(f)), n - 1)
+			}
+			(I::SysreqN { native, .. }, n) => {
+				params(self.native_params(*native as usize), n)
+			}
+			(I::SysreqC { native_1 }, n) => {
+				params(self.native_params(*native_1 as usize), n - 1)
+			}
+			_ => return None,
+		})
+	}
+
+	/// Return the constant operands of the reachable instructions of a
+	/// function, with the ways that the data-flow analysis shows their
+	/// values to be used.
+	fn constants(&self, map: &FunctionMap, function: &Function) -> Vec<Constant> {
+		let flow = DataFlow::function(function);
+		let mut usages: HashMap<(usize, Location), BTreeSet<Usage>> = HashMap::new();
+		for (pc, instruction) in function.instructions.iter() {
+			for (n, read) in flow.uses_at(*pc).iter().enumerate() {
+				let Some(usage) = self.usage(map, instruction, n) else {
+					continue
+				};
+				for (def, location) in flow.origins(*pc, read.location) {
+					if let Def::At(def) = def {
+						usages.entry((def, location)).or_default().insert(usage);
+					}
+				}
+			}
+		}
+
+		let mut constants = Vec::new();
+		for (pc, instruction) in function.instructions.iter() {
+			let Some(state) = flow.state_at(*pc) else {
+				continue
+			};
+			let mut instruction = instruction.clone();
+			let values: Vec<Cell> = instruction.constants_mut().into_iter()
+				.map(move |value| *value)
+				.collect();
+			for (n, value) in values.into_iter().enumerate() {
+				let usages = constant_location(&instruction, n, state.depth)
+					.and_then(|location| usages.get(&(*pc, location)).cloned());
+				constants.push(Constant {
+					offset: *pc,
+					n,
+					value,
+					usages: usages.unwrap_or_default(),
+				});
+			}
+		}
+		constants
+	}
+}
+
+/// Replace a constant operand of the instruction at a code offset.
+fn patch_constant(
+	code: &mut [u8],
+	function: &Function,
+	constant: &Constant,
+	value: Cell,
+) {
+	let Some((_, instruction)) = function.instructions.iter()
+		.find(move |(offset, _)| *offset == constant.offset)
+	else {
+		return
+	};
+	let mut instruction = instruction.clone();
+	if let Some(operand) = instruction.constants_mut().into_iter().nth(constant.n) {
+		*operand = value;
+	}
+	let mut bytes = Vec::new();
+	let _ = instruction.write_to(&mut bytes);
+	code[constant.offset..constant.offset + bytes.len()].copy_from_slice(&bytes);
+}
+
+/// Copy public functions of `source` with their dependencies into `target`.
+pub fn link<E, Name, Sect>(
+	target: &mut Smx<CString, Vec<u8>>,
+	source: &Smx<Name, Sect>,
+	functions: &[&CStr],
+) -> Result<LinkReport, LinkError>
+where
+	E: ByteOrder,
+	Name: Borrow<CStr> + Eq + Hash,
+	Sect: AsRef<[u8]>,
+{
+	let mut report = LinkReport::default();
+	let src = Plugin::from_smx::<E, _, _>(source)?;
+	let src_types = Types::from_smx::<E, _, _>(source)?;
+	let map = discover_plugin(&src, None)?;
+	let mut dst = Plugin::from_smx::<E, _, _>(target)?;
+	let dst_types = Types::from_smx::<E, _, _>(target)?;
+	let mut rtti = Rtti::from_smx::<E, _, _>(target)?;
+
+	let mut stack = Vec::new();
+	for name in functions.iter().copied() {
+		if dst.find_public(name).is_some() {
+			return Err(LinkError::Conflict(name.to_owned()))
+		}
+		let function = src.find_public(name)
+			.and_then(|index| map.at(src.publics[index].address as usize))
+			.ok_or_else(move || LinkError::UnknownFunction(name.to_owned()))?;
+		stack.push(function);
+	}
+
+	// Global variables of the source that hold function ids, with the index
+	// of the public function.
+	let cell_at = move |data: &DataSection, address: usize| {
+		data.bytes.get(address..address + size_of!(Cell)).map(E::read_i32)
+	};
+	let src_publics = src.publics.len();
+	let mut function_globals = BTreeMap::new();
+	for address in src_types.function_globals() {
+		let Some(value) = cell_at(&src.data, address as usize) else {
+			continue
+		};
+		if value > 0 && value & 1 == 1 && ((value >> 1) as usize) < src_publics {
+			function_globals.insert(address, (value >> 1) as usize);
+		}
+	}
+
+	// Functions to copy, called functions resolved to the target's, and the
+	// public functions that copied constants refer to by their ids.
+	let mut copied = BTreeSet::new();
+	let mut resolved = BTreeMap::new();
+	let mut constants = HashMap::new();
+	let mut referenced = BTreeSet::new();
+	while let Some(function) = stack.pop() {
+		if !copied.insert(function.start) {
+			continue
+		}
+		let function_constants = src_types.constants(&map, function);
+		let mut callees = Vec::new();
+		for (offset, instruction) in function.instructions.iter() {
+			if let Instruction::Call { func_1 } = instruction {
+				callees.push((*offset, *func_1 as usize));
+			}
+			for address in instruction.clone().data_refs_mut() {
+				if let Some(index) = function_globals.get(address) {
+					referenced.insert(*index);
+					callees.push((*offset, src.publics[*index].address as usize));
+				}
+			}
+		}
+		for constant in function_constants.iter() {
+			let index = constant.public_index(move |index| index < src_publics)?;
+			if let Some(index) = index {
+				callees.push((constant.offset, src.publics[index].address as usize));
+			}
+		}
+		constants.insert(function.start, function_constants);
+
+		for (offset, start) in callees {
+			let callee = map.at(start)
+				.filter(move |callee| {
+					matches!(callee.instructions.first(), Some((_, Instruction::Proc)))
+				})
+				.ok_or(LinkError::Target { offset, target: start as Cell })?;
+			let existing = callee.name.as_deref()
+				.filter(|name| !functions.contains(name))
+				.and_then(|name| Some((name, dst.find_public(name)?)));
+			match existing {
+				Some((name, index)) => {
+					let address = dst.publics[index].address;
+					if resolved.insert(callee.start, address).is_none() {
+						report.resolved.push(name.to_owned());
+					}
+				}
+				None => stack.push(callee),
+			}
+		}
+	}
+
+	let mut start = dst.code.code.len();
+	let mut starts = HashMap::new();
+	for function in copied.iter().filter_map(|start| map.at(*start)) {
+		starts.insert(function.start, start as Cell);
+		report.functions.push(LinkedFunction {
+			name: function.name.clone(),
+			source_start: function.start,
+			start,
+		});
+		start += function.instructions.iter()
+			.map(move |(_, instruction)| instruction.encoded_len())
+			.sum::<usize>();
+	}
+	for (start, address) in resolved {
+		starts.insert(start, address as Cell);
+	}
+
+	// SourceMod finds publics with a binary search, so copied publics are
+	// inserted in order, and the ids of the target's publics are renumbered.
+	let mut publics: Vec<(CString, Option<usize>, Public)> = (0..dst.publics.len())
+		.map(|index| {
+			let name = dst.public_name(index).unwrap_or_default();
+			(name, Some(index), dst.publics[index])
+		})
+		.collect();
+	for function in report.functions.iter() {
+		if let Some(name) = function.name.clone() {
+			let public = Public {
+				address: function.start as u32,
+				name: dst.names.insert(&name) as u32,
+			};
+			publics.push((name, None, public));
+		}
+	}
+	publics.sort_by(move |a, b| a.0.cmp(&b.0));
+	let mut renumbered = vec![0; dst.publics.len()];
+	for (new, (_, old, _)) in publics.iter().enumerate() {
+		if let Some(old) = old {
+			renumbered[*old] = new;
+		}
+	}
+	if renumbered.iter().enumerate().any(move |(old, new)| old != *new) {
+		let moved = |index: usize| renumbered.get(index).is_some_and(|new| *new != index);
+		let dst_map = discover_plugin(&dst, None)?;
+		for function in dst_map.functions.iter() {
+			for constant in dst_types.constants(&dst_map, function) {
+				if let Some(index) = constant.public_index(moved)? {
+					let id = function_id(renumbered[index]);
+					patch_constant(&mut dst.code.code, function, &constant, id);
+				}
+			}
+		}
+		for address in dst_types.function_globals() {
+			let address = address as usize;
+			let Some(value) = cell_at(&dst.data, address) else {
+				continue
+			};
+			if value > 0 && value & 1 == 1 && moved((value >> 1) as usize) {
+				let id = function_id(renumbered[(value >> 1) as usize]);
+				E::write_i32(&mut dst.data.bytes[address..], id);
+			}
+		}
+	}
+	dst.publics = publics.into_iter().map(move |(_, _, public)| public).collect();
+	// Ids of the source's publics in the target, for those that were linked.
+	let public_ids: Vec<Option<Cell>> = (0..src_publics)
+		.map(|index| {
+			let name = src.public_name(index).unwrap_or_default();
+			dst.find_public(&name).map(function_id)
+		})
+		.collect();
+
+	let data_base = dst.data.bytes.len().next_multiple_of(size_of!(Cell));
+	report.data_base = data_base as Cell;
+	dst.data.bytes.resize(data_base, 0);
+	dst.data.bytes.extend_from_slice(&src.data.bytes);
+	dst.data.extra_memory = dst.data.extra_memory.max(src.data.extra_memory);
+	for (address, index) in function_globals.iter() {
+		if let Some(id) = public_ids[*index].filter(|_| referenced.contains(index)) {
+			let address = data_base + *address as usize;
+			E::write_i32(&mut dst.data.bytes[address..], id);
+		}
+	}
+	let mut addresses: BTreeSet<Cell> = src_types.global_addresses().collect();
+	for function in copied.iter().filter_map(|start| map.at(*start)) {
+		for (_, instruction) in function.instructions.iter() {
+			addresses.extend(instruction.clone().data_refs_mut().into_iter().map(|r| *r));
+		}
+	}
+	let may_be_address = |value: Cell| {
+		addresses.contains(&value)
+			|| usize::try_from(value)
+				.is_ok_and(|address| src.data.is_string_start(address))
+	};
+
+	let mut natives = HashMap::new();
+	for function in copied.iter().filter_map(|start| map.at(*start)) {
+		let mut replaced = HashMap::new();
+		for constant in constants[&function.start].iter() {
+			let value = match constant.public_index(move |index| index < src_publics)? {
+				Some(index) => public_ids[index],
+				None if constant.is_address(may_be_address)? => {
+					Some(constant.value + data_base as Cell)
+				}
+				None => None,
+			};
+			if let Some(value) = value {
+				replaced.insert((constant.offset, constant.n), value);
+			}
+		}
+		let new_start = starts[&function.start];
+		let relocate = move |offset: usize, target: &mut Cell| {
+			if !function.contains(*target as usize) {
+				return Err(LinkError::Target { offset, target: *target })
+			}
+			*target += new_start - function.start as Cell;
+			Ok(())
+		};
+		for (offset, instruction) in function.instructions.iter() {
+			let mut instruction = instruction.clone();
+			match &mut instruction {
+				Instruction::Call { func_1 } => {
+					*func_1 = starts[&(*func_1 as usize)];
+				}
+				Instruction::SysreqN { native, .. }
+					| Instruction::SysreqC { native_1: native } => {
+					let name = src.native_name(*native as usize).unwrap_or_default();
+					*native = *natives.entry(*native).or_insert_with(|| {
+						let count = dst.natives.len();
+						let index = dst.add_native(&name);
+						if dst.natives.len() > count {
+							if !rtti.natives.is_empty() {
+								rtti.add_untyped_native(dst.natives[index].name);
+							}
+							report.added_natives.push(name);
+						}
+						index as Cell
+					});
+				}
+				Instruction::SysreqPri =>
+					return Err(LinkError::DynamicNative { offset: *offset }),
+				Instruction::Casetbl { switch: Some(switch), .. } => {
+					relocate(*offset, switch)?;
+				}
+				_ => (),
+			}
+			if !matches!(instruction, Instruction::Call { .. }) {
+				for target in instruction.code_refs_mut() {
+					relocate(*offset, target)?;
+				}
+			}
+			for address in instruction.data_refs_mut() {
+				*address += data_base as Cell;
+			}
+			for (n, constant) in instruction.constants_mut().into_iter().enumerate() {
+				if let Some(value) = replaced.get(&(*offset, n)) {
+					*constant = *value;
+				}
+			}
+			let _ = instruction.write_to(&mut dst.code.code);
+		}
+	}
+
+	dst.write_into::<E>(target);
+	rtti.write_into::<E>(target);
+	Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::{
+		decoder::Decoder,
+		rtti::{
+			type_codes as t,
+			RttiNative,
+		},
+		sections::Native,
+		verify::verify_plugin,
+	};
+	use byteorder::LittleEndian as Le;
+
+	/// Write a plugin into an SMX file, with an RTTI signature for each of
+	/// its natives that has one.
+	fn smx_with_natives(plugin: &Plugin, signatures: &[&[u8]]) -> Smx<CString, Vec<u8>> {
+		let mut rtti = Rtti::default();
+		for (native, signature) in plugin.natives.iter().zip(signatures) {
+			rtti.natives.push(RttiNative {
+				name: native.name,
+				signature: rtti.data.len() as u32,
+			});
+			rtti.data.extend_from_slice(signature);
+		}
+		let mut smx = Smx::new();
+		plugin.write_into::<Le>(&mut smx);
+		rtti.write_into::<Le>(&mut smx);
+		smx
+	}
+
+	/// Signature of `void (const char[] format, any ...)`.
+	const FORMAT: &[u8] = &[
+		t::FUNCTION, 2, t::VARIADIC, t::VOID, t::CONST, t::ARRAY, t::CHAR8, t::ANY,
+	];
+
+	fn plugin(
+		code: impl IntoIterator<Item = Instruction>,
+		publics: &[(u32, &CStr)],
+		natives: &[&CStr],
+	) -> Plugin {
+		let mut plugin = Plugin::default();
+		plugin.code.version = 13;
+		plugin.code.cell_size = 4;
+		for instruction in code {
+			let _ = instruction.write_to(&mut plugin.code.code);
+		}
+		for (address, name) in publics.iter().copied() {
+			let name = plugin.names.insert(name) as u32;
+			plugin.publics.push(Public { address, name });
+		}
+		for name in natives.iter().copied() {
+			let name = plugin.names.insert(name) as u32;
+			plugin.natives.push(Native { name });
+		}
+		plugin
+	}
+
+	#[test]
+	fn diagnostics() -> Result<(), Box<dyn Error>> {
+		let mut library = plugin(
+			[
+				// 0x00: Diag_Log
+				Instruction::Proc,
+				Instruction::PushC { const_1: 4 },
+				Instruction::SysreqN { native: 1, n_args: 1 },
+				Instruction::PushC { const_1: 0 },
+				Instruction::Call { func_1: 0x40 },
+				Instruction::PushC { const_1: 0 },
+				Instruction::Call { func_1: 0x54 },
+				Instruction::Retn,
+				Instruction::Endproc,
+				// 0x40: helper
+				Instruction::Proc,
+				Instruction::Inc { addr_1: 0 },
+				Instruction::Retn,
+				Instruction::Endproc,
+				// 0x54: Shared
+				Instruction::Proc,
+				Instruction::Retn,
+				Instruction::Endproc,
+				// 0x60: Diag_Unused
+				Instruction::Proc,
+				Instruction::Retn,
+				Instruction::Endproc,
+			],
+			&[(0x00, c"Diag_Log"), (0x54, c"Shared"), (0x60, c"Diag_Unused")],
+			&[c"LogMessage", c"PrintToServer"],
+		);
+		library.data.bytes = vec![0; 4];
+		library.data.push_string(c"diag");
+		let source = smx_with_natives(&library, &[FORMAT, FORMAT]);
+
+		let mut host = plugin(
+			[
+				Instruction::Proc,
+				Instruction::Retn,
+				Instruction::Endproc,
+				Instruction::Proc,
+				Instruction::Retn,
+				Instruction::Endproc,
+			],
+			&[(0x00, c"OnPluginStart"), (0x0c, c"Shared")],
+			&[c"PrintToServer"],
+		);
+		host.data.push_string(c"host");
+		let mut target = Smx::new();
+		host.write_into::<Le>(&mut target);
+
+		assert!(matches!(
+			link::<Le, _, _>(&mut target.clone(), &source, &[c"Shared"]),
+			Err(LinkError::Conflict(_))
+		));
+		assert!(matches!(
+			link::<Le, _, _>(&mut target.clone(), &source, &[c"Diag_Missing"]),
+			Err(LinkError::UnknownFunction(_))
+		));
+
+		let report = link::<Le, _, _>(&mut target, &source, &[c"Diag_Log"])?;
+		assert_eq!(report.start_of(c"Diag_Log"), Some(0x18));
+		assert_eq!(report.functions.len(), 2);
+		assert_eq!(report.resolved, [c"Shared".to_owned()]);
+		assert!(report.added_natives.is_empty());
+		assert_eq!(report.data_base, 8);
+
+		let linked = Plugin::from_smx::<Le, _, _>(&target)?;
+		verify_plugin(&linked)?;
+		assert_eq!(linked.natives.len(), 1);
+		let names: Vec<_> = (0..linked.publics.len())
+			.filter_map(|index| linked.public_name(index))
+			.collect();
+		assert_eq!(names, [c"Diag_Log", c"OnPluginStart", c"Shared"]);
+		assert_eq!(linked.publics[0].address, 0x18);
+		assert_eq!(linked.data.string_at(12).as_deref(), Some("diag"));
+		let code = Decoder::new(&linked.code.code[0x18..])
+			.map(move |decoded| decoded.map(move |(_, instruction)| instruction))
+			.collect::<Result<Vec<_>, _>>()?;
+		assert_eq!(code, [
+			Instruction::Proc,
+			Instruction::PushC { const_1: 12 },
+			Instruction::SysreqN { native: 0, n_args: 1 },
+			Instruction::PushC { const_1: 0 },
+			Instruction::Call { func_1: 0x58 },
+			Instruction::PushC { const_1: 0 },
+			Instruction::Call { func_1: 0x0c },
+			Instruction::Retn,
+			Instruction::Endproc,
+			Instruction::Proc,
+			Instruction::Inc { addr_1: 8 },
+			Instruction::Retn,
+			Instruction::Endproc,
+		]);
+		Ok(())
+	}
+
+	#[test]
+	fn constant_arguments() -> Result<(), Box<dyn Error>> {
+		let mut library = plugin(
+			[
+				// 0x00: Diag_Store
+				Instruction::Proc,
+				Instruction::PushC { const_1: 4 },
+				Instruction::PushC { const_1: 0 },
+				Instruction::SysreqN { native: 0, n_args: 2 },
+				Instruction::Inc { addr_1: 0 },
+				Instruction::Retn,
+				Instruction::Endproc,
+				// 0x30: Diag_Untyped
+				Instruction::Proc,
+				Instruction::PushC { const_1: 4 },
+				Instruction::SysreqN { native: 1, n_args: 1 },
+				Instruction::Retn,
+				Instruction::Endproc,
+			],
+			&[(0x00, c"Diag_Store"), (0x30, c"Diag_Untyped")],
+			&[c"Store", c"Untyped"],
+		);
+		library.data.bytes = vec![0; 4];
+		library.data.push_string(c"diag");
+		// void Store(int value, const char[] name)
+		let store = &[t::FUNCTION, 2, t::VOID, t::INT32, t::CONST, t::ARRAY, t::CHAR8];
+		let source = smx_with_natives(&library, &[store]);
+
+		let mut host = plugin([], &[], &[]);
+		host.data.bytes = vec![0; 4];
+		let mut target = Smx::new();
+		host.write_into::<Le>(&mut target);
+
+		assert!(matches!(
+			link::<Le, _, _>(&mut target.clone(), &source, &[c"Diag_Untyped"]),
+			Err(LinkError::Constant { offset: 0x34, value: 4 })
+		));
+
+		let report = link::<Le, _, _>(&mut target, &source, &[c"Diag_Store"])?;
+		assert_eq!(report.data_base, 4);
+		let linked = Plugin::from_smx::<Le, _, _>(&target)?;
+		let code = Decoder::new(&linked.code.code)
+			.map(move |decoded| decoded.map(move |(_, instruction)| instruction))
+			.collect::<Result<Vec<_>, _>>()?;
+		assert_eq!(code, [
+			Instruction::Proc,
+			Instruction::PushC { const_1: 8 },
+			Instruction::PushC { const_1: 0 },
+			Instruction::SysreqN { native: 0, n_args: 2 },
+			Instruction::Inc { addr_1: 4 },
+			Instruction::Retn,
+			Instruction::Endproc,
+		]);
+		Ok(())
+	}
+
+	#[test]
+	fn function_ids() -> Result<(), Box<dyn Error>> {
+		// void RequestFrame(Function callback, any data)
+		let request_frame = &[t::FUNCTION, 2, t::VOID, t::TOP_FUNCTION, t::ANY];
+		let library = plugin(
+			[
+				// 0x00: Diag_Callback
+				Instruction::Proc,
+				Instruction::Retn,
+				Instruction::Endproc,
+				// 0x0c: Diag_Defer
+				Instruction::Proc,
+				Instruction::PushC { const_1: 0 },
+				Instruction::PushC { const_1: 1 },
+				Instruction::SysreqN { native: 0, n_args: 2 },
+				Instruction::Retn,
+				Instruction::Endproc,
+			],
+			&[(0x00, c"Diag_Callback"), (0x0c, c"Diag_Defer")],
+			&[c"RequestFrame"],
+		);
+		let source = smx_with_natives(&library, &[request_frame]);
+
+		let host = plugin(
+			[
+				// 0x00: Alpha
+				Instruction::Proc,
+				Instruction::PushC { const_1: 3 },
+				Instruction::PushC { const_1: 3 },
+				Instruction::SysreqN { native: 0, n_args: 2 },
+				Instruction::Retn,
+				Instruction::Endproc,
+				// 0x2c: Shared
+				Instruction::Proc,
+				Instruction::Retn,
+				Instruction::Endproc,
+			],
+			&[(0x00, c"Alpha"), (0x2c, c"Shared")],
+			&[c"RequestFrame"],
+		);
+		let mut target = smx_with_natives(&host, &[request_frame]);
+
+		let report = link::<Le, _, _>(&mut target, &source, &[c"Diag_Defer"])?;
+		assert_eq!(report.functions.len(), 2);
+		let linked = Plugin::from_smx::<Le, _, _>(&target)?;
+		let names: Vec<_> = (0..linked.publics.len())
+			.filter_map(|index| linked.public_name(index))
+			.collect();
+		assert_eq!(names, [c"Alpha", c"Diag_Callback", c"Diag_Defer", c"Shared"]);
+
+		let code = Decoder::new(&linked.code.code)
+			.map(move |decoded| decoded.map(move |(_, instruction)| instruction))
+			.collect::<Result<Vec<_>, _>>()?;
+		assert_eq!(code[..4], [
+			Instruction::Proc,
+			Instruction::PushC { const_1: 3 },
+			Instruction::PushC { const_1: 7 },
+			Instruction::SysreqN { native: 0, n_args: 2 },
+		]);
+		let defer = report.start_of(c"Diag_Defer").unwrap();
+		let defer = Decoder::new(&linked.code.code[defer..])
+			.map(move |decoded| decoded.map(move |(_, instruction)| instruction))
+			.take(3)
+			.collect::<Result<Vec<_>, _>>()?;
+		assert_eq!(defer, [
+			Instruction::Proc,
+			Instruction::PushC { const_1: 0 },
+			Instruction::PushC { const_1: 3 },
+		]);
+		Ok(())
+	}
+}
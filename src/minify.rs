@@ -0,0 +1,567 @@
+//! Stripping and minification of compiled plugins.
+//!
+//! Minification performs the following steps:
+//! - every section that [`is_debug_section`] selects is removed, along with
+//!   the debug flag of `.code`;
+//! - functions that are not reachable through `CALL` from a `.publics` entry
+//!   are removed, along with code that does not belong to any function and
+//!   their `.rtti.methods` entries;
+//! - natives that are not called anymore are removed from `.natives` and
+//!   `.rtti.natives`, and `SYSREQ.N` and `SYSREQ.C` are renumbered;
+//! - `.names` is rebuilt with only the names of `.publics`, `.pubvars`,
+//!   `.natives`, `.tags`, `.rtti.methods` and `.rtti.natives` entries.
+//!
+//! Public functions and variables are never removed, and the data image is
+//! left unchanged.
+
+use crate::{
+	companion::is_debug_section,
+	decoder::{
+		DecodeError,
+		Decoder,
+	},
+	functions::discover_plugin,
+	plugin::Plugin,
+	rtti::Rtti,
+	sections::{
+		self,
+		read_table,
+		write_table,
+		CodeSection,
+		Tag,
+	},
+	smx::{
+		Endianness,
+		SmxError,
+	},
+	smx_table::CStrTable,
+	vm_types::Cell,
+	CompressionLevel,
+	Instruction,
+	Smx,
+};
+
+use byteorder::{
+	BigEndian,
+	ByteOrder,
+	LittleEndian,
+};
+use never_say_never::Never;
+use std::{
+	collections::{
+		BTreeMap,
+		BTreeSet,
+	},
+	error::Error,
+	ffi::CString,
+	fmt,
+	io::{
+		Cursor,
+		Error as IoError,
+	},
+};
+
+/// Compression level that minified plugins are written with.
+pub const COMPRESSION: CompressionLevel = CompressionLevel::UberCompression;
+
+/// Summary of a minification.
+#[derive(Default, Debug, Clone, PartialEq, Eq)]
+pub struct MinifyReport {
+	/// Size of the plugin before minification, in bytes.
+	pub size_before: usize,
+	/// Size of the plugin after minification, in bytes.
+	pub size_after: usize,
+	/// Names of the removed sections.
+	pub removed_sections: Vec<CString>,
+	/// Old code ranges of the removed functions.
+	pub removed_functions: Vec<(usize, usize)>,
+	/// Names of the removed natives.
+	pub removed_natives: Vec<CString>,
+	/// Number of bytes that were removed from `.names`.
+	pub names_saved: usize,
+}
+
+impl fmt::Display for MinifyReport {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		writeln!(f, "{} -> {} bytes", self.size_before, self.size_after)?;
+		for name in self.removed_sections.iter() {
+			writeln!(f, "removed section {}", name.to_string_lossy())?;
+		}
+		for (start, end) in self.removed_functions.iter() {
+			writeln!(f, "removed function 0x{start:08x}..0x{end:08x}")?;
+		}
+		for name in self.removed_natives.iter() {
+			writeln!(f, "removed native {}", name.to_string_lossy())?;
+		}
+		write!(f, "removed {} bytes of names", self.names_saved)
+	}
+}
+
+/// Structure for an error that has occurred while minifying a plugin.
+#[derive(Debug)]
+pub enum MinifyError {
+	/// The SMX file could not be read.
+	Smx(SmxError<Never>),
+	/// A section could not be parsed or written.
+	Io(IoError),
+	/// Code could not be decoded.
+	Decode(DecodeError),
+	/// A `.publics` entry is not the start of a function.
+	InvalidPublic {
+		index: usize,
+		address: u32,
+	},
+	/// A kept instruction refers to removed code.
+	Target {
+		offset: usize,
+		target: Cell,
+	},
+	/// A native is called through `SYSREQ.PRI`, so natives cannot be
+	/// renumbered.
+	DynamicNative {
+		offset: usize,
+	},
+}
+
+impl fmt::Display for MinifyError {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			Self::Smx(e) => write!(f, "smx error {e}"),
+			Self::Io(e) => write!(f, "io error {e}"),
+			Self::Decode(e) => write!(f, "decode error {e}"),
+			Self::InvalidPublic { index, address } => write!(
+				f,
+				"public {index} at 0x{address:08x} is not the start of a function"
+			),
+			Self::Target { offset, target } => write!(
+				f,
+				"instruction at 0x{offset:08x} refers to removed code at 0x{target:08x}"
+			),
+			Self::DynamicNative { offset } =>
+				write!(f, "native is called dynamically at 0x{offset:08x}"),
+		}
+	}
+}
+
+impl Error for MinifyError {}
+
+impl From<SmxError<Never>> for MinifyError {
+	fn from(value: SmxError<Never>) -> Self {
+		Self::Smx(value)
+	}
+}
+
+impl From<IoError> for MinifyError {
+	fn from(value: IoError) -> Self {
+		Self::Io(value)
+	}
+}
+
+impl From<DecodeError> for MinifyError {
+	fn from(value: DecodeError) -> Self {
+		Self::Decode(value)
+	}
+}
+
+/// Return the size of an SMX file when written with [`COMPRESSION`].
+fn written_len<E: ByteOrder>(smx: &Smx<CString, Vec<u8>>) -> Result<usize, IoError> {
+	let mut bytes = Vec::new();
+	smx.write_to::<E>(&mut bytes, COMPRESSION)?;
+	Ok(bytes.len())
+}
+
+/// Minify an SMX file in place.
+///
+/// The sizes in the report are those of the SMX file written with
+/// [`COMPRESSION`] before and after minification.
+pub fn minify_smx<E: ByteOrder>(
+	smx: &mut Smx<CString, Vec<u8>>,
+) -> Result<MinifyReport, MinifyError> {
+	let mut report = MinifyReport {
+		size_before: written_len::<E>(smx)?,
+		..Default::default()
+	};
+
+	report.removed_sections = strip_debug(smx);
+
+	let mut plugin = Plugin::from_smx::<E, _, _>(smx)?;
+	if !report.removed_sections.is_empty() {
+		plugin.code.flags &= !CodeSection::FLAG_DEBUG;
+	}
+	let mut rtti = Rtti::from_smx::<E, _, _>(smx)?;
+	report.removed_functions = remove_unreachable(&mut plugin, &mut rtti)?;
+	let renumber;
+	(report.removed_natives, renumber) = remove_unused_natives(&mut plugin)?;
+	rtti.natives = rtti.natives.into_iter()
+		.enumerate()
+		.filter(|(index, _)| renumber.contains_key(&(*index as Cell)))
+		.map(move |(_, native)| native)
+		.collect();
+	rtti.write_into::<E>(smx);
+
+	report.names_saved = compact_names::<E>(smx, &mut plugin)?;
+	plugin.write_into::<E>(smx);
+	report.size_after = written_len::<E>(smx)?;
+	Ok(report)
+}
+
+/// Remove every section of an SMX file that [`is_debug_section`] selects,
+/// returning their sorted names.
+pub(crate) fn strip_debug(smx: &mut Smx<CString, Vec<u8>>) -> Vec<CString> {
+	let mut removed: Vec<CString> = smx.sections.keys()
+		.filter(move |name| is_debug_section(name))
+		.cloned()
+		.collect();
+	removed.sort();
+	for name in removed.iter() {
+		smx.sections.remove(name);
+	}
+	removed
+}
+
+/// Rebuild `.names` with only the names of `.publics`, `.pubvars`, `.natives`,
+/// `.tags`, `.rtti.methods` and `.rtti.natives` entries, returning the number
+/// of bytes saved.
+///
+/// `.tags` and the RTTI sections are written back into `smx`, but `plugin` is
+/// not.
+pub(crate) fn compact_names<E: ByteOrder>(
+	smx: &mut Smx<CString, Vec<u8>>,
+	plugin: &mut Plugin,
+) -> Result<usize, IoError> {
+	let mut tags: Vec<Tag> = smx.section(sections::TAGS)
+		.map(move |section| read_table::<E, _>(section))
+		.transpose()?
+		.unwrap_or_default();
+	let mut rtti = Rtti::from_smx::<E, _, _>(smx)?;
+	let names_before = plugin.names.len();
+	let mut names = CStrTable::new();
+	let mut rename = |name: &mut u32| {
+		let old = plugin.names.get_c_string(*name as _).unwrap_or_default();
+		*name = names.insert(old) as _;
+	};
+	plugin.publics.iter_mut().for_each(|public| rename(&mut public.name));
+	plugin.pubvars.iter_mut().for_each(|pubvar| rename(&mut pubvar.name));
+	plugin.natives.iter_mut().for_each(|native| rename(&mut native.name));
+	tags.iter_mut().for_each(|tag| rename(&mut tag.name));
+	rtti.methods.iter_mut().for_each(|method| rename(&mut method.name));
+	rtti.natives.iter_mut().for_each(|native| rename(&mut native.name));
+	plugin.names = names;
+	rtti.write_into::<E>(smx);
+
+	if smx.section(sections::TAGS).is_some() {
+		smx.sections.insert(sections::TAGS.to_owned(), write_table::<E, _>(&tags));
+	}
+	Ok(names_before.saturating_sub(plugin.names.len()))
+}
+
+/// Minify the bytes of an SMX file, writing the result with [`COMPRESSION`].
+///
+/// The size before minification in the report is the size of `bytes`.
+pub fn minify(bytes: &[u8]) -> Result<(Vec<u8>, MinifyReport), MinifyError> {
+	let (mut smx, endianness) = Smx::read_from(&mut Cursor::new(bytes))?;
+	let mut minified = Vec::new();
+	let mut report = match endianness {
+		Endianness::Little => {
+			let report = minify_smx::<LittleEndian>(&mut smx)?;
+			smx.write_to::<LittleEndian>(&mut minified, COMPRESSION)?;
+			report
+		}
+		Endianness::Big => {
+			let report = minify_smx::<BigEndian>(&mut smx)?;
+			smx.write_to::<BigEndian>(&mut minified, COMPRESSION)?;
+			report
+		}
+	};
+	report.size_before = bytes.len();
+	report.size_after = minified.len();
+	Ok((minified, report))
+}
+
+/// Remove functions that are unreachable from `.publics`, along with their
+/// `.rtti.methods` entries, returning their old code ranges.
+fn remove_unreachable(
+	plugin: &mut Plugin,
+	rtti: &mut Rtti,
+) -> Result<Vec<(usize, usize)>, MinifyError> {
+	let map = discover_plugin(plugin, None)?;
+	let index_of = |offset: usize| map.functions
+		.binary_search_by_key(&map.containing(offset)?.start, move |f| f.start)
+		.ok();
+
+	let mut kept = vec![false; map.functions.len()];
+	let mut stack = Vec::new();
+	for (index, public) in plugin.publics.iter().enumerate() {
+		let address = public.address;
+		let function = map.functions
+			.binary_search_by_key(&(address as usize), move |f| f.start)
+			.map_err(move |_| MinifyError::InvalidPublic { index, address })?;
+		stack.push(function);
+	}
+	while let Some(function) = stack.pop() {
+		if std::mem::replace(&mut kept[function], true) {
+			continue
+		}
+		for (_, instruction) in map.functions[function].instructions.iter() {
+			if let Instruction::Call { func_1 } = instruction {
+				stack.extend(index_of(*func_1 as usize));
+			}
+		}
+	}
+
+	let mut relocations = BTreeMap::new();
+	let mut instructions = Vec::new();
+	let mut removed = Vec::new();
+	let mut offset = 0;
+	for (function, kept) in map.functions.iter().zip(kept) {
+		if !kept {
+			removed.push((function.start, function.end));
+			continue
+		}
+		for (old, instruction) in function.instructions.iter() {
+			relocations.insert(*old, offset);
+			instructions.push((*old, instruction.clone()));
+			offset += instruction.encoded_len();
+		}
+		relocations.entry(function.end).or_insert(offset);
+	}
+	relocations.insert(plugin.code.code.len(), offset);
+
+	let relocate = move |offset: usize, target: &mut Cell| {
+		*target = *relocations.get(&(*target as usize))
+			.ok_or(MinifyError::Target { offset, target: *target })? as Cell;
+		Ok::<_, MinifyError>(())
+	};
+	let mut code = Vec::with_capacity(offset);
+	for (old, mut instruction) in instructions {
+		for target in instruction.code_refs_mut() {
+			relocate(old, target)?;
+		}
+		if let Instruction::Casetbl { switch: Some(switch), .. } = &mut instruction {
+			relocate(old, switch)?;
+		}
+		let _ = instruction.write_to(&mut code);
+	}
+	for public in plugin.publics.iter_mut() {
+		let mut address = public.address as Cell;
+		relocate(0, &mut address)?;
+		public.address = address as _;
+	}
+	let mut main = plugin.code.main as Cell;
+	if relocate(0, &mut main).is_ok() {
+		plugin.code.main = main as _;
+	}
+	rtti.methods.retain(|method| {
+		let start = method.pcode_start as usize;
+		!removed.iter().any(move |(removed_start, removed_end)| {
+			(*removed_start..*removed_end).contains(&start)
+		})
+	});
+	for method in rtti.methods.iter_mut() {
+		let (mut start, mut end) = (method.pcode_start as Cell, method.pcode_end as Cell);
+		relocate(0, &mut start)?;
+		relocate(0, &mut end)?;
+		(method.pcode_start, method.pcode_end) = (start as _, end as _);
+	}
+	plugin.code.code = code;
+	Ok(removed)
+}
+
+/// Remove natives that are not called, returning their names and the map of
+/// old indices of the kept natives to new ones.
+pub(crate) fn remove_unused_natives(
+	plugin: &mut Plugin,
+) -> Result<(Vec<CString>, BTreeMap<Cell, Cell>), MinifyError> {
+	let instructions = Decoder::new(&plugin.code.code).collect::<Result<Vec<_>, _>>()?;
+	let mut used = BTreeSet::new();
+	for (offset, instruction) in instructions.iter() {
+		match instruction {
+			Instruction::SysreqN { native, .. }
+				| Instruction::SysreqC { native_1: native } => {
+				used.insert(*native);
+			}
+			Instruction::SysreqPri =>
+				return Err(MinifyError::DynamicNative { offset: *offset }),
+			_ => (),
+		}
+	}
+
+	let mut removed = Vec::new();
+	let mut renumber = BTreeMap::new();
+	let natives = std::mem::take(&mut plugin.natives);
+	for (index, native) in natives.into_iter().enumerate() {
+		if used.contains(&(index as Cell)) {
+			renumber.insert(index as Cell, plugin.natives.len() as Cell);
+			plugin.natives.push(native);
+		} else {
+			removed.push(plugin.name(native.name).unwrap_or_default());
+		}
+	}
+	if removed.is_empty() {
+		return Ok((removed, renumber))
+	}
+
+	let mut code = Vec::with_capacity(plugin.code.code.len());
+	for (_, mut instruction) in instructions {
+		match &mut instruction {
+			Instruction::SysreqN { native, .. }
+				| Instruction::SysreqC { native_1: native } => {
+				*native = renumber.get(native).copied().unwrap_or(*native);
+			}
+			_ => (),
+		}
+		let _ = instruction.write_to(&mut code);
+	}
+	plugin.code.code = code;
+	Ok((removed, renumber))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::{
+		rtti::{
+			RttiMethod,
+			RttiTypedef,
+		},
+		sections::{
+			Native,
+			Public,
+			Pubvar,
+		},
+	};
+
+	fn plugin() -> Plugin {
+		let mut plugin = Plugin::default();
+		plugin.code.version = 13;
+		plugin.code.cell_size = 4;
+		plugin.code.flags = CodeSection::FLAG_DEBUG;
+		for instruction in [
+			// 0x00: unused
+			Instruction::Proc,
+			Instruction::SysreqN { native: 0, n_args: 0 },
+			Instruction::Retn,
+			Instruction::Endproc,
+			// 0x18: OnPluginStart
+			Instruction::Proc,
+			Instruction::PushC { const_1: 0 },
+			Instruction::Call { func_1: 0x34 },
+			Instruction::Retn,
+			Instruction::Endproc,
+			// 0x34: helper
+			Instruction::Proc,
+			Instruction::SysreqN { native: 1, n_args: 0 },
+			Instruction::Jump { jump_1: 0x4c },
+			Instruction::Retn,
+			Instruction::Endproc,
+		] {
+			let _ = instruction.write_to(&mut plugin.code.code);
+		}
+		let name = plugin.names.insert(c"OnPluginStart") as u32;
+		plugin.publics.push(Public { address: 0x18, name });
+		let name = plugin.names.insert(c"myinfo") as u32;
+		plugin.pubvars.push(Pubvar { address: 0, name });
+		for native in [c"Unused", c"PrintToServer"] {
+			let name = plugin.names.insert(native) as u32;
+			plugin.natives.push(Native { name });
+		}
+		plugin
+	}
+
+	#[test]
+	fn minify_plugin() -> Result<(), MinifyError> {
+		let mut smx = Smx::new();
+		plugin().write_into::<LittleEndian>(&mut smx);
+		smx.sections.insert(c".dbg.info".to_owned(), vec![0; 16]);
+
+		let report = minify_smx::<LittleEndian>(&mut smx)?;
+		assert_eq!(report.removed_sections, [c".dbg.info".to_owned()]);
+		assert_eq!(report.removed_functions, [(0x00, 0x18)]);
+		assert_eq!(report.removed_natives, [c"Unused".to_owned()]);
+		assert_eq!(report.names_saved, 7);
+
+		let plugin = Plugin::from_smx::<LittleEndian, _, _>(&smx)?;
+		assert_eq!(plugin.code.flags, 0);
+		assert_eq!(plugin.public_name(0).as_deref(), Some(c"OnPluginStart"));
+		assert_eq!(plugin.publics[0].address, 0);
+		assert_eq!(plugin.pubvar_name(0).as_deref(), Some(c"myinfo"));
+		assert_eq!(plugin.native_name(0).as_deref(), Some(c"PrintToServer"));
+
+		let code = Decoder::new(&plugin.code.code)
+			.map(move |decoded| decoded.map(move |(_, instruction)| instruction))
+			.collect::<Result<Vec<_>, _>>()?;
+		assert_eq!(code, [
+			Instruction::Proc,
+			Instruction::PushC { const_1: 0 },
+			Instruction::Call { func_1: 0x1c },
+			Instruction::Retn,
+			Instruction::Endproc,
+			Instruction::Proc,
+			Instruction::SysreqN { native: 0, n_args: 0 },
+			Instruction::Jump { jump_1: 0x34 },
+			Instruction::Retn,
+			Instruction::Endproc,
+		]);
+		Ok(())
+	}
+
+	#[test]
+	fn round_trip() -> Result<(), MinifyError> {
+		let mut smx = Smx::new();
+		plugin().write_into::<LittleEndian>(&mut smx);
+		let mut bytes = Vec::new();
+		smx.write_to::<LittleEndian>(&mut bytes, CompressionLevel::NoCompression)?;
+
+		let (minified, report) = minify(&bytes)?;
+		assert_eq!(report.size_before, bytes.len());
+		assert_eq!(report.size_after, minified.len());
+		let (smx, _) = Smx::<CString, Vec<u8>>::read_from(&mut Cursor::new(minified))?;
+		assert_eq!(Plugin::from_smx::<LittleEndian, _, _>(&smx)?.natives.len(), 1);
+		Ok(())
+	}
+
+	#[test]
+	fn keep_runtime_rtti() -> Result<(), MinifyError> {
+		let mut plugin = plugin();
+		let mut rtti = Rtti::default();
+		let end = plugin.code.code.len() as u32;
+		for (name, pcode_start, pcode_end) in [
+			(c"Unused", 0x00, 0x18),
+			(c"OnPluginStart", 0x18, 0x34),
+			(c"Helper", 0x34, end),
+		] {
+			let name = plugin.names.insert(name) as u32;
+			rtti.methods.push(RttiMethod { name, pcode_start, pcode_end, signature: 0 });
+		}
+		for native in plugin.natives.clone() {
+			rtti.add_untyped_native(native.name);
+		}
+		let name = plugin.names.insert(c"Callback") as u32;
+		rtti.typedefs.push(RttiTypedef { name, type_id: 0 });
+		let mut smx = Smx::new();
+		plugin.write_into::<LittleEndian>(&mut smx);
+		rtti.write_into::<LittleEndian>(&mut smx);
+
+		let report = minify_smx::<LittleEndian>(&mut smx)?;
+		assert_eq!(report.removed_sections, [c".rtti.typedefs".to_owned()]);
+
+		let plugin = Plugin::from_smx::<LittleEndian, _, _>(&smx)?;
+		let rtti = Rtti::from_smx::<LittleEndian, _, _>(&smx)?;
+		let methods: Vec<_> = rtti.methods.iter()
+			.map(|method| {
+				let name = plugin.name(method.name).unwrap_or_default();
+				(name, method.pcode_start, method.pcode_end)
+			})
+			.collect();
+		assert_eq!(methods, [
+			(c"OnPluginStart".to_owned(), 0x00, 0x1c),
+			(c"Helper".to_owned(), 0x1c, 0x3c),
+		]);
+		assert_eq!(rtti.natives.len(), 1);
+		assert_eq!(plugin.name(rtti.natives[0].name).as_deref(), Some(c"PrintToServer"));
+		let signature = rtti.signature_at(rtti.natives[0].signature);
+		assert_eq!(signature.map(move |signature| signature.args.len()), Ok(1));
+		assert!(!plugin.names.iter().any(move |(_, name)| name == b"Callback"));
+		Ok(())
+	}
+}
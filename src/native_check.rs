@@ -0,0 +1,177 @@
+//! Checks of native calls in `.code` against prototypes from include files.
+
+use crate::{
+	decoder::{
+		DecodeError,
+		Decoder,
+	},
+	includes::{
+		Includes,
+		Prototype,
+	},
+	plugin::Plugin,
+	vm_types::Cell,
+	Instruction,
+};
+
+use std::{
+	collections::BTreeSet,
+	fmt,
+};
+
+/// Call of a native with an argument count that its prototype does not
+/// accept.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ArgCountMismatch {
+	/// Code offset of the `SYSREQ.N`.
+	pub offset: usize,
+	/// Number of arguments that are passed.
+	pub n_args: Cell,
+	/// Prototype of the native.
+	pub prototype: Prototype,
+}
+
+/// Result of checking the native calls of a plugin.
+#[derive(Default, Debug, Clone, PartialEq, Eq)]
+pub struct NativeReport {
+	/// Calls with wrong argument counts, sorted by offset.
+	pub mismatches: Vec<ArgCountMismatch>,
+	/// Names of natives in `.natives` without a prototype.
+	pub unknown: BTreeSet<String>,
+	/// Names of natives in `.natives` that are marked as optional.
+	pub optional: BTreeSet<String>,
+	/// Indices of natives that are called, but are not in `.natives`.
+	pub invalid: BTreeSet<Cell>,
+}
+
+impl NativeReport {
+	/// Return `true` if no call has a wrong argument count, and every native
+	/// has a prototype.
+	pub fn is_clean(&self) -> bool {
+		self.mismatches.is_empty() && self.unknown.is_empty() && self.invalid.is_empty()
+	}
+}
+
+impl fmt::Display for NativeReport {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		for mismatch in self.mismatches.iter() {
+			let prototype = &mismatch.prototype;
+			write!(
+				f,
+				"0x{:08x}: {} called with {} arguments, expected {}",
+				mismatch.offset, prototype.name, mismatch.n_args, prototype.required
+			)?;
+			if prototype.variadic {
+				writeln!(f, " or more")?;
+			} else if prototype.max != prototype.required {
+				writeln!(f, " to {}", prototype.max)?;
+			} else {
+				writeln!(f)?;
+			}
+		}
+		for native in self.invalid.iter() {
+			writeln!(f, "native index {native} is not in .natives")?;
+		}
+		for name in self.unknown.iter() {
+			writeln!(f, "{name} has no prototype")?;
+		}
+		for name in self.optional.iter() {
+			writeln!(f, "{name} is optional")?;
+		}
+		Ok(())
+	}
+}
+
+/// Check every `SYSREQ.N` in the code of a plugin against the prototypes of
+/// its natives.
+pub fn check(plugin: &Plugin, includes: &Includes) -> Result<NativeReport, DecodeError> {
+	let mut report = NativeReport::default();
+
+	let names: Vec<Option<String>> = (0..plugin.natives.len())
+		.map(move |index| plugin.native_name(index)
+			.map(move |name| name.to_string_lossy().into_owned()))
+		.collect();
+	for name in names.iter().flatten() {
+		if includes.get(name).is_none() {
+			report.unknown.insert(name.clone());
+		}
+		if includes.is_optional(name) {
+			report.optional.insert(name.clone());
+		}
+	}
+
+	for decoded in Decoder::new(&plugin.code.code) {
+		let (offset, instruction) = decoded?;
+		let Instruction::SysreqN { native, n_args } = instruction else {
+			continue
+		};
+		let name = usize::try_from(native).ok()
+			.and_then(|index| names.get(index).cloned().flatten());
+		let Some(name) = name else {
+			report.invalid.insert(native);
+			continue
+		};
+		let Some(prototype) = includes.get(&name) else {
+			continue
+		};
+		if n_args < 0 || !prototype.accepts(n_args as usize) {
+			report.mismatches.push(ArgCountMismatch {
+				offset,
+				n_args,
+				prototype: prototype.clone(),
+			});
+		}
+	}
+
+	Ok(report)
+}
+
+#[test]
+fn check_plugin() -> Result<(), DecodeError> {
+	use crate::{
+		instruction::layout,
+		sections::Native,
+	};
+
+	let mut includes = Includes::new();
+	includes.parse(r#"
+		native void PrintToServer(const char[] format, any ...);
+		native int GetClientCount(bool inGameOnly = true);
+		native void Foo_Bar();
+		public void __pl_foo_SetNTVOptional() { MarkNativeAsOptional("Foo_Bar"); }
+	"#);
+
+	let mut plugin = Plugin::default();
+	for name in [c"PrintToServer", c"GetClientCount", c"Foo_Bar", c"LogMessage"] {
+		let name = plugin.names.insert(name) as u32;
+		plugin.natives.push(Native { name });
+	}
+	let (code, _) = layout(vec![
+		Instruction::SysreqN { native: 0, n_args: 3 },
+		Instruction::SysreqN { native: 0, n_args: 0 },
+		Instruction::SysreqN { native: 1, n_args: 2 },
+		Instruction::SysreqN { native: 2, n_args: 0 },
+		Instruction::SysreqN { native: 7, n_args: 0 },
+	]);
+	for (_, instruction) in code {
+		let _ = instruction.write_to(&mut plugin.code.code);
+	}
+
+	let report = check(&plugin, &includes)?;
+	assert_eq!(
+		report.mismatches.iter().map(move |m| m.offset).collect::<Vec<_>>(),
+		[12, 24]
+	);
+	assert_eq!(report.unknown, BTreeSet::from(["LogMessage".into()]));
+	assert_eq!(report.optional, BTreeSet::from(["Foo_Bar".into()]));
+	assert_eq!(report.invalid, BTreeSet::from([7]));
+	assert!(!report.is_clean());
+	assert_eq!(
+		report.to_string().lines().take(2).collect::<Vec<_>>(),
+		[
+			"0x0000000c: PrintToServer called with 0 arguments, expected 1 or more",
+			"0x00000018: GetClientCount called with 2 arguments, expected 0 to 1",
+		]
+	);
+	Ok(())
+}
@@ -0,0 +1,505 @@
+//! Obfuscation of public names, with a map to restore them.
+//!
+//! Obfuscation renames every public function and variable that SourceMod does
+//! not look up by name, removes every debug section, renames `.rtti.methods`
+//! entries after their public or their code offset, and rebuilds `.names` so
+//! that the original names are gone. Names that are kept are [`REQUIRED`],
+//! those starting with `__`, such as `__version` or `__ext_core`, and those of
+//! a supplied allowlist.
+//!
+//! SourceMod finds publics with a binary search, so obfuscated names are
+//! chosen to sort between the kept names around them, and the indices of
+//! every entry are unchanged. These names are not necessarily valid
+//! identifiers. In the rare case where no such name exists, the original
+//! names are kept.
+//!
+//! Deobfuscation restores the names of publics and pubvars from a [`NameMap`],
+//! along with those of their `.rtti.methods` entries. Debug information and
+//! the names of other methods cannot be restored.
+
+use crate::{
+	minify::{
+		compact_names,
+		strip_debug,
+	},
+	plugin::Plugin,
+	rtti::Rtti,
+	sections::CodeSection,
+	Smx,
+};
+
+use byteorder::ByteOrder;
+use core::{
+	ffi::CStr,
+	str::FromStr,
+};
+use std::{
+	error::Error,
+	ffi::CString,
+	fmt,
+	io::Error as IoError,
+};
+
+/// Names that SourceMod looks up directly, and that are never obfuscated.
+pub const REQUIRED: &[&CStr] = &[
+	c"AskPluginLoad",
+	c"AskPluginLoad2",
+	c"MaxClients",
+	c"NULL_STRING",
+	c"NULL_VECTOR",
+	c"OnAllPluginsLoaded",
+	c"OnPluginEnd",
+	c"OnPluginPauseChange",
+	c"OnPluginStart",
+	c"myinfo",
+];
+
+/// Table that an obfuscated name belongs to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum NameKind {
+	/// `.publics`.
+	Public,
+	/// `.pubvars`.
+	Pubvar,
+}
+
+impl NameKind {
+	const fn as_str(self) -> &'static str {
+		match self {
+			Self::Public => "public",
+			Self::Pubvar => "pubvar",
+		}
+	}
+}
+
+/// Obfuscated name with its original.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct MappedName {
+	pub kind: NameKind,
+	pub obfuscated: CString,
+	pub original: CString,
+}
+
+/// Map from obfuscated names to original ones.
+///
+/// As text, it holds one line per name, with its kind, its obfuscated name
+/// and its original name separated by tabs.
+#[derive(Default, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct NameMap {
+	pub entries: Vec<MappedName>,
+}
+
+impl NameMap {
+	/// Return the original name of an obfuscated one.
+	pub fn original(&self, kind: NameKind, obfuscated: &CStr) -> Option<&CStr> {
+		self.entries.iter()
+			.find(move |entry| entry.kind == kind && *entry.obfuscated == *obfuscated)
+			.map(move |entry| entry.original.as_c_str())
+	}
+}
+
+impl fmt::Display for NameMap {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		for entry in self.entries.iter() {
+			writeln!(
+				f,
+				"{}\t{}\t{}",
+				entry.kind.as_str(),
+				entry.obfuscated.to_string_lossy(),
+				entry.original.to_string_lossy()
+			)?;
+		}
+		Ok(())
+	}
+}
+
+impl FromStr for NameMap {
+	type Err = ObfuscateError;
+
+	/// Parse a map, ignoring empty lines.
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		let mut entries = Vec::new();
+		for (index, line) in s.lines().enumerate() {
+			if line.trim().is_empty() {
+				continue
+			}
+			let invalid = move || ObfuscateError::InvalidLine { line: index + 1 };
+			let name = move |name: &str| {
+				CString::new(name).ok().filter(move |name| !name.is_empty())
+			};
+			let fields: Vec<&str> = line.split('\t').collect();
+			let [kind, obfuscated, original] = fields[..] else {
+				return Err(invalid())
+			};
+			let kind = match kind {
+				"public" => NameKind::Public,
+				"pubvar" => NameKind::Pubvar,
+				_ => return Err(invalid()),
+			};
+			entries.push(MappedName {
+				kind,
+				obfuscated: name(obfuscated).ok_or_else(invalid)?,
+				original: name(original).ok_or_else(invalid)?,
+			});
+		}
+		Ok(Self { entries })
+	}
+}
+
+/// Structure for an error that has occurred while obfuscating or
+/// deobfuscating a plugin.
+#[derive(Debug)]
+pub enum ObfuscateError {
+	/// Sections could not be parsed.
+	Io(IoError),
+	/// A line of a name map is invalid.
+	InvalidLine {
+		line: usize,
+	},
+	/// The plugin has no entry with an obfuscated name of the map.
+	UnknownName {
+		kind: NameKind,
+		name: CString,
+	},
+}
+
+impl fmt::Display for ObfuscateError {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			Self::Io(e) => write!(f, "io error {e}"),
+			Self::InvalidLine { line } => write!(f, "invalid name map line {line}"),
+			Self::UnknownName { kind, name } =>
+				write!(f, "plugin has no {} {name:?}", kind.as_str()),
+		}
+	}
+}
+
+impl Error for ObfuscateError {}
+
+impl From<IoError> for ObfuscateError {
+	fn from(value: IoError) -> Self {
+		Self::Io(value)
+	}
+}
+
+/// Return `count` names that sort after `lo` and before `hi`, in order.
+fn names_between(
+	lo: Option<&CStr>,
+	hi: Option<&CStr>,
+	count: usize,
+) -> Option<Vec<CString>> {
+	let lo = lo.map(CStr::to_bytes).unwrap_or_default();
+	let mut separator = b'_';
+	if let Some(hi) = hi.map(CStr::to_bytes) {
+		match hi.strip_prefix(lo) {
+			// Names are `lo` followed by a suffix that must sort before the
+			// rest of `hi`, and digits sort before every other character of
+			// identifiers.
+			Some(rest) => {
+				separator = b'0';
+				if rest.first().is_none_or(move |c| *c <= separator) {
+					return None
+				}
+			}
+			None if hi <= lo => return None,
+			None => (),
+		}
+	}
+	let width = count.saturating_sub(1).to_string().len();
+	let names = (0..count)
+		.map(move |index| {
+			let mut name = lo.to_vec();
+			name.push(separator);
+			name.extend_from_slice(format!("{index:0width$}").as_bytes());
+			CString::new(name).unwrap_or_default()
+		})
+		.collect();
+	Some(names)
+}
+
+/// Return the obfuscated names of a table, keeping the names that `keep`
+/// returns `true` for.
+fn obfuscate_names(names: &[CString], keep: impl Fn(&CStr) -> bool) -> Vec<CString> {
+	let sorted = names.is_sorted();
+	let mut out: Vec<CString> = Vec::with_capacity(names.len());
+	let mut start = 0;
+	while start < names.len() {
+		if keep(&names[start]) {
+			out.push(names[start].clone());
+			start += 1;
+			continue
+		}
+		let end = names[start..].iter()
+			.position(|name| keep(name))
+			.map_or(names.len(), move |len| start + len);
+		let (lo, hi) = if sorted {
+			(out.last().map(CString::as_c_str), names.get(end).map(CString::as_c_str))
+		} else {
+			(None, None)
+		};
+		out.extend(
+			names_between(lo, hi, end - start)
+				.unwrap_or_else(|| names[start..end].to_vec())
+		);
+		start = end;
+	}
+	out
+}
+
+/// Obfuscate an SMX file in place, keeping the names in `keep`, and returning
+/// the map to restore the obfuscated names.
+pub fn obfuscate<E: ByteOrder>(
+	smx: &mut Smx<CString, Vec<u8>>,
+	keep: &[&CStr],
+) -> Result<NameMap, ObfuscateError> {
+	let removed = strip_debug(smx);
+	let mut plugin = Plugin::from_smx::<E, _, _>(smx)?;
+	if !removed.is_empty() {
+		plugin.code.flags &= !CodeSection::FLAG_DEBUG;
+	}
+	let is_kept = move |name: &CStr| {
+		name.to_bytes().starts_with(b"__")
+			|| REQUIRED.contains(&name)
+			|| keep.contains(&name)
+	};
+
+	let mut map = NameMap::default();
+	let publics: Vec<u32> = plugin.publics.iter().map(move |entry| entry.name).collect();
+	let pubvars: Vec<u32> = plugin.pubvars.iter().map(move |entry| entry.name).collect();
+	for (kind, offsets) in [(NameKind::Public, publics), (NameKind::Pubvar, pubvars)] {
+		let names: Vec<CString> = offsets.iter()
+			.map(|offset| plugin.name(*offset).unwrap_or_default())
+			.collect();
+		let obfuscated = obfuscate_names(&names, is_kept);
+		let renamed = names.into_iter().zip(obfuscated).enumerate();
+		for (index, (original, obfuscated)) in renamed {
+			if original == obfuscated {
+				continue
+			}
+			let name = plugin.names.insert(&obfuscated) as u32;
+			match kind {
+				NameKind::Public => plugin.publics[index].name = name,
+				NameKind::Pubvar => plugin.pubvars[index].name = name,
+			}
+			map.entries.push(MappedName { kind, obfuscated, original });
+		}
+	}
+
+	let mut rtti = Rtti::from_smx::<E, _, _>(smx)?;
+	for method in rtti.methods.iter_mut() {
+		let start = method.pcode_start;
+		let public = plugin.publics.iter().find(move |public| public.address == start);
+		method.name = match public {
+			Some(public) => public.name,
+			None => {
+				let name = CString::new(format!("sub_{start:08x}")).unwrap_or_default();
+				plugin.names.insert(&name) as u32
+			}
+		};
+	}
+	rtti.write_into::<E>(smx);
+
+	compact_names::<E>(smx, &mut plugin)?;
+	plugin.write_into::<E>(smx);
+	Ok(map)
+}
+
+/// Restore the names of an obfuscated SMX file in place.
+pub fn deobfuscate<E: ByteOrder>(
+	smx: &mut Smx<CString, Vec<u8>>,
+	map: &NameMap,
+) -> Result<(), ObfuscateError> {
+	let mut plugin = Plugin::from_smx::<E, _, _>(smx)?;
+	let mut rtti = Rtti::from_smx::<E, _, _>(smx)?;
+	for entry in map.entries.iter() {
+		let unknown = || ObfuscateError::UnknownName {
+			kind: entry.kind,
+			name: entry.obfuscated.clone(),
+		};
+		let name = plugin.names.insert(&entry.original) as u32;
+		match entry.kind {
+			NameKind::Public => {
+				let index = plugin.find_public(&entry.obfuscated).ok_or_else(unknown)?;
+				plugin.publics[index].name = name;
+				let address = plugin.publics[index].address;
+				rtti.methods.iter_mut()
+					.filter(move |method| method.pcode_start == address)
+					.for_each(move |method| method.name = name);
+			}
+			NameKind::Pubvar => {
+				let index = plugin.find_pubvar(&entry.obfuscated).ok_or_else(unknown)?;
+				plugin.pubvars[index].name = name;
+			}
+		}
+	}
+	rtti.write_into::<E>(smx);
+	compact_names::<E>(smx, &mut plugin)?;
+	plugin.write_into::<E>(smx);
+	Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::{
+		debug::{
+			DebugInfo,
+			DebugLine,
+			LINES,
+		},
+		rtti::RttiMethod,
+		sections::{
+			Public,
+			Pubvar,
+		},
+	};
+	use byteorder::LittleEndian as Le;
+
+	#[test]
+	fn round_trip() -> Result<(), Box<dyn Error>> {
+		let mut plugin = Plugin::default();
+		plugin.code.version = 13;
+		plugin.code.cell_size = 4;
+		plugin.code.flags = CodeSection::FLAG_DEBUG;
+		for name in [
+			c"Apple",
+			c"OnPluginStart",
+			c"OnPluginStartA",
+			c"OnPluginStartB",
+			c"Zeta",
+			c"__ext_core_SetNTVOptional",
+		] {
+			let name = plugin.names.insert(name) as u32;
+			plugin.publics.push(Public { address: 0, name });
+		}
+		for name in [c"g_Secret", c"myinfo"] {
+			let name = plugin.names.insert(name) as u32;
+			plugin.pubvars.push(Pubvar { address: 0, name });
+		}
+		let mut smx = Smx::new();
+		plugin.write_into::<Le>(&mut smx);
+		let mut debug = DebugInfo::default();
+		debug.lines.push(DebugLine { address: 0, line: 1 });
+		debug.write_into::<Le>(&mut smx);
+		let original = smx.clone();
+
+		let map = obfuscate::<Le>(&mut smx, &[c"OnPluginStartB"])?;
+		let obfuscated = Plugin::from_smx::<Le, _, _>(&smx)?;
+		let names: Vec<CString> = (0..obfuscated.publics.len())
+			.filter_map(|index| obfuscated.public_name(index))
+			.collect();
+		assert_eq!(names, [
+			c"00",
+			c"OnPluginStart",
+			c"OnPluginStart00",
+			c"OnPluginStartB",
+			c"OnPluginStartB_0",
+			c"__ext_core_SetNTVOptional",
+		]);
+		assert_eq!(obfuscated.pubvar_name(0).as_deref(), Some(c"00"));
+		assert_eq!(obfuscated.code.flags, 0);
+		assert!(smx.section(LINES).is_none());
+		assert!(!obfuscated.names.iter().any(move |(_, name)| name == b"Apple"));
+
+		let map: NameMap = map.to_string().parse()?;
+		assert_eq!(map.original(NameKind::Pubvar, c"00"), Some(c"g_Secret"));
+		deobfuscate::<Le>(&mut smx, &map)?;
+		let mut restored = Plugin::from_smx::<Le, _, _>(&original)?;
+		restored.code.flags = 0;
+		assert_eq!(Plugin::from_smx::<Le, _, _>(&smx)?, restored);
+
+		assert!(matches!(
+			"public\t00".parse::<NameMap>(),
+			Err(ObfuscateError::InvalidLine { line: 1 })
+		));
+		Ok(())
+	}
+
+	#[test]
+	fn keep_bound_pubvars() -> Result<(), Box<dyn Error>> {
+		let mut plugin = Plugin::default();
+		plugin.code.version = 13;
+		plugin.code.cell_size = 4;
+		for name in [
+			c"MaxClients",
+			c"NULL_STRING",
+			c"NULL_VECTOR",
+			c"g_Count",
+			c"myinfo",
+		] {
+			let name = plugin.names.insert(name) as u32;
+			plugin.pubvars.push(Pubvar { address: 0, name });
+		}
+		let mut smx = Smx::new();
+		plugin.write_into::<Le>(&mut smx);
+
+		let map = obfuscate::<Le>(&mut smx, &[])?;
+		let obfuscated = Plugin::from_smx::<Le, _, _>(&smx)?;
+		let names: Vec<CString> = (0..obfuscated.pubvars.len())
+			.filter_map(|index| obfuscated.pubvar_name(index))
+			.collect();
+		assert_eq!(names, [
+			c"MaxClients",
+			c"NULL_STRING",
+			c"NULL_VECTOR",
+			c"NULL_VECTOR_0",
+			c"myinfo",
+		]);
+		assert_eq!(map.entries.len(), 1);
+		Ok(())
+	}
+
+	#[test]
+	fn rename_methods() -> Result<(), Box<dyn Error>> {
+		let mut plugin = Plugin::default();
+		plugin.code.version = 13;
+		plugin.code.cell_size = 4;
+		let mut rtti = Rtti::default();
+		for (name, address) in [(c"OnPluginStart", 0x00), (c"Secret", 0x10)] {
+			let name = plugin.names.insert(name) as u32;
+			plugin.publics.push(Public { address, name });
+		}
+		for (name, pcode_start) in [
+			(c"OnPluginStart", 0x00),
+			(c"Secret", 0x10),
+			(c"Helper", 0x20),
+		] {
+			let name = plugin.names.insert(name) as u32;
+			rtti.methods.push(RttiMethod {
+				name,
+				pcode_start,
+				pcode_end: pcode_start + 0x10,
+				signature: 0,
+			});
+		}
+		let mut smx = Smx::new();
+		plugin.write_into::<Le>(&mut smx);
+		rtti.write_into::<Le>(&mut smx);
+
+		let method_names = move |smx: &Smx<CString, Vec<u8>>| {
+			let plugin = Plugin::from_smx::<Le, _, _>(smx)?;
+			let rtti = Rtti::from_smx::<Le, _, _>(smx)?;
+			Ok::<_, IoError>(rtti.methods.iter()
+				.map(move |method| plugin.name(method.name).unwrap_or_default())
+				.collect::<Vec<_>>())
+		};
+		let map = obfuscate::<Le>(&mut smx, &[])?;
+		assert_eq!(method_names(&smx)?, [
+			c"OnPluginStart".to_owned(),
+			c"OnPluginStart_0".to_owned(),
+			c"sub_00000020".to_owned(),
+		]);
+		let obfuscated = Plugin::from_smx::<Le, _, _>(&smx)?;
+		assert!(!obfuscated.names.iter().any(move |(_, name)| name == b"Helper"));
+
+		deobfuscate::<Le>(&mut smx, &map)?;
+		assert_eq!(method_names(&smx)?, [
+			c"OnPluginStart".to_owned(),
+			c"Secret".to_owned(),
+			c"sub_00000020".to_owned(),
+		]);
+		let restored = Plugin::from_smx::<Le, _, _>(&smx)?;
+		assert_eq!(restored.public_name(1).as_deref(), Some(c"Secret"));
+		Ok(())
+	}
+}
@@ -1,7 +1,15 @@
-use crate::vm_types::{
-	Cell,
-	read_cell,
-	write_cell
+use crate::{
+	case_table::{
+		self,
+		Case,
+	},
+	vm_types::{
+		Cell,
+		read_cell,
+		read_cells,
+		write_cell,
+		write_cells
+	}
 };
 
 use byteorder::{
@@ -17,7 +25,7 @@ use std::io::{
 /// Enumeration of every possible SourcePawn instruction.
 /// 
 /// This type is generated automatically by a script.
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 #[repr(C)]
 pub enum Instruction {
 	None,
@@ -33,6 +41,12 @@ pub enum Instruction {
 	LoadSAlt {
 		offset: Cell,
 	},
+	LrefPri {
+		offset: Cell,
+	},
+	LrefAlt {
+		offset: Cell,
+	},
 	LrefSPri {
 		offset: Cell,
 	},
@@ -67,6 +81,12 @@ pub enum Instruction {
 	StorSAlt {
 		offset: Cell,
 	},
+	SrefPri {
+		offset: Cell,
+	},
+	SrefAlt {
+		offset: Cell,
+	},
 	SrefSPri {
 		offset: Cell,
 	},
@@ -78,12 +98,33 @@ pub enum Instruction {
 		width: Cell,
 	},
 	Lidx,
+	LidxB {
+		shift: Cell,
+	},
 	Idxaddr,
+	IdxaddrB {
+		shift: Cell,
+	},
+	AlignPri {
+		const_1: Cell,
+	},
+	AlignAlt {
+		const_1: Cell,
+	},
+	Lctrl {
+		index: Cell,
+	},
+	Sctrl {
+		index: Cell,
+	},
 	MovePri,
 	MoveAlt,
 	Xchg,
 	PushPri,
 	PushAlt,
+	PushR {
+		const_1: Cell,
+	},
 	PushC {
 		const_1: Cell,
 	},
@@ -102,13 +143,18 @@ pub enum Instruction {
 		const_1: Cell,
 	},
 	Proc,
+	Ret,
 	Retn,
 	Call {
 		func_1: Cell,
 	},
+	CallPri,
 	Jump {
 		jump_1: Cell,
 	},
+	Jrel {
+		jump_1: Cell,
+	},
 	Jzer {
 		jump_1: Cell,
 	},
@@ -121,6 +167,18 @@ pub enum Instruction {
 	Jneq {
 		jump_1: Cell,
 	},
+	Jless {
+		jump_1: Cell,
+	},
+	Jleq {
+		jump_1: Cell,
+	},
+	Jgrtr {
+		jump_1: Cell,
+	},
+	Jgeq {
+		jump_1: Cell,
+	},
 	Jsless {
 		jump_1: Cell,
 	},
@@ -142,9 +200,18 @@ pub enum Instruction {
 	ShlCAlt {
 		const_1: Cell,
 	},
+	ShrCPri {
+		const_1: Cell,
+	},
+	ShrCAlt {
+		const_1: Cell,
+	},
 	Smul,
 	Sdiv,
 	SdivAlt,
+	Umul,
+	Udiv,
+	UdivAlt,
 	Add,
 	Sub,
 	SubAlt,
@@ -168,8 +235,14 @@ pub enum Instruction {
 	ZeroS {
 		stack_1: Cell,
 	},
+	SignPri,
+	SignAlt,
 	Eq,
 	Neq,
+	Less,
+	Leq,
+	Grtr,
+	Geq,
 	Sless,
 	Sleq,
 	Sgrtr,
@@ -201,6 +274,9 @@ pub enum Instruction {
 	Movs {
 		const_1: Cell,
 	},
+	Cmps {
+		const_1: Cell,
+	},
 	Fill {
 		const_1: Cell,
 	},
@@ -210,15 +286,34 @@ pub enum Instruction {
 	Bounds {
 		const_1: Cell,
 	},
+	SysreqPri,
 	SysreqC {
 		native_1: Cell,
 	},
+	File {
+		size: Cell,
+		data: Vec<Cell>,
+	},
+	Line {
+		line: Cell,
+		file: Cell,
+	},
+	Symbol {
+		size: Cell,
+		data: Vec<Cell>,
+	},
+	Srange {
+		level: Cell,
+		size: Cell,
+	},
+	JumpPri,
 	Switch {
 		jump_1: Cell,
 	},
 	Casetbl {
-		const_1: Cell,
-		jump_1: Cell,
+		default: Cell,
+		cases: Vec<Case>,
+		switch: Option<Cell>,
 	},
 	SwapPri,
 	SwapAlt,
@@ -230,6 +325,9 @@ pub enum Instruction {
 		native: Cell,
 		n_args: Cell,
 	},
+	Symtag {
+		const_1: Cell,
+	},
 	Break,
 	Push2C {
 		const_1: Cell,
@@ -335,6 +433,13 @@ pub enum Instruction {
 		stack_1: Cell,
 		const_1: Cell,
 	},
+	SysreqD {
+		addr_1: Cell,
+	},
+	SysreqNd {
+		addr_1: Cell,
+		n_args: Cell,
+	},
 	TrackerPushC {
 		const_1: Cell,
 	},
@@ -346,7 +451,16 @@ pub enum Instruction {
 		const_1: Cell,
 	},
 	StradjustPri,
+	Stkadjust,
 	Endproc,
+	LdgfnPri {
+		func_1: Cell,
+	},
+	Rebase {
+		addr_1: Cell,
+		const_1: Cell,
+		const_2: Cell,
+	},
 	InitarrayPri {
 		addr_1: Cell,
 		const_1: Cell,
@@ -383,8 +497,29 @@ pub enum Instruction {
 	FloatNot,
 }
 
+/// Set of opcodes accepted by [`Instruction::read_with`].
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum OpcodeSet {
+	/// Opcodes supported by the current SourcePawn VM.
+	#[default]
+	Current,
+	/// Every opcode number that has ever been assigned, including ones that
+	/// were removed from SourcePawn (see [`Instruction::is_legacy`]).
+	Legacy,
+}
+
 impl Instruction {
+	/// Read an instruction from a reader, accepting only opcodes supported by
+	/// the current SourcePawn VM.
 	pub fn read_from(r: &mut impl ReadBytesExt) -> IoResult<Self> {
+		Self::read_with(r, OpcodeSet::Current)
+	}
+
+	/// Read an instruction from a reader, accepting opcodes from the specified
+	/// [`OpcodeSet`].
+	pub fn read_with(
+		r: &mut impl ReadBytesExt, set: OpcodeSet,
+	) -> IoResult<Self> {
 		match read_cell(r)? {
 			0 => Ok(Self::None),
 			1 => {
@@ -411,6 +546,18 @@ impl Instruction {
 					offset,
 				})
 			}
+			5 if set == OpcodeSet::Legacy => {
+				let offset = read_cell(r)?;
+				Ok(Self::LrefPri {
+					offset,
+				})
+			}
+			6 if set == OpcodeSet::Legacy => {
+				let offset = read_cell(r)?;
+				Ok(Self::LrefAlt {
+					offset,
+				})
+			}
 			7 => {
 				let offset = read_cell(r)?;
 				Ok(Self::LrefSPri {
@@ -478,6 +625,18 @@ impl Instruction {
 					offset,
 				})
 			}
+			19 if set == OpcodeSet::Legacy => {
+				let offset = read_cell(r)?;
+				Ok(Self::SrefPri {
+					offset,
+				})
+			}
+			20 if set == OpcodeSet::Legacy => {
+				let offset = read_cell(r)?;
+				Ok(Self::SrefAlt {
+					offset,
+				})
+			}
 			21 => {
 				let offset = read_cell(r)?;
 				Ok(Self::SrefSPri {
@@ -498,12 +657,54 @@ impl Instruction {
 				})
 			}
 			25 => Ok(Self::Lidx),
+			26 if set == OpcodeSet::Legacy => {
+				let shift = read_cell(r)?;
+				Ok(Self::LidxB {
+					shift,
+				})
+			}
 			27 => Ok(Self::Idxaddr),
+			28 if set == OpcodeSet::Legacy => {
+				let shift = read_cell(r)?;
+				Ok(Self::IdxaddrB {
+					shift,
+				})
+			}
+			29 if set == OpcodeSet::Legacy => {
+				let const_1 = read_cell(r)?;
+				Ok(Self::AlignPri {
+					const_1,
+				})
+			}
+			30 if set == OpcodeSet::Legacy => {
+				let const_1 = read_cell(r)?;
+				Ok(Self::AlignAlt {
+					const_1,
+				})
+			}
+			31 if set == OpcodeSet::Legacy => {
+				let index = read_cell(r)?;
+				Ok(Self::Lctrl {
+					index,
+				})
+			}
+			32 if set == OpcodeSet::Legacy => {
+				let index = read_cell(r)?;
+				Ok(Self::Sctrl {
+					index,
+				})
+			}
 			33 => Ok(Self::MovePri),
 			34 => Ok(Self::MoveAlt),
 			35 => Ok(Self::Xchg),
 			36 => Ok(Self::PushPri),
 			37 => Ok(Self::PushAlt),
+			38 if set == OpcodeSet::Legacy => {
+				let const_1 = read_cell(r)?;
+				Ok(Self::PushR {
+					const_1,
+				})
+			}
 			39 => {
 				let const_1 = read_cell(r)?;
 				Ok(Self::PushC {
@@ -537,6 +738,7 @@ impl Instruction {
 				})
 			}
 			46 => Ok(Self::Proc),
+			47 if set == OpcodeSet::Legacy => Ok(Self::Ret),
 			48 => Ok(Self::Retn),
 			49 => {
 				let func_1 = read_cell(r)?;
@@ -544,12 +746,19 @@ impl Instruction {
 					func_1,
 				})
 			}
+			50 if set == OpcodeSet::Legacy => Ok(Self::CallPri),
 			51 => {
 				let jump_1 = read_cell(r)?;
 				Ok(Self::Jump {
 					jump_1,
 				})
 			}
+			52 if set == OpcodeSet::Legacy => {
+				let jump_1 = read_cell(r)?;
+				Ok(Self::Jrel {
+					jump_1,
+				})
+			}
 			53 => {
 				let jump_1 = read_cell(r)?;
 				Ok(Self::Jzer {
@@ -574,6 +783,30 @@ impl Instruction {
 					jump_1,
 				})
 			}
+			57 if set == OpcodeSet::Legacy => {
+				let jump_1 = read_cell(r)?;
+				Ok(Self::Jless {
+					jump_1,
+				})
+			}
+			58 if set == OpcodeSet::Legacy => {
+				let jump_1 = read_cell(r)?;
+				Ok(Self::Jleq {
+					jump_1,
+				})
+			}
+			59 if set == OpcodeSet::Legacy => {
+				let jump_1 = read_cell(r)?;
+				Ok(Self::Jgrtr {
+					jump_1,
+				})
+			}
+			60 if set == OpcodeSet::Legacy => {
+				let jump_1 = read_cell(r)?;
+				Ok(Self::Jgeq {
+					jump_1,
+				})
+			}
 			61 => {
 				let jump_1 = read_cell(r)?;
 				Ok(Self::Jsless {
@@ -613,9 +846,24 @@ impl Instruction {
 					const_1,
 				})
 			}
+			70 if set == OpcodeSet::Legacy => {
+				let const_1 = read_cell(r)?;
+				Ok(Self::ShrCPri {
+					const_1,
+				})
+			}
+			71 if set == OpcodeSet::Legacy => {
+				let const_1 = read_cell(r)?;
+				Ok(Self::ShrCAlt {
+					const_1,
+				})
+			}
 			72 => Ok(Self::Smul),
 			73 => Ok(Self::Sdiv),
 			74 => Ok(Self::SdivAlt),
+			75 if set == OpcodeSet::Legacy => Ok(Self::Umul),
+			76 if set == OpcodeSet::Legacy => Ok(Self::Udiv),
+			77 if set == OpcodeSet::Legacy => Ok(Self::UdivAlt),
 			78 => Ok(Self::Add),
 			79 => Ok(Self::Sub),
 			80 => Ok(Self::SubAlt),
@@ -651,8 +899,14 @@ impl Instruction {
 					stack_1,
 				})
 			}
+			93 if set == OpcodeSet::Legacy => Ok(Self::SignPri),
+			94 if set == OpcodeSet::Legacy => Ok(Self::SignAlt),
 			95 => Ok(Self::Eq),
 			96 => Ok(Self::Neq),
+			97 if set == OpcodeSet::Legacy => Ok(Self::Less),
+			98 if set == OpcodeSet::Legacy => Ok(Self::Leq),
+			99 if set == OpcodeSet::Legacy => Ok(Self::Grtr),
+			100 if set == OpcodeSet::Legacy => Ok(Self::Geq),
 			101 => Ok(Self::Sless),
 			102 => Ok(Self::Sleq),
 			103 => Ok(Self::Sgrtr),
@@ -705,6 +959,12 @@ impl Instruction {
 					const_1,
 				})
 			}
+			118 if set == OpcodeSet::Legacy => {
+				let const_1 = read_cell(r)?;
+				Ok(Self::Cmps {
+					const_1,
+				})
+			}
 			119 => {
 				let const_1 = read_cell(r)?;
 				Ok(Self::Fill {
@@ -723,12 +983,46 @@ impl Instruction {
 					const_1,
 				})
 			}
+			122 if set == OpcodeSet::Legacy => Ok(Self::SysreqPri),
 			123 => {
 				let native_1 = read_cell(r)?;
 				Ok(Self::SysreqC {
 					native_1,
 				})
 			}
+			124 if set == OpcodeSet::Legacy => {
+				let size = read_cell(r)?;
+				let data = read_cells(r, size)?;
+				Ok(Self::File {
+					size,
+					data,
+				})
+			}
+			125 if set == OpcodeSet::Legacy => {
+				let line = read_cell(r)?;
+				let file = read_cell(r)?;
+				Ok(Self::Line {
+					line,
+					file,
+				})
+			}
+			126 if set == OpcodeSet::Legacy => {
+				let size = read_cell(r)?;
+				let data = read_cells(r, size)?;
+				Ok(Self::Symbol {
+					size,
+					data,
+				})
+			}
+			127 if set == OpcodeSet::Legacy => {
+				let level = read_cell(r)?;
+				let size = read_cell(r)?;
+				Ok(Self::Srange {
+					level,
+					size,
+				})
+			}
+			128 if set == OpcodeSet::Legacy => Ok(Self::JumpPri),
 			129 => {
 				let jump_1 = read_cell(r)?;
 				Ok(Self::Switch {
@@ -736,11 +1030,11 @@ impl Instruction {
 				})
 			}
 			130 => {
-				let const_1 = read_cell(r)?;
-				let jump_1 = read_cell(r)?;
+				let (default, cases) = case_table::read_from(r)?;
 				Ok(Self::Casetbl {
-					const_1,
-					jump_1,
+					default,
+					cases,
+					switch: None,
 				})
 			}
 			131 => Ok(Self::SwapPri),
@@ -760,6 +1054,12 @@ impl Instruction {
 					n_args,
 				})
 			}
+			136 if set == OpcodeSet::Legacy => {
+				let const_1 = read_cell(r)?;
+				Ok(Self::Symtag {
+					const_1,
+				})
+			}
 			137 => Ok(Self::Break),
 			138 => {
 				let const_1 = read_cell(r)?;
@@ -969,6 +1269,20 @@ impl Instruction {
 					const_1,
 				})
 			}
+			158 if set == OpcodeSet::Legacy => {
+				let addr_1 = read_cell(r)?;
+				Ok(Self::SysreqD {
+					addr_1,
+				})
+			}
+			159 if set == OpcodeSet::Legacy => {
+				let addr_1 = read_cell(r)?;
+				let n_args = read_cell(r)?;
+				Ok(Self::SysreqNd {
+					addr_1,
+					n_args,
+				})
+			}
 			160 => {
 				let const_1 = read_cell(r)?;
 				Ok(Self::TrackerPushC {
@@ -989,7 +1303,24 @@ impl Instruction {
 				})
 			}
 			164 => Ok(Self::StradjustPri),
+			165 if set == OpcodeSet::Legacy => Ok(Self::Stkadjust),
 			166 => Ok(Self::Endproc),
+			167 if set == OpcodeSet::Legacy => {
+				let func_1 = read_cell(r)?;
+				Ok(Self::LdgfnPri {
+					func_1,
+				})
+			}
+			168 if set == OpcodeSet::Legacy => {
+				let addr_1 = read_cell(r)?;
+				let const_1 = read_cell(r)?;
+				let const_2 = read_cell(r)?;
+				Ok(Self::Rebase {
+					addr_1,
+					const_1,
+					const_2,
+				})
+			}
 			169 => {
 				let addr_1 = read_cell(r)?;
 				let const_1 = read_cell(r)?;
@@ -1067,6 +1398,16 @@ impl Instruction {
 				write_cell(w, *offset)?;
 				Ok(())
 			}
+			Self::LrefPri { offset, } => {
+				write_cell(w, 5)?;
+				write_cell(w, *offset)?;
+				Ok(())
+			}
+			Self::LrefAlt { offset, } => {
+				write_cell(w, 6)?;
+				write_cell(w, *offset)?;
+				Ok(())
+			}
 			Self::LrefSPri { offset, } => {
 				write_cell(w, 7)?;
 				write_cell(w, *offset)?;
@@ -1123,6 +1464,16 @@ impl Instruction {
 				write_cell(w, *offset)?;
 				Ok(())
 			}
+			Self::SrefPri { offset, } => {
+				write_cell(w, 19)?;
+				write_cell(w, *offset)?;
+				Ok(())
+			}
+			Self::SrefAlt { offset, } => {
+				write_cell(w, 20)?;
+				write_cell(w, *offset)?;
+				Ok(())
+			}
 			Self::SrefSPri { offset, } => {
 				write_cell(w, 21)?;
 				write_cell(w, *offset)?;
@@ -1140,12 +1491,47 @@ impl Instruction {
 				Ok(())
 			}
 			Self::Lidx => write_cell(w, 25),
+			Self::LidxB { shift, } => {
+				write_cell(w, 26)?;
+				write_cell(w, *shift)?;
+				Ok(())
+			}
 			Self::Idxaddr => write_cell(w, 27),
+			Self::IdxaddrB { shift, } => {
+				write_cell(w, 28)?;
+				write_cell(w, *shift)?;
+				Ok(())
+			}
+			Self::AlignPri { const_1, } => {
+				write_cell(w, 29)?;
+				write_cell(w, *const_1)?;
+				Ok(())
+			}
+			Self::AlignAlt { const_1, } => {
+				write_cell(w, 30)?;
+				write_cell(w, *const_1)?;
+				Ok(())
+			}
+			Self::Lctrl { index, } => {
+				write_cell(w, 31)?;
+				write_cell(w, *index)?;
+				Ok(())
+			}
+			Self::Sctrl { index, } => {
+				write_cell(w, 32)?;
+				write_cell(w, *index)?;
+				Ok(())
+			}
 			Self::MovePri => write_cell(w, 33),
 			Self::MoveAlt => write_cell(w, 34),
 			Self::Xchg => write_cell(w, 35),
 			Self::PushPri => write_cell(w, 36),
 			Self::PushAlt => write_cell(w, 37),
+			Self::PushR { const_1, } => {
+				write_cell(w, 38)?;
+				write_cell(w, *const_1)?;
+				Ok(())
+			}
 			Self::PushC { const_1, } => {
 				write_cell(w, 39)?;
 				write_cell(w, *const_1)?;
@@ -1174,17 +1560,24 @@ impl Instruction {
 				Ok(())
 			}
 			Self::Proc => write_cell(w, 46),
+			Self::Ret => write_cell(w, 47),
 			Self::Retn => write_cell(w, 48),
 			Self::Call { func_1, } => {
 				write_cell(w, 49)?;
 				write_cell(w, *func_1)?;
 				Ok(())
 			}
+			Self::CallPri => write_cell(w, 50),
 			Self::Jump { jump_1, } => {
 				write_cell(w, 51)?;
 				write_cell(w, *jump_1)?;
 				Ok(())
 			}
+			Self::Jrel { jump_1, } => {
+				write_cell(w, 52)?;
+				write_cell(w, *jump_1)?;
+				Ok(())
+			}
 			Self::Jzer { jump_1, } => {
 				write_cell(w, 53)?;
 				write_cell(w, *jump_1)?;
@@ -1205,6 +1598,26 @@ impl Instruction {
 				write_cell(w, *jump_1)?;
 				Ok(())
 			}
+			Self::Jless { jump_1, } => {
+				write_cell(w, 57)?;
+				write_cell(w, *jump_1)?;
+				Ok(())
+			}
+			Self::Jleq { jump_1, } => {
+				write_cell(w, 58)?;
+				write_cell(w, *jump_1)?;
+				Ok(())
+			}
+			Self::Jgrtr { jump_1, } => {
+				write_cell(w, 59)?;
+				write_cell(w, *jump_1)?;
+				Ok(())
+			}
+			Self::Jgeq { jump_1, } => {
+				write_cell(w, 60)?;
+				write_cell(w, *jump_1)?;
+				Ok(())
+			}
 			Self::Jsless { jump_1, } => {
 				write_cell(w, 61)?;
 				write_cell(w, *jump_1)?;
@@ -1238,9 +1651,22 @@ impl Instruction {
 				write_cell(w, *const_1)?;
 				Ok(())
 			}
+			Self::ShrCPri { const_1, } => {
+				write_cell(w, 70)?;
+				write_cell(w, *const_1)?;
+				Ok(())
+			}
+			Self::ShrCAlt { const_1, } => {
+				write_cell(w, 71)?;
+				write_cell(w, *const_1)?;
+				Ok(())
+			}
 			Self::Smul => write_cell(w, 72),
 			Self::Sdiv => write_cell(w, 73),
 			Self::SdivAlt => write_cell(w, 74),
+			Self::Umul => write_cell(w, 75),
+			Self::Udiv => write_cell(w, 76),
+			Self::UdivAlt => write_cell(w, 77),
 			Self::Add => write_cell(w, 78),
 			Self::Sub => write_cell(w, 79),
 			Self::SubAlt => write_cell(w, 80),
@@ -1272,8 +1698,14 @@ impl Instruction {
 				write_cell(w, *stack_1)?;
 				Ok(())
 			}
+			Self::SignPri => write_cell(w, 93),
+			Self::SignAlt => write_cell(w, 94),
 			Self::Eq => write_cell(w, 95),
 			Self::Neq => write_cell(w, 96),
+			Self::Less => write_cell(w, 97),
+			Self::Leq => write_cell(w, 98),
+			Self::Grtr => write_cell(w, 99),
+			Self::Geq => write_cell(w, 100),
 			Self::Sless => write_cell(w, 101),
 			Self::Sleq => write_cell(w, 102),
 			Self::Sgrtr => write_cell(w, 103),
@@ -1319,6 +1751,11 @@ impl Instruction {
 				write_cell(w, *const_1)?;
 				Ok(())
 			}
+			Self::Cmps { const_1, } => {
+				write_cell(w, 118)?;
+				write_cell(w, *const_1)?;
+				Ok(())
+			}
 			Self::Fill { const_1, } => {
 				write_cell(w, 119)?;
 				write_cell(w, *const_1)?;
@@ -1334,20 +1771,45 @@ impl Instruction {
 				write_cell(w, *const_1)?;
 				Ok(())
 			}
+			Self::SysreqPri => write_cell(w, 122),
 			Self::SysreqC { native_1, } => {
 				write_cell(w, 123)?;
 				write_cell(w, *native_1)?;
 				Ok(())
 			}
+			Self::File { size, data, } => {
+				write_cell(w, 124)?;
+				write_cell(w, *size)?;
+				write_cells(w, data)?;
+				Ok(())
+			}
+			Self::Line { line, file, } => {
+				write_cell(w, 125)?;
+				write_cell(w, *line)?;
+				write_cell(w, *file)?;
+				Ok(())
+			}
+			Self::Symbol { size, data, } => {
+				write_cell(w, 126)?;
+				write_cell(w, *size)?;
+				write_cells(w, data)?;
+				Ok(())
+			}
+			Self::Srange { level, size, } => {
+				write_cell(w, 127)?;
+				write_cell(w, *level)?;
+				write_cell(w, *size)?;
+				Ok(())
+			}
+			Self::JumpPri => write_cell(w, 128),
 			Self::Switch { jump_1, } => {
 				write_cell(w, 129)?;
 				write_cell(w, *jump_1)?;
 				Ok(())
 			}
-			Self::Casetbl { const_1, jump_1, } => {
+			Self::Casetbl { default, cases, .. } => {
 				write_cell(w, 130)?;
-				write_cell(w, *const_1)?;
-				write_cell(w, *jump_1)?;
+				case_table::write_to(w, *default, cases)?;
 				Ok(())
 			}
 			Self::SwapPri => write_cell(w, 131),
@@ -1364,6 +1826,11 @@ impl Instruction {
 				write_cell(w, *n_args)?;
 				Ok(())
 			}
+			Self::Symtag { const_1, } => {
+				write_cell(w, 136)?;
+				write_cell(w, *const_1)?;
+				Ok(())
+			}
 			Self::Break => write_cell(w, 137),
 			Self::Push2C { const_1, const_2, } => {
 				write_cell(w, 138)?;
@@ -1509,6 +1976,17 @@ impl Instruction {
 				write_cell(w, *const_1)?;
 				Ok(())
 			}
+			Self::SysreqD { addr_1, } => {
+				write_cell(w, 158)?;
+				write_cell(w, *addr_1)?;
+				Ok(())
+			}
+			Self::SysreqNd { addr_1, n_args, } => {
+				write_cell(w, 159)?;
+				write_cell(w, *addr_1)?;
+				write_cell(w, *n_args)?;
+				Ok(())
+			}
 			Self::TrackerPushC { const_1, } => {
 				write_cell(w, 160)?;
 				write_cell(w, *const_1)?;
@@ -1526,7 +2004,20 @@ impl Instruction {
 				Ok(())
 			}
 			Self::StradjustPri => write_cell(w, 164),
+			Self::Stkadjust => write_cell(w, 165),
 			Self::Endproc => write_cell(w, 166),
+			Self::LdgfnPri { func_1, } => {
+				write_cell(w, 167)?;
+				write_cell(w, *func_1)?;
+				Ok(())
+			}
+			Self::Rebase { addr_1, const_1, const_2, } => {
+				write_cell(w, 168)?;
+				write_cell(w, *addr_1)?;
+				write_cell(w, *const_1)?;
+				write_cell(w, *const_2)?;
+				Ok(())
+			}
 			Self::InitarrayPri { addr_1, const_1, const_2, const_3, const_4, } => {
 				write_cell(w, 169)?;
 				write_cell(w, *addr_1)?;
@@ -1567,4 +2058,251 @@ impl Instruction {
 			Self::FloatNot => write_cell(w, 191),
 		}
 	}
+
+	/// Return `true` if this instruction uses an opcode that was removed from
+	/// SourcePawn, which is only decoded with [`OpcodeSet::Legacy`].
+	pub const fn is_legacy(&self) -> bool {
+		matches!(
+			self,
+			Self::LrefPri { .. }
+				| Self::LrefAlt { .. }
+				| Self::SrefPri { .. }
+				| Self::SrefAlt { .. }
+				| Self::LidxB { .. }
+				| Self::IdxaddrB { .. }
+				| Self::AlignPri { .. }
+				| Self::AlignAlt { .. }
+				| Self::Lctrl { .. }
+				| Self::Sctrl { .. }
+				| Self::PushR { .. }
+				| Self::Ret
+				| Self::CallPri
+				| Self::Jrel { .. }
+				| Self::Jless { .. }
+				| Self::Jleq { .. }
+				| Self::Jgrtr { .. }
+				| Self::Jgeq { .. }
+				| Self::ShrCPri { .. }
+				| Self::ShrCAlt { .. }
+				| Self::Umul
+				| Self::Udiv
+				| Self::UdivAlt
+				| Self::SignPri
+				| Self::SignAlt
+				| Self::Less
+				| Self::Leq
+				| Self::Grtr
+				| Self::Geq
+				| Self::Cmps { .. }
+				| Self::SysreqPri
+				| Self::File { .. }
+				| Self::Line { .. }
+				| Self::Symbol { .. }
+				| Self::Srange { .. }
+				| Self::JumpPri
+				| Self::Symtag { .. }
+				| Self::SysreqD { .. }
+				| Self::SysreqNd { .. }
+				| Self::Stkadjust
+				| Self::LdgfnPri { .. }
+				| Self::Rebase { .. }
+		)
+	}
+
+	/// Return the mnemonic of this instruction's opcode.
+	pub const fn mnemonic(&self) -> &'static str {
+		match self {
+			Self::None => "none",
+			Self::LoadPri { .. } => "load.pri",
+			Self::LoadAlt { .. } => "load.alt",
+			Self::LoadSPri { .. } => "load.s.pri",
+			Self::LoadSAlt { .. } => "load.s.alt",
+			Self::LrefPri { .. } => "lref.pri",
+			Self::LrefAlt { .. } => "lref.alt",
+			Self::LrefSPri { .. } => "lref.s.pri",
+			Self::LrefSAlt { .. } => "lref.s.alt",
+			Self::LoadI => "load.i",
+			Self::LodbI { .. } => "lodb.i",
+			Self::ConstPri { .. } => "const.pri",
+			Self::ConstAlt { .. } => "const.alt",
+			Self::AddrPri { .. } => "addr.pri",
+			Self::AddrAlt { .. } => "addr.alt",
+			Self::StorPri { .. } => "stor.pri",
+			Self::StorAlt { .. } => "stor.alt",
+			Self::StorSPri { .. } => "stor.s.pri",
+			Self::StorSAlt { .. } => "stor.s.alt",
+			Self::SrefPri { .. } => "sref.pri",
+			Self::SrefAlt { .. } => "sref.alt",
+			Self::SrefSPri { .. } => "sref.s.pri",
+			Self::SrefSAlt { .. } => "sref.s.alt",
+			Self::StorI => "stor.i",
+			Self::StrbI { .. } => "strb.i",
+			Self::Lidx => "lidx",
+			Self::LidxB { .. } => "lidx.b",
+			Self::Idxaddr => "idxaddr",
+			Self::IdxaddrB { .. } => "idxaddr.b",
+			Self::AlignPri { .. } => "align.pri",
+			Self::AlignAlt { .. } => "align.alt",
+			Self::Lctrl { .. } => "lctrl",
+			Self::Sctrl { .. } => "sctrl",
+			Self::MovePri => "move.pri",
+			Self::MoveAlt => "move.alt",
+			Self::Xchg => "xchg",
+			Self::PushPri => "push.pri",
+			Self::PushAlt => "push.alt",
+			Self::PushR { .. } => "push.r",
+			Self::PushC { .. } => "push.c",
+			Self::Push { .. } => "push",
+			Self::PushS { .. } => "push.s",
+			Self::PopPri => "pop.pri",
+			Self::PopAlt => "pop.alt",
+			Self::Stack { .. } => "stack",
+			Self::Heap { .. } => "heap",
+			Self::Proc => "proc",
+			Self::Ret => "ret",
+			Self::Retn => "retn",
+			Self::Call { .. } => "call",
+			Self::CallPri => "call.pri",
+			Self::Jump { .. } => "jump",
+			Self::Jrel { .. } => "jrel",
+			Self::Jzer { .. } => "jzer",
+			Self::Jnz { .. } => "jnz",
+			Self::Jeq { .. } => "jeq",
+			Self::Jneq { .. } => "jneq",
+			Self::Jless { .. } => "jless",
+			Self::Jleq { .. } => "jleq",
+			Self::Jgrtr { .. } => "jgrtr",
+			Self::Jgeq { .. } => "jgeq",
+			Self::Jsless { .. } => "jsless",
+			Self::Jsleq { .. } => "jsleq",
+			Self::Jsgrtr { .. } => "jsgrtr",
+			Self::Jsgeq { .. } => "jsgeq",
+			Self::Shl => "shl",
+			Self::Shr => "shr",
+			Self::Sshr => "sshr",
+			Self::ShlCPri { .. } => "shl.c.pri",
+			Self::ShlCAlt { .. } => "shl.c.alt",
+			Self::ShrCPri { .. } => "shr.c.pri",
+			Self::ShrCAlt { .. } => "shr.c.alt",
+			Self::Smul => "smul",
+			Self::Sdiv => "sdiv",
+			Self::SdivAlt => "sdiv.alt",
+			Self::Umul => "umul",
+			Self::Udiv => "udiv",
+			Self::UdivAlt => "udiv.alt",
+			Self::Add => "add",
+			Self::Sub => "sub",
+			Self::SubAlt => "sub.alt",
+			Self::And => "and",
+			Self::Or => "or",
+			Self::Xor => "xor",
+			Self::Not => "not",
+			Self::Neg => "neg",
+			Self::Invert => "invert",
+			Self::AddC { .. } => "add.c",
+			Self::SmulC { .. } => "smul.c",
+			Self::ZeroPri => "zero.pri",
+			Self::ZeroAlt => "zero.alt",
+			Self::Zero { .. } => "zero",
+			Self::ZeroS { .. } => "zero.s",
+			Self::SignPri => "sign.pri",
+			Self::SignAlt => "sign.alt",
+			Self::Eq => "eq",
+			Self::Neq => "neq",
+			Self::Less => "less",
+			Self::Leq => "leq",
+			Self::Grtr => "grtr",
+			Self::Geq => "geq",
+			Self::Sless => "sless",
+			Self::Sleq => "sleq",
+			Self::Sgrtr => "sgrtr",
+			Self::Sgeq => "sgeq",
+			Self::EqCPri { .. } => "eq.c.pri",
+			Self::EqCAlt { .. } => "eq.c.alt",
+			Self::IncPri => "inc.pri",
+			Self::IncAlt => "inc.alt",
+			Self::Inc { .. } => "inc",
+			Self::IncS { .. } => "inc.s",
+			Self::IncI => "inc.i",
+			Self::DecPri => "dec.pri",
+			Self::DecAlt => "dec.alt",
+			Self::Dec { .. } => "dec",
+			Self::DecS { .. } => "dec.s",
+			Self::DecI => "dec.i",
+			Self::Movs { .. } => "movs",
+			Self::Cmps { .. } => "cmps",
+			Self::Fill { .. } => "fill",
+			Self::Halt { .. } => "halt",
+			Self::Bounds { .. } => "bounds",
+			Self::SysreqPri => "sysreq.pri",
+			Self::SysreqC { .. } => "sysreq.c",
+			Self::File { .. } => "file",
+			Self::Line { .. } => "line",
+			Self::Symbol { .. } => "symbol",
+			Self::Srange { .. } => "srange",
+			Self::JumpPri => "jump.pri",
+			Self::Switch { .. } => "switch",
+			Self::Casetbl { .. } => "casetbl",
+			Self::SwapPri => "swap.pri",
+			Self::SwapAlt => "swap.alt",
+			Self::PushAdr { .. } => "push.adr",
+			Self::Nop => "nop",
+			Self::SysreqN { .. } => "sysreq.n",
+			Self::Symtag { .. } => "symtag",
+			Self::Break => "break",
+			Self::Push2C { .. } => "push2.c",
+			Self::Push2 { .. } => "push2",
+			Self::Push2S { .. } => "push2.s",
+			Self::Push2Adr { .. } => "push2.adr",
+			Self::Push3C { .. } => "push3.c",
+			Self::Push3 { .. } => "push3",
+			Self::Push3S { .. } => "push3.s",
+			Self::Push3Adr { .. } => "push3.adr",
+			Self::Push4C { .. } => "push4.c",
+			Self::Push4 { .. } => "push4",
+			Self::Push4S { .. } => "push4.s",
+			Self::Push4Adr { .. } => "push4.adr",
+			Self::Push5C { .. } => "push5.c",
+			Self::Push5 { .. } => "push5",
+			Self::Push5S { .. } => "push5.s",
+			Self::Push5Adr { .. } => "push5.adr",
+			Self::LoadBoth { .. } => "load.both",
+			Self::LoadSBoth { .. } => "load.s.both",
+			Self::Const { .. } => "const",
+			Self::ConstS { .. } => "const.s",
+			Self::SysreqD { .. } => "sysreq.d",
+			Self::SysreqNd { .. } => "sysreq.nd",
+			Self::TrackerPushC { .. } => "tracker.push.c",
+			Self::TrackerPopSetheap => "tracker.pop.setheap",
+			Self::Genarray { .. } => "genarray",
+			Self::GenarrayZ { .. } => "genarray.z",
+			Self::StradjustPri => "stradjust.pri",
+			Self::Stkadjust => "stkadjust",
+			Self::Endproc => "endproc",
+			Self::LdgfnPri { .. } => "ldgfn.pri",
+			Self::Rebase { .. } => "rebase",
+			Self::InitarrayPri { .. } => "initarray.pri",
+			Self::InitarrayAlt { .. } => "initarray.alt",
+			Self::HeapSave => "heap.save",
+			Self::HeapRestore => "heap.restore",
+			Self::Fabs => "fabs",
+			Self::Float => "float",
+			Self::Floatadd => "float.add",
+			Self::Floatsub => "float.sub",
+			Self::Floatmul => "float.mul",
+			Self::Floatdiv => "float.div",
+			Self::RndToNearest => "round",
+			Self::RndToFloor => "floor",
+			Self::RndToCeil => "ceil",
+			Self::RndToZero => "rndtozero",
+			Self::Floatcmp => "float.cmp",
+			Self::FloatGt => "float.gt",
+			Self::FloatGe => "float.ge",
+			Self::FloatLt => "float.lt",
+			Self::FloatLe => "float.le",
+			Self::FloatNe => "float.ne",
+			Self::FloatEq => "float.eq",
+			Self::FloatNot => "float.not",
+		}
+	}
 }
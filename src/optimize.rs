@@ -0,0 +1,545 @@
+//! Peephole optimizer that fuses instruction sequences into the macro
+//! instructions of the SourcePawn VM.
+//!
+//! The following rewrites are performed:
+//! - runs of up to five `PUSH.C`, `PUSH`, `PUSH.S` or `PUSH.ADR` are fused into
+//!   `PUSHn.C`, `PUSHn`, `PUSHn.S` or `PUSHn.ADR`;
+//! - `LOAD.PRI` followed by `LOAD.ALT` (in any order) is fused into
+//!   `LOAD.BOTH`, and likewise for `LOAD.S.BOTH`;
+//! - `CONST.PRI` followed by `STOR.PRI` or `STOR.S.PRI` is fused into `CONST`
+//!   or `CONST.S` when the next instruction overwrites `PRI` without reading
+//!   it;
+//! - redundant `MOVE.PRI`, `MOVE.ALT` and `XCHG` pairs are removed.
+//!
+//! Sequences are never fused across an instruction that is the target of a
+//! code reference, and every code reference is relocated to the new layout.
+
+use crate::{
+	decoder::{
+		DecodeError,
+		Decoder,
+	},
+	vm_types::Cell,
+	Instruction,
+};
+
+use std::{
+	collections::{
+		BTreeMap,
+		HashSet,
+	},
+	error::Error,
+	fmt,
+};
+
+/// Result of an optimization pass.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Optimized {
+	/// Optimized instructions paired with their new code offsets.
+	pub instructions: Vec<(usize, Instruction)>,
+	/// Map of old instruction offsets to new ones.
+	///
+	/// This includes the offset one past the last instruction, so that it can
+	/// be used to relocate code ranges.
+	pub relocations: BTreeMap<usize, usize>,
+	/// Number of bytes that were removed from the code.
+	pub bytes_saved: usize,
+}
+
+impl Optimized {
+	/// Encode the optimized instructions into a code blob.
+	pub fn to_code(&self) -> Vec<u8> {
+		let mut code = Vec::new();
+		for (_, instruction) in self.instructions.iter() {
+			let _ = instruction.write_to(&mut code);
+		}
+		code
+	}
+
+	/// Relocate an old code offset to the new layout, if it is the offset of
+	/// an instruction or the end of the code.
+	pub fn relocate(&self, offset: usize) -> Option<usize> {
+		self.relocations.get(&offset).copied()
+	}
+}
+
+/// Structure for an error that has occurred while optimizing code.
+#[derive(Debug)]
+pub enum OptimizeError {
+	/// Code could not be decoded.
+	Decode(DecodeError),
+	/// An instruction refers to a code offset that is not the start of an
+	/// instruction.
+	Target {
+		offset: usize,
+		target: Cell,
+	},
+}
+
+impl fmt::Display for OptimizeError {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			Self::Decode(e) => write!(f, "decode error {e}"),
+			Self::Target { offset, target } => write!(
+				f,
+				"instruction at 0x{offset:08x} refers to invalid offset 0x{target:08x}"
+			),
+		}
+	}
+}
+
+impl Error for OptimizeError {}
+
+impl From<DecodeError> for OptimizeError {
+	fn from(value: DecodeError) -> Self {
+		Self::Decode(value)
+	}
+}
+
+/// Decode and optimize a code blob.
+///
+/// `entry_points` are offsets that are referred to from outside of the code,
+/// such as `.publics` entries; these are never fused with preceding
+/// instructions.
+pub fn optimize_code(
+	code: &[u8],
+	entry_points: impl IntoIterator<Item = usize>,
+) -> Result<Optimized, OptimizeError> {
+	let instructions = Decoder::new(code).collect::<Result<Vec<_>, _>>()?;
+	optimize(&instructions, code.len(), entry_points)
+}
+
+/// Optimize decoded instructions paired with their offsets, where `code_len`
+/// is the size of the code that they were decoded from.
+///
+/// See [`optimize_code`].
+pub fn optimize(
+	instructions: &[(usize, Instruction)],
+	code_len: usize,
+	entry_points: impl IntoIterator<Item = usize>,
+) -> Result<Optimized, OptimizeError> {
+	let mut labels: HashSet<usize> = entry_points.into_iter().collect();
+	for (_, instruction) in instructions.iter() {
+		labels.extend(instruction.code_refs().into_iter().map(move |t| t as usize));
+	}
+
+	let items = expand_pushes(instructions);
+	let (items, trailing) = remove_moves(items, &labels);
+	let items = fuse(items, &labels);
+
+	let mut relocations = BTreeMap::new();
+	let mut laid_out = Vec::with_capacity(items.len());
+	let mut offset = 0;
+	for item in items {
+		for origin in item.origins {
+			relocations.insert(origin, offset);
+		}
+		let len = item.instruction.encoded_len();
+		laid_out.push((offset, item.instruction));
+		offset += len;
+	}
+	for origin in trailing {
+		relocations.insert(origin, offset);
+	}
+	relocations.insert(code_len, offset);
+
+	for (offset, instruction) in laid_out.iter_mut() {
+		for target in instruction.code_refs_mut() {
+			*target = match relocations.get(&(*target as usize)) {
+				Some(new) => *new as Cell,
+				None => return Err(OptimizeError::Target {
+					offset: *offset,
+					target: *target,
+				}),
+			};
+		}
+		if let Instruction::Casetbl { switch: Some(switch), .. } = instruction {
+			if let Some(new) = relocations.get(&(*switch as usize)) {
+				*switch = *new as Cell;
+			}
+		}
+	}
+
+	Ok(Optimized {
+		instructions: laid_out,
+		relocations,
+		bytes_saved: code_len.saturating_sub(offset),
+	})
+}
+
+/// Instruction in the middle of optimization, with the old offsets that are
+/// relocated to it.
+#[derive(Debug)]
+struct Item {
+	origins: Vec<usize>,
+	instruction: Instruction,
+}
+
+impl Item {
+	fn is_label(&self, labels: &HashSet<usize>) -> bool {
+		self.origins.iter().any(move |origin| labels.contains(origin))
+	}
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum PushKind {
+	Const,
+	Addr,
+	Stack,
+	Adr,
+}
+
+/// Return the kind and operands of a single or fused push.
+pub(crate) fn push_operands(
+	instruction: &Instruction,
+) -> Option<(PushKind, Vec<Cell>)> {
+	use Instruction as I;
+	use PushKind as K;
+	Some(match *instruction {
+		I::PushC { const_1 } => (K::Const, vec![const_1]),
+		I::Push2C { const_1, const_2 } => (K::Const, vec![const_1, const_2]),
+		I::Push3C { const_1, const_2, const_3 } =>
+			(K::Const, vec![const_1, const_2, const_3]),
+		I::Push4C { const_1, const_2, const_3, const_4 } =>
+			(K::Const, vec![const_1, const_2, const_3, const_4]),
+		I::Push5C { const_1, const_2, const_3, const_4, const_5 } =>
+			(K::Const, vec![const_1, const_2, const_3, const_4, const_5]),
+		I::Push { addr_1 } => (K::Addr, vec![addr_1]),
+		I::Push2 { addr_1, addr_2 } => (K::Addr, vec![addr_1, addr_2]),
+		I::Push3 { addr_1, addr_2, addr_3 } =>
+			(K::Addr, vec![addr_1, addr_2, addr_3]),
+		I::Push4 { addr_1, addr_2, addr_3, addr_4 } =>
+			(K::Addr, vec![addr_1, addr_2, addr_3, addr_4]),
+		I::Push5 { addr_1, addr_2, addr_3, addr_4, addr_5 } =>
+			(K::Addr, vec![addr_1, addr_2, addr_3, addr_4, addr_5]),
+		I::PushS { stack_1 } => (K::Stack, vec![stack_1]),
+		I::Push2S { stack_1, stack_2 } => (K::Stack, vec![stack_1, stack_2]),
+		I::Push3S { stack_1, stack_2, stack_3 } =>
+			(K::Stack, vec![stack_1, stack_2, stack_3]),
+		I::Push4S { stack_1, stack_2, stack_3, stack_4 } =>
+			(K::Stack, vec![stack_1, stack_2, stack_3, stack_4]),
+		I::Push5S { stack_1, stack_2, stack_3, stack_4, stack_5 } =>
+			(K::Stack, vec![stack_1, stack_2, stack_3, stack_4, stack_5]),
+		I::PushAdr { stack_1 } => (K::Adr, vec![stack_1]),
+		I::Push2Adr { stack_1, stack_2 } => (K::Adr, vec![stack_1, stack_2]),
+		I::Push3Adr { stack_1, stack_2, stack_3 } =>
+			(K::Adr, vec![stack_1, stack_2, stack_3]),
+		I::Push4Adr { stack_1, stack_2, stack_3, stack_4 } =>
+			(K::Adr, vec![stack_1, stack_2, stack_3, stack_4]),
+		I::Push5Adr { stack_1, stack_2, stack_3, stack_4, stack_5 } =>
+			(K::Adr, vec![stack_1, stack_2, stack_3, stack_4, stack_5]),
+		_ => return None,
+	})
+}
+
+/// Create a push of the specified kind with 1 to 5 operands.
+fn make_push(kind: PushKind, ops: &[Cell]) -> Instruction {
+	use Instruction as I;
+	use PushKind as K;
+	match (kind, ops) {
+		(K::Const, &[const_1]) => I::PushC { const_1 },
+		(K::Const, &[const_1, const_2]) => I::Push2C { const_1, const_2 },
+		(K::Const, &[const_1, const_2, const_3]) =>
+			I::Push3C { const_1, const_2, const_3 },
+		(K::Const, &[const_1, const_2, const_3, const_4]) =>
+			I::Push4C { const_1, const_2, const_3, const_4 },
+		(K::Const, &[const_1, const_2, const_3, const_4, const_5]) =>
+			I::Push5C { const_1, const_2, const_3, const_4, const_5 },
+		(K::Addr, &[addr_1]) => I::Push { addr_1 },
+		(K::Addr, &[addr_1, addr_2]) => I::Push2 { addr_1, addr_2 },
+		(K::Addr, &[addr_1, addr_2, addr_3]) => I::Push3 { addr_1, addr_2, addr_3 },
+		(K::Addr, &[addr_1, addr_2, addr_3, addr_4]) =>
+			I::Push4 { addr_1, addr_2, addr_3, addr_4 },
+		(K::Addr, &[addr_1, addr_2, addr_3, addr_4, addr_5]) =>
+			I::Push5 { addr_1, addr_2, addr_3, addr_4, addr_5 },
+		(K::Stack, &[stack_1]) => I::PushS { stack_1 },
+		(K::Stack, &[stack_1, stack_2]) => I::Push2S { stack_1, stack_2 },
+		(K::Stack, &[stack_1, stack_2, stack_3]) =>
+			I::Push3S { stack_1, stack_2, stack_3 },
+		(K::Stack, &[stack_1, stack_2, stack_3, stack_4]) =>
+			I::Push4S { stack_1, stack_2, stack_3, stack_4 },
+		(K::Stack, &[stack_1, stack_2, stack_3, stack_4, stack_5]) =>
+			I::Push5S { stack_1, stack_2, stack_3, stack_4, stack_5 },
+		(K::Adr, &[stack_1]) => I::PushAdr { stack_1 },
+		(K::Adr, &[stack_1, stack_2]) => I::Push2Adr { stack_1, stack_2 },
+		(K::Adr, &[stack_1, stack_2, stack_3]) =>
+			I::Push3Adr { stack_1, stack_2, stack_3 },
+		(K::Adr, &[stack_1, stack_2, stack_3, stack_4]) =>
+			I::Push4Adr { stack_1, stack_2, stack_3, stack_4 },
+		(K::Adr, &[stack_1, stack_2, stack_3, stack_4, stack_5]) =>
+			I::Push5Adr { stack_1, stack_2, stack_3, stack_4, stack_5 },
+		_ => unreachable!("push must have 1 to 5 operands"),
+	}
+}
+
+/// Split every fused push into single pushes, so that they can be regrouped.
+fn expand_pushes(instructions: &[(usize, Instruction)]) -> Vec<Item> {
+	let mut items = Vec::with_capacity(instructions.len());
+	for (offset, instruction) in instructions.iter() {
+		match push_operands(instruction) {
+			Some((kind, ops)) if ops.len() > 1 => {
+				for (idx, op) in ops.iter().enumerate() {
+					items.push(Item {
+						origins: if idx == 0 { vec![*offset] } else { Vec::new() },
+						instruction: make_push(kind, &[*op]),
+					});
+				}
+			}
+			_ => items.push(Item {
+				origins: vec![*offset],
+				instruction: instruction.clone(),
+			}),
+		}
+	}
+	items
+}
+
+/// Remove redundant register moves, also returning the origins of removed
+/// instructions at the end of the code.
+fn remove_moves(
+	items: Vec<Item>, labels: &HashSet<usize>,
+) -> (Vec<Item>, Vec<usize>) {
+	use Instruction as I;
+
+	let mut out: Vec<Item> = Vec::with_capacity(items.len());
+	let mut pending_origins = Vec::new();
+	for mut item in items {
+		item.origins.append(&mut pending_origins);
+		if !item.is_label(labels) {
+			if let Some(prev) = out.last() {
+				match (&prev.instruction, &item.instruction) {
+					(I::Xchg, I::Xchg) => {
+						let prev = out.pop().unwrap();
+						pending_origins.extend(prev.origins);
+						pending_origins.extend(item.origins);
+						continue
+					}
+					(I::MovePri, I::MovePri | I::MoveAlt)
+						| (I::MoveAlt, I::MoveAlt | I::MovePri) => {
+						pending_origins.extend(item.origins);
+						continue
+					}
+					_ => {}
+				}
+			}
+		}
+		out.push(item);
+	}
+	(out, pending_origins)
+}
+
+/// Return `true` if an instruction overwrites `PRI` without reading it first.
+fn kills_pri(instruction: &Instruction) -> bool {
+	use Instruction as I;
+	matches!(
+		instruction,
+		I::ConstPri { .. }
+			| I::LoadPri { .. }
+			| I::LoadSPri { .. }
+			| I::LrefSPri { .. }
+			| I::AddrPri { .. }
+			| I::ZeroPri
+			| I::PopPri
+			| I::MovePri
+			| I::LoadBoth { .. }
+			| I::LoadSBoth { .. }
+	)
+}
+
+/// Fuse pushes, loads and constant stores.
+fn fuse(items: Vec<Item>, labels: &HashSet<usize>) -> Vec<Item> {
+	use Instruction as I;
+
+	let mut out = Vec::with_capacity(items.len());
+	let mut idx = 0;
+	while idx < items.len() {
+		let item = &items[idx];
+		let next = items.get(idx + 1)
+			.filter(move |next| !next.is_label(labels));
+
+		if let Some((kind, _)) = push_operands(&item.instruction) {
+			let mut ops = Vec::new();
+			let mut origins = Vec::new();
+			let mut end = idx;
+			while end < items.len() && ops.len() < 5 {
+				let candidate = &items[end];
+				if end != idx && candidate.is_label(labels) {
+					break
+				}
+				match push_operands(&candidate.instruction) {
+					Some((k, candidate_ops)) if k == kind => {
+						ops.extend(candidate_ops);
+						origins.extend(candidate.origins.iter().copied());
+					}
+					_ => break,
+				}
+				end += 1;
+			}
+			out.push(Item {
+				origins,
+				instruction: make_push(kind, &ops),
+			});
+			idx = end;
+			continue
+		}
+
+		let fused = match (&item.instruction, next.map(move |n| &n.instruction)) {
+			(I::LoadPri { offset: a }, Some(I::LoadAlt { offset: b }))
+				| (I::LoadAlt { offset: b }, Some(I::LoadPri { offset: a })) => {
+				Some(I::LoadBoth { addr_1: *a, addr_2: *b })
+			}
+			(I::LoadSPri { offset: a }, Some(I::LoadSAlt { offset: b }))
+				| (I::LoadSAlt { offset: b }, Some(I::LoadSPri { offset: a })) => {
+				Some(I::LoadSBoth { stack_1: *a, stack_2: *b })
+			}
+			(I::ConstPri { value }, Some(I::StorPri { offset }))
+				if items.get(idx + 2).is_some_and(move |i| kills_pri(&i.instruction)) => {
+				Some(I::Const { addr_1: *offset, const_1: *value })
+			}
+			(I::ConstPri { value }, Some(I::StorSPri { offset }))
+				if items.get(idx + 2).is_some_and(move |i| kills_pri(&i.instruction)) => {
+				Some(I::ConstS { stack_1: *offset, const_1: *value })
+			}
+			_ => None,
+		};
+
+		match fused {
+			Some(instruction) => {
+				let mut origins = item.origins.clone();
+				origins.extend(items[idx + 1].origins.iter().copied());
+				out.push(Item {
+					origins,
+					instruction,
+				});
+				idx += 2;
+			}
+			None => {
+				out.push(Item {
+					origins: item.origins.clone(),
+					instruction: item.instruction.clone(),
+				});
+				idx += 1;
+			}
+		}
+	}
+	out
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::instruction::layout;
+
+	#[test]
+	fn fuse_pushes() -> Result<(), OptimizeError> {
+		let (code, len) = layout(vec![
+			Instruction::Proc,
+			Instruction::PushC { const_1: 1 },
+			Instruction::PushC { const_1: 2 },
+			Instruction::Push2C { const_1: 3, const_2: 4 },
+			Instruction::PushC { const_1: 5 },
+			Instruction::PushC { const_1: 6 },
+			Instruction::PushS { stack_1: 12 },
+			Instruction::SysreqN { native: 0, n_args: 7 },
+			Instruction::Retn,
+		]);
+		let optimized = optimize(&code, len, [])?;
+		let instructions: Vec<_> = optimized.instructions.iter()
+			.map(move |(_, i)| i.clone())
+			.collect();
+		assert_eq!(instructions, [
+			Instruction::Proc,
+			Instruction::Push5C {
+				const_1: 1, const_2: 2, const_3: 3, const_4: 4, const_5: 5,
+			},
+			Instruction::PushC { const_1: 6 },
+			Instruction::PushS { stack_1: 12 },
+			Instruction::SysreqN { native: 0, n_args: 7 },
+			Instruction::Retn,
+		]);
+		assert_eq!(optimized.bytes_saved, len - optimized.to_code().len());
+		Ok(())
+	}
+
+	#[test]
+	fn relocate_jumps() -> Result<(), OptimizeError> {
+		let (code, len) = layout(vec![
+			Instruction::Proc,
+			Instruction::LoadPri { offset: 0 },
+			Instruction::LoadAlt { offset: 4 },
+			Instruction::Xchg,
+			Instruction::Xchg,
+			Instruction::Jump { jump_1: 44 },
+			Instruction::PushC { const_1: 1 },
+			Instruction::PushC { const_1: 2 },
+			Instruction::Retn,
+		]);
+		assert_eq!(code[6].0, 36);
+		assert_eq!(code[7].0, 44);
+
+		let optimized = optimize(&code, len, [])?;
+		let instructions: Vec<_> = optimized.instructions.iter()
+			.map(move |(_, i)| i.clone())
+			.collect();
+		assert_eq!(instructions, [
+			Instruction::Proc,
+			Instruction::LoadBoth { addr_1: 0, addr_2: 4 },
+			Instruction::Jump { jump_1: 32 },
+			Instruction::PushC { const_1: 1 },
+			Instruction::PushC { const_1: 2 },
+			Instruction::Retn,
+		]);
+		assert_eq!(optimized.relocate(36), Some(24));
+		assert_eq!(optimized.relocate(len), Some(optimized.to_code().len()));
+		Ok(())
+	}
+
+	#[test]
+	fn const_store_needs_dead_pri() -> Result<(), OptimizeError> {
+		let (code, len) = layout(vec![
+			Instruction::ConstPri { value: 7 },
+			Instruction::StorPri { offset: 8 },
+			Instruction::Retn,
+			Instruction::ConstPri { value: 7 },
+			Instruction::StorSPri { offset: -4 },
+			Instruction::ZeroPri,
+		]);
+		let optimized = optimize(&code, len, [])?;
+		let instructions: Vec<_> = optimized.instructions.iter()
+			.map(move |(_, i)| i.clone())
+			.collect();
+		assert_eq!(instructions, [
+			Instruction::ConstPri { value: 7 },
+			Instruction::StorPri { offset: 8 },
+			Instruction::Retn,
+			Instruction::ConstS { stack_1: -4, const_1: 7 },
+			Instruction::ZeroPri,
+		]);
+		Ok(())
+	}
+
+	#[test]
+	fn const_store_before_move_alt() -> Result<(), OptimizeError> {
+		let mut code = Vec::new();
+		for instruction in [
+			Instruction::ConstPri { value: 5 },
+			Instruction::StorPri { offset: 0 },
+			Instruction::MoveAlt,
+			Instruction::Retn,
+		] {
+			instruction.write_to(&mut code).unwrap();
+		}
+		let optimized = optimize_code(&code, [])?;
+		let instructions: Vec<_> = optimized.instructions.iter()
+			.map(move |(_, i)| i.clone())
+			.collect();
+		assert_eq!(instructions, [
+			Instruction::ConstPri { value: 5 },
+			Instruction::StorPri { offset: 0 },
+			Instruction::MoveAlt,
+			Instruction::Retn,
+		]);
+		assert_eq!(optimized.bytes_saved, 0);
+		Ok(())
+	}
+}
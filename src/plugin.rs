@@ -0,0 +1,171 @@
+//! See [`Plugin`].
+
+use crate::{
+	sections::{
+		self,
+		read_table,
+		write_table,
+		CodeSection,
+		DataSection,
+		Native,
+		Public,
+		Pubvar,
+	},
+	smx_table::CStrTable,
+	Smx,
+};
+
+use byteorder::ByteOrder;
+use core::ffi::CStr;
+use std::{
+	borrow::Borrow,
+	ffi::CString,
+	hash::Hash,
+	io::Result as IoResult,
+};
+
+/// Parsed view of the standard sections of an SMX file.
+///
+/// Sections that are missing from the file are treated as empty.
+#[derive(Default, Debug, Clone, PartialEq, Eq)]
+pub struct Plugin {
+	/// `.code` section.
+	pub code: CodeSection,
+	/// `.data` section.
+	pub data: DataSection,
+	/// `.names` section.
+	pub names: CStrTable,
+	/// `.publics` section.
+	pub publics: Vec<Public>,
+	/// `.pubvars` section.
+	pub pubvars: Vec<Pubvar>,
+	/// `.natives` section.
+	pub natives: Vec<Native>,
+}
+
+impl Plugin {
+	/// Parse the standard sections of an SMX file.
+	pub fn from_smx<E, Name, Sect>(smx: &Smx<Name, Sect>) -> IoResult<Self>
+	where
+		E: ByteOrder,
+		Name: Borrow<CStr> + Eq + Hash,
+		Sect: AsRef<[u8]>,
+	{
+		let section = move |name: &CStr| smx.section(name).map(AsRef::as_ref);
+		Ok(Self {
+			code: section(sections::CODE)
+				.map(CodeSection::from_bytes::<E>)
+				.transpose()?
+				.unwrap_or_default(),
+			data: section(sections::DATA)
+				.map(DataSection::from_bytes::<E>)
+				.transpose()?
+				.unwrap_or_default(),
+			names: section(sections::NAMES)
+				.map(move |blob| CStrTable::from_blob(blob.to_vec()))
+				.unwrap_or_default(),
+			publics: section(sections::PUBLICS)
+				.map(read_table::<E, _>)
+				.transpose()?
+				.unwrap_or_default(),
+			pubvars: section(sections::PUBVARS)
+				.map(read_table::<E, _>)
+				.transpose()?
+				.unwrap_or_default(),
+			natives: section(sections::NATIVES)
+				.map(read_table::<E, _>)
+				.transpose()?
+				.unwrap_or_default(),
+		})
+	}
+
+	/// Write the standard sections back into an SMX file.
+	///
+	/// Table sections are only written if they are non-empty, or if they
+	/// already exist in `smx`.
+	pub fn write_into<E: ByteOrder>(&self, smx: &mut Smx<CString, Vec<u8>>) {
+		let mut put = move |name: &CStr, data: Vec<u8>, always: bool| {
+			if always || !data.is_empty() || smx.section(name).is_some() {
+				smx.sections.insert(name.to_owned(), data);
+			}
+		};
+		put(sections::CODE, self.code.to_vec::<E>(), true);
+		put(sections::DATA, self.data.to_vec::<E>(), true);
+		put(sections::NAMES, self.names.blob().clone(), false);
+		put(sections::PUBLICS, write_table::<E, _>(&self.publics), false);
+		put(sections::PUBVARS, write_table::<E, _>(&self.pubvars), false);
+		put(sections::NATIVES, write_table::<E, _>(&self.natives), false);
+	}
+
+	/// Return the name at an offset into `.names`.
+	pub fn name(&self, offset: u32) -> Option<CString> {
+		self.names.get_c_string(offset as _)
+	}
+
+	/// Return the name of the public function with the specified index.
+	pub fn public_name(&self, index: usize) -> Option<CString> {
+		self.publics.get(index).and_then(move |public| self.name(public.name))
+	}
+
+	/// Return the name of the public variable with the specified index.
+	pub fn pubvar_name(&self, index: usize) -> Option<CString> {
+		self.pubvars.get(index).and_then(move |pubvar| self.name(pubvar.name))
+	}
+
+	/// Return the name of the native with the specified index.
+	pub fn native_name(&self, index: usize) -> Option<CString> {
+		self.natives.get(index).and_then(move |native| self.name(native.name))
+	}
+
+	/// Find a public function by name, returning its index.
+	pub fn find_public(&self, name: &CStr) -> Option<usize> {
+		(0..self.publics.len())
+			.find(move |index| self.public_name(*index).as_deref() == Some(name))
+	}
+
+	/// Find a public variable by name, returning its index.
+	pub fn find_pubvar(&self, name: &CStr) -> Option<usize> {
+		(0..self.pubvars.len())
+			.find(move |index| self.pubvar_name(*index).as_deref() == Some(name))
+	}
+
+	/// Find a native by name, returning its index.
+	pub fn find_native(&self, name: &CStr) -> Option<usize> {
+		(0..self.natives.len())
+			.find(move |index| self.native_name(*index).as_deref() == Some(name))
+	}
+
+	/// Find a native by name, adding it to `.natives` if it is missing, and
+	/// returning its index.
+	pub fn add_native(&mut self, name: &CStr) -> usize {
+		self.find_native(name).unwrap_or_else(move || {
+			let name = self.names.insert(name) as u32;
+			self.natives.push(sections::Native { name });
+			self.natives.len() - 1
+		})
+	}
+}
+
+#[test]
+fn round_trip() -> IoResult<()> {
+	use byteorder::LittleEndian as Le;
+
+	let mut plugin = Plugin::default();
+	plugin.code.version = 13;
+	plugin.code.cell_size = 4;
+	plugin.code.code = vec![0; 8];
+	let name = plugin.names.insert(c"OnPluginStart") as u32;
+	plugin.publics.push(Public { address: 0, name });
+	let name = plugin.names.insert(c"LogMessage") as u32;
+	plugin.natives.push(Native { name });
+
+	let mut smx = Smx::new();
+	plugin.write_into::<Le>(&mut smx);
+	assert!(smx.section(sections::PUBVARS).is_none());
+	assert_eq!(Plugin::from_smx::<Le, _, _>(&smx)?, plugin);
+
+	assert_eq!(plugin.find_public(c"OnPluginStart"), Some(0));
+	assert_eq!(plugin.find_native(c"LogMessage"), Some(0));
+	assert_eq!(plugin.find_native(c"PrintToServer"), None);
+	Ok(())
+}
@@ -0,0 +1,342 @@
+//! Redirection of native calls to another native.
+//!
+//! Every `SYSREQ.N` and `SYSREQ.C` of the old native is retargeted to the new
+//! one, which is added to `.natives` if needed. Extra arguments are pushed
+//! right before each `SYSREQ.N`, so they become the leading arguments of the
+//! call. Natives that are not called anymore are removed afterwards, along
+//! with their `.rtti.natives` and `.dbg.natives` entries.
+
+use crate::{
+	code_edit::{
+		commit_smx,
+		CodeEditError,
+		CodeEditor,
+	},
+	debug::DebugInfo,
+	minify::{
+		remove_unused_natives,
+		MinifyError,
+	},
+	plugin::Plugin,
+	rtti::Rtti,
+	vm_types::Cell,
+	Instruction,
+	Smx,
+};
+
+use byteorder::ByteOrder;
+use core::ffi::CStr;
+use std::{
+	error::Error,
+	ffi::CString,
+	fmt,
+	io::Error as IoError,
+};
+
+/// Extra argument of a redirected call.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum ExtraArg {
+	/// Constant cell.
+	Const(Cell),
+	/// String, which is appended to the data image.
+	String(CString),
+}
+
+/// Redirection of calls from one native to another.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Redirect {
+	/// Name of the native whose calls are redirected.
+	pub from: CString,
+	/// Name of the native that calls are redirected to.
+	pub to: CString,
+	/// Arguments that are passed before the original arguments.
+	pub extra_args: Vec<ExtraArg>,
+}
+
+impl Redirect {
+	/// Create a redirection without extra arguments.
+	pub fn new(from: impl Into<CString>, to: impl Into<CString>) -> Self {
+		Self {
+			from: from.into(),
+			to: to.into(),
+			extra_args: Vec::new(),
+		}
+	}
+}
+
+/// Summary of a redirection.
+#[derive(Default, Debug, Clone, PartialEq, Eq)]
+pub struct RedirectReport {
+	/// Original code offsets of the redirected calls.
+	pub calls: Vec<usize>,
+	/// `true` if the new native was added to `.natives`.
+	pub added: bool,
+	/// Names of the natives that were removed.
+	pub removed_natives: Vec<CString>,
+}
+
+/// Structure for an error that has occurred while redirecting natives.
+#[derive(Debug)]
+pub enum RedirectError {
+	/// Sections could not be parsed.
+	Io(IoError),
+	/// Code could not be edited.
+	Edit(CodeEditError),
+	/// Unused natives could not be removed.
+	Minify(MinifyError),
+	/// The plugin does not use a native.
+	UnknownNative(CString),
+	/// Extra arguments cannot be added to a `SYSREQ.C` call, whose argument
+	/// count is pushed separately.
+	SysreqC {
+		offset: usize,
+	},
+}
+
+impl fmt::Display for RedirectError {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			Self::Io(e) => write!(f, "io error {e}"),
+			Self::Edit(e) => write!(f, "{e}"),
+			Self::Minify(e) => write!(f, "{e}"),
+			Self::UnknownNative(name) => write!(f, "plugin does not use native {name:?}"),
+			Self::SysreqC { offset } =>
+				write!(f, "cannot add arguments to `sysreq.c` at 0x{offset:08x}"),
+		}
+	}
+}
+
+impl Error for RedirectError {}
+
+impl From<IoError> for RedirectError {
+	fn from(value: IoError) -> Self {
+		Self::Io(value)
+	}
+}
+
+impl From<CodeEditError> for RedirectError {
+	fn from(value: CodeEditError) -> Self {
+		Self::Edit(value)
+	}
+}
+
+impl From<MinifyError> for RedirectError {
+	fn from(value: MinifyError) -> Self {
+		Self::Minify(value)
+	}
+}
+
+/// Rename a native in place, keeping its index.
+///
+/// If the plugin already uses a native with the new name, use
+/// [`redirect_native`] instead, which merges the two.
+pub fn rename_native(
+	plugin: &mut Plugin,
+	from: &CStr,
+	to: &CStr,
+) -> Result<(), RedirectError> {
+	let index = plugin.find_native(from)
+		.ok_or_else(move || RedirectError::UnknownNative(from.to_owned()))?;
+	plugin.natives[index].name = plugin.names.insert(to) as u32;
+	Ok(())
+}
+
+/// Redirect every call to a native in an SMX file.
+pub fn redirect_native<E: ByteOrder>(
+	smx: &mut Smx<CString, Vec<u8>>,
+	redirect: &Redirect,
+) -> Result<RedirectReport, RedirectError> {
+	let mut report = RedirectReport::default();
+	let mut plugin = Plugin::from_smx::<E, _, _>(smx)?;
+	let from = plugin.find_native(&redirect.from)
+		.ok_or_else(|| RedirectError::UnknownNative(redirect.from.clone()))? as Cell;
+	let natives = plugin.natives.len();
+	let to = plugin.add_native(&redirect.to) as Cell;
+	report.added = plugin.natives.len() > natives;
+
+	// Arguments are pushed in reverse, so the first extra argument goes last.
+	let pushes: Vec<Instruction> = redirect.extra_args.iter()
+		.rev()
+		.map(|arg| Instruction::PushC {
+			const_1: match arg {
+				ExtraArg::Const(value) => *value,
+				ExtraArg::String(value) => plugin.data.push_string(value),
+			},
+		})
+		.collect();
+
+	let mut editor = CodeEditor::new(&plugin.code.code).map_err(CodeEditError::from)?;
+	let calls: Vec<(usize, Instruction)> = editor.instructions()
+		.filter_map(move |(offset, instruction)| Some((offset?, instruction.clone())))
+		.collect();
+	for (offset, instruction) in calls {
+		let replacement = match instruction {
+			Instruction::SysreqN { native, n_args } if native == from => {
+				let n_args = n_args + pushes.len() as Cell;
+				let mut replacement = pushes.clone();
+				replacement.push(Instruction::SysreqN { native: to, n_args });
+				replacement
+			}
+			Instruction::SysreqC { native_1 } if native_1 == from => {
+				if !pushes.is_empty() {
+					return Err(RedirectError::SysreqC { offset })
+				}
+				vec![Instruction::SysreqC { native_1: to }]
+			}
+			_ => continue,
+		};
+		editor.replace(offset, replacement)?;
+		report.calls.push(offset);
+	}
+
+	// Edit a copy, so that an error leaves `smx` untouched.
+	let mut edited = smx.clone();
+	plugin.write_into::<E>(&mut edited);
+	commit_smx::<E>(&mut edited, &editor)?;
+
+	let mut plugin = Plugin::from_smx::<E, _, _>(&edited)?;
+	let mut rtti = Rtti::from_smx::<E, _, _>(&edited)?;
+	let mut debug = DebugInfo::from_smx::<E, _, _>(&edited)?;
+	if report.added && !rtti.natives.is_empty() {
+		rtti.add_untyped_native(plugin.natives[to as usize].name);
+	}
+
+	let renumber;
+	(report.removed_natives, renumber) = remove_unused_natives(&mut plugin)?;
+	rtti.natives = rtti.natives.into_iter()
+		.enumerate()
+		.filter(|(index, _)| renumber.contains_key(&(*index as Cell)))
+		.map(move |(_, native)| native)
+		.collect();
+	debug.natives.retain_mut(move |native| match renumber.get(&(native.index as Cell)) {
+		Some(new) => {
+			native.index = *new as _;
+			true
+		}
+		None => false,
+	});
+
+	plugin.write_into::<E>(&mut edited);
+	rtti.write_into::<E>(&mut edited);
+	debug.write_into::<E>(&mut edited);
+	*smx = edited;
+	Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::{
+		debug::DebugLine,
+		decoder::Decoder,
+		sections::{
+			Native,
+			Public,
+		},
+	};
+	use byteorder::LittleEndian as Le;
+
+	#[test]
+	fn log_to_file() -> Result<(), Box<dyn Error>> {
+		let mut plugin = Plugin::default();
+		plugin.code.version = 13;
+		plugin.code.cell_size = 4;
+		for instruction in [
+			Instruction::Proc,
+			Instruction::PushC { const_1: 0 },
+			Instruction::SysreqN { native: 1, n_args: 1 },
+			Instruction::SysreqN { native: 0, n_args: 0 },
+			Instruction::Retn,
+			Instruction::Endproc,
+		] {
+			let _ = instruction.write_to(&mut plugin.code.code);
+		}
+		plugin.data.push_string(c"hello");
+		let name = plugin.names.insert(c"OnPluginStart") as u32;
+		plugin.publics.push(Public { address: 0, name });
+		for native in [c"GetEngineTime", c"LogMessage"] {
+			let name = plugin.names.insert(native) as u32;
+			plugin.natives.push(Native { name });
+		}
+		let mut debug = DebugInfo::default();
+		debug.lines.push(DebugLine { address: 24, line: 5 });
+		let mut smx = Smx::new();
+		plugin.write_into::<Le>(&mut smx);
+		debug.write_into::<Le>(&mut smx);
+
+		let redirect = Redirect {
+			extra_args: vec![ExtraArg::String(c"addons/log.txt".into())],
+			..Redirect::new(c"LogMessage", c"LogToFileEx")
+		};
+		let report = redirect_native::<Le>(&mut smx, &redirect)?;
+		assert_eq!(report.calls, [12]);
+		assert!(report.added);
+		assert_eq!(report.removed_natives, [c"LogMessage".to_owned()]);
+
+		let plugin = Plugin::from_smx::<Le, _, _>(&smx)?;
+		assert_eq!(plugin.native_name(1).as_deref(), Some(c"LogToFileEx"));
+		assert_eq!(plugin.data.string_at(8).as_deref(), Some("addons/log.txt"));
+		let code = Decoder::new(&plugin.code.code)
+			.map(move |decoded| decoded.map(move |(_, instruction)| instruction))
+			.collect::<Result<Vec<_>, _>>()?;
+		assert_eq!(code, [
+			Instruction::Proc,
+			Instruction::PushC { const_1: 0 },
+			Instruction::PushC { const_1: 8 },
+			Instruction::SysreqN { native: 1, n_args: 2 },
+			Instruction::SysreqN { native: 0, n_args: 0 },
+			Instruction::Retn,
+			Instruction::Endproc,
+		]);
+		assert_eq!(DebugInfo::from_smx::<Le, _, _>(&smx)?.lines[0].address, 32);
+		Ok(())
+	}
+
+	#[test]
+	fn failed_edit() {
+		let mut plugin = Plugin::default();
+		plugin.code.version = 13;
+		plugin.code.cell_size = 4;
+		for instruction in [
+			Instruction::Proc,
+			Instruction::Jump { jump_1: 2 },
+			Instruction::SysreqN { native: 0, n_args: 0 },
+			Instruction::Retn,
+			Instruction::Endproc,
+		] {
+			let _ = instruction.write_to(&mut plugin.code.code);
+		}
+		let name = plugin.names.insert(c"LogMessage") as u32;
+		plugin.natives.push(Native { name });
+		let mut smx = Smx::new();
+		plugin.write_into::<Le>(&mut smx);
+		let original = smx.clone();
+
+		let redirect = Redirect {
+			extra_args: vec![ExtraArg::String(c"addons/log.txt".into())],
+			..Redirect::new(c"LogMessage", c"LogToFileEx")
+		};
+		assert!(matches!(
+			redirect_native::<Le>(&mut smx, &redirect),
+			Err(RedirectError::Edit(CodeEditError::Target { target: 2, .. }))
+		));
+		assert_eq!(smx, original);
+	}
+
+	#[test]
+	fn rename() -> Result<(), RedirectError> {
+		let mut plugin = Plugin::default();
+		let name = plugin.names.insert(c"GetClientAuthString") as u32;
+		plugin.natives.push(Native { name });
+		rename_native(&mut plugin, c"GetClientAuthString", c"GetClientAuthId")?;
+		assert_eq!(plugin.native_name(0).as_deref(), Some(c"GetClientAuthId"));
+
+		let mut smx = Smx::new();
+		plugin.write_into::<Le>(&mut smx);
+		assert!(matches!(
+			redirect_native::<Le>(&mut smx, &Redirect::new(c"A", c"B")),
+			Err(RedirectError::UnknownNative(_))
+		));
+		Ok(())
+	}
+}
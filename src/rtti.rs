@@ -0,0 +1,827 @@
+//! Models of the RTTI sections emitted by newer compilers, along with the
+//! debug sections that are built on them.
+//!
+//! Every section except `.rtti.data` is a table with a header that holds the
+//! header size, the row size and the row count. Names are offsets into
+//! `.names`, and types are type ids, which either hold up to 28 bits of type
+//! data inline, or point into `.rtti.data`.
+
+use crate::{
+	sections::TableEntry,
+	size_of,
+	smx_table::CStrTable,
+	Smx,
+};
+
+use byteorder::{
+	ByteOrder,
+	ReadBytesExt,
+	WriteBytesExt,
+};
+use core::ffi::CStr;
+use std::{
+	borrow::Borrow,
+	error::Error,
+	ffi::CString,
+	fmt,
+	hash::Hash,
+	io::{
+		Cursor,
+		Error as IoError,
+		ErrorKind as IoErrorKind,
+		Result as IoResult,
+	},
+};
+
+/// Name of the section with encoded type data.
+pub const DATA: &CStr = c".rtti.data";
+/// Name of the section with methods.
+pub const METHODS: &CStr = c".rtti.methods";
+/// Name of the section with native signatures.
+pub const NATIVES: &CStr = c".rtti.natives";
+/// Name of the section with enums.
+pub const ENUMS: &CStr = c".rtti.enums";
+/// Name of the section with typedefs.
+pub const TYPEDEFS: &CStr = c".rtti.typedefs";
+/// Name of the section with typesets.
+pub const TYPESETS: &CStr = c".rtti.typesets";
+/// Name of the section with class definitions, such as methodmaps.
+pub const CLASSDEFS: &CStr = c".rtti.classdefs";
+/// Name of the section with fields of class definitions.
+pub const FIELDS: &CStr = c".rtti.fields";
+/// Name of the section with enum structs.
+pub const ENUMSTRUCTS: &CStr = c".rtti.enumstructs";
+/// Name of the section with fields of enum structs.
+pub const ENUMSTRUCT_FIELDS: &CStr = c".rtti.es_fields";
+/// Name of the section with debug information of methods.
+pub const DBG_METHODS: &CStr = c".dbg.methods";
+/// Name of the section with global variables.
+pub const DBG_GLOBALS: &CStr = c".dbg.globals";
+/// Name of the section with local variables.
+pub const DBG_LOCALS: &CStr = c".dbg.locals";
+
+/// Size of the header of RTTI tables.
+pub const TABLE_HEADER_LEN: usize = size_of!(u32 + u32 + u32);
+
+/// Read an RTTI table from its bytes.
+///
+/// Rows may be larger than [`TableEntry::SIZE`], in which case their extra
+/// bytes are skipped.
+pub fn read_rtti_table<E: ByteOrder, T: TableEntry>(bytes: &[u8]) -> IoResult<Vec<T>> {
+	let mut r = Cursor::new(bytes);
+	let header_len = r.read_u32::<E>()? as usize;
+	let row_len = r.read_u32::<E>()? as usize;
+	let row_count = r.read_u32::<E>()? as usize;
+	if header_len < TABLE_HEADER_LEN || row_len < T::SIZE {
+		return Err(IoError::new(
+			IoErrorKind::InvalidData,
+			format!("invalid RTTI table header size {header_len} or row size {row_len}"),
+		))
+	}
+
+	let mut rows = Vec::with_capacity(row_count.min(bytes.len() / row_len));
+	for n in 0..row_count {
+		let start = n.checked_mul(row_len).and_then(move |o| o.checked_add(header_len));
+		let row = start.and_then(move |start| bytes.get(start..start + row_len))
+			.ok_or_else(move || IoError::from(IoErrorKind::UnexpectedEof))?;
+		rows.push(T::read::<E>(&mut Cursor::new(row))?);
+	}
+	Ok(rows)
+}
+
+/// Write an RTTI table to a vector.
+pub fn write_rtti_table<E: ByteOrder, T: TableEntry>(rows: &[T]) -> Vec<u8> {
+	let mut buffer = Vec::with_capacity(TABLE_HEADER_LEN + rows.len() * T::SIZE);
+	let _ = buffer.write_u32::<E>(TABLE_HEADER_LEN as _);
+	let _ = buffer.write_u32::<E>(T::SIZE as _);
+	let _ = buffer.write_u32::<E>(rows.len() as _);
+	for row in rows {
+		let _ = row.write::<E>(&mut buffer);
+	}
+	buffer
+}
+
+/// Define a row of an RTTI table with only `u32` fields.
+macro_rules! rtti_row {
+	(
+		$(#[$attr:meta])*
+		$name:ident {
+			$($(#[$field_attr:meta])* $field:ident,)*
+		}
+	) => {
+		$(#[$attr])*
+		#[derive(Default, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+		pub struct $name {
+			$($(#[$field_attr])* pub $field: u32,)*
+		}
+
+		impl TableEntry for $name {
+			const SIZE: usize = [$(stringify!($field)),*].len() * size_of!(u32);
+
+			fn read<E: ByteOrder>(r: &mut impl ReadBytesExt) -> IoResult<Self> {
+				Ok(Self {
+					$($field: r.read_u32::<E>()?,)*
+				})
+			}
+
+			fn write<E: ByteOrder>(&self, w: &mut impl WriteBytesExt) -> IoResult<()> {
+				$(w.write_u32::<E>(self.$field)?;)*
+				Ok(())
+			}
+		}
+	};
+}
+
+rtti_row! {
+	/// Row of `.rtti.enums`.
+	RttiEnum {
+		/// Offset of the name into `.names`.
+		name,
+		reserved0,
+		reserved1,
+		reserved2,
+	}
+}
+
+rtti_row! {
+	/// Row of `.rtti.methods`.
+	RttiMethod {
+		/// Offset of the name into `.names`.
+		name,
+		/// Code offset of the first instruction.
+		pcode_start,
+		/// Code offset one past the last instruction.
+		pcode_end,
+		/// Offset of the function signature into `.rtti.data`.
+		signature,
+	}
+}
+
+rtti_row! {
+	/// Row of `.rtti.natives`.
+	RttiNative {
+		/// Offset of the name into `.names`.
+		name,
+		/// Offset of the function signature into `.rtti.data`.
+		signature,
+	}
+}
+
+rtti_row! {
+	/// Row of `.rtti.typedefs`.
+	RttiTypedef {
+		/// Offset of the name into `.names`.
+		name,
+		/// Type id of the aliased type.
+		type_id,
+	}
+}
+
+rtti_row! {
+	/// Row of `.rtti.typesets`.
+	RttiTypeset {
+		/// Offset of the name into `.names`.
+		name,
+		/// Offset into `.rtti.data` of the type count, followed by the types.
+		signature,
+	}
+}
+
+rtti_row! {
+	/// Row of `.rtti.classdefs`.
+	RttiClassdef {
+		/// Kind of the class definition in the low bits.
+		flags,
+		/// Offset of the name into `.names`.
+		name,
+		/// Index of the first field in `.rtti.fields`.
+		first_field,
+		reserved0,
+		reserved1,
+		reserved2,
+		reserved3,
+	}
+}
+
+rtti_row! {
+	/// Row of `.rtti.enumstructs`.
+	RttiEnumStruct {
+		/// Offset of the name into `.names`.
+		name,
+		/// Index of the first field in `.rtti.es_fields`.
+		first_field,
+		/// Size of the enum struct, in cells.
+		size,
+	}
+}
+
+rtti_row! {
+	/// Row of `.rtti.es_fields`.
+	RttiEnumStructField {
+		/// Offset of the name into `.names`.
+		name,
+		/// Type id of the field.
+		type_id,
+		/// Offset of the field into the enum struct, in bytes.
+		offset,
+	}
+}
+
+rtti_row! {
+	/// Row of `.dbg.methods`.
+	DebugMethod {
+		/// Index of the method in `.rtti.methods`.
+		method_index,
+		/// Index of the first local of the method in `.dbg.locals`.
+		first_local,
+	}
+}
+
+/// Row of `.rtti.fields`.
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct RttiField {
+	/// Field flags.
+	pub flags: u16,
+	/// Offset of the name into `.names`.
+	pub name: u32,
+	/// Type id of the field.
+	pub type_id: u32,
+}
+
+impl TableEntry for RttiField {
+	const SIZE: usize = size_of!(u16 + u32 + u32);
+
+	fn read<E: ByteOrder>(r: &mut impl ReadBytesExt) -> IoResult<Self> {
+		Ok(Self {
+			flags: r.read_u16::<E>()?,
+			name: r.read_u32::<E>()?,
+			type_id: r.read_u32::<E>()?,
+		})
+	}
+
+	fn write<E: ByteOrder>(&self, w: &mut impl WriteBytesExt) -> IoResult<()> {
+		w.write_u16::<E>(self.flags)?;
+		w.write_u32::<E>(self.name)?;
+		w.write_u32::<E>(self.type_id)
+	}
+}
+
+/// Class of a variable in `.dbg.globals` and `.dbg.locals`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum VarClass {
+	Global,
+	Local,
+	Static,
+	Arg,
+}
+
+/// Row of `.dbg.globals` and `.dbg.locals`.
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct DebugVar {
+	/// Address relative to the data image or the frame.
+	pub address: i32,
+	/// Variable class in the low 2 bits.
+	pub vclass: u8,
+	/// Offset of the name into `.names`.
+	pub name: u32,
+	/// Code offset where the variable comes into scope.
+	pub code_start: u32,
+	/// Code offset where the variable goes out of scope.
+	pub code_end: u32,
+	/// Type id of the variable.
+	pub type_id: u32,
+}
+
+impl DebugVar {
+	/// Return the class of this variable.
+	pub const fn class(&self) -> VarClass {
+		match self.vclass & 0b11 {
+			0 => VarClass::Global,
+			1 => VarClass::Local,
+			2 => VarClass::Static,
+			_ => VarClass::Arg,
+		}
+	}
+}
+
+impl TableEntry for DebugVar {
+	const SIZE: usize = size_of!(i32 + u8 + u32 + u32 + u32 + u32);
+
+	fn read<E: ByteOrder>(r: &mut impl ReadBytesExt) -> IoResult<Self> {
+		Ok(Self {
+			address: r.read_i32::<E>()?,
+			vclass: r.read_u8()?,
+			name: r.read_u32::<E>()?,
+			code_start: r.read_u32::<E>()?,
+			code_end: r.read_u32::<E>()?,
+			type_id: r.read_u32::<E>()?,
+		})
+	}
+
+	fn write<E: ByteOrder>(&self, w: &mut impl WriteBytesExt) -> IoResult<()> {
+		w.write_i32::<E>(self.address)?;
+		w.write_u8(self.vclass)?;
+		w.write_u32::<E>(self.name)?;
+		w.write_u32::<E>(self.code_start)?;
+		w.write_u32::<E>(self.code_end)?;
+		w.write_u32::<E>(self.type_id)
+	}
+}
+
+/// Codes of the type encoding in `.rtti.data`.
+pub mod type_codes {
+	pub const BOOL: u8 = 0x01;
+	pub const INT32: u8 = 0x06;
+	pub const FLOAT32: u8 = 0x0c;
+	pub const CHAR8: u8 = 0x0e;
+	pub const ANY: u8 = 0x10;
+	pub const TOP_FUNCTION: u8 = 0x11;
+	pub const FIXED_ARRAY: u8 = 0x30;
+	pub const ARRAY: u8 = 0x31;
+	pub const FUNCTION: u8 = 0x32;
+	pub const ENUM: u8 = 0x42;
+	pub const TYPEDEF: u8 = 0x43;
+	pub const TYPESET: u8 = 0x44;
+	pub const CLASSDEF: u8 = 0x45;
+	pub const ENUM_STRUCT: u8 = 0x46;
+	pub const VOID: u8 = 0x70;
+	pub const VARIADIC: u8 = 0x71;
+	pub const BY_REF: u8 = 0x72;
+	pub const CONST: u8 = 0x73;
+}
+
+/// Kind of a type id, in its low 4 bits.
+const TYPE_ID_INLINE: u32 = 0x0;
+const TYPE_ID_COMPLEX: u32 = 0x1;
+const TYPE_ID_SHIFT: u32 = 4;
+
+/// Decoded type.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Type {
+	Bool,
+	Int,
+	Float,
+	Char,
+	Any,
+	/// Any function.
+	TopFunction,
+	Void,
+	/// Array with a fixed size.
+	FixedArray {
+		size: u32,
+		element: Box<Type>,
+	},
+	/// Array with an unspecified size.
+	Array(Box<Type>),
+	Function(Box<Signature>),
+	/// Index into `.rtti.enums`.
+	Enum(u32),
+	/// Index into `.rtti.typedefs`.
+	Typedef(u32),
+	/// Index into `.rtti.typesets`.
+	Typeset(u32),
+	/// Index into `.rtti.classdefs`.
+	Classdef(u32),
+	/// Index into `.rtti.enumstructs`.
+	EnumStruct(u32),
+	/// Argument that is passed by reference.
+	ByRef(Box<Type>),
+	Const(Box<Type>),
+	/// Variadic arguments.
+	Variadic(Box<Type>),
+}
+
+/// Decoded function signature.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Signature {
+	/// Return type, which is [`Type::Void`] for functions without one.
+	pub ret: Type,
+	/// Argument types, where the last one may be [`Type::Variadic`].
+	pub args: Vec<Type>,
+}
+
+/// Structure for an error that has occurred while decoding a type.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TypeError {
+	/// Type data ends prematurely.
+	UnexpectedEnd,
+	/// Type data has an unknown code.
+	UnknownCode(u8),
+	/// A type id has an unknown kind.
+	UnknownKind(u32),
+	/// A compressed integer is too large.
+	Overflow,
+}
+
+impl fmt::Display for TypeError {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			Self::UnexpectedEnd => f.write_str("type data ends prematurely"),
+			Self::UnknownCode(code) => write!(f, "unknown type code 0x{code:02x}"),
+			Self::UnknownKind(id) => write!(f, "type id 0x{id:08x} has an unknown kind"),
+			Self::Overflow => f.write_str("compressed integer is too large"),
+		}
+	}
+}
+
+impl Error for TypeError {}
+
+/// Decoder of the type encoding.
+struct TypeDecoder<'a> {
+	bytes: &'a [u8],
+	offset: usize,
+}
+
+impl TypeDecoder<'_> {
+	fn byte(&mut self) -> Result<u8, TypeError> {
+		let byte = *self.bytes.get(self.offset).ok_or(TypeError::UnexpectedEnd)?;
+		self.offset += 1;
+		Ok(byte)
+	}
+
+	fn peek(&self) -> Option<u8> {
+		self.bytes.get(self.offset).copied()
+	}
+
+	/// Decode an integer with 7 bits per byte, where the high bit marks that
+	/// more bytes follow.
+	fn compressed_u32(&mut self) -> Result<u32, TypeError> {
+		let mut value = 0u32;
+		for shift in (0..35).step_by(7) {
+			let byte = self.byte()?;
+			let bits = u32::from(byte & 0x7f).checked_shl(shift);
+			value |= bits.ok_or(TypeError::Overflow)?;
+			if byte & 0x80 == 0 {
+				return Ok(value)
+			}
+		}
+		Err(TypeError::Overflow)
+	}
+
+	fn ty(&mut self) -> Result<Type, TypeError> {
+		use type_codes as c;
+		Ok(match self.byte()? {
+			c::BOOL => Type::Bool,
+			c::INT32 => Type::Int,
+			c::FLOAT32 => Type::Float,
+			c::CHAR8 => Type::Char,
+			c::ANY => Type::Any,
+			c::TOP_FUNCTION => Type::TopFunction,
+			c::VOID => Type::Void,
+			c::FIXED_ARRAY => Type::FixedArray {
+				size: self.compressed_u32()?,
+				element: Box::new(self.ty()?),
+			},
+			c::ARRAY => Type::Array(Box::new(self.ty()?)),
+			c::FUNCTION => Type::Function(Box::new(self.signature()?)),
+			c::ENUM => Type::Enum(self.compressed_u32()?),
+			c::TYPEDEF => Type::Typedef(self.compressed_u32()?),
+			c::TYPESET => Type::Typeset(self.compressed_u32()?),
+			c::CLASSDEF => Type::Classdef(self.compressed_u32()?),
+			c::ENUM_STRUCT => Type::EnumStruct(self.compressed_u32()?),
+			c::BY_REF => Type::ByRef(Box::new(self.ty()?)),
+			c::CONST => Type::Const(Box::new(self.ty()?)),
+			code => return Err(TypeError::UnknownCode(code)),
+		})
+	}
+
+	/// Decode a signature after its [`type_codes::FUNCTION`] code.
+	fn signature(&mut self) -> Result<Signature, TypeError> {
+		let argc = self.byte()?;
+		let variadic = self.peek() == Some(type_codes::VARIADIC);
+		if variadic {
+			self.offset += 1;
+		}
+		let ret = self.ty()?;
+		let mut args = (0..argc).map(|_| self.ty()).collect::<Result<Vec<_>, _>>()?;
+		if variadic {
+			if let Some(last) = args.pop() {
+				args.push(Type::Variadic(Box::new(last)));
+			}
+		}
+		Ok(Signature {
+			ret,
+			args,
+		})
+	}
+}
+
+/// Parsed view of the RTTI sections of an SMX file.
+///
+/// Sections that are missing from the file are treated as empty.
+#[derive(Default, Debug, Clone, PartialEq, Eq)]
+pub struct Rtti {
+	/// `.rtti.data` section.
+	pub data: Vec<u8>,
+	/// `.rtti.methods` section.
+	pub methods: Vec<RttiMethod>,
+	/// `.rtti.natives` section.
+	pub natives: Vec<RttiNative>,
+	/// `.rtti.enums` section.
+	pub enums: Vec<RttiEnum>,
+	/// `.rtti.typedefs` section.
+	pub typedefs: Vec<RttiTypedef>,
+	/// `.rtti.typesets` section.
+	pub typesets: Vec<RttiTypeset>,
+	/// `.rtti.classdefs` section.
+	pub classdefs: Vec<RttiClassdef>,
+	/// `.rtti.fields` section.
+	pub fields: Vec<RttiField>,
+	/// `.rtti.enumstructs` section.
+	pub enum_structs: Vec<RttiEnumStruct>,
+	/// `.rtti.es_fields` section.
+	pub enum_struct_fields: Vec<RttiEnumStructField>,
+	/// `.dbg.methods` section.
+	pub debug_methods: Vec<DebugMethod>,
+	/// `.dbg.globals` section.
+	pub globals: Vec<DebugVar>,
+	/// `.dbg.locals` section.
+	pub locals: Vec<DebugVar>,
+}
+
+impl Rtti {
+	/// Parse the RTTI sections of an SMX file.
+	pub fn from_smx<E, Name, Sect>(smx: &Smx<Name, Sect>) -> IoResult<Self>
+	where
+		E: ByteOrder,
+		Name: Borrow<CStr> + Eq + Hash,
+		Sect: AsRef<[u8]>,
+	{
+		let section = move |name: &CStr| smx.section(name).map(AsRef::as_ref);
+		fn table<E: ByteOrder, T: TableEntry>(bytes: Option<&[u8]>) -> IoResult<Vec<T>> {
+			Ok(bytes.map(read_rtti_table::<E, T>).transpose()?.unwrap_or_default())
+		}
+		Ok(Self {
+			data: section(DATA).map(<[u8]>::to_vec).unwrap_or_default(),
+			methods: table::<E, _>(section(METHODS))?,
+			natives: table::<E, _>(section(NATIVES))?,
+			enums: table::<E, _>(section(ENUMS))?,
+			typedefs: table::<E, _>(section(TYPEDEFS))?,
+			typesets: table::<E, _>(section(TYPESETS))?,
+			classdefs: table::<E, _>(section(CLASSDEFS))?,
+			fields: table::<E, _>(section(FIELDS))?,
+			enum_structs: table::<E, _>(section(ENUMSTRUCTS))?,
+			enum_struct_fields: table::<E, _>(section(ENUMSTRUCT_FIELDS))?,
+			debug_methods: table::<E, _>(section(DBG_METHODS))?,
+			globals: table::<E, _>(section(DBG_GLOBALS))?,
+			locals: table::<E, _>(section(DBG_LOCALS))?,
+		})
+	}
+
+	/// Write the RTTI sections into an SMX file.
+	///
+	/// Tables are only written if they are non-empty, or if they already
+	/// exist in `smx`.
+	pub fn write_into<E: ByteOrder>(&self, smx: &mut Smx<CString, Vec<u8>>) {
+		fn put<E: ByteOrder, T: TableEntry>(
+			smx: &mut Smx<CString, Vec<u8>>,
+			name: &CStr,
+			rows: &[T],
+		) {
+			if !rows.is_empty() || smx.section(name).is_some() {
+				smx.sections.insert(name.to_owned(), write_rtti_table::<E, _>(rows));
+			}
+		}
+		if !self.data.is_empty() || smx.section(DATA).is_some() {
+			smx.sections.insert(DATA.to_owned(), self.data.clone());
+		}
+		put::<E, _>(smx, METHODS, &self.methods);
+		put::<E, _>(smx, NATIVES, &self.natives);
+		put::<E, _>(smx, ENUMS, &self.enums);
+		put::<E, _>(smx, TYPEDEFS, &self.typedefs);
+		put::<E, _>(smx, TYPESETS, &self.typesets);
+		put::<E, _>(smx, CLASSDEFS, &self.classdefs);
+		put::<E, _>(smx, FIELDS, &self.fields);
+		put::<E, _>(smx, ENUMSTRUCTS, &self.enum_structs);
+		put::<E, _>(smx, ENUMSTRUCT_FIELDS, &self.enum_struct_fields);
+		put::<E, _>(smx, DBG_METHODS, &self.debug_methods);
+		put::<E, _>(smx, DBG_GLOBALS, &self.globals);
+		put::<E, _>(smx, DBG_LOCALS, &self.locals);
+	}
+
+	fn decoder_at(&self, offset: u32) -> TypeDecoder<'_> {
+		TypeDecoder {
+			bytes: &self.data,
+			offset: offset as usize,
+		}
+	}
+
+	/// Add a native whose signature is not known, as `any f(any ...)`.
+	pub fn add_untyped_native(&mut self, name: u32) {
+		let signature = self.data.len() as u32;
+		self.data.extend_from_slice(&[
+			type_codes::FUNCTION,
+			1,
+			type_codes::VARIADIC,
+			type_codes::ANY,
+			type_codes::ANY,
+		]);
+		self.natives.push(RttiNative { name, signature });
+	}
+
+	/// Decode a type id.
+	pub fn type_of(&self, type_id: u32) -> Result<Type, TypeError> {
+		let payload = type_id >> TYPE_ID_SHIFT;
+		match type_id & ((1 << TYPE_ID_SHIFT) - 1) {
+			TYPE_ID_INLINE => {
+				let bytes = payload.to_le_bytes();
+				TypeDecoder { bytes: &bytes, offset: 0 }.ty()
+			}
+			TYPE_ID_COMPLEX => self.decoder_at(payload).ty(),
+			_ => Err(TypeError::UnknownKind(type_id)),
+		}
+	}
+
+	/// Decode the function signature at an offset into `.rtti.data`.
+	pub fn signature_at(&self, offset: u32) -> Result<Signature, TypeError> {
+		let mut decoder = self.decoder_at(offset);
+		match decoder.byte()? {
+			type_codes::FUNCTION => decoder.signature(),
+			code => Err(TypeError::UnknownCode(code)),
+		}
+	}
+
+	/// Decode the types of a typeset.
+	pub fn typeset_types(&self, typeset: &RttiTypeset) -> Result<Vec<Type>, TypeError> {
+		let mut decoder = self.decoder_at(typeset.signature);
+		let count = decoder.compressed_u32()?;
+		(0..count).map(move |_| decoder.ty()).collect()
+	}
+
+	/// Return the method whose code contains a code offset.
+	pub fn method_at(&self, offset: usize) -> Option<&RttiMethod> {
+		self.methods.iter().find(move |method| {
+			method.pcode_start as usize <= offset && offset < method.pcode_end as usize
+		})
+	}
+
+	/// Return the fields of an enum struct.
+	pub fn enum_struct_fields(&self, index: usize) -> &[RttiEnumStructField] {
+		let Some(es) = self.enum_structs.get(index) else {
+			return &[]
+		};
+		let end = self.enum_structs.get(index + 1)
+			.map_or(self.enum_struct_fields.len(), move |next| next.first_field as usize);
+		self.enum_struct_fields.get(es.first_field as usize..end).unwrap_or_default()
+	}
+
+	/// Return the fields of a class definition.
+	pub fn classdef_fields(&self, index: usize) -> &[RttiField] {
+		let Some(classdef) = self.classdefs.get(index) else {
+			return &[]
+		};
+		let end = self.classdefs.get(index + 1)
+			.map_or(self.fields.len(), move |next| next.first_field as usize);
+		self.fields.get(classdef.first_field as usize..end).unwrap_or_default()
+	}
+
+	/// Return the locals of the method with the specified index in
+	/// [`Self::debug_methods`].
+	pub fn method_locals(&self, index: usize) -> &[DebugVar] {
+		let Some(method) = self.debug_methods.get(index) else {
+			return &[]
+		};
+		let end = self.debug_methods.get(index + 1)
+			.map_or(self.locals.len(), move |next| next.first_local as usize);
+		self.locals.get(method.first_local as usize..end).unwrap_or_default()
+	}
+
+	/// Format a type as SourcePawn, with names from `.names`.
+	pub fn format_type(&self, ty: &Type, names: &CStrTable) -> String {
+		let name = move |offset: Option<u32>, kind: &str, index: &u32| offset
+			.and_then(move |offset| names.get_c_string(offset as _))
+			.map(move |name| name.to_string_lossy().into_owned())
+			.unwrap_or_else(move || format!("{kind}#{index}"));
+		match ty {
+			Type::Bool => "bool".into(),
+			Type::Int => "int".into(),
+			Type::Float => "float".into(),
+			Type::Char => "char".into(),
+			Type::Any => "any".into(),
+			Type::TopFunction => "Function".into(),
+			Type::Void => "void".into(),
+			Type::FixedArray { size, element } =>
+				format!("{}[{size}]", self.format_type(element, names)),
+			Type::Array(element) => format!("{}[]", self.format_type(element, names)),
+			Type::Function(signature) => {
+				format!("function {}", self.format_signature("", signature, names))
+			}
+			Type::Enum(index) =>
+				name(self.enums.get(*index as usize).map(move |e| e.name), "enum", index),
+			Type::Typedef(index) => name(
+				self.typedefs.get(*index as usize).map(move |t| t.name), "typedef", index
+			),
+			Type::Typeset(index) => name(
+				self.typesets.get(*index as usize).map(move |t| t.name), "typeset", index
+			),
+			Type::Classdef(index) => name(
+				self.classdefs.get(*index as usize).map(move |c| c.name), "class", index
+			),
+			Type::EnumStruct(index) => name(
+				self.enum_structs.get(*index as usize).map(move |e| e.name),
+				"enum struct",
+				index,
+			),
+			Type::ByRef(inner) => format!("{}&", self.format_type(inner, names)),
+			Type::Const(inner) => format!("const {}", self.format_type(inner, names)),
+			Type::Variadic(inner) => format!("{} ...", self.format_type(inner, names)),
+		}
+	}
+
+	/// Format a function signature as SourcePawn, with names from `.names`.
+	pub fn format_signature(
+		&self,
+		name: &str,
+		signature: &Signature,
+		names: &CStrTable,
+	) -> String {
+		let args: Vec<String> = signature.args.iter()
+			.map(move |arg| self.format_type(arg, names))
+			.collect();
+		format!("{} {name}({})", self.format_type(&signature.ret, names), args.join(", "))
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use byteorder::LittleEndian as Le;
+	use type_codes as c;
+
+	#[test]
+	fn types() -> Result<(), TypeError> {
+		let mut names = CStrTable::new();
+		let handle = names.insert(c"Handle") as u32;
+
+		let mut rtti = Rtti::default();
+		rtti.classdefs.push(RttiClassdef { name: handle, ..Default::default() });
+		// int[200]
+		rtti.data.extend([c::FIXED_ARRAY, 0xc8, 0x01, c::INT32]);
+		// void f(const char[] format, any ...)
+		rtti.data.extend([c::FUNCTION, 2, c::VARIADIC, c::VOID]);
+		rtti.data.extend([c::CONST, c::ARRAY, c::CHAR8, c::ANY]);
+		// Handle f(float& value)
+		rtti.data.extend([c::FUNCTION, 1, c::CLASSDEF, 0, c::BY_REF, c::FLOAT32]);
+
+		assert_eq!(rtti.type_of(u32::from(c::BOOL) << 4)?, Type::Bool);
+		let array = u32::from_le_bytes([c::ARRAY, c::CHAR8, 0, 0]) << 4;
+		assert_eq!(rtti.type_of(array)?, Type::Array(Box::new(Type::Char)));
+		assert_eq!(rtti.type_of(1)?, Type::FixedArray {
+			size: 200,
+			element: Box::new(Type::Int),
+		});
+		assert_eq!(rtti.type_of(2), Err(TypeError::UnknownKind(2)));
+
+		let print = rtti.signature_at(4)?;
+		assert_eq!(
+			rtti.format_signature("PrintToServer", &print, &names),
+			"void PrintToServer(const char[], any ...)"
+		);
+		let find = rtti.signature_at(12)?;
+		assert_eq!(rtti.format_signature("Find", &find, &names), "Handle Find(float&)");
+		Ok(())
+	}
+
+	#[test]
+	fn round_trip() -> IoResult<()> {
+		let mut rtti = Rtti {
+			data: vec![c::FUNCTION, 0, c::VOID],
+			..Default::default()
+		};
+		rtti.methods.push(RttiMethod {
+			name: 0,
+			pcode_start: 0,
+			pcode_end: 16,
+			signature: 0,
+		});
+		rtti.enum_structs.push(RttiEnumStruct { name: 0, first_field: 0, size: 2 });
+		for (type_id, offset) in [(0x60, 0), (0xc0, 4)] {
+			let field = RttiEnumStructField { name: 0, type_id, offset };
+			rtti.enum_struct_fields.push(field);
+		}
+		rtti.fields.push(RttiField { flags: 0, name: 0, type_id: 0x60 });
+		rtti.debug_methods.push(DebugMethod { method_index: 0, first_local: 0 });
+		rtti.locals.push(DebugVar {
+			address: 12,
+			vclass: 3,
+			name: 0,
+			code_start: 0,
+			code_end: 16,
+			type_id: 0x60,
+		});
+
+		let mut smx = Smx::new();
+		rtti.write_into::<Le>(&mut smx);
+		assert!(smx.section(TYPESETS).is_none());
+		assert_eq!(
+			smx.section(FIELDS).unwrap().len(),
+			TABLE_HEADER_LEN + RttiField::SIZE
+		);
+		assert_eq!(Rtti::from_smx::<Le, _, _>(&smx)?, rtti);
+
+		assert_eq!(rtti.method_at(8), Some(&rtti.methods[0]));
+		assert_eq!(rtti.enum_struct_fields(0).len(), 2);
+		assert_eq!(rtti.method_locals(0)[0].class(), VarClass::Arg);
+		Ok(())
+	}
+}
@@ -0,0 +1,409 @@
+//! Models of the standard sections of an SMX file.
+
+use crate::{
+	size_of,
+	vm_types::{
+		Cell,
+		read_cell,
+	},
+};
+
+use byteorder::{
+	ByteOrder,
+	ReadBytesExt,
+	WriteBytesExt,
+};
+use core::ffi::CStr;
+use std::io::{
+	Cursor,
+	Error as IoError,
+	ErrorKind as IoErrorKind,
+	Result as IoResult,
+};
+
+/// Name of the section with code.
+pub const CODE: &CStr = c".code";
+/// Name of the section with the data image.
+pub const DATA: &CStr = c".data";
+/// Name of the section with the names of publics, pubvars and natives.
+pub const NAMES: &CStr = c".names";
+/// Name of the section with public functions.
+pub const PUBLICS: &CStr = c".publics";
+/// Name of the section with public variables.
+pub const PUBVARS: &CStr = c".pubvars";
+/// Name of the section with natives.
+pub const NATIVES: &CStr = c".natives";
+/// Name of the section with tag names.
+pub const TAGS: &CStr = c".tags";
+
+fn invalid_data(message: impl Into<String>) -> IoError {
+	IoError::new(IoErrorKind::InvalidData, message.into())
+}
+
+/// `.code` section.
+#[derive(Default, Debug, Clone, PartialEq, Eq)]
+pub struct CodeSection {
+	/// Size of a cell, in bytes.
+	pub cell_size: u8,
+	/// Code version.
+	pub version: u8,
+	/// Code flags.
+	pub flags: u16,
+	/// Offset of the `main` function, if any.
+	pub main: u32,
+	/// Code features, which are only stored for [`Self::version`] 13 and
+	/// later.
+	pub features: u32,
+	/// Code blob.
+	pub code: Vec<u8>,
+}
+
+impl CodeSection {
+	/// Size of the header for code versions before 13.
+	pub const OLD_HEADER_LEN: usize = size_of!(u32 + u8 + u8 + u16 + u32 + u32);
+	/// Size of the header for code version 13 and later.
+	pub const HEADER_LEN: usize = Self::OLD_HEADER_LEN + size_of!(u32);
+
+	/// Flag for code that was compiled with debug information.
+	pub const FLAG_DEBUG: u16 = 1 << 0;
+
+	/// Return the header size that is used by this section's code version.
+	pub const fn header_len(&self) -> usize {
+		if self.version >= 13 {
+			Self::HEADER_LEN
+		} else {
+			Self::OLD_HEADER_LEN
+		}
+	}
+
+	/// Read a `.code` section from its bytes.
+	pub fn from_bytes<E: ByteOrder>(bytes: &[u8]) -> IoResult<Self> {
+		let mut r = Cursor::new(bytes);
+		let code_size = r.read_u32::<E>()? as usize;
+		let cell_size = r.read_u8()?;
+		let version = r.read_u8()?;
+		let flags = r.read_u16::<E>()?;
+		let main = r.read_u32::<E>()?;
+		let code_offset = r.read_u32::<E>()? as usize;
+		let features = if version >= 13 {
+			r.read_u32::<E>()?
+		} else {
+			0
+		};
+
+		let code = code_offset.checked_add(code_size)
+			.and_then(move |end| bytes.get(code_offset..end))
+			.ok_or_else(move || invalid_data(format!(
+				"code of size 0x{code_size:08x} at 0x{code_offset:08x} is out of bounds"
+			)))?;
+
+		Ok(Self {
+			cell_size,
+			version,
+			flags,
+			main,
+			features,
+			code: code.to_vec(),
+		})
+	}
+
+	/// Write this section to a vector.
+	pub fn to_vec<E: ByteOrder>(&self) -> Vec<u8> {
+		let header_len = self.header_len();
+		let mut buffer = Vec::with_capacity(header_len + self.code.len());
+		let _ = buffer.write_u32::<E>(self.code.len() as _);
+		let _ = buffer.write_u8(self.cell_size);
+		let _ = buffer.write_u8(self.version);
+		let _ = buffer.write_u16::<E>(self.flags);
+		let _ = buffer.write_u32::<E>(self.main);
+		let _ = buffer.write_u32::<E>(header_len as _);
+		if self.version >= 13 {
+			let _ = buffer.write_u32::<E>(self.features);
+		}
+		buffer.extend_from_slice(&self.code);
+		buffer
+	}
+}
+
+/// `.data` section.
+#[derive(Default, Debug, Clone, PartialEq, Eq)]
+pub struct DataSection {
+	/// Memory reserved for the heap and stack, on top of the data image.
+	pub extra_memory: u32,
+	/// Data image.
+	pub bytes: Vec<u8>,
+}
+
+impl DataSection {
+	/// Size of the section header.
+	pub const HEADER_LEN: usize = size_of!(u32 + u32 + u32);
+
+	/// Return the total memory size of the plugin, including the data image.
+	pub fn memory_size(&self) -> u32 {
+		self.bytes.len() as u32 + self.extra_memory
+	}
+
+	/// Return the cell at an offset into the data image.
+	pub fn cell_at(&self, offset: usize) -> Option<Cell> {
+		let bytes = self.bytes.get(offset..)?;
+		read_cell(&mut Cursor::new(bytes)).ok()
+	}
+
+	/// Return `N` consecutive cells at an offset into the data image.
+	pub fn cells_at<const N: usize>(&self, offset: usize) -> Option<[Cell; N]> {
+		let mut cells = [0; N];
+		for (n, cell) in cells.iter_mut().enumerate() {
+			*cell = self.cell_at(offset.checked_add(n * size_of!(Cell))?)?;
+		}
+		Some(cells)
+	}
+
+	/// Return the string that a cell points to, replacing invalid UTF-8.
+	pub fn string_at(&self, address: Cell) -> Option<String> {
+		let address = usize::try_from(address).ok()?;
+		Some(self.c_str_at(address)?.to_string_lossy().into_owned())
+	}
+
+	/// Return the null-terminated string at an offset into the data image.
+	pub fn c_str_at(&self, offset: usize) -> Option<&CStr> {
+		CStr::from_bytes_until_nul(self.bytes.get(offset..)?).ok()
+	}
+
+	/// Return `true` if an address is the start of a non-empty, printable
+	/// string of the data image.
+	pub(crate) fn is_string_start(&self, address: usize) -> bool {
+		if !address.is_multiple_of(size_of!(Cell))
+			|| address.checked_sub(1).is_some_and(|before| self.bytes[before] != 0)
+		{
+			return false
+		}
+		self.c_str_at(address).is_some_and(move |string| {
+			!string.is_empty() && string.to_bytes()
+				.iter()
+				.all(move |b| !b.is_ascii_control() || b.is_ascii_whitespace())
+		})
+	}
+
+	/// Append a string to the data image, padded to a whole number of cells,
+	/// returning its address.
+	pub fn push_string(&mut self, value: &CStr) -> Cell {
+		self.bytes.resize(self.bytes.len().next_multiple_of(size_of!(Cell)), 0);
+		let address = self.bytes.len() as Cell;
+		self.bytes.extend_from_slice(value.to_bytes_with_nul());
+		self.bytes.resize(self.bytes.len().next_multiple_of(size_of!(Cell)), 0);
+		address
+	}
+
+	/// Read a `.data` section from its bytes.
+	pub fn from_bytes<E: ByteOrder>(bytes: &[u8]) -> IoResult<Self> {
+		let mut r = Cursor::new(bytes);
+		let data_size = r.read_u32::<E>()? as usize;
+		let memory_size = r.read_u32::<E>()?;
+		let data_offset = r.read_u32::<E>()? as usize;
+
+		let data = data_offset.checked_add(data_size)
+			.and_then(move |end| bytes.get(data_offset..end))
+			.ok_or_else(move || invalid_data(format!(
+				"data of size 0x{data_size:08x} at 0x{data_offset:08x} is out of bounds"
+			)))?;
+
+		Ok(Self {
+			extra_memory: memory_size.checked_sub(data_size as u32)
+				.ok_or_else(move || invalid_data(format!(
+					"memory size 0x{memory_size:08x} is less than data size"
+				)))?,
+			bytes: data.to_vec(),
+		})
+	}
+
+	/// Write this section to a vector.
+	pub fn to_vec<E: ByteOrder>(&self) -> Vec<u8> {
+		let mut buffer = Vec::with_capacity(Self::HEADER_LEN + self.bytes.len());
+		let _ = buffer.write_u32::<E>(self.bytes.len() as _);
+		let _ = buffer.write_u32::<E>(self.memory_size());
+		let _ = buffer.write_u32::<E>(Self::HEADER_LEN as _);
+		buffer.extend_from_slice(&self.bytes);
+		buffer
+	}
+}
+
+/// Trait for fixed-size entries of table sections.
+pub trait TableEntry: Sized {
+	/// Size of an entry, in bytes.
+	const SIZE: usize;
+
+	/// Read an entry from a reader.
+	fn read<E: ByteOrder>(r: &mut impl ReadBytesExt) -> IoResult<Self>;
+
+	/// Write this entry to a writer.
+	fn write<E: ByteOrder>(&self, w: &mut impl WriteBytesExt) -> IoResult<()>;
+}
+
+/// Read a table section from its bytes.
+pub fn read_table<E: ByteOrder, T: TableEntry>(bytes: &[u8]) -> IoResult<Vec<T>> {
+	if !bytes.len().is_multiple_of(T::SIZE) {
+		return Err(invalid_data(format!(
+			"table of size 0x{:08x} is not a multiple of entry size {}",
+			bytes.len(), T::SIZE
+		)))
+	}
+
+	let mut r = Cursor::new(bytes);
+	(0..bytes.len() / T::SIZE)
+		.map(move |_| T::read::<E>(&mut r))
+		.collect()
+}
+
+/// Write a table section to a vector.
+pub fn write_table<E: ByteOrder, T: TableEntry>(entries: &[T]) -> Vec<u8> {
+	let mut buffer = Vec::with_capacity(entries.len() * T::SIZE);
+	for entry in entries {
+		let _ = entry.write::<E>(&mut buffer);
+	}
+	buffer
+}
+
+/// Entry of the `.publics` section.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Public {
+	/// Code offset of the function.
+	pub address: u32,
+	/// Offset of the name into `.names`.
+	pub name: u32,
+}
+
+impl TableEntry for Public {
+	const SIZE: usize = size_of!(u32 + u32);
+
+	fn read<E: ByteOrder>(r: &mut impl ReadBytesExt) -> IoResult<Self> {
+		Ok(Self {
+			address: r.read_u32::<E>()?,
+			name: r.read_u32::<E>()?,
+		})
+	}
+
+	fn write<E: ByteOrder>(&self, w: &mut impl WriteBytesExt) -> IoResult<()> {
+		w.write_u32::<E>(self.address)?;
+		w.write_u32::<E>(self.name)
+	}
+}
+
+/// Entry of the `.pubvars` section.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Pubvar {
+	/// Data offset of the variable.
+	pub address: u32,
+	/// Offset of the name into `.names`.
+	pub name: u32,
+}
+
+impl TableEntry for Pubvar {
+	const SIZE: usize = size_of!(u32 + u32);
+
+	fn read<E: ByteOrder>(r: &mut impl ReadBytesExt) -> IoResult<Self> {
+		Ok(Self {
+			address: r.read_u32::<E>()?,
+			name: r.read_u32::<E>()?,
+		})
+	}
+
+	fn write<E: ByteOrder>(&self, w: &mut impl WriteBytesExt) -> IoResult<()> {
+		w.write_u32::<E>(self.address)?;
+		w.write_u32::<E>(self.name)
+	}
+}
+
+/// Entry of the `.natives` section.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Native {
+	/// Offset of the name into `.names`.
+	pub name: u32,
+}
+
+impl TableEntry for Native {
+	const SIZE: usize = size_of!(u32);
+
+	fn read<E: ByteOrder>(r: &mut impl ReadBytesExt) -> IoResult<Self> {
+		Ok(Self {
+			name: r.read_u32::<E>()?,
+		})
+	}
+
+	fn write<E: ByteOrder>(&self, w: &mut impl WriteBytesExt) -> IoResult<()> {
+		w.write_u32::<E>(self.name)
+	}
+}
+
+/// Entry of the `.tags` section.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Tag {
+	/// Tag id, with flags in the high bits.
+	pub id: u32,
+	/// Offset of the name into `.names`.
+	pub name: u32,
+}
+
+impl TableEntry for Tag {
+	const SIZE: usize = size_of!(u32 + u32);
+
+	fn read<E: ByteOrder>(r: &mut impl ReadBytesExt) -> IoResult<Self> {
+		Ok(Self {
+			id: r.read_u32::<E>()?,
+			name: r.read_u32::<E>()?,
+		})
+	}
+
+	fn write<E: ByteOrder>(&self, w: &mut impl WriteBytesExt) -> IoResult<()> {
+		w.write_u32::<E>(self.id)?;
+		w.write_u32::<E>(self.name)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use byteorder::LittleEndian as Le;
+
+	#[test]
+	fn code_round_trip() -> IoResult<()> {
+		let section = CodeSection {
+			cell_size: 4,
+			version: 13,
+			flags: 0,
+			main: 0,
+			features: 1 << 2,
+			code: vec![1, 2, 3, 4, 5, 6, 7, 8],
+		};
+		let bytes = section.to_vec::<Le>();
+		assert_eq!(bytes.len(), CodeSection::HEADER_LEN + 8);
+		assert_eq!(CodeSection::from_bytes::<Le>(&bytes)?, section);
+		Ok(())
+	}
+
+	#[test]
+	fn data_round_trip() -> IoResult<()> {
+		let section = DataSection {
+			extra_memory: 0x1000,
+			bytes: b"Hello\0\0\0".to_vec(),
+		};
+		let bytes = section.to_vec::<Le>();
+		assert_eq!(DataSection::from_bytes::<Le>(&bytes)?, section);
+		assert_eq!(section.c_str_at(2), Some(c"llo"));
+		assert_eq!(section.c_str_at(8), None);
+		assert_eq!(section.cell_at(4), Some(Cell::from_ne_bytes(*b"o\0\0\0")));
+		assert_eq!(section.cell_at(5), None);
+		Ok(())
+	}
+
+	#[test]
+	fn table_round_trip() -> IoResult<()> {
+		let publics = [
+			Public { address: 0, name: 0 },
+			Public { address: 0x40, name: 14 },
+		];
+		let bytes = write_table::<Le, _>(&publics);
+		assert_eq!(read_table::<Le, Public>(&bytes)?, publics);
+		assert!(read_table::<Le, Public>(&bytes[1..]).is_err());
+		Ok(())
+	}
+}
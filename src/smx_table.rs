@@ -137,6 +137,7 @@ fn empty() -> Result<(), Box<dyn std::error::Error>> {
 }
 
 #[test]
+#[allow(clippy::manual_c_str_literals)]
 fn one_entry() -> Result<(), Box<dyn std::error::Error>> {
 	let mut table = CStrTable::new();
 	table.insert(CStr::from_bytes_with_nul(b".code\0")?);
@@ -147,6 +148,7 @@ fn one_entry() -> Result<(), Box<dyn std::error::Error>> {
 }
 
 #[test]
+#[allow(clippy::manual_c_str_literals)]
 fn dup_entry() -> Result<(), Box<dyn std::error::Error>> {
 	let mut table = CStrTable::new();
 	let key_1 = table.insert(CStr::from_bytes_with_nul(b".code\0")?);
@@ -160,6 +162,7 @@ fn dup_entry() -> Result<(), Box<dyn std::error::Error>> {
 }
 
 #[test]
+#[allow(clippy::manual_c_str_literals)]
 fn entries_and_dup() -> Result<(), Box<dyn std::error::Error>> {
 	let mut table = CStrTable::new();
 	table.insert(CStr::from_bytes_with_nul(b"OnPluginStart\0")?);
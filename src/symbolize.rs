@@ -0,0 +1,260 @@
+//! Mapping of code offsets to source locations, and annotation of the call
+//! stacks that SourceMod writes to its error logs.
+//!
+//! Source files and lines always come from `.dbg.files` and `.dbg.lines`.
+//! Functions come from `.rtti.methods` if present, and otherwise from the
+//! function symbols of `.dbg.symbols`. For stripped plugins, these sections
+//! can be read from a companion file, see [`crate::companion`].
+
+use crate::{
+	companion::{
+		self,
+		CompanionError,
+	},
+	debug::DebugInfo,
+	rtti::Rtti,
+	smx_table::CStrTable,
+	Smx,
+};
+
+use byteorder::ByteOrder;
+use core::ffi::CStr;
+use std::{
+	borrow::Borrow,
+	fmt,
+	hash::Hash,
+	ffi::CString,
+	io::Result as IoResult,
+};
+
+/// Source location of a code offset.
+#[derive(Default, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Location {
+	/// Source file.
+	pub file: Option<String>,
+	/// One-based source line.
+	pub line: Option<u32>,
+	/// Enclosing function.
+	pub function: Option<String>,
+}
+
+impl fmt::Display for Location {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.write_str(self.function.as_deref().unwrap_or("<unknown>"))?;
+		if let Some(file) = self.file.as_deref() {
+			write!(f, " ({file}")?;
+			if let Some(line) = self.line {
+				write!(f, ":{line}")?;
+			}
+			f.write_str(")")?;
+		}
+		Ok(())
+	}
+}
+
+/// Debug information of a plugin, for looking up source locations.
+#[derive(Default, Debug, Clone, PartialEq, Eq)]
+pub struct Symbolizer {
+	/// Legacy debug sections.
+	pub debug: DebugInfo,
+	/// RTTI sections.
+	pub rtti: Rtti,
+	/// `.names` section, which RTTI names are offsets into.
+	pub names: CStrTable,
+}
+
+impl Symbolizer {
+	/// Read the debug information of an SMX file.
+	pub fn from_smx<E, Name, Sect>(smx: &Smx<Name, Sect>) -> IoResult<Self>
+	where
+		E: ByteOrder,
+		Name: Borrow<CStr> + Eq + Hash,
+		Sect: AsRef<[u8]>,
+	{
+		Ok(Self {
+			debug: DebugInfo::from_smx::<E, _, _>(smx)?,
+			rtti: Rtti::from_smx::<E, _, _>(smx)?,
+			names: smx.section(c".names")
+				.map(move |blob| CStrTable::from_blob(blob.as_ref().to_vec()))
+				.unwrap_or_default(),
+		})
+	}
+
+	/// Read the debug information of a stripped plugin from its companion
+	/// file, after checking that they belong together.
+	pub fn from_companion<E: ByteOrder>(
+		smx: &Smx<CString, Vec<u8>>,
+		companion: &Smx<CString, Vec<u8>>,
+	) -> Result<Self, CompanionError> {
+		let mut merged = smx.clone();
+		companion::merge::<E>(&mut merged, companion)?;
+		Ok(Self::from_smx::<E, _, _>(&merged)?)
+	}
+
+	fn rtti_name(&self, offset: u32) -> Option<String> {
+		let name = self.names.get_c_string(offset as _)?;
+		Some(name.to_string_lossy().into_owned())
+	}
+
+	/// Return the name of the function whose code contains an offset.
+	pub fn function_at(&self, offset: usize) -> Option<String> {
+		if !self.rtti.methods.is_empty() {
+			return self.rtti_name(self.rtti.method_at(offset)?.name)
+		}
+		let symbol = self.debug.function_at(offset)?;
+		Some(self.debug.name(symbol.name)?.to_string_lossy().into_owned())
+	}
+
+	/// Return the code offset of the function with a name.
+	pub fn function_start(&self, name: &str) -> Option<usize> {
+		if !self.rtti.methods.is_empty() {
+			return self.rtti.methods.iter()
+				.find(move |method| self.rtti_name(method.name).as_deref() == Some(name))
+				.map(move |method| method.pcode_start as usize)
+		}
+		self.debug.function_names().into_iter()
+			.find(move |(_, function)| function.to_bytes() == name.as_bytes())
+			.map(move |(start, _)| start)
+	}
+
+	/// Look up the source location of a code offset.
+	pub fn locate(&self, offset: usize) -> Location {
+		Location {
+			file: self.debug.file_at(offset)
+				.map(move |file| file.to_string_lossy().into_owned()),
+			line: self.debug.line_at(offset),
+			function: self.function_at(offset),
+		}
+	}
+
+	/// Annotate a line of an error log that is a frame of a call stack, such
+	/// as `[SM]   [1] 0x000001a4` or `[SM]   [0] OnPluginStart()`.
+	///
+	/// Frames with a code address are annotated with its location, and frames
+	/// with only a function name are annotated with the location of the
+	/// function. Other lines are returned unchanged.
+	pub fn annotate_line(&self, line: &str) -> String {
+		let location = frame(line).and_then(move |frame| {
+			if let Some(offset) = hex_address(frame) {
+				return Some(self.locate(offset))
+			}
+			let name = frame.strip_suffix("()")?;
+			let offset = self.function_start(name)?;
+			Some(Location {
+				function: Some(name.into()),
+				..self.locate(offset)
+			})
+		});
+		match location {
+			Some(location) => format!("{line}  ; {location}"),
+			None => line.into(),
+		}
+	}
+
+	/// Annotate every call stack frame of an error log.
+	pub fn annotate_log(&self, log: &str) -> String {
+		let mut annotated = String::with_capacity(log.len());
+		for line in log.lines() {
+			annotated.push_str(&self.annotate_line(line));
+			annotated.push('\n');
+		}
+		annotated
+	}
+}
+
+/// Return the text of a call stack frame after its index, such as `0x1a4` in
+/// `L 01/01/2026 - 00:00:00: [SM]   [1] 0x1a4`.
+fn frame(line: &str) -> Option<&str> {
+	let rest = line.split_once("[SM]").map_or(line, move |(_, rest)| rest).trim_start();
+	let (index, rest) = rest.strip_prefix('[')?.split_once(']')?;
+	if index.is_empty() || !index.bytes().all(move |b| b.is_ascii_digit()) {
+		return None
+	}
+	Some(rest.trim())
+}
+
+/// Parse the first hexadecimal number with a `0x` prefix.
+fn hex_address(s: &str) -> Option<usize> {
+	let (_, digits) = s.split_once("0x")?;
+	let end = digits.find(move |c: char| !c.is_ascii_hexdigit()).unwrap_or(digits.len());
+	usize::from_str_radix(&digits[..end], 16).ok()
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::{
+		debug::{
+			DebugFile,
+			DebugLine,
+			Symbol,
+			SymbolKind,
+			SymbolScope,
+		},
+		rtti::RttiMethod,
+	};
+
+	fn legacy() -> Symbolizer {
+		let mut debug = DebugInfo::default();
+		let file = debug.strings.insert(c"test.sp") as u32;
+		let functions = [(c"OnPluginStart", 0, 32), (c"Helper", 32, 64)];
+		for (name, code_start, code_end) in functions {
+			let name = debug.strings.insert(name) as u32;
+			debug.symbols.push(Symbol {
+				address: code_start as _,
+				tag: 0,
+				code_start,
+				code_end,
+				kind: SymbolKind::Function,
+				scope: SymbolScope::Global,
+				dims: Vec::new(),
+				name,
+			});
+		}
+		debug.files.push(DebugFile { address: 0, name: file });
+		for (address, line) in [(0, 4), (8, 5), (32, 9), (40, 10)] {
+			debug.lines.push(DebugLine { address, line });
+		}
+		Symbolizer {
+			debug,
+			..Default::default()
+		}
+	}
+
+	#[test]
+	fn locate() {
+		let symbolizer = legacy();
+		assert_eq!(symbolizer.locate(44), Location {
+			file: Some("test.sp".into()),
+			line: Some(11),
+			function: Some("Helper".into()),
+		});
+		assert_eq!(symbolizer.locate(12).to_string(), "OnPluginStart (test.sp:6)");
+
+		let mut symbolizer = symbolizer;
+		let name = symbolizer.names.insert(c"Modern") as u32;
+		symbolizer.rtti.methods.push(RttiMethod {
+			name,
+			pcode_start: 0,
+			pcode_end: 64,
+			signature: 0,
+		});
+		assert_eq!(symbolizer.function_at(40).as_deref(), Some("Modern"));
+		assert_eq!(symbolizer.function_start("Modern"), Some(0));
+	}
+
+	#[test]
+	fn annotate() {
+		let symbolizer = legacy();
+		let log = "\
+			L 10/18/2026 - 12:00:00: [SM] Call stack trace:\n\
+			[SM]   [0] ThrowError\n\
+			[SM]   [1] 0x0000002c\n\
+			[SM]   [2] OnPluginStart()\n";
+		assert_eq!(symbolizer.annotate_log(log), "\
+			L 10/18/2026 - 12:00:00: [SM] Call stack trace:\n\
+			[SM]   [0] ThrowError\n\
+			[SM]   [1] 0x0000002c  ; Helper (test.sp:11)\n\
+			[SM]   [2] OnPluginStart()  ; OnPluginStart (test.sp:5)\n");
+	}
+}
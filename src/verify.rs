@@ -0,0 +1,502 @@
+//! Static verifier of stack and heap usage, similar to the method verifier of
+//! the SourcePawn VM.
+//!
+//! Stack depth is tracked in bytes below the frame of `PROC`, and must be the
+//! same on every path into a block, and zero at `RETN`. Heap allocations with
+//! `HEAP` must be balanced at `RETN`, and `HEAP.RESTORE` must match an earlier
+//! `HEAP.SAVE`.
+
+use crate::{
+	cfg::{
+		self,
+		Cfg,
+		CfgIssue,
+	},
+	decoder::DecodeError,
+	dot::function_name,
+	functions::{
+		self,
+		Function,
+	},
+	optimize::{
+		push_operands,
+		PushKind,
+	},
+	plugin::Plugin,
+	vm_types::{
+		Cell,
+		ARG_SIZE,
+		CELL,
+		FIRST_ARG,
+	},
+	Instruction,
+};
+
+use std::{
+	error::Error,
+	fmt,
+};
+
+/// Kind of a verification failure.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VerifyErrorKind {
+	/// A jump targets the middle of an instruction.
+	MisalignedTarget {
+		target: Cell,
+	},
+	/// A jump targets code outside of the function.
+	OutsideTarget {
+		target: Cell,
+	},
+	/// A `SWITCH` does not refer to a case table.
+	MissingCaseTable {
+		target: Cell,
+	},
+	/// More bytes are popped than were pushed in the frame.
+	StackUnderflow,
+	/// A block is entered with different stack depths.
+	DepthMismatch {
+		expected: Cell,
+		found: Cell,
+	},
+	/// A block is entered with different heap allocations.
+	HeapMismatch {
+		expected: Cell,
+		found: Cell,
+	},
+	/// A block is entered with different numbers of saved heap pointers.
+	SaveMismatch {
+		expected: usize,
+		found: usize,
+	},
+	/// The function returns with bytes left on the stack.
+	ReturnDepth {
+		depth: Cell,
+	},
+	/// The function returns with bytes left on the heap.
+	HeapImbalance {
+		heap: Cell,
+	},
+	/// The function returns with heap pointers that were saved, but not
+	/// restored.
+	UnbalancedSave {
+		count: usize,
+	},
+	/// `HEAP.RESTORE` without a matching `HEAP.SAVE`.
+	RestoreWithoutSave,
+	/// A frame offset is outside of the arguments and the allocated locals.
+	FrameOffset {
+		offset: Cell,
+		depth: Cell,
+	},
+	/// `CALL` is not preceded by a constant argument count.
+	UnknownArgCount,
+}
+
+impl fmt::Display for VerifyErrorKind {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			Self::MisalignedTarget { target } =>
+				write!(f, "jump into the middle of an instruction at 0x{target:08x}"),
+			Self::OutsideTarget { target } =>
+				write!(f, "jump outside of the function to 0x{target:08x}"),
+			Self::MissingCaseTable { target } =>
+				write!(f, "switch without a case table at 0x{target:08x}"),
+			Self::StackUnderflow => f.write_str("stack underflow"),
+			Self::DepthMismatch { expected, found } => write!(
+				f,
+				"stack depth mismatch at join, {expected} bytes vs {found} bytes"
+			),
+			Self::HeapMismatch { expected, found } => write!(
+				f,
+				"heap allocation mismatch at join, {expected} bytes vs {found} bytes"
+			),
+			Self::SaveMismatch { expected, found } => write!(
+				f,
+				"saved heap pointer mismatch at join, {expected} vs {found}"
+			),
+			Self::ReturnDepth { depth } =>
+				write!(f, "return with {depth} bytes left on the stack"),
+			Self::HeapImbalance { heap } =>
+				write!(f, "return with {heap} bytes left on the heap"),
+			Self::UnbalancedSave { count } =>
+				write!(f, "return with {count} saved heap pointers not restored"),
+			Self::RestoreWithoutSave => f.write_str("heap restore without a save"),
+			Self::FrameOffset { offset, depth } => write!(
+				f,
+				"frame offset {offset} is outside of the frame with {depth} bytes of \
+				locals"
+			),
+			Self::UnknownArgCount =>
+				f.write_str("call is not preceded by a constant argument count"),
+		}
+	}
+}
+
+/// Verification failure at a code offset.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct VerifyError {
+	/// Code offset of the offending instruction or block.
+	pub offset: usize,
+	/// Kind of the failure.
+	pub kind: VerifyErrorKind,
+}
+
+impl fmt::Display for VerifyError {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		write!(f, "0x{:08x}: {}", self.offset, self.kind)
+	}
+}
+
+impl Error for VerifyError {}
+
+/// Verification failures of a single function.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FunctionErrors {
+	/// Code offset of the function.
+	pub start: usize,
+	/// Name of the function.
+	pub name: String,
+	/// Failures, in order of their discovery.
+	pub errors: Vec<VerifyError>,
+}
+
+/// Structure for an error that has occurred while verifying a plugin.
+#[derive(Debug)]
+pub enum PluginVerifyError {
+	/// Code could not be decoded.
+	Decode(DecodeError),
+	/// Functions failed verification.
+	Invalid(Vec<FunctionErrors>),
+}
+
+impl fmt::Display for PluginVerifyError {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			Self::Decode(e) => write!(f, "decode error {e}"),
+			Self::Invalid(functions) => {
+				f.write_str("verification failed")?;
+				for function in functions.iter() {
+					write!(f, "\n{} (0x{:08x}):", function.name, function.start)?;
+					for error in function.errors.iter() {
+						write!(f, "\n\t{error}")?;
+					}
+				}
+				Ok(())
+			}
+		}
+	}
+}
+
+impl Error for PluginVerifyError {}
+
+impl From<DecodeError> for PluginVerifyError {
+	fn from(value: DecodeError) -> Self {
+		Self::Decode(value)
+	}
+}
+
+/// Abstract machine state at an instruction.
+#[derive(Debug, Clone)]
+struct State {
+	/// Bytes pushed below the frame.
+	depth: Cell,
+	/// Bytes allocated on the heap, if known.
+	heap: Option<Cell>,
+	/// Heap allocations at each `HEAP.SAVE`.
+	saves: Vec<Option<Cell>>,
+	/// `true` after `PROC`.
+	framed: bool,
+	/// Constant on top of the stack, if known.
+	top: Option<Cell>,
+}
+
+impl State {
+	const ENTRY: Self = Self {
+		depth: 0,
+		heap: Some(0),
+		saves: Vec::new(),
+		framed: false,
+		top: None,
+	};
+
+	/// Compare the state on another path into a block with this one.
+	fn check_join(&self, other: &Self, offset: usize, errors: &mut Vec<VerifyError>) {
+		let mut error = move |kind| errors.push(VerifyError { offset, kind });
+		if self.depth != other.depth {
+			error(VerifyErrorKind::DepthMismatch {
+				expected: self.depth,
+				found: other.depth,
+			});
+		}
+		if let (Some(expected), Some(found)) = (self.heap, other.heap) {
+			if expected != found {
+				error(VerifyErrorKind::HeapMismatch { expected, found });
+			}
+		}
+		if self.saves.len() != other.saves.len() {
+			error(VerifyErrorKind::SaveMismatch {
+				expected: self.saves.len(),
+				found: other.saves.len(),
+			});
+		}
+	}
+
+	fn pop(&mut self, bytes: Cell, error: &mut impl FnMut(VerifyErrorKind)) {
+		self.depth -= bytes;
+		if self.depth < 0 {
+			error(VerifyErrorKind::StackUnderflow);
+			self.depth = 0;
+		}
+	}
+
+	fn frame(&self, offset: Cell, error: &mut impl FnMut(VerifyErrorKind)) {
+		if !self.framed {
+			return
+		}
+		let valid = offset % CELL == 0
+			&& (offset == ARG_SIZE
+				|| offset >= FIRST_ARG
+				|| (offset < 0 && -offset <= self.depth));
+		if !valid {
+			error(VerifyErrorKind::FrameOffset {
+				offset,
+				depth: self.depth,
+			});
+		}
+	}
+
+	/// Execute an instruction.
+	fn step(
+		&mut self,
+		instruction: &Instruction,
+		error: &mut impl FnMut(VerifyErrorKind),
+	) {
+		use Instruction as I;
+
+		let mut top = None;
+		if let Some((kind, ops)) = push_operands(instruction) {
+			if matches!(kind, PushKind::Stack | PushKind::Adr) {
+				for op in ops.iter() {
+					self.frame(*op, error);
+				}
+			}
+			if kind == PushKind::Const {
+				top = ops.last().copied();
+			}
+			self.depth += CELL * ops.len() as Cell;
+			self.top = top;
+			return
+		}
+
+		match *instruction {
+			I::Proc => {
+				self.depth = 0;
+				self.framed = true;
+			}
+			I::PushPri | I::PushAlt => self.depth += CELL,
+			I::PushR { const_1 } => self.depth += CELL * const_1.max(0),
+			I::PopPri | I::PopAlt => self.pop(CELL, error),
+			I::Stack { const_1 } => self.pop(const_1, error),
+			I::Call { .. } => match self.top {
+				Some(args) => self.pop(args + CELL, error),
+				None => error(VerifyErrorKind::UnknownArgCount),
+			},
+			I::SysreqN { n_args, .. } => self.pop(CELL * n_args, error),
+			I::Genarray { const_1 } | I::GenarrayZ { const_1 } => {
+				self.pop(CELL * (const_1 - 1), error);
+				self.heap = None;
+			}
+			I::Retn | I::Ret => {
+				if self.depth != 0 {
+					error(VerifyErrorKind::ReturnDepth { depth: self.depth });
+				}
+				if let Some(heap) = self.heap.filter(move |heap| *heap != 0) {
+					error(VerifyErrorKind::HeapImbalance { heap });
+				}
+				if !self.saves.is_empty() {
+					error(VerifyErrorKind::UnbalancedSave { count: self.saves.len() });
+				}
+			}
+			I::Heap { const_1 } => self.heap = self.heap.map(move |heap| heap + const_1),
+			I::HeapSave => self.saves.push(self.heap),
+			I::HeapRestore => match self.saves.pop() {
+				Some(heap) => self.heap = heap,
+				None => error(VerifyErrorKind::RestoreWithoutSave),
+			},
+			I::LoadSPri { offset }
+				| I::LoadSAlt { offset }
+				| I::LrefSPri { offset }
+				| I::LrefSAlt { offset }
+				| I::StorSPri { offset }
+				| I::StorSAlt { offset }
+				| I::SrefSPri { offset }
+				| I::SrefSAlt { offset }
+				| I::AddrPri { offset }
+				| I::AddrAlt { offset }
+				| I::ZeroS { stack_1: offset }
+				| I::IncS { stack_1: offset }
+				| I::DecS { stack_1: offset }
+				| I::ConstS { stack_1: offset, .. } => self.frame(offset, error),
+			I::LoadSBoth { stack_1, stack_2 } => {
+				self.frame(stack_1, error);
+				self.frame(stack_2, error);
+			}
+			_ => {}
+		}
+		self.top = top;
+	}
+}
+
+/// Verify a control-flow graph, returning every failure that was found.
+pub fn verify(cfg: &Cfg) -> Vec<VerifyError> {
+	let mut errors: Vec<VerifyError> = cfg.issues.iter()
+		.map(move |issue| match *issue {
+			CfgIssue::MisalignedTarget { offset, target } => VerifyError {
+				offset,
+				kind: VerifyErrorKind::MisalignedTarget { target },
+			},
+			CfgIssue::OutsideTarget { offset, target } => VerifyError {
+				offset,
+				kind: VerifyErrorKind::OutsideTarget { target },
+			},
+			CfgIssue::MissingCaseTable { offset, target } => VerifyError {
+				offset,
+				kind: VerifyErrorKind::MissingCaseTable { target },
+			},
+		})
+		.collect();
+
+	let mut entry_states: Vec<Option<State>> = vec![None; cfg.blocks.len()];
+	let mut worklist = Vec::new();
+	if !cfg.blocks.is_empty() {
+		entry_states[0] = Some(State::ENTRY);
+		worklist.push(0);
+	}
+	while let Some(idx) = worklist.pop() {
+		let block = &cfg.blocks[idx];
+		let mut state = entry_states[idx].clone().unwrap();
+		for (offset, instruction) in block.instructions.iter() {
+			let mut error = |kind| errors.push(VerifyError { offset: *offset, kind });
+			state.step(instruction, &mut error);
+		}
+		for edge in block.successors.iter() {
+			let next = &mut entry_states[edge.block];
+			match next {
+				Some(expected) => {
+					let start = cfg.blocks[edge.block].start;
+					expected.check_join(&state, start, &mut errors);
+				}
+				None => {
+					*next = Some(state.clone());
+					worklist.push(edge.block);
+				}
+			}
+		}
+	}
+
+	errors
+}
+
+/// Verify a function.
+pub fn verify_function(function: &Function) -> Vec<VerifyError> {
+	verify(&cfg::build_function(function))
+}
+
+/// Decode and verify every function of a plugin.
+pub fn verify_plugin(plugin: &Plugin) -> Result<(), PluginVerifyError> {
+	let map = functions::discover_plugin(plugin, None)?;
+	let invalid: Vec<FunctionErrors> = map.functions.iter()
+		.filter_map(move |function| {
+			let errors = verify_function(function);
+			(!errors.is_empty()).then(move || FunctionErrors {
+				start: function.start,
+				name: function_name(function),
+				errors,
+			})
+		})
+		.collect();
+	if invalid.is_empty() {
+		Ok(())
+	} else {
+		Err(PluginVerifyError::Invalid(invalid))
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::instruction::layout;
+
+	fn verify_code(instructions: Vec<Instruction>) -> Vec<VerifyError> {
+		let (code, len) = layout(instructions);
+		verify(&cfg::build(&code, len))
+	}
+
+	#[test]
+	fn balanced() {
+		let errors = verify_code(vec![
+			Instruction::Proc,
+			Instruction::Stack { const_1: -8 },
+			Instruction::ZeroS { stack_1: -8 },
+			Instruction::PushS { stack_1: 12 },
+			Instruction::PushC { const_1: 4 },
+			Instruction::Call { func_1: 0 },
+			Instruction::HeapSave,
+			Instruction::Heap { const_1: 16 },
+			Instruction::HeapRestore,
+			Instruction::PushPri,
+			Instruction::SysreqN { native: 0, n_args: 1 },
+			Instruction::Stack { const_1: 8 },
+			Instruction::Retn,
+		]);
+		assert_eq!(errors, []);
+	}
+
+	#[test]
+	fn depth_mismatch() {
+		let errors = verify_code(vec![
+			Instruction::Proc,                // 0
+			Instruction::Jzer { jump_1: 20 }, // 4
+			Instruction::PushPri,             // 12
+			Instruction::PushAlt,             // 16
+			Instruction::Retn,                // 20
+		]);
+		assert_eq!(errors, [
+			VerifyError {
+				offset: 20,
+				kind: VerifyErrorKind::DepthMismatch { expected: 0, found: 8 },
+			},
+		]);
+	}
+
+	#[test]
+	fn frame_and_heap() {
+		let errors = verify_code(vec![
+			Instruction::Proc,                      // 0
+			Instruction::LoadSPri { offset: -4 },   // 4
+			Instruction::PopPri,                    // 12
+			Instruction::LoadSAlt { offset: 4 },    // 16
+			Instruction::Heap { const_1: 4 },       // 24
+			Instruction::HeapRestore,               // 32
+			Instruction::Retn,                      // 36
+		]);
+		assert_eq!(errors, [
+			VerifyError {
+				offset: 4,
+				kind: VerifyErrorKind::FrameOffset { offset: -4, depth: 0 },
+			},
+			VerifyError { offset: 12, kind: VerifyErrorKind::StackUnderflow },
+			VerifyError {
+				offset: 16,
+				kind: VerifyErrorKind::FrameOffset { offset: 4, depth: 0 },
+			},
+			VerifyError { offset: 32, kind: VerifyErrorKind::RestoreWithoutSave },
+			VerifyError { offset: 36, kind: VerifyErrorKind::HeapImbalance { heap: 4 } },
+		]);
+		assert_eq!(
+			errors[0].to_string(),
+			"0x00000004: frame offset -4 is outside of the frame with 0 bytes of locals"
+		);
+	}
+}
@@ -0,0 +1,212 @@
+//! See [`VersionInfo`].
+
+use crate::{
+	plugin::Plugin,
+	vm_types::Cell,
+};
+
+use core::{
+	ffi::CStr,
+	str::FromStr,
+};
+use std::{
+	error::Error,
+	fmt,
+};
+
+/// Name of the public variable with version information.
+pub const VERSION_VAR: &CStr = c"__version";
+
+/// SourceMod version, such as `1.11.0.6911`.
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct SmVersion {
+	pub major: u32,
+	pub minor: u32,
+	pub release: u32,
+	/// Build number, if known.
+	pub build: Option<u32>,
+}
+
+impl SmVersion {
+	/// Create a version without a build number.
+	pub const fn new(major: u32, minor: u32, release: u32) -> Self {
+		Self {
+			major,
+			minor,
+			release,
+			build: None,
+		}
+	}
+}
+
+impl fmt::Display for SmVersion {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		write!(f, "{}.{}.{}", self.major, self.minor, self.release)?;
+		if let Some(build) = self.build {
+			write!(f, ".{build}")?;
+		}
+		Ok(())
+	}
+}
+
+impl FromStr for SmVersion {
+	type Err = VersionError;
+
+	/// Parse a version, ignoring any suffix after the numbers, such as
+	/// `-manual` or `-dev+1234`.
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		let invalid = || VersionError::InvalidVersion(s.into());
+		let end = s.find(move |c: char| !c.is_ascii_digit() && c != '.')
+			.unwrap_or(s.len());
+		let mut parts = s[..end].split('.').map(str::parse::<u32>);
+		let mut next = move || parts.next().transpose().map_err(move |_| invalid());
+		let major = next()?.ok_or_else(invalid)?;
+		let minor = next()?.ok_or_else(invalid)?;
+		Ok(Self {
+			major,
+			minor,
+			release: next()?.unwrap_or(0),
+			build: next()?,
+		})
+	}
+}
+
+/// Version information from `public PlVers __version`, whose data holds the
+/// following struct, one cell per field, with strings as data offsets:
+///
+/// ```sp
+/// struct PlVers {
+///     public int version;
+///     public const char[] filevers;
+///     public const char[] date;
+///     public const char[] time;
+/// };
+/// ```
+#[derive(Default, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct VersionInfo {
+	/// Plugin API version, `SOURCEMOD_PLUGINAPI_VERSION`.
+	pub api_version: Cell,
+	/// SourceMod version of the includes that the plugin was compiled with,
+	/// `SOURCEMOD_VERSION`.
+	pub file_version: String,
+	/// Build date, `__DATE__`.
+	pub date: String,
+	/// Build time, `__TIME__`.
+	pub time: String,
+}
+
+/// Structure for an error that has occurred while reading or checking
+/// [`VersionInfo`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum VersionError {
+	/// The plugin has no `__version` public variable.
+	Missing,
+	/// `__version` has invalid data.
+	InvalidData,
+	/// A version string could not be parsed.
+	InvalidVersion(String),
+	/// The plugin was built for a newer SourceMod than the target.
+	Newer {
+		plugin: SmVersion,
+		target: SmVersion,
+	},
+}
+
+impl fmt::Display for VersionError {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			Self::Missing => f.write_str("plugin has no `__version`"),
+			Self::InvalidData => f.write_str("`__version` has invalid data"),
+			Self::InvalidVersion(s) => write!(f, "invalid SourceMod version {s:?}"),
+			Self::Newer { plugin, target } => write!(
+				f,
+				"plugin was built for SourceMod {plugin}, which is newer than {target}"
+			),
+		}
+	}
+}
+
+impl Error for VersionError {}
+
+impl VersionInfo {
+	/// Locate `__version` through `.pubvars` and read it from the data image.
+	pub fn from_plugin(plugin: &Plugin) -> Result<Self, VersionError> {
+		let index = plugin.find_pubvar(VERSION_VAR).ok_or(VersionError::Missing)?;
+		let address = plugin.pubvars[index].address as usize;
+		let [api_version, file_version, date, time] = plugin.data.cells_at(address)
+			.ok_or(VersionError::InvalidData)?;
+		let string = move |address| plugin.data.string_at(address)
+			.ok_or(VersionError::InvalidData);
+		Ok(Self {
+			api_version,
+			file_version: string(file_version)?,
+			date: string(date)?,
+			time: string(time)?,
+		})
+	}
+
+	/// Parse [`Self::file_version`].
+	pub fn sm_version(&self) -> Result<SmVersion, VersionError> {
+		self.file_version.parse()
+	}
+
+	/// Check that the plugin can run on a target SourceMod version, which
+	/// requires its major and minor version to be no newer.
+	pub fn check(&self, target: SmVersion) -> Result<(), VersionError> {
+		let plugin = self.sm_version()?;
+		if (plugin.major, plugin.minor) > (target.major, target.minor) {
+			Err(VersionError::Newer { plugin, target })
+		} else {
+			Ok(())
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::sections::Pubvar;
+
+	#[test]
+	fn parse_version() {
+		assert_eq!("1.11.0.6911".parse(), Ok(SmVersion {
+			build: Some(6911),
+			..SmVersion::new(1, 11, 0)
+		}));
+		assert_eq!("1.12.0-manual".parse(), Ok(SmVersion::new(1, 12, 0)));
+		assert_eq!("1.10".parse(), Ok(SmVersion::new(1, 10, 0)));
+		assert!("dev".parse::<SmVersion>().is_err());
+	}
+
+	#[test]
+	fn from_plugin() -> Result<(), VersionError> {
+		let mut plugin = Plugin::default();
+		let mut cells = vec![5];
+		for s in ["1.12.0.7000", "Oct 18 2026", "12:00:00"] {
+			cells.push(plugin.data.bytes.len() as Cell);
+			plugin.data.bytes.extend_from_slice(s.as_bytes());
+			plugin.data.bytes.push(0);
+			plugin.data.bytes.resize(plugin.data.bytes.len().next_multiple_of(4), 0);
+		}
+		let address = plugin.data.bytes.len() as u32;
+		for cell in cells {
+			plugin.data.bytes.extend_from_slice(&cell.to_ne_bytes());
+		}
+		let name = plugin.names.insert(VERSION_VAR) as u32;
+		plugin.pubvars.push(Pubvar { address, name });
+
+		let info = VersionInfo::from_plugin(&plugin)?;
+		assert_eq!(info, VersionInfo {
+			api_version: 5,
+			file_version: "1.12.0.7000".into(),
+			date: "Oct 18 2026".into(),
+			time: "12:00:00".into(),
+		});
+		info.check(SmVersion::new(1, 12, 0))?;
+		assert!(matches!(
+			info.check(SmVersion::new(1, 11, 0)),
+			Err(VersionError::Newer { .. })
+		));
+		Ok(())
+	}
+}
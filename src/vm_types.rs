@@ -4,7 +4,11 @@ use byteorder::{
 	NativeEndian as Ne,
 	ReadBytesExt, WriteBytesExt,
 };
-use std::io::Result as IoResult;
+use std::io::{
+	Error as IoError,
+	ErrorKind as IoErrorKind,
+	Result as IoResult,
+};
 
 /// `ucell_t`.
 pub type Ucell = u32;
@@ -17,6 +21,16 @@ pub fn read_ucell(reader: &mut impl ReadBytesExt) -> IoResult<Ucell> {
 /// `cell_t`.
 pub type Cell = i32;
 
+/// Size of a [`Cell`], in bytes.
+pub(crate) const CELL: Cell = core::mem::size_of::<Cell>() as _;
+
+/// Frame offset of the size of the arguments in bytes, past the saved frame
+/// and the return address.
+pub(crate) const ARG_SIZE: Cell = 2 * CELL;
+
+/// Frame offset of the first argument, past the size of the arguments.
+pub(crate) const FIRST_ARG: Cell = 3 * CELL;
+
 /// Read a [`Cell`] from a reader.
 pub fn read_cell(reader: &mut impl ReadBytesExt) -> IoResult<Cell> {
 	reader.read_i32::<Ne>()
@@ -27,6 +41,33 @@ pub fn write_cell(writer: &mut impl WriteBytesExt, cell: Cell) -> IoResult<()> {
 	writer.write_i32::<Ne>(cell)
 }
 
+/// Read as many [`Cell`]s from a reader as are needed to hold `size` bytes.
+pub fn read_cells(
+	reader: &mut impl ReadBytesExt, size: Cell,
+) -> IoResult<Vec<Cell>> {
+	let Ok(size) = usize::try_from(size) else {
+		return Err(IoError::new(
+			IoErrorKind::InvalidData, format!("invalid byte size: {size}")
+		))
+	};
+
+	let mut cells = Vec::new();
+	for _ in 0..size.div_ceil(core::mem::size_of::<Cell>()) {
+		cells.push(read_cell(reader)?);
+	}
+	Ok(cells)
+}
+
+/// Write a slice of [`Cell`]s to a writer.
+pub fn write_cells(
+	writer: &mut impl WriteBytesExt, cells: &[Cell],
+) -> IoResult<()> {
+	for cell in cells.iter().copied() {
+		write_cell(writer, cell)?;
+	}
+	Ok(())
+}
+
 /// `funcid_t`.
 pub type FuncId = u32;
 
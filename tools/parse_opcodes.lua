@@ -5,6 +5,8 @@
 
 --- @class Instruction: table<integer, string>
 --- @field description? string
+--- @field legacy? boolean Opcode was removed from SourcePawn.
+--- @field variable? boolean Operands are a byte size followed by cells.
 
 --- See `Interpreter::visit*` methods in
 --- <https://github.com/alliedmodders/sourcepawn/blob/master/vm/interpreter.cpp>,
@@ -32,8 +34,16 @@ local OPCODE_MAP = {
 		description = "`R[1] = Frame[offset]`";
 		"offset";
 	};
-	LREF_PRI = {};
-	LREF_ALT = {};
+	LREF_PRI = {
+		description = "`R[0] = Memory[Memory[offset]]`";
+		legacy = true;
+		"offset";
+	};
+	LREF_ALT = {
+		description = "`R[1] = Memory[Memory[offset]]`";
+		legacy = true;
+		"offset";
+	};
 	LREF_S_PRI = {
 		description = "`R[0] = &Memory[offset]`";
 		"offset";
@@ -81,8 +91,16 @@ local OPCODE_MAP = {
 		description = "`Frame[offset] = R[1];`";
 		"offset";
 	};
-	SREF_PRI = {};
-	SREF_ALT = {};
+	SREF_PRI = {
+		description = "`Memory[Memory[offset]] = R[0]`";
+		legacy = true;
+		"offset";
+	};
+	SREF_ALT = {
+		description = "`Memory[Memory[offset]] = R[1]`";
+		legacy = true;
+		"offset";
+	};
 	SREF_S_PRI = {
 		description = "`Memory[offset] = R[0]`";
 		"offset";
@@ -101,15 +119,39 @@ local OPCODE_MAP = {
 	LIDX = {
 		description = "``";
 	};
-	LIDX_B = {};
+	LIDX_B = {
+		description = "`R[0] = Memory[R[1] + (R[0] << shift)]`";
+		legacy = true;
+		"shift";
+	};
 	IDXADDR = {
 		description = "``";
 	};
-	IDXADDR_B = {};
-	ALIGN_PRI = {};
-	ALIGN_ALT = {};
-	LCTRL = {};
-	SCTRL = {};
+	IDXADDR_B = {
+		description = "`R[0] = R[1] + (R[0] << shift)`";
+		legacy = true;
+		"shift";
+	};
+	ALIGN_PRI = {
+		description = "Align `R[0]` for little-endian byte access.";
+		legacy = true;
+		"const_1";
+	};
+	ALIGN_ALT = {
+		description = "Align `R[1]` for little-endian byte access.";
+		legacy = true;
+		"const_1";
+	};
+	LCTRL = {
+		description = "`R[0] = Control[index]`";
+		legacy = true;
+		"index";
+	};
+	SCTRL = {
+		description = "`Control[index] = R[0]`";
+		legacy = true;
+		"index";
+	};
 	MOVE_PRI = {
 		description = "``";
 	};
@@ -125,7 +167,11 @@ local OPCODE_MAP = {
 	PUSH_ALT = {
 		description = "``";
 	};
-	PUSH_R = {};
+	PUSH_R = {
+		description = "Push `const_1` copies of `R[0]`.";
+		legacy = true;
+		"const_1";
+	};
 	PUSH_C = {
 		description = "``";
 		"const_1";
@@ -155,7 +201,10 @@ local OPCODE_MAP = {
 	PROC = {
 		description = "Indicates the start of a function (or \"procedure\").";
 	};
-	RET = {};
+	RET = {
+		description = "Return from a function without removing its arguments.";
+		legacy = true;
+	};
 	RETN = {
 		description = "``";
 	};
@@ -163,12 +212,19 @@ local OPCODE_MAP = {
 		description = "``";
 		"func_1";
 	};
-	CALL_PRI = {};
+	CALL_PRI = {
+		description = "Call the function at `R[0]`.";
+		legacy = true;
+	};
 	JUMP = {
 		description = "``";
 		"jump_1";
 	};
-	JREL = {};
+	JREL = {
+		description = "Jump by `jump_1` bytes relative to the next instruction.";
+		legacy = true;
+		"jump_1";
+	};
 	JZER = {
 		description = "``";
 		"jump_1";
@@ -185,10 +241,26 @@ local OPCODE_MAP = {
 		description = "``";
 		"jump_1";
 	};
-	JLESS = {};
-	JLEQ = {};
-	JGRTR = {};
-	JGEQ = {};
+	JLESS = {
+		description = "Unsigned `jump_1` if `R[0] < R[1]`.";
+		legacy = true;
+		"jump_1";
+	};
+	JLEQ = {
+		description = "Unsigned `jump_1` if `R[0] <= R[1]`.";
+		legacy = true;
+		"jump_1";
+	};
+	JGRTR = {
+		description = "Unsigned `jump_1` if `R[0] > R[1]`.";
+		legacy = true;
+		"jump_1";
+	};
+	JGEQ = {
+		description = "Unsigned `jump_1` if `R[0] >= R[1]`.";
+		legacy = true;
+		"jump_1";
+	};
 	JSLESS = {
 		description = "``";
 		"jump_1";
@@ -222,8 +294,16 @@ local OPCODE_MAP = {
 		description = "``";
 		"const_1";
 	};
-	SHR_C_PRI = {};
-	SHR_C_ALT = {};
+	SHR_C_PRI = {
+		description = "`R[0] = R[0] >> const_1` (unsigned)";
+		legacy = true;
+		"const_1";
+	};
+	SHR_C_ALT = {
+		description = "`R[1] = R[1] >> const_1` (unsigned)";
+		legacy = true;
+		"const_1";
+	};
 	SMUL = {
 		description = "``";
 	};
@@ -233,9 +313,18 @@ local OPCODE_MAP = {
 	SDIV_ALT = {
 		description = "``";
 	};
-	UMUL = {};
-	UDIV = {};
-	UDIV_ALT = {};
+	UMUL = {
+		description = "Unsigned `R[0] = R[0] * R[1]`";
+		legacy = true;
+	};
+	UDIV = {
+		description = "Unsigned `R[0] = R[0] / R[1]; R[1] = R[0] % R[1]`";
+		legacy = true;
+	};
+	UDIV_ALT = {
+		description = "Unsigned `R[0] = R[1] / R[0]; R[1] = R[1] % R[0]`";
+		legacy = true;
+	};
 	ADD = {
 		description = "``";
 	};
@@ -285,18 +374,36 @@ local OPCODE_MAP = {
 		description = "``";
 		"stack_1";
 	};
-	SIGN_PRI = {};
-	SIGN_ALT = {};
+	SIGN_PRI = {
+		description = "Sign-extend the low byte of `R[0]`.";
+		legacy = true;
+	};
+	SIGN_ALT = {
+		description = "Sign-extend the low byte of `R[1]`.";
+		legacy = true;
+	};
 	EQ = {
 		description = "``";
 	};
 	NEQ = {
 		description = "``";
 	};
-	LESS = {};
-	LEQ = {};
-	GRTR = {};
-	GEQ = {};
+	LESS = {
+		description = "Unsigned `R[0] = R[0] < R[1]`";
+		legacy = true;
+	};
+	LEQ = {
+		description = "Unsigned `R[0] = R[0] <= R[1]`";
+		legacy = true;
+	};
+	GRTR = {
+		description = "Unsigned `R[0] = R[0] > R[1]`";
+		legacy = true;
+	};
+	GEQ = {
+		description = "Unsigned `R[0] = R[0] >= R[1]`";
+		legacy = true;
+	};
 	SLESS = {
 		description = "``";
 	};
@@ -355,7 +462,11 @@ local OPCODE_MAP = {
 		description = "``";
 		"const_1";
 	};
-	CMPS = {};
+	CMPS = {
+		description = "Compare `const_1` bytes at `R[0]` and `R[1]`.";
+		legacy = true;
+		"const_1";
+	};
 	FILL = {
 		description = "``";
 		"const_1";
@@ -368,23 +479,44 @@ local OPCODE_MAP = {
 		description = "``";
 		"const_1";
 	};
-	SYSREQ_PRI = {};
+	SYSREQ_PRI = {
+		description = "Invoke the native whose index is in `R[0]`.";
+		legacy = true;
+	};
 	SYSREQ_C = {
 		description = "``";
 		"native_1";
 	};
-	FILE = {};
-	LINE = {};
-	SYMBOL = {};
-	SRANGE = {};
-	JUMP_PRI = {};
+	FILE = {
+		description = "Debug file record: `size` bytes of data follow.";
+		legacy = true;
+		variable = true;
+	};
+	LINE = {
+		description = "Debug line record.";
+		legacy = true;
+		"line", "file";
+	};
+	SYMBOL = {
+		description = "Debug symbol record: `size` bytes of data follow.";
+		legacy = true;
+		variable = true;
+	};
+	SRANGE = {
+		description = "Debug symbol array range.";
+		legacy = true;
+		"level", "size";
+	};
+	JUMP_PRI = {
+		description = "Jump to the code offset in `R[0]`.";
+		legacy = true;
+	};
 	SWITCH = {
 		description = "``";
 		"jump_1";
 	};
 	CASETBL = {
-		description = "``";
-		"const_1", "jump_1";
+		description = "Case table of a `SWITCH`, with variable length.";
 	};
 	SWAP_PRI = {
 		description = "``";
@@ -403,7 +535,11 @@ local OPCODE_MAP = {
 		description = "Invoke native `native` with `n_args` arguments.";
 		"native", "n_args";
 	};
-	SYMTAG = {};
+	SYMTAG = {
+		description = "Debug symbol tag.";
+		legacy = true;
+		"const_1";
+	};
 	BREAK = {
 		description = "Invoke a debug line break.";
 	};
@@ -487,8 +623,16 @@ local OPCODE_MAP = {
 		description = "``";
 		"stack_1", "const_1";
 	};
-	SYSREQ_D = {};
-	SYSREQ_ND = {};
+	SYSREQ_D = {
+		description = "Invoke the native at address `addr_1`.";
+		legacy = true;
+		"addr_1";
+	};
+	SYSREQ_ND = {
+		description = "Invoke the native at address `addr_1` with `n_args` arguments.";
+		legacy = true;
+		"addr_1", "n_args";
+	};
 	TRACKER_PUSH_C = {
 		description = "``";
 		"const_1";
@@ -507,12 +651,23 @@ local OPCODE_MAP = {
 	STRADJUST_PRI = {
 		description = "``";
 	};
-	STKADJUST = {};
+	STKADJUST = {
+		description = "Adjust the stack by `R[0]` bytes.";
+		legacy = true;
+	};
 	ENDPROC = {
 		description = "``";
 	};
-	LDGFN_PRI = {};
-	REBASE = {};
+	LDGFN_PRI = {
+		description = "Load the global function `func_1` into `R[0]`.";
+		legacy = true;
+		"func_1";
+	};
+	REBASE = {
+		description = "Rebase the indirection vectors of an array.";
+		legacy = true;
+		"addr_1", "const_1", "const_2";
+	};
 	INITARRAY_PRI = {
 		description = "``";
 		"addr_1", "const_1", "const_2", "const_3", "const_4";
@@ -640,6 +795,11 @@ for line in read_line do
 	end
 end
 
+--- Return `true` if the instruction at index `i` gets an enum variant.
+local function is_emitted(i)
+	return instructions[i + 2] or OPCODE_MAP[instructions[i]].legacy
+end
+
 local function rustify_opcode(identifier)
 	return identifier:lower()
 		:gsub("_([a-z])", string_upper)
@@ -662,6 +822,12 @@ for i = 1, instructions_i, 3 do
 		)
 	end
 
+	if doc.legacy and n_cells then
+		return error(
+			"instruction " .. opcode .. " is marked as legacy, but is supported"
+		)
+	end
+
 	if n_cells and n_cells > 1 then
 		local arg_n = n_cells - 1
 		if #doc ~= arg_n then
@@ -674,10 +840,18 @@ for i = 1, instructions_i, 3 do
 end
 
 io_write([[
-use crate::vm_types::{
-	Cell,
-	read_cell,
-	write_cell
+use crate::{
+	case_table::{
+		self,
+		Case,
+	},
+	vm_types::{
+		Cell,
+		read_cell,
+		read_cells,
+		write_cell,
+		write_cells
+	}
 };
 
 use byteorder::{
@@ -697,16 +871,24 @@ io_write [[
 /// 
 /// This type is generated automatically by a script.
 ]]
-io_write("#[derive(Debug, Clone, Copy, PartialEq, Eq)]\n")
+io_write("#[derive(Debug, Clone, PartialEq, Eq)]\n")
 io_write("#[repr(C)]\n")
 io_write("pub enum Instruction {\n")
 for i = 1, instructions_i, 3 do
 	local opcode = instructions[i]
 	local rust_opcode = rustify_opcode(opcode)
-	-- if opcode == "CASETBL" then
-	--	io_write('\t', rust_opcode, ",\n")
-	-- else
-	if instructions[i + 2] then
+	if opcode == "CASETBL" then
+		io_write('\t', rust_opcode, " {\n")
+		io_write("\t\tdefault: Cell,\n")
+		io_write("\t\tcases: Vec<Case>,\n")
+		io_write("\t\tswitch: Option<Cell>,\n")
+		io_write("\t},\n")
+	elseif OPCODE_MAP[opcode].variable then
+		io_write('\t', rust_opcode, " {\n")
+		io_write("\t\tsize: Cell,\n")
+		io_write("\t\tdata: Vec<Cell>,\n")
+		io_write("\t},\n")
+	elseif is_emitted(i) then
 		local doc = OPCODE_MAP[opcode]
 		if #doc > 0 then
 			io_write('\t', rust_opcode, " {\n")
@@ -721,18 +903,68 @@ for i = 1, instructions_i, 3 do
 end
 io_write("}\n\n")
 
+-- OPCODE_SET_BEGIN
+io_write[[
+/// Set of opcodes accepted by [`Instruction::read_with`].
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum OpcodeSet {
+	/// Opcodes supported by the current SourcePawn VM.
+	#[default]
+	Current,
+	/// Every opcode number that has ever been assigned, including ones that
+	/// were removed from SourcePawn (see [`Instruction::is_legacy`]).
+	Legacy,
+}
+
+]]
+
 io_write("impl Instruction {\n")
 
-io_write("\tpub fn read_from(r: &mut impl ReadBytesExt) -> IoResult<Self> {\n")
+-- READ_BEGIN
+io_write[[
+	/// Read an instruction from a reader, accepting only opcodes supported by
+	/// the current SourcePawn VM.
+	pub fn read_from(r: &mut impl ReadBytesExt) -> IoResult<Self> {
+		Self::read_with(r, OpcodeSet::Current)
+	}
+
+	/// Read an instruction from a reader, accepting opcodes from the specified
+	/// [`OpcodeSet`].
+	pub fn read_with(
+		r: &mut impl ReadBytesExt, set: OpcodeSet,
+	) -> IoResult<Self> {
+]]
 io_write("\t\tmatch read_cell(r)? {\n")
 do
 	local opcode_byte = 0
 	for i = 1, instructions_i, 3 do
-		if instructions[i + 2] then
+		if is_emitted(i) then
 			local opcode = instructions[i]
 			local doc = OPCODE_MAP[opcode]
-			io_write("\t\t\t", opcode_byte, " => ")
-			if #doc > 0 then
+			io_write("\t\t\t", opcode_byte)
+			if doc.legacy then
+				io_write(" if set == OpcodeSet::Legacy")
+			end
+			io_write(" => ")
+			if opcode == "CASETBL" then
+				io_write("{\n")
+				io_write("\t\t\t\tlet (default, cases) = case_table::read_from(r)?;\n")
+				io_write("\t\t\t\tOk(Self::", rustify_opcode(opcode), " {\n")
+				io_write("\t\t\t\t\tdefault,\n")
+				io_write("\t\t\t\t\tcases,\n")
+				io_write("\t\t\t\t\tswitch: None,\n")
+				io_write("\t\t\t\t})\n")
+				io_write("\t\t\t}\n")
+			elseif doc.variable then
+				io_write("{\n")
+				io_write("\t\t\t\tlet size = read_cell(r)?;\n")
+				io_write("\t\t\t\tlet data = read_cells(r, size)?;\n")
+				io_write("\t\t\t\tOk(Self::", rustify_opcode(opcode), " {\n")
+				io_write("\t\t\t\t\tsize,\n")
+				io_write("\t\t\t\t\tdata,\n")
+				io_write("\t\t\t\t})\n")
+				io_write("\t\t\t}\n")
+			elseif #doc > 0 then
 				io_write("{\n")
 				for i = 1, #doc do
 					io_write("\t\t\t\tlet ", doc[i], " = read_cell(r)?;\n")
@@ -763,11 +995,24 @@ io_write("\t\tmatch self {\n")
 do
 	local opcode_byte = 0
 	for i = 1, instructions_i, 3 do
-		if instructions[i + 2] then
+		if is_emitted(i) then
 			local opcode = instructions[i]
 			local doc = OPCODE_MAP[opcode]
 			io_write("\t\t\tSelf::", (rustify_opcode(opcode)))
-			if #doc > 0 then
+			if opcode == "CASETBL" then
+				io_write(" { default, cases, .. } => {\n")
+				io_write("\t\t\t\twrite_cell(w, ", opcode_byte, ")?;\n")
+				io_write("\t\t\t\tcase_table::write_to(w, *default, cases)?;\n")
+				io_write("\t\t\t\tOk(())\n")
+				io_write("\t\t\t}\n")
+			elseif doc.variable then
+				io_write(" { size, data, } => {\n")
+				io_write("\t\t\t\twrite_cell(w, ", opcode_byte, ")?;\n")
+				io_write("\t\t\t\twrite_cell(w, *size)?;\n")
+				io_write("\t\t\t\twrite_cells(w, data)?;\n")
+				io_write("\t\t\t\tOk(())\n")
+				io_write("\t\t\t}\n")
+			elseif #doc > 0 then
 				io_write(" { ")
 				for i = 1, #doc do
 					io_write(doc[i], ", ")
@@ -789,4 +1034,56 @@ end
 io_write("\t\t}\n")
 io_write("\t}\n")
 
+-- IS_LEGACY_BEGIN
+io_write[[
+
+	/// Return `true` if this instruction uses an opcode that was removed from
+	/// SourcePawn, which is only decoded with [`OpcodeSet::Legacy`].
+	pub const fn is_legacy(&self) -> bool {
+		matches!(
+			self,
+]]
+do
+	local first = true
+	for i = 1, instructions_i, 3 do
+		local opcode = instructions[i]
+		local doc = OPCODE_MAP[opcode]
+		if doc.legacy then
+			if first then
+				io_write("\t\t\tSelf::", rustify_opcode(opcode))
+				first = false
+			else
+				io_write("\t\t\t\t| Self::", rustify_opcode(opcode))
+			end
+			if #doc > 0 or doc.variable then
+				io_write(" { .. }")
+			end
+			io_write("\n")
+		end
+	end
+end
+io_write("\t\t)\n")
+io_write("\t}\n")
+
+-- MNEMONIC_BEGIN
+io_write[[
+
+	/// Return the mnemonic of this instruction's opcode.
+	pub const fn mnemonic(&self) -> &'static str {
+		match self {
+]]
+for i = 1, instructions_i, 3 do
+	if is_emitted(i) then
+		local opcode = instructions[i]
+		local doc = OPCODE_MAP[opcode]
+		io_write("\t\t\tSelf::", rustify_opcode(opcode))
+		if #doc > 0 or doc.variable or opcode == "CASETBL" then
+			io_write(" { .. }")
+		end
+		io_write(" => \"", instructions[i + 1], "\",\n")
+	end
+end
+io_write("\t\t}\n")
+io_write("\t}\n")
+
 io_write("}\n")